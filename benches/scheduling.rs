@@ -0,0 +1,71 @@
+#![feature(test)]
+
+extern crate test;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use test::Bencher;
+
+use eva::configuration::SchedulingStrategy;
+use eva::{Schedule, Task};
+
+/// Generates `n` tasks with varying durations, importances and deadlines that leave enough slack
+/// to be schedulable. A simple linear congruential generator keeps the task sets reproducible.
+fn generate_tasks(start: DateTime<Utc>, n: u32) -> Vec<Task> {
+    let mut seed: u64 = 42;
+    let mut random = move |bound: u64| {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) % bound
+    };
+    let mut total_duration = Duration::zero();
+    (0..n)
+        .map(|id| {
+            let duration = Duration::minutes(10 + random(230) as i64);
+            total_duration = total_duration + duration;
+            // Give every task enough time to be finished after all tasks generated before it, plus
+            // a random amount of slack.
+            let deadline = start + total_duration * 2 + Duration::hours(random(24 * 14) as i64);
+            Task {
+                id,
+                content: format!("task {}", id),
+                deadline,
+                duration,
+                importance: 1 + random(10) as u32,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+fn bench_schedule(b: &mut Bencher, n: u32, strategy: fn() -> SchedulingStrategy) {
+    let start = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+    let tasks = generate_tasks(start, n);
+    b.iter(|| Schedule::schedule(start, tasks.clone(), strategy()).unwrap());
+}
+
+#[bench]
+fn schedule_1k_tasks_by_importance(b: &mut Bencher) {
+    bench_schedule(b, 1_000, || SchedulingStrategy::Importance);
+}
+
+#[bench]
+fn schedule_1k_tasks_by_urgency(b: &mut Bencher) {
+    bench_schedule(b, 1_000, || SchedulingStrategy::Urgency);
+}
+
+#[bench]
+fn schedule_10k_tasks_by_importance(b: &mut Bencher) {
+    bench_schedule(b, 10_000, || SchedulingStrategy::Importance);
+}
+
+#[bench]
+fn schedule_10k_tasks_by_urgency(b: &mut Bencher) {
+    bench_schedule(b, 10_000, || SchedulingStrategy::Urgency);
+}
+
+#[bench]
+fn schedule_50_tasks_optimally(b: &mut Bencher) {
+    bench_schedule(b, 50, || SchedulingStrategy::Optimal);
+}
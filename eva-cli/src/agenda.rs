@@ -0,0 +1,329 @@
+use std::env;
+
+use ansi_term::{Colour, Style};
+use chrono::prelude::*;
+use chrono::Duration;
+use itertools::Itertools;
+
+use crate::pretty_print::PrettyPrint;
+use crate::timezone;
+
+
+/// How many characters of the content of a task the agenda shows at most.
+const MAX_TITLE_LENGTH: usize = 72;
+
+
+/// Renders schedules as a day-by-day agenda, with times relative to `now` and, when `color` is
+/// on, the important tasks and the ones that are cutting it close to their deadline in color.
+#[derive(Debug, Clone, Copy)]
+pub struct Agenda {
+    pub now: DateTime<Utc>,
+    pub color: bool,
+}
+
+
+/// Tells whether to use colors on the terminal: not when asked for plain output, when the
+/// `NO_COLOR` environment variable is set (see https://no-color.org) or when the output doesn't
+/// go to a terminal.
+pub fn use_color(plain: bool) -> bool {
+    let no_color = env::var_os("NO_COLOR").map_or(false, |no_color| !no_color.is_empty());
+    !plain && !no_color && atty::is(atty::Stream::Stdout)
+}
+
+
+impl Agenda {
+    pub fn render(&self, schedule: &eva::Schedule) -> String {
+        if schedule.0.is_empty() {
+            return "Nothing is scheduled.".to_owned();
+        }
+        let days = schedule.0.iter()
+            .group_by(|scheduled_task| local_date(scheduled_task.when));
+        (&days).into_iter()
+            .map(|(date, scheduled_tasks)| {
+                let tasks = scheduled_tasks.map(|scheduled_task| self.entry(scheduled_task))
+                    .join("\n");
+                format!("{}\n{}", self.paint(Style::new().bold(), &self.day(date)), tasks)
+            })
+            .join("\n\n")
+    }
+
+    /// Renders focus sessions like a schedule, with a line for the break after every session that
+    /// has one.
+    pub fn render_sessions(&self, sessions: &[eva::FocusSession]) -> String {
+        if sessions.is_empty() {
+            return "Nothing is scheduled.".to_owned();
+        }
+        let entries = sessions.iter()
+            .flat_map(|session| {
+                let slots = session.slots.iter()
+                    .map(move |scheduled_task| (scheduled_task.when, self.entry(scheduled_task)));
+                let pause = session.pause_until
+                    .map(|until| (session.end, self.pause(session.end, until)));
+                slots.chain(pause)
+            });
+        let days = entries.group_by(|&(when, _)| local_date(when));
+        (&days).into_iter()
+            .map(|(date, entries)| {
+                let entries = entries.map(|(_, entry)| entry).join("\n");
+                format!("{}\n{}", self.paint(Style::new().bold(), &self.day(date)), entries)
+            })
+            .join("\n\n")
+    }
+
+    fn pause(&self, start: DateTime<Utc>, until: DateTime<Utc>) -> String {
+        let time = timezone::to_local(start).format("%-H:%M").to_string();
+        let pause = format!("Break until {}", timezone::to_local(until).format("%-H:%M"));
+        format!("  {:>5}  {}", time, self.paint(Style::new().dimmed(), &pause))
+    }
+
+    /// Returns a header like "Today, Tue 22 Aug" or "Fri 25 Aug".
+    fn day(&self, date: NaiveDate) -> String {
+        let today = local_date(self.now);
+        let format = if date.year() == today.year() { "%a %-d %b" } else { "%a %-d %b %Y" };
+        let formatted = date.format(format).to_string();
+        if date == today {
+            format!("Today, {}", formatted)
+        } else if date == today.succ() {
+            format!("Tomorrow, {}", formatted)
+        } else {
+            formatted
+        }
+    }
+
+    /// Returns a moment like "today 14:00", "tomorrow 9:30" or "Fri 25 Aug 14:00".
+    fn moment(&self, moment: DateTime<Utc>) -> String {
+        let today = local_date(self.now);
+        let date = local_date(moment);
+        let time = timezone::to_local(moment).format("%-H:%M");
+        if date == today {
+            format!("today {}", time)
+        } else if date == today.succ() {
+            format!("tomorrow {}", time)
+        } else if date == today.pred() {
+            format!("yesterday {}", time)
+        } else if date.year() == today.year() {
+            format!("{} {}", date.format("%a %-d %b"), time)
+        } else {
+            format!("{} {}", date.format("%a %-d %b %Y"), time)
+        }
+    }
+
+    fn entry(&self, scheduled_task: &eva::ScheduledTask) -> String {
+        let task = &scheduled_task.task;
+        let time = timezone::to_local(scheduled_task.when).format("%-H:%M").to_string();
+        let title = format!("{}. {}", task.id, shorten(&task.content));
+        // The time takes up to five characters, and two spaces are left on both sides of it
+        let indent = " ".repeat(9);
+        let tags = if task.tags.is_empty() {
+            String::new()
+        } else {
+            format!(", tags: {}", task.tags.join(", "))
+        };
+        let assignee = match task.assignee {
+            Some(ref assignee) => format!(", assignee: {}", assignee),
+            None => String::new(),
+        };
+        let attachments = match task.attachments.len() {
+            0 => String::new(),
+            1 => ", 1 attachment".to_owned(),
+            count => format!(", {} attachments", count),
+        };
+        let deadline = format!("deadline: {}", self.moment(task.deadline));
+        let note = match scheduled_task.note {
+            Some(ref note) => format!("\n{}Note: {}", indent, note),
+            None => String::new(),
+        };
+        format!("  {:>5}  {}\n{}({}, duration: {}, importance: {}{}{}{}){}",
+                time,
+                self.paint(importance_style(task.importance), &title),
+                indent,
+                self.paint(urgency_style(scheduled_task), &deadline),
+                task.duration.pretty_print(),
+                task.importance,
+                assignee,
+                tags,
+                attachments,
+                note)
+    }
+
+    fn paint(&self, style: Style, text: &str) -> String {
+        if self.color {
+            style.paint(text).to_string()
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+
+/// Returns the first line of `content`, cut off at `MAX_TITLE_LENGTH` characters. An ellipsis
+/// shows that there is more, which `eva tasks` shows in full.
+fn shorten(content: &str) -> String {
+    let first_line = content.lines().next().unwrap_or("");
+    let mut title: String = first_line.chars().take(MAX_TITLE_LENGTH).collect();
+    if title.len() < content.trim_end().len() {
+        title.push('…');
+    }
+    title
+}
+
+fn local_date(moment: DateTime<Utc>) -> NaiveDate {
+    timezone::to_local(moment).date().naive_local()
+}
+
+/// Makes very important tasks stand out, and important ones a bit.
+fn importance_style(importance: u32) -> Style {
+    if importance >= 8 {
+        Colour::Red.bold()
+    } else if importance >= 5 {
+        Colour::Yellow.normal()
+    } else {
+        Style::new()
+    }
+}
+
+/// Warns about tasks that are scheduled to end less than a day before their deadline, and a bit
+/// less loudly about those that end less than a week before it.
+fn urgency_style(scheduled_task: &eva::ScheduledTask) -> Style {
+    let end = scheduled_task.when + scheduled_task.task.duration;
+    let slack = scheduled_task.task.deadline.signed_duration_since(end);
+    if slack < Duration::days(1) {
+        Colour::Red.normal()
+    } else if slack < Duration::weeks(1) {
+        Colour::Yellow.normal()
+    } else {
+        Style::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use eva::configuration::FocusSessions;
+    use eva::{Schedule, ScheduledTask, Task};
+
+    use super::*;
+
+    fn agenda(color: bool) -> Agenda {
+        timezone::activate(Some(chrono_tz::Tz::UTC), None);
+        Agenda {
+            now: Utc.ymd(2017, 8, 22).and_hms(8, 0, 0),
+            color: color,
+        }
+    }
+
+    fn scheduled_task(id: u32, when: DateTime<Utc>, deadline: DateTime<Utc>, importance: u32)
+        -> ScheduledTask
+    {
+        ScheduledTask {
+            task: Task {
+                id: id,
+                content: format!("Task {}", id),
+                deadline: deadline,
+                duration: Duration::hours(2),
+                importance: importance,
+                ..Default::default()
+            },
+            when: when,
+            note: None,
+            held_back_by: None,
+        }
+    }
+
+    #[test]
+    fn tasks_are_grouped_per_day() {
+        let schedule = Schedule(vec![
+            scheduled_task(8, Utc.ymd(2017, 8, 22).and_hms(14, 0, 0),
+                           Utc.ymd(2017, 8, 22).and_hms(19, 0, 0), 10),
+            scheduled_task(6, Utc.ymd(2017, 8, 22).and_hms(16, 30, 0),
+                           Utc.ymd(2017, 8, 24).and_hms(0, 0, 0), 8),
+            scheduled_task(1, Utc.ymd(2017, 8, 23).and_hms(9, 0, 0),
+                           Utc.ymd(2017, 9, 3).and_hms(0, 0, 0), 9),
+            scheduled_task(3, Utc.ymd(2017, 8, 25).and_hms(9, 0, 0),
+                           Utc.ymd(2018, 1, 4).and_hms(0, 0, 0), 2),
+        ]);
+        assert_eq!(agenda(false).render(&schedule), "\
+Today, Tue 22 Aug
+  14:00  8. Task 8
+         (deadline: today 19:00, duration: 2h0, importance: 10)
+  16:30  6. Task 6
+         (deadline: Thu 24 Aug 0:00, duration: 2h0, importance: 8)
+
+Tomorrow, Wed 23 Aug
+   9:00  1. Task 1
+         (deadline: Sun 3 Sep 0:00, duration: 2h0, importance: 9)
+
+Fri 25 Aug
+   9:00  3. Task 3
+         (deadline: Thu 4 Jan 2018 0:00, duration: 2h0, importance: 2)");
+    }
+
+    #[test]
+    fn breaks_are_shown_between_sessions() {
+        let when = Utc.ymd(2017, 8, 22).and_hms(14, 0, 0);
+        let deadline = Utc.ymd(2017, 9, 22).and_hms(19, 0, 0);
+        let schedule = Schedule(vec![scheduled_task(3, when, deadline, 2)]);
+        let settings = FocusSessions {
+            focus: Duration::minutes(50),
+            pause: Duration::minutes(10),
+        };
+        let sessions = schedule.focus_sessions(settings, |_| vec![]);
+        assert_eq!(agenda(false).render_sessions(&sessions), "\
+Today, Tue 22 Aug
+  14:00  3. Task 3
+         (deadline: Fri 22 Sep 19:00, duration: 0h50, importance: 2)
+  14:50  Break until 15:00
+  15:00  3. Task 3
+         (deadline: Fri 22 Sep 19:00, duration: 0h50, importance: 2)
+  15:50  Break until 16:00
+  16:00  3. Task 3
+         (deadline: Fri 22 Sep 19:00, duration: 0h20, importance: 2)");
+    }
+
+    #[test]
+    fn empty_schedules_say_so() {
+        assert_eq!(agenda(false).render(&Schedule(vec![])), "Nothing is scheduled.");
+    }
+
+    #[test]
+    fn colors_show_importance_and_deadline_proximity() {
+        let when = Utc.ymd(2017, 8, 22).and_hms(14, 0, 0);
+        let urgent = scheduled_task(8, when, Utc.ymd(2017, 8, 22).and_hms(19, 0, 0), 10);
+        let relaxed = scheduled_task(3, when, Utc.ymd(2017, 9, 22).and_hms(19, 0, 0), 2);
+        let agenda = agenda(true);
+        assert_eq!(agenda.entry(&urgent), format!(
+            "  14:00  {}\n         ({}, duration: 2h0, importance: 10)",
+            Colour::Red.bold().paint("8. Task 8"),
+            Colour::Red.paint("deadline: today 19:00")));
+        assert_eq!(agenda.entry(&relaxed),
+                   "  14:00  3. Task 3\n         \
+                    (deadline: Fri 22 Sep 19:00, duration: 2h0, importance: 2)");
+    }
+
+    #[test]
+    fn long_contents_are_cut_off() {
+        let when = Utc.ymd(2017, 8, 22).and_hms(14, 0, 0);
+        let mut scheduled_task =
+            scheduled_task(3, when, Utc.ymd(2017, 9, 22).and_hms(19, 0, 0), 2);
+        scheduled_task.task.content = format!("{}\nMore details", "Write ".repeat(20));
+        scheduled_task.task.attachments = vec!["~/draft.md".to_owned()];
+        assert_eq!(agenda(false).entry(&scheduled_task),
+                   format!("  14:00  3. {}…\n         \
+                            (deadline: Fri 22 Sep 19:00, duration: 2h0, importance: 2, \
+                            1 attachment)",
+                           &"Write ".repeat(12)));
+    }
+
+    #[test]
+    fn notes_are_shown_under_their_slot() {
+        let when = Utc.ymd(2017, 8, 22).and_hms(14, 0, 0);
+        let scheduled_task = ScheduledTask {
+            note: Some("At the library".to_owned()),
+            ..scheduled_task(3, when, Utc.ymd(2017, 9, 22).and_hms(19, 0, 0), 2)
+        };
+        assert_eq!(agenda(false).entry(&scheduled_task),
+                   "  14:00  3. Task 3\n         \
+                    (deadline: Fri 22 Sep 19:00, duration: 2h0, importance: 2)\n         \
+                    Note: At the library");
+    }
+}
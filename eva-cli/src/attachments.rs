@@ -0,0 +1,41 @@
+use std::process::Command;
+
+use shellexpand;
+
+pub use self::errors::*;
+
+mod errors {
+    error_chain! {
+        errors {
+            Open(attachment: String, reason: String) {
+                description("attachment error")
+                display("I could not open {} ({})", attachment, reason)
+            }
+        }
+    }
+}
+
+
+/// Opens `attachment`, a path or a URL, with the program the desktop uses for it: `open` on
+/// macOS, `start` on Windows and `xdg-open` elsewhere. A path can start with `~`.
+pub fn open(attachment: &str) -> Result<()> {
+    let open_error = |reason: String| ErrorKind::Open(attachment.to_owned(), reason);
+    let target = shellexpand::tilde(attachment);
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        // The first quoted argument of start is the title of the window
+        command.args(&["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = command.arg(target.as_ref())
+        .status()
+        .chain_err(|| open_error("the program to open it with didn't start".to_owned()))?;
+    if !status.success() {
+        bail!(open_error(format!("the program to open it with exited with {}", status)));
+    }
+    Ok(())
+}
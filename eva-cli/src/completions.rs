@@ -0,0 +1,207 @@
+use std::io::{self, Write};
+
+use clap::{App, Shell};
+
+
+/// The name of the argument of the subcommands that take a single task id.
+const ID_ARGUMENT: &str = "task-id";
+/// The name of the arguments that are task ids, or tags prefixed with a plus sign.
+const SELECTION_ARGUMENT: &str = "tasks";
+
+
+/// Writes the completion script of `shell` for `app` to `out`.
+///
+/// On top of what clap generates, the scripts for bash, zsh and fish complete the ids of the
+/// pending tasks and their tags where a subcommand expects them, by running `eva porcelain`.
+pub fn generate(app: &mut App, shell: Shell, out: &mut Write) -> io::Result<()> {
+    let mut script = vec![];
+    app.gen_completions_to("eva", shell, &mut script);
+    let script = String::from_utf8_lossy(&script);
+    match shell {
+        Shell::Bash => {
+            out.write_all(script.as_bytes())?;
+            out.write_all(bash(app).as_bytes())
+        },
+        Shell::Zsh => out.write_all(zsh(&script).as_bytes()),
+        Shell::Fish => {
+            out.write_all(script.as_bytes())?;
+            out.write_all(fish(app).as_bytes())
+        },
+        _ => out.write_all(script.as_bytes()),
+    }
+}
+
+
+/// Returns the subcommands of `app` that take a positional argument with the given name, with
+/// the position of that argument, counting from 1.
+fn subcommands_with(app: &App, argument: &str) -> Vec<(String, u64)> {
+    // clap doesn't offer another way to look into the arguments of a subcommand
+    app.p.subcommands.iter()
+        .filter_map(|subcommand| {
+            subcommand.p.positionals.values()
+                .find(|positional| positional.b.name == argument)
+                .map(|positional| (subcommand.p.meta.name.clone(), positional.index))
+        })
+        .collect()
+}
+
+
+/// Returns the names of the subcommands of `app` that take a selection of tasks.
+fn selecting_subcommands(app: &App) -> Vec<String> {
+    subcommands_with(app, SELECTION_ARGUMENT).into_iter()
+        .map(|(name, _position)| name)
+        .collect()
+}
+
+
+/// Returns a completion function that completes task ids and tags, and hands everything else
+/// over to the one generated by clap.
+fn bash(app: &App) -> String {
+    let ids: String = subcommands_with(app, ID_ARGUMENT).into_iter()
+        .map(|(name, position)| format!(r#"
+        {})
+            if [[ "$cur" != -* && $COMP_CWORD -eq {} ]]; then
+                COMPREPLY=($(compgen -W "$(eva porcelain tasks 2>/dev/null | cut -f1)" -- "$cur"))
+                return 0
+            fi
+            ;;"#, name, position + 1))
+        .collect();
+    format!(r#"
+_eva_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    case "${{COMP_WORDS[1]}}" in
+        {selecting})
+            if [[ "$cur" == +* ]]; then
+                COMPREPLY=($(compgen -P + -W "$(eva porcelain tags 2>/dev/null)" -- "${{cur#+}}"))
+                return 0
+            elif [[ "$cur" != -* ]]; then
+                COMPREPLY=($(compgen -W "$(eva porcelain tasks 2>/dev/null | cut -f1)" -- "$cur"))
+                return 0
+            fi
+            ;;{ids}
+    esac
+    _eva "$@"
+}}
+
+complete -F _eva_dynamic -o bashdefault -o default eva
+"#,
+            selecting = selecting_subcommands(app).join("|"),
+            ids = ids)
+}
+
+
+/// Makes the positional task ids and selections of `script` complete with the pending tasks
+/// instead of with files, and adds the functions that do so.
+fn zsh(script: &str) -> String {
+    let mut lines: Vec<String> = script.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("':task-id") {
+                line.replacen(":_files'", ":_eva_tasks'", 1)
+            } else if trimmed.starts_with("'*:tasks") {
+                line.replacen(":_files'", ":_eva_selection'", 1)
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect();
+    lines.push(r#"
+(( $+functions[_eva_tasks] )) ||
+_eva_tasks() {
+    local -a tasks
+    tasks=(${(f)"$(eva porcelain tasks 2>/dev/null | sed -e 's/:/\\:/g' -e $'s/\t/:/')"})
+    _describe -t tasks 'task' tasks
+}
+
+(( $+functions[_eva_selection] )) ||
+_eva_selection() {
+    if [[ "$PREFIX" == +* ]]; then
+        local -a tags
+        tags=(${(f)"$(eva porcelain tags 2>/dev/null)"})
+        compset -P '+'
+        _describe -t tags 'tag' tags
+    else
+        _eva_tasks
+    fi
+}"#.to_owned());
+    // The script generated by clap ends with a call to _eva, which needs the functions above
+    if let Some(position) = lines.iter().position(|line| line.trim() == "_eva \"$@\"") {
+        let call = lines.remove(position);
+        lines.push(String::new());
+        lines.push(call);
+    }
+    lines.join("\n") + "\n"
+}
+
+
+fn fish(app: &App) -> String {
+    let tasks = "(eva porcelain tasks 2>/dev/null)";
+    let tags = "(eva porcelain tags 2>/dev/null | sed 's/^/+/')";
+    let ids: Vec<String> = subcommands_with(app, ID_ARGUMENT).into_iter()
+        .map(|(name, _position)| name)
+        .collect();
+    let ids = ids.join(" ");
+    let selecting = selecting_subcommands(app).join(" ");
+    format!("\ncomplete -c eva -n \"__fish_seen_subcommand_from {} {}\" -f -a \"{}\"\n",
+            ids, selecting, tasks)
+        + &format!("complete -c eva -n \"__fish_seen_subcommand_from {}\" -f -a \"{}\"\n",
+                   selecting, tags)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(shell: Shell) -> String {
+        let mut script = vec![];
+        generate(&mut crate::cli(), shell, &mut script).unwrap();
+        String::from_utf8(script).unwrap()
+    }
+
+    #[test]
+    fn subcommands_are_found_by_their_arguments() {
+        let app = crate::cli();
+        let ids = subcommands_with(&app, ID_ARGUMENT);
+        for name in &["done", "start", "rm", "required", "open", "explain", "note"] {
+            assert!(ids.contains(&(name.to_string(), 1)), "{} takes a task id", name);
+        }
+        assert!(ids.contains(&("set".to_owned(), 2)));
+        assert_eq!(selecting_subcommands(&app), vec!["edit", "postpone", "snooze", "bump"]);
+    }
+
+    #[test]
+    fn every_subcommand_that_takes_tasks_is_completed() {
+        let app = crate::cli();
+        let bash = script(Shell::Bash);
+        let fish = script(Shell::Fish);
+        let fish_tasks: Vec<&str> = fish.lines()
+            .find(|line| line.contains("(eva porcelain tasks"))
+            .unwrap()
+            .split_whitespace()
+            .map(|word| word.trim_matches('"'))
+            .collect();
+        for (name, position) in subcommands_with(&app, ID_ARGUMENT) {
+            let case = format!("        {})\n            \
+                                if [[ \"$cur\" != -* && $COMP_CWORD -eq {} ]]",
+                               name, position + 1);
+            assert!(bash.contains(&case), "bash doesn't complete the id of {}", name);
+            assert!(fish_tasks.contains(&name.as_str()),
+                    "fish doesn't complete the id of {}", name);
+        }
+        let selecting = selecting_subcommands(&app);
+        assert!(bash.contains(&format!("        {})\n", selecting.join("|"))));
+        for name in selecting {
+            assert!(fish_tasks.contains(&name.as_str()),
+                    "fish doesn't complete the tasks of {}", name);
+        }
+    }
+
+    #[test]
+    fn zsh_completes_task_ids_instead_of_files() {
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.contains(":_eva_tasks'"));
+        assert!(zsh.contains(":_eva_selection'"));
+        assert!(zsh.trim_end().ends_with("_eva \"$@\""));
+    }
+}
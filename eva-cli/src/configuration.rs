@@ -1,13 +1,26 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
+use std::time::SystemTime;
 
 use app_dirs;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
 use app_dirs::{AppDataType, AppInfo};
 use config;
 use eva;
-use eva::configuration::{Configuration, SchedulingStrategy};
+use eva::configuration::{Budget, Configuration, SchedulingStrategy, UrgencyCoefficients};
+use eva::database::Database;
+use eva::database::encrypted::Encrypted;
 use shellexpand;
 
+use crate::encryption::{Aes, Secret};
+use crate::hooks::Hooks;
+use crate::parse;
+use crate::timezone;
+
 pub use self::errors::*;
 
 mod errors {
@@ -34,6 +47,23 @@ mod errors {
                 display("An error occurred while trying to set the default configuration of {}",
                         what)
             }
+            UnknownContext(name: String) {
+                description("unknown context")
+                display("There is no context called {}. You can add one with a [contexts.{}] \
+                        section in your configuration file",
+                        name, name)
+            }
+            UnknownDaysOff(days_off: String) {
+                description("unknown days off")
+                display("You didn't take {} off. `eva off --list` shows the days you did take off",
+                        days_off)
+            }
+            UnknownHolidays(country: String) {
+                description("unknown holidays")
+                display("I don't know the public holidays of {} yet. You can download them with \
+                        `eva holidays fetch --country {}`",
+                        country, country)
+            }
         }
     }
 }
@@ -41,42 +71,650 @@ mod errors {
 const APP_INFO: AppInfo = AppInfo { name: "eva", author: "Stijn Seghers" };
 
 
-pub fn read() -> Result<Configuration> {
-    let config_filename = config_root()?.join("eva.toml");
-    let config_filename = config_filename.to_str()
-        .ok_or_else(|| ErrorKind::FileCreation("my configuration directory".to_owned()))?;
+/// The context that is used when no other one is chosen. Its settings are the ones at the top
+/// level of the configuration file.
+pub const DEFAULT_CONTEXT: &str = "default";
 
-    let mut configuration = config::Config::new();
 
-    set_defaults(&mut configuration)?
-        .merge(config::File::with_name(config_filename).required(false))
-        .chain_err(|| ErrorKind::Read(format!("the local configuration file {}.toml",
-                                              config_filename)))?
-        .merge(config::Environment::with_prefix("eva"))
-        .chain_err(|| ErrorKind::Read("environment variables".to_owned()))?;
+/// Reads the configuration of the given context, or of the current one if none is given (see
+/// `current_context`).
+///
+/// Every context has its own database, and can override the top-level `scheduling_strategy`,
+/// `projects`, `reminders`, `buffer`, `holidays`, `off`, `assignees`, `horizon`, `budgets`,
+/// `urgency`, `tag_rules` and encryption settings in a section like `[contexts.work]`. The days
+/// taken off with `take_days_off` apply to every context.
+pub fn read(context: Option<&str>) -> Result<Configuration> {
+    let configuration = read_file()?;
+    let context = match context {
+        Some(context) => context.to_owned(),
+        None => current_context()?,
+    };
+    if !contexts_in(&configuration)?.contains(&context) {
+        bail!(ErrorKind::UnknownContext(context));
+    }
 
-    let database_path = configuration.get_str("database")
-        .chain_err(|| ErrorKind::Read("the database path".to_owned()))?
-        .expand("the database path")?;
+    let database_path = if context == DEFAULT_CONTEXT {
+        configuration.get_str("database")
+            .chain_err(|| ErrorKind::Read("the database path".to_owned()))?
+    } else {
+        match optional(configuration.get_str(&context_key(&context, "database")),
+                       "the database path")? {
+            Some(database_path) => database_path,
+            None => default_database_path(&context)?,
+        }
+    };
+    let database_path = database_path.expand("the database path")?;
     ensure_exists(&database_path, "the database path")?;
     let database = connect_to_database(&database_path)?;
+    let database: Box<Database> = match secret(&configuration, &context)? {
+        Some(secret) => Box::new(Encrypted::new(database, Aes::new(secret))),
+        None => Box::new(database),
+    };
 
     let scheduling_strategy = match
-        configuration.get_str("scheduling_strategy")
-        .chain_err(|| ErrorKind::Read("the scheduling strategy".to_owned()))?
+        setting(&configuration, &context, "scheduling_strategy", "the scheduling strategy",
+                |configuration, key| configuration.get_str(key))?
+        .ok_or_else(|| ErrorKind::Read("the scheduling strategy".to_owned()))?
         .as_str() {
             "importance" => SchedulingStrategy::Importance,
             "urgency" => SchedulingStrategy::Urgency,
+            "optimal" => SchedulingStrategy::Optimal,
             _ => bail!(ErrorKind::Read("the scheduling strategy".to_owned())),
         };
 
+    // The weights of the projects are given by a table like `[projects]\nwork = 3`
+    let project_weights =
+        setting(&configuration, &context, "projects", "the project weights",
+                |configuration, key| configuration.get::<HashMap<String, u32>>(key))?
+        .unwrap_or_default();
+
+    // The reminders are given as durations like `reminders = ["1d", "10m"]`
+    let reminders =
+        setting(&configuration, &context, "reminders", "the reminders",
+                |configuration, key| configuration.get::<Vec<String>>(key))?
+        .unwrap_or_default()
+        .iter()
+        .map(|reminder| parse::duration(reminder))
+        .collect::<parse::Result<Vec<_>>>()
+        .chain_err(|| ErrorKind::Read("the reminders".to_owned()))?;
+
+    let buffer =
+        setting(&configuration, &context, "buffer", "the buffer",
+                |configuration, key| configuration.get_str(key))?
+        .map(|buffer| parse::buffer(&buffer))
+        .transpose()
+        .chain_err(|| ErrorKind::Read("the buffer".to_owned()))?
+        .unwrap_or_else(chrono::Duration::zero);
+
+    // The countries whose public holidays are taken off are given like `holidays = ["BE", "NL"]`.
+    // Without that setting, the holidays of every country that was downloaded apply.
+    let mut known_holidays = holidays()?;
+    let countries =
+        setting(&configuration, &context, "holidays", "the holidays",
+                |configuration, key| configuration.get::<Vec<String>>(key))?
+        .map(|countries| {
+            countries.iter()
+                .map(|country| parse::country(country))
+                .collect::<parse::Result<Vec<_>>>()
+        })
+        .transpose()
+        .chain_err(|| ErrorKind::Read("the holidays".to_owned()))?
+        .unwrap_or_else(|| known_holidays.keys().cloned().collect());
+    let mut unavailable = vec![];
+    for country in countries {
+        let dates = known_holidays.remove(&country)
+            .ok_or_else(|| ErrorKind::UnknownHolidays(country.clone()))?;
+        unavailable.extend(dates.into_iter().map(timezone::day));
+    }
+
+    // Days off are given like `off = ["2024-08-01..2024-08-14", "2024-12-24"]`
+    let configured_days_off =
+        setting(&configuration, &context, "off", "the days off",
+                |configuration, key| configuration.get::<Vec<String>>(key))?
+        .unwrap_or_default()
+        .iter()
+        .map(|range| parse::date_range(range).map(|(first, last)| DaysOff { first, last }))
+        .collect::<parse::Result<Vec<_>>>()
+        .chain_err(|| ErrorKind::Read("the days off".to_owned()))?;
+    unavailable.extend(configured_days_off.iter().chain(&days_off()?).map(DaysOff::period));
+
+    // The assignees are given by a table of their own days off, like
+    // `[assignees]\nalice = ["2024-08-01..2024-08-14"]\nbob = []`
+    let assignees =
+        setting(&configuration, &context, "assignees", "the assignees",
+                |configuration, key| configuration.get::<HashMap<String, Vec<String>>>(key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(assignee, ranges)| {
+            let days_off = ranges.iter()
+                .map(|range| {
+                    parse::date_range(range).map(|(first, last)| DaysOff { first, last }.period())
+                })
+                .collect::<parse::Result<Vec<_>>>()?;
+            Ok((assignee, days_off))
+        })
+        .collect::<parse::Result<HashMap<_, _>>>()
+        .chain_err(|| ErrorKind::Read("the assignees".to_owned()))?;
+
+    let horizon =
+        setting(&configuration, &context, "horizon", "the horizon",
+                |configuration, key| configuration.get_str(key))?
+        .map(|horizon| parse::duration(&horizon))
+        .transpose()
+        .chain_err(|| ErrorKind::Read("the horizon".to_owned()))?;
+
+    let focus_sessions =
+        setting(&configuration, &context, "focus_sessions", "the lengths of the focus sessions",
+                |configuration, key| configuration.get_str(key))?
+        .map(|focus_sessions| parse::focus_sessions(&focus_sessions))
+        .transpose()
+        .chain_err(|| ErrorKind::Read("the lengths of the focus sessions".to_owned()))?;
+
+    // The weekly budgets are given by tables like `[budgets.health]\nminimum = "6h"`
+    let budgets =
+        setting(&configuration, &context, "budgets", "the budgets",
+                |configuration, key| {
+                    configuration.get::<HashMap<String, HashMap<String, String>>>(key)
+                })?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(tag, limits)| Ok((tag, budget(&limits)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    // The urgency coefficients are given by a table like `[urgency]\ndue = 12.0\nhorizon = "7d"`
+    let default_urgency = UrgencyCoefficients::default();
+    let urgency = UrgencyCoefficients {
+        importance:
+            setting(&configuration, &context, "urgency.importance", "the urgency coefficients",
+                    |configuration, key| configuration.get_float(key))?
+            .unwrap_or(default_urgency.importance),
+        due:
+            setting(&configuration, &context, "urgency.due", "the urgency coefficients",
+                    |configuration, key| configuration.get_float(key))?
+            .unwrap_or(default_urgency.due),
+        horizon:
+            setting(&configuration, &context, "urgency.horizon", "the urgency coefficients",
+                    |configuration, key| configuration.get_str(key))?
+            .map(|horizon| parse::duration(&horizon))
+            .transpose()
+            .chain_err(|| ErrorKind::Read("the urgency coefficients".to_owned()))?
+            .unwrap_or(default_urgency.horizon),
+    };
+
+    // The tag rules are given by a table like `[tag_rules]\nerrand = ["buy", "post office"]`
+    let tag_rules =
+        setting(&configuration, &context, "tag_rules", "the tag rules",
+                |configuration, key| configuration.get::<HashMap<String, Vec<String>>>(key))?
+        .unwrap_or_default();
+
     Ok(Configuration {
-        database: Box::new(database),
+        database: database,
         scheduling_strategy: scheduling_strategy,
+        project_weights: project_weights,
+        reminders: reminders,
+        buffer: buffer,
+        unavailable: unavailable,
+        assignees: assignees,
+        horizon: horizon,
+        budgets: budgets,
+        urgency: urgency,
+        tag_rules: tag_rules,
+        place: place()?,
+        local_time: Box::new(timezone::shown()),
+        focus_sessions: focus_sessions,
+    })
+}
+
+
+/// When `eva daemon` rolls over the day, and how it lets you know.
+pub struct RolloverSettings {
+    /// The time of day at which the day is rolled over.
+    pub time: NaiveTime,
+    /// The shell command that gets a summary of every rollover, if any.
+    pub notify_command: Option<String>,
+}
+
+
+/// Reads the rollover settings of the given context, or of the current one if none is given.
+///
+/// They are given like `rollover_time = "23:00"` and `notify_command = "mail -s Eva me"`, and can
+/// be overridden in the section of a context.
+pub fn rollover(context: Option<&str>) -> Result<RolloverSettings> {
+    let configuration = read_file()?;
+    let context = match context {
+        Some(context) => context.to_owned(),
+        None => current_context()?,
+    };
+    if !contexts_in(&configuration)?.contains(&context) {
+        bail!(ErrorKind::UnknownContext(context));
+    }
+
+    let time =
+        setting(&configuration, &context, "rollover_time", "the rollover time",
+                |configuration, key| configuration.get_str(key))?
+        .ok_or_else(|| ErrorKind::Read("the rollover time".to_owned()))?;
+    let time = parse::time(&time).chain_err(|| ErrorKind::Read("the rollover time".to_owned()))?;
+    let notify_command =
+        setting(&configuration, &context, "notify_command", "the notify command",
+                |configuration, key| configuration.get_str(key))?;
+    Ok(RolloverSettings { time, notify_command })
+}
+
+
+/// Returns when the configuration file and the files that remember the current context, the
+/// timezone override and the place were changed last, with `None` for those that don't exist, so
+/// that `eva daemon` can tell when to read them again.
+pub fn last_changes() -> Result<Vec<Option<SystemTime>>> {
+    let mut last_changes = vec![];
+    for name in &["eva.toml", "context", "timezone", "place"] {
+        let filename = config_root()?.join(name);
+        if !filename.exists() {
+            last_changes.push(None);
+            continue;
+        }
+        let modified = fs::metadata(&filename)
+            .and_then(|metadata| metadata.modified())
+            .chain_err(|| ErrorKind::Read(format!("when {} was changed", filename.display())))?;
+        last_changes.push(Some(modified));
+    }
+    Ok(last_changes)
+}
+
+
+/// Reads the hooks of the given context, or of the current one if none is given.
+///
+/// They are given as lists of shell commands in a table like
+/// `[hooks]\non_add = ["~/bin/track-task"]`, with `on_add`, `on_complete` and `on_schedule`, and
+/// can be overridden in a section like `[contexts.work.hooks]`. There are no hooks by default.
+pub fn hooks(context: Option<&str>) -> Result<Hooks> {
+    let configuration = read_file()?;
+    let context = match context {
+        Some(context) => context.to_owned(),
+        None => current_context()?,
+    };
+    let commands = |hook: &str| {
+        setting(&configuration, &context, &format!("hooks.{}", hook), "the hooks",
+                |configuration, key| configuration.get::<Vec<String>>(key))
+            .map(Option::unwrap_or_default)
+    };
+    Ok(Hooks {
+        on_add: commands("on_add")?,
+        on_complete: commands("on_complete")?,
+        on_schedule: commands("on_schedule")?,
     })
 }
 
 
+/// Tells whether the database of the given context, or of the current one if none is given, is
+/// encrypted.
+pub fn encrypts(context: Option<&str>) -> Result<bool> {
+    let configuration = read_file()?;
+    let context = match context {
+        Some(context) => context.to_owned(),
+        None => current_context()?,
+    };
+    Ok(secret(&configuration, &context)?.is_some())
+}
+
+
+/// Returns the names of all contexts, in alphabetical order.
+pub fn contexts() -> Result<Vec<String>> {
+    contexts_in(&read_file()?)
+}
+
+
+/// Returns the context to use when none is given explicitly: the one that was last switched to,
+/// or the default context.
+pub fn current_context() -> Result<String> {
+    let context_filename = config_root()?.join("context");
+    if !context_filename.exists() {
+        return Ok(DEFAULT_CONTEXT.to_owned());
+    }
+    let context = fs::read_to_string(&context_filename)
+        .chain_err(|| ErrorKind::Read("the current context".to_owned()))?;
+    Ok(context.trim().to_owned())
+}
+
+
+/// Makes `context` the current context.
+pub fn switch_context(context: &str) -> Result<()> {
+    if !contexts()?.iter().any(|name| name == context) {
+        bail!(ErrorKind::UnknownContext(context.to_owned()));
+    }
+    let context_filename = config_root()?.join("context");
+    fs::write(&context_filename, context)
+        .chain_err(|| ErrorKind::FileCreation("the file that remembers the context".to_owned()))
+}
+
+
+/// Returns where you are now, if you said so (see `set_place`).
+pub fn place() -> Result<Option<String>> {
+    let place_filename = config_root()?.join("place");
+    if !place_filename.exists() {
+        return Ok(None);
+    }
+    let place = fs::read_to_string(&place_filename)
+        .chain_err(|| ErrorKind::Read("the current place".to_owned()))?;
+    Ok(Some(place.trim().to_owned()))
+}
+
+
+/// Remembers that you are at `place`, until it is cleared with `clear_place`. The tasks that are
+/// snoozed until you're at a place are scheduled again once you are.
+pub fn set_place(place: &str) -> Result<()> {
+    let place_filename = config_root()?.join("place");
+    fs::write(&place_filename, place)
+        .chain_err(|| ErrorKind::FileCreation("the file that remembers the place".to_owned()))
+}
+
+
+/// Forgets where you are.
+pub fn clear_place() -> Result<()> {
+    let place_filename = config_root()?.join("place");
+    if place_filename.exists() {
+        fs::remove_file(&place_filename).chain_err(|| {
+            ErrorKind::FileCreation("the file that remembers the place".to_owned())
+        })?;
+    }
+    Ok(())
+}
+
+
+/// A timezone that temporarily replaces the local one, e.g. while travelling.
+pub struct TimezoneOverride {
+    pub zone: Tz,
+    /// The override ends when this date starts in `zone`. Without it, the override lasts until
+    /// it is removed.
+    pub until: Option<NaiveDate>,
+}
+
+
+/// Returns the timezone override, if there is one. An override that ended is removed.
+pub fn timezone_override() -> Result<Option<TimezoneOverride>> {
+    let timezone_filename = config_root()?.join("timezone");
+    if !timezone_filename.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&timezone_filename)
+        .chain_err(|| ErrorKind::Read("the timezone override".to_owned()))?;
+    let mut words = contents.split_whitespace();
+    let zone = parse::timezone(words.next().unwrap_or_default())
+        .chain_err(|| ErrorKind::Read("the timezone override".to_owned()))?;
+    let until = words.next().map(parse::date).transpose()
+        .chain_err(|| ErrorKind::Read("the timezone override".to_owned()))?;
+    if until.map_or(false, |until| until <= timezone::today_in(zone)) {
+        remove_timezone_override()?;
+        return Ok(None);
+    }
+    Ok(Some(TimezoneOverride { zone, until }))
+}
+
+
+/// Makes the current thread show and read dates and times in the timezone of the override until
+/// it ends, if there is one, and in the local timezone otherwise. See `timezone::activate`.
+pub fn activate_timezone() -> Result<()> {
+    match timezone_override()? {
+        Some(TimezoneOverride { zone, until }) => timezone::activate(Some(zone), until),
+        None => timezone::activate(None, None),
+    }
+    Ok(())
+}
+
+
+pub fn override_timezone(timezone_override: &TimezoneOverride) -> Result<()> {
+    let timezone_filename = config_root()?.join("timezone");
+    let contents = match timezone_override.until {
+        Some(until) => format!("{} {}", timezone_override.zone.name(), until.format("%Y-%m-%d")),
+        None => timezone_override.zone.name().to_owned(),
+    };
+    fs::write(&timezone_filename, contents)
+        .chain_err(|| ErrorKind::FileCreation("the file that remembers the timezone".to_owned()))
+}
+
+
+pub fn remove_timezone_override() -> Result<()> {
+    let timezone_filename = config_root()?.join("timezone");
+    if timezone_filename.exists() {
+        fs::remove_file(&timezone_filename).chain_err(|| {
+            ErrorKind::FileCreation("the file that remembers the timezone".to_owned())
+        })?;
+    }
+    Ok(())
+}
+
+
+/// A stretch of days off, like a holiday or a trip, from `first` up to and including `last`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DaysOff {
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+}
+
+impl DaysOff {
+    /// Returns the period that these days cover in the timezone in which dates and times are
+    /// shown.
+    pub fn period(&self) -> Range<DateTime<Utc>> {
+        timezone::day(self.first).start..timezone::day(self.last).end
+    }
+
+    fn to_setting(&self) -> String {
+        format!("{}..{}", self.first.format("%Y-%m-%d"), self.last.format("%Y-%m-%d"))
+    }
+}
+
+
+/// Returns the days that were taken off with `take_days_off`, in chronological order.
+pub fn days_off() -> Result<Vec<DaysOff>> {
+    let days_off_filename = config_root()?.join("off");
+    if !days_off_filename.exists() {
+        return Ok(vec![]);
+    }
+    fs::read_to_string(&days_off_filename)
+        .chain_err(|| ErrorKind::Read("the days off".to_owned()))?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse::date_range(line.trim()).map(|(first, last)| DaysOff { first, last }))
+        .collect::<parse::Result<Vec<_>>>()
+        .chain_err(|| ErrorKind::Read("the days off".to_owned()))
+}
+
+
+/// Remembers `days_off`, on which nothing gets scheduled in any context.
+pub fn take_days_off(days_off: DaysOff) -> Result<()> {
+    let mut all_days_off = self::days_off()?;
+    all_days_off.push(days_off);
+    all_days_off.sort();
+    all_days_off.dedup();
+    write_days_off(&all_days_off)
+}
+
+
+/// Forgets `days_off`, which were taken with `take_days_off`.
+pub fn cancel_days_off(days_off: DaysOff) -> Result<()> {
+    let mut all_days_off = self::days_off()?;
+    let count = all_days_off.len();
+    all_days_off.retain(|&other| other != days_off);
+    if all_days_off.len() == count {
+        bail!(ErrorKind::UnknownDaysOff(days_off.to_setting()));
+    }
+    write_days_off(&all_days_off)
+}
+
+
+fn write_days_off(all_days_off: &[DaysOff]) -> Result<()> {
+    let contents: Vec<String> = all_days_off.iter().map(DaysOff::to_setting).collect();
+    fs::write(config_root()?.join("off"), contents.join("\n"))
+        .chain_err(|| ErrorKind::FileCreation("the file with the days off".to_owned()))
+}
+
+
+/// Returns the public holidays that were downloaded, in chronological order, by country.
+pub fn holidays() -> Result<BTreeMap<String, Vec<NaiveDate>>> {
+    let holidays_root = config_root()?.join("holidays");
+    let mut holidays = BTreeMap::new();
+    if !holidays_root.exists() {
+        return Ok(holidays);
+    }
+    let files = fs::read_dir(&holidays_root)
+        .chain_err(|| ErrorKind::Read("the holidays".to_owned()))?;
+    for file in files {
+        let path = file.chain_err(|| ErrorKind::Read("the holidays".to_owned()))?.path();
+        let country = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        let dates = fs::read_to_string(&path)
+            .chain_err(|| ErrorKind::Read(format!("the holidays of {}", country)))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| parse::date(line.trim()))
+            .collect::<parse::Result<Vec<_>>>()
+            .chain_err(|| ErrorKind::Read(format!("the holidays of {}", country)))?;
+        holidays.insert(country, dates);
+    }
+    Ok(holidays)
+}
+
+
+/// Remembers `dates` as public holidays of `country`, next to the ones that are already known.
+pub fn add_holidays(country: &str, dates: &[NaiveDate]) -> Result<()> {
+    let mut all_dates = holidays()?.remove(country).unwrap_or_default();
+    all_dates.extend_from_slice(dates);
+    all_dates.sort();
+    all_dates.dedup();
+    let holidays_root = config_root()?.join("holidays");
+    fs::create_dir_all(&holidays_root)
+        .chain_err(|| ErrorKind::FileCreation("the directory of the holidays".to_owned()))?;
+    let contents: Vec<String> =
+        all_dates.iter().map(|date| date.format("%Y-%m-%d").to_string()).collect();
+    fs::write(holidays_root.join(country), contents.join("\n"))
+        .chain_err(|| ErrorKind::FileCreation(format!("the file with the holidays of {}", country)))
+}
+
+
+pub fn forget_holidays(country: &str) -> Result<()> {
+    let holidays_filename = config_root()?.join("holidays").join(country);
+    if !holidays_filename.exists() {
+        bail!(ErrorKind::UnknownHolidays(country.to_owned()));
+    }
+    fs::remove_file(&holidays_filename).chain_err(|| {
+        ErrorKind::FileCreation(format!("the file with the holidays of {}", country))
+    })
+}
+
+
+fn read_file() -> Result<config::Config> {
+    let config_filename = config_root()?.join("eva.toml");
+    let config_filename = config_filename.to_str()
+        .ok_or_else(|| ErrorKind::FileCreation("my configuration directory".to_owned()))?;
+
+    let mut configuration = config::Config::new();
+
+    set_defaults(&mut configuration)?
+        .merge(config::File::with_name(config_filename).required(false))
+        .chain_err(|| ErrorKind::Read(format!("the local configuration file {}.toml",
+                                              config_filename)))?
+        .merge(config::Environment::with_prefix("eva"))
+        .chain_err(|| ErrorKind::Read("environment variables".to_owned()))?;
+
+    Ok(configuration)
+}
+
+
+fn contexts_in(configuration: &config::Config) -> Result<Vec<String>> {
+    let mut contexts: Vec<String> =
+        optional(configuration.get_table("contexts"), "the contexts")?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    contexts.push(DEFAULT_CONTEXT.to_owned());
+    contexts.sort();
+    contexts.dedup();
+    Ok(contexts)
+}
+
+
+/// Returns the setting `key` of `context`, falling back to the top-level setting.
+fn setting<T, F>(configuration: &config::Config, context: &str, key: &str, what: &str, get: F)
+    -> Result<Option<T>>
+    where F: Fn(&config::Config, &str) -> StdResult<T, config::ConfigError>
+{
+    if context != DEFAULT_CONTEXT {
+        if let Some(value) = optional(get(configuration, &context_key(context, key)), what)? {
+            return Ok(Some(value));
+        }
+    }
+    optional(get(configuration, key), what)
+}
+
+
+fn context_key(context: &str, key: &str) -> String {
+    format!("contexts.{}.{}", context, key)
+}
+
+
+/// Turns a missing setting into `None`, keeping the other errors.
+fn optional<T>(result: StdResult<T, config::ConfigError>, what: &str) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(config::ConfigError::NotFound(_)) => Ok(None),
+        Err(error) => Err(error).chain_err(|| ErrorKind::Read(what.to_owned())),
+    }
+}
+
+
+/// Reads what the database of `context` is encrypted with, if it is. That's either a passphrase,
+/// given like `encryption_passphrase = "..."`, or a keyfile, given like
+/// `encryption_keyfile = "~/.eva.key"`.
+fn secret(configuration: &config::Config, context: &str) -> Result<Option<Secret>> {
+    let passphrase =
+        setting(configuration, context, "encryption_passphrase", "the encryption passphrase",
+                |configuration, key| configuration.get_str(key))?;
+    let keyfile =
+        setting(configuration, context, "encryption_keyfile", "the encryption keyfile",
+                |configuration, key| configuration.get_str(key))?;
+    match (passphrase, keyfile) {
+        (Some(_passphrase), Some(_keyfile)) => {
+            bail!(ErrorKind::Read("the encryption settings, which can't have both a passphrase \
+                                   and a keyfile".to_owned()))
+        },
+        (Some(passphrase), None) => Ok(Some(Secret::Passphrase(passphrase))),
+        (None, Some(keyfile)) => {
+            let keyfile = keyfile.expand("the encryption keyfile")?;
+            let contents = fs::read(&keyfile)
+                .chain_err(|| ErrorKind::Read(format!("the encryption keyfile {}", keyfile)))?;
+            Ok(Some(Secret::Keyfile(contents)))
+        },
+        (None, None) => Ok(None),
+    }
+}
+
+
+/// Reads a budget from its `maximum` and `minimum` durations, which can both be left out.
+fn budget(limits: &HashMap<String, String>) -> Result<Budget> {
+    let mut budget = Budget::default();
+    for (limit, duration) in limits {
+        let duration = parse::duration(duration)
+            .chain_err(|| ErrorKind::Read("the budgets".to_owned()))?;
+        match limit.as_str() {
+            "maximum" => budget.maximum = Some(duration),
+            "minimum" => budget.minimum = Some(duration),
+            _ => bail!(ErrorKind::Read(format!("the budgets, which have no {}", limit))),
+        }
+    }
+    Ok(budget)
+}
+
+
+fn default_database_path(context: &str) -> Result<String> {
+    let db_filename = data_root()?.join(format!("{}.sqlite", context));
+    db_filename.to_str()
+        .map(str::to_owned)
+        .ok_or_else(|| ErrorKind::Default("the database path".to_owned()).into())
+}
+
+
 fn config_root() -> Result<PathBuf> {
     app_dirs::get_app_root(AppDataType::UserConfig, &APP_INFO)
         .chain_err(|| ErrorKind::FileCreation("my configuration directory".to_owned()))
@@ -99,6 +737,8 @@ fn set_defaults(configuration: &mut config::Config) -> Result<&mut config::Confi
         .chain_err(|| ErrorKind::Default("the scheduling strategy".to_owned()))?
         .set_default("database", db_filename)
         .chain_err(|| ErrorKind::Default("the database path".to_owned()))?
+        .set_default("rollover_time", "23:00")
+        .chain_err(|| ErrorKind::Default("the rollover time".to_owned()))?
         )
 }
 
@@ -0,0 +1,288 @@
+use std::cmp::min;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::SystemTime;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use eva::Rollover;
+use futures::executor::block_on;
+use itertools::Itertools;
+
+use crate::agenda::Agenda;
+use crate::configuration;
+use crate::describe;
+use crate::pretty_print::PrettyPrint;
+use crate::timezone;
+
+pub use self::errors::*;
+
+mod errors {
+    use crate::configuration;
+
+    error_chain! {
+        links {
+            Configuration(configuration::Error, configuration::ErrorKind);
+        }
+        foreign_links {
+            EvaCore(eva::Error);
+            Io(std::io::Error);
+        }
+        errors {
+            Notify(command: String, reason: String) {
+                description("notification error")
+                display("I could not notify you with `{}` ({})", command, reason)
+            }
+        }
+    }
+}
+
+
+/// Rolls over the day of `context` every day at the rollover time of its configuration (see
+/// `configuration::rollover`), and hands a summary to the notify command. In between, it reminds
+/// of the tasks in the accepted schedule at the times their reminders, or else those of the
+/// configuration, ask for. This runs until it's stopped.
+///
+/// The configuration is watched while waiting, and read again as soon as it changes, so a new
+/// rollover time or notify command applies right away, without a restart. A configuration with
+/// mistakes in it doesn't stop the daemon: the notify command is told what's wrong, and the
+/// previous settings stay until it's fixed. When a rollover fails, the notify command is told so
+/// and the next one is waited for.
+pub fn run(context: Option<&str>, strategy: Option<&str>) -> Result<()> {
+    let mut live = LiveSettings::read(context)?;
+    // The reminders up to this moment were sent already
+    let mut reminded_until = Utc::now();
+    let mut reminders_failure = None;
+    loop {
+        let rollover = timezone::next_occurrence(live.settings.time, Utc::now());
+        let reminders = match upcoming_reminders(context, reminded_until) {
+            Ok(reminders) => {
+                reminders_failure = None;
+                reminders
+            },
+            Err(error) => {
+                // Only tell once for as long as the reminders fail the same way
+                let failure = format!("I could not look up your reminders: {}.", describe(&error));
+                if reminders_failure.as_ref() != Some(&failure) {
+                    live.tell(&failure);
+                    reminders_failure = Some(failure);
+                }
+                vec![]
+            },
+        };
+        // The schedule is looked at again now and then, in case another one was accepted
+        let refresh = Utc::now() + Duration::minutes(1);
+        let reminder = reminders.first().map(|&(moment, _)| moment)
+            .filter(|&moment| moment < min(rollover, refresh));
+        let moment = reminder.unwrap_or_else(|| min(rollover, refresh));
+        if !sleep_until(moment, || live.reload(context)) {
+            // The rollover time may have changed
+            continue;
+        }
+        if let Some(reminder) = reminder {
+            for (_, message) in reminders.iter().take_while(|&&(moment, _)| moment == reminder) {
+                live.tell(message);
+            }
+            reminded_until = reminder;
+            continue;
+        }
+        if moment < rollover {
+            continue;
+        }
+        let summary = match roll_over(context, strategy) {
+            Ok(summary) => summary,
+            Err(error) => format!("I could not roll over the day: {}.", describe(&error)),
+        };
+        live.tell(&summary);
+    }
+}
+
+
+/// The rollover settings the daemon goes by, which are read again whenever the configuration
+/// changes.
+struct LiveSettings {
+    settings: configuration::RolloverSettings,
+    /// How many times the settings were read again since the daemon started.
+    version: u32,
+    /// When the files of the configuration were changed last before the settings were read, see
+    /// `configuration::last_changes`.
+    last_changes: Vec<Option<SystemTime>>,
+}
+
+impl LiveSettings {
+    fn read(context: Option<&str>) -> Result<LiveSettings> {
+        Ok(LiveSettings {
+            last_changes: configuration::last_changes()?,
+            settings: configuration::rollover(context)?,
+            version: 0,
+        })
+    }
+
+    /// Reads the settings again if the configuration changed since they were read, and tells
+    /// whether they did. When the changed configuration can't be read, the previous settings are
+    /// kept and the notify command is told why.
+    fn reload(&mut self, context: Option<&str>) -> bool {
+        let last_changes = match configuration::last_changes() {
+            Ok(last_changes) => last_changes,
+            Err(error) => {
+                let error = Error::from(error);
+                self.tell(&format!("I could not check your configuration: {}.", describe(&error)));
+                return false;
+            },
+        };
+        if last_changes == self.last_changes {
+            return false;
+        }
+        // Whatever comes of it, the same change isn't looked at twice
+        self.last_changes = last_changes;
+        match validate(context) {
+            Ok(settings) => {
+                self.settings = settings;
+                self.version += 1;
+                println!("I read your changed configuration (version {}).\n", self.version);
+                true
+            },
+            Err(error) => {
+                self.tell(&format!("I could not read your changed configuration, so I keep going \
+                                    by the previous one: {}.", describe(&error)));
+                false
+            },
+        }
+    }
+
+    /// Prints `message` and hands it to the notify command, if any.
+    fn tell(&self, message: &str) {
+        println!("{}\n", message);
+        if let Some(ref command) = self.settings.notify_command {
+            if let Err(error) = notify(command, message) {
+                eprintln!("{}.", error);
+            }
+        }
+    }
+}
+
+
+/// Reads the rollover settings of `context`, after making sure that the rest of its
+/// configuration and its hooks can be read too, so that the next rollover doesn't fail on them.
+/// Once they can, the timezone override is activated again, since the rollover time is read in
+/// it.
+fn validate(context: Option<&str>) -> Result<configuration::RolloverSettings> {
+    configuration::read(context)?;
+    configuration::hooks(context)?;
+    let settings = configuration::rollover(context)?;
+    configuration::activate_timezone()?;
+    Ok(settings)
+}
+
+
+/// Summarizes a rollover: the tasks that slipped, the snoozed tasks that can't be done in time
+/// and what is scheduled up to the end of tomorrow.
+pub fn summary(rollover: &Rollover, agenda: &Agenda) -> String {
+    let slipped = if rollover.slipped.is_empty() {
+        "Everything that was scheduled is done.".to_owned()
+    } else {
+        let tasks = rollover.slipped.iter()
+            .map(|task| task.pretty_print().lines().join("\n  "))
+            .join("\n  ");
+        format!("These tasks slipped and are scheduled again:\n  {}", tasks)
+    };
+    let tomorrow = timezone::to_local(agenda.now).date().naive_local().succ();
+    let end_of_tomorrow = timezone::day(tomorrow).end;
+    let coming_up = eva::Schedule(rollover.schedule.0.iter()
+        .filter(|scheduled_task| scheduled_task.when < end_of_tomorrow)
+        .cloned()
+        .collect());
+    let stuck = if rollover.stuck.is_empty() {
+        String::new()
+    } else {
+        format!("\n\n{}", stuck(&rollover.stuck))
+    };
+    format!("{}{}\n\n{}", slipped, stuck, agenda.render(&coming_up))
+}
+
+/// Lists why snoozed tasks can't be done in time after the tasks they wait for.
+pub fn stuck(errors: &[eva::ScheduleError]) -> String {
+    let errors = errors.iter()
+        .map(|error| error.to_string().lines().join("\n  "))
+        .join("\n  ");
+    format!("These snoozed tasks can't be done in time:\n  {}", errors)
+}
+
+
+fn roll_over(context: Option<&str>, strategy: Option<&str>) -> Result<String> {
+    configuration::activate_timezone()?;
+    let configuration = configuration::read(context)?;
+    let strategy = strategy.unwrap_or_else(|| configuration.scheduling_strategy.as_str());
+    let rollover = block_on(eva::roll_over(&configuration, strategy))?;
+    configuration::hooks(context)?.scheduled(&rollover.schedule);
+    let agenda = Agenda { now: configuration.now(), color: false };
+    Ok(summary(&rollover, &agenda))
+}
+
+
+/// Returns the reminders of the tasks in the accepted schedule of `context` that are due after
+/// `after`, in chronological order, with what to tell at each of them.
+fn upcoming_reminders(context: Option<&str>, after: DateTime<Utc>)
+    -> Result<Vec<(DateTime<Utc>, String)>>
+{
+    let configuration = configuration::read(context)?;
+    let schedule = match block_on(eva::accepted_schedule(&configuration))? {
+        Some(schedule) => schedule,
+        None => return Ok(vec![]),
+    };
+    let mut reminders: Vec<(DateTime<Utc>, String)> = schedule.0.iter()
+        .flat_map(|scheduled_task| {
+            scheduled_task.reminders(&configuration.reminders).into_iter()
+                .filter(|&moment| moment > after)
+                .map(move |moment| (moment, reminder(scheduled_task)))
+        })
+        .collect();
+    reminders.sort_by_key(|&(moment, _)| moment);
+    Ok(reminders)
+}
+
+/// Tells what is coming up when reminding of `scheduled_task`.
+fn reminder(scheduled_task: &eva::ScheduledTask) -> String {
+    format!("Coming up at {}:\n  {}", scheduled_task.when.pretty_print(),
+            scheduled_task.task.pretty_print().lines().join("\n  "))
+}
+
+
+/// Sleeps in short stretches until `moment`, so that a computer that was suspended in the
+/// meantime doesn't oversleep it by much. After every stretch, `interrupted` tells whether to
+/// wake up early, in which case this returns false.
+fn sleep_until<F>(moment: DateTime<Utc>, mut interrupted: F) -> bool
+    where F: FnMut() -> bool
+{
+    loop {
+        let left = moment.signed_duration_since(Utc::now());
+        if left <= Duration::zero() {
+            return true;
+        }
+        thread::sleep(min(left, Duration::seconds(10)).to_std().unwrap());
+        if interrupted() {
+            return false;
+        }
+    }
+}
+
+
+/// Runs `command` in a shell with the summary on its standard input and in `EVA_SUMMARY`.
+fn notify(command: &str, summary: &str) -> Result<()> {
+    let notify_error = |reason: String| ErrorKind::Notify(command.to_owned(), reason);
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("EVA_SUMMARY", summary)
+        .stdin(Stdio::piped())
+        .spawn()
+        .chain_err(|| notify_error("it didn't start".to_owned()))?;
+    child.stdin.take().unwrap().write_all(summary.as_bytes())
+        .chain_err(|| notify_error("it didn't take the summary".to_owned()))?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(notify_error(format!("it exited with {}", status)));
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use chrono::{Duration, Utc};
+use eva;
+use eva::configuration::{Configuration, SchedulingStrategy, UrgencyCoefficients};
+use eva::NewTask;
+use futures::executor::block_on;
+
+pub use self::errors::*;
+
+mod errors {
+    error_chain! {
+        foreign_links {
+            EvaCore(eva::Error);
+        }
+    }
+}
+
+/// What the generated tasks are about, after the examples in the README and the tests. When more
+/// tasks are generated than there are contents, the contents are numbered.
+const CONTENTS: &[&str] = &[
+    "Think of plan to get rid of The Ring",
+    "Ask advice from Saruman",
+    "Visit Bilbo in Rivendel",
+    "Make some firework for the hobbits",
+    "Get riders of Rohan to help Gondor",
+    "Find some good pipe-weed",
+    "Go shop for white clothing",
+    "Prepare epic-sounding one-liners",
+    "Recharge staff batteries",
+    "Take over the world",
+    "Make onion soup",
+    "Publish Commander Mango 3",
+    "Sell Commander Mango 3",
+    "Learn to play the ocarina",
+];
+
+/// The tags that some of the generated tasks get.
+const TAGS: &[&str] = &["fellowship", "errand", "home"];
+
+
+/// Makes a configuration with the default settings and an in-memory database, which is gone when
+/// eva stops. It holds `count` generated tasks and an accepted schedule for them.
+///
+/// The tasks are about as long, important and urgent as real ones, and leave enough time to be
+/// scheduled. The same `seed` always gives the same tasks, with deadlines relative to now.
+pub fn configuration(count: u32, seed: u64) -> Result<Configuration> {
+    let configuration = Configuration {
+        database: Box::new(eva::database::sqlite::make_connection(":memory:")?),
+        scheduling_strategy: SchedulingStrategy::Importance,
+        project_weights: Default::default(),
+        reminders: vec![],
+        buffer: Duration::zero(),
+        unavailable: vec![],
+        assignees: Default::default(),
+        horizon: None,
+        budgets: Default::default(),
+        urgency: UrgencyCoefficients::default(),
+        tag_rules: Default::default(),
+        place: None,
+        local_time: Box::new(Utc),
+        focus_sessions: None,
+    };
+    for new_task in generate_tasks(count, seed) {
+        block_on(eva::add(&configuration, new_task))?;
+    }
+    block_on(eva::propose_schedule(&configuration, "importance"))?;
+    block_on(eva::accept_schedule(&configuration))?;
+    Ok(configuration)
+}
+
+
+fn generate_tasks(count: u32, seed: u64) -> Vec<NewTask> {
+    // A simple linear congruential generator keeps the tasks the same for the same seed
+    let mut state = seed;
+    let mut random = move |bound: u64| {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) % bound
+    };
+    let now = Utc::now();
+    let mut total_duration = Duration::zero();
+    (0..count as usize)
+        .map(|index| {
+            let content = match index / CONTENTS.len() {
+                0 => CONTENTS[index].to_owned(),
+                round => format!("{} ({})", CONTENTS[index % CONTENTS.len()], round + 1),
+            };
+            let duration = Duration::minutes(15 + random(8 * 60 - 15) as i64);
+            total_duration = total_duration + duration;
+            // Every task can be finished after all tasks generated before it, with some slack
+            let deadline = now + Duration::days(1) + total_duration * 2
+                + Duration::hours(random(24 * 14) as i64);
+            let tags = match random(TAGS.len() as u64 + 1) as usize {
+                tag if tag < TAGS.len() => vec![TAGS[tag].to_owned()],
+                _ => vec![],
+            };
+            NewTask {
+                content: content,
+                deadline: deadline,
+                duration: duration,
+                importance: 1 + random(10) as u32,
+                tags: tags,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+
+use base64;
+use eva;
+use eva::database::encrypted::Cipher;
+use ring::aead::{self, Aad, Nonce, OpeningKey, SealingKey, AES_256_GCM};
+use ring::digest;
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// How often a passphrase is hashed to make a key, to make guessing it slow.
+const PASSPHRASE_ITERATIONS: u32 = 100_000;
+
+
+/// What the key is made from.
+pub enum Secret {
+    /// A passphrase, which is stretched into a key.
+    Passphrase(String),
+    /// The contents of a keyfile, which are long and random enough to make a key right away.
+    Keyfile(Vec<u8>),
+}
+
+
+/// Encrypts with AES-256-GCM, under a key that is derived from a `Secret` and a random salt.
+///
+/// Every encrypted value carries its salt and nonce along, encoded in Base64 together with the
+/// ciphertext. Deriving a key from a passphrase takes a while, so the keys are remembered by
+/// salt, and new values are encrypted with the salt of the first value that was decrypted.
+pub struct Aes {
+    secret: Secret,
+    random: SystemRandom,
+    salt: Mutex<Option<[u8; SALT_LEN]>>,
+    keys: Mutex<HashMap<[u8; SALT_LEN], [u8; KEY_LEN]>>,
+}
+
+impl Aes {
+    pub fn new(secret: Secret) -> Aes {
+        Aes {
+            secret: secret,
+            random: SystemRandom::new(),
+            salt: Mutex::new(None),
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(&self, salt: [u8; SALT_LEN]) -> [u8; KEY_LEN] {
+        let mut keys = self.keys.lock().unwrap();
+        *keys.entry(salt).or_insert_with(|| {
+            let (secret, iterations) = match self.secret {
+                Secret::Passphrase(ref passphrase) =>
+                    (passphrase.as_bytes(), PASSPHRASE_ITERATIONS),
+                Secret::Keyfile(ref contents) => (&contents[..], 1),
+            };
+            let iterations = NonZeroU32::new(iterations).unwrap();
+            let mut key = [0; KEY_LEN];
+            pbkdf2::derive(&digest::SHA256, iterations, &salt, secret, &mut key);
+            key
+        })
+    }
+
+    fn salt(&self) -> eva::Result<[u8; SALT_LEN]> {
+        let mut salt = self.salt.lock().unwrap();
+        if salt.is_none() {
+            let mut new_salt = [0; SALT_LEN];
+            self.random.fill(&mut new_salt)
+                .map_err(|_| failed("while trying to make a salt"))?;
+            *salt = Some(new_salt);
+        }
+        Ok(salt.unwrap())
+    }
+}
+
+impl Cipher for Aes {
+    fn encrypt(&self, plaintext: &str) -> eva::Result<String> {
+        let salt = self.salt()?;
+        let mut nonce = [0; aead::NONCE_LEN];
+        self.random.fill(&mut nonce)
+            .map_err(|_| failed("while trying to make a nonce"))?;
+        let key = SealingKey::new(&AES_256_GCM, &self.key(salt))
+            .map_err(|_| failed("while trying to make a key"))?;
+
+        let mut in_out = plaintext.as_bytes().to_vec();
+        in_out.extend(vec![0; AES_256_GCM.tag_len()]);
+        let len = aead::seal_in_place(&key, Nonce::assume_unique_for_key(nonce), Aad::empty(),
+                                      &mut in_out, AES_256_GCM.tag_len())
+            .map_err(|_| failed("while trying to encrypt"))?;
+
+        let mut sealed = salt.to_vec();
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&in_out[..len]);
+        Ok(base64::encode(&sealed))
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> eva::Result<String> {
+        let sealed = base64::decode(ciphertext)
+            .map_err(|_| failed("while trying to decode what was encrypted"))?;
+        if sealed.len() < SALT_LEN + aead::NONCE_LEN {
+            return Err(failed("while trying to decode what was encrypted"));
+        }
+        let (salt_bytes, rest) = sealed.split_at(SALT_LEN);
+        let (nonce_bytes, rest) = rest.split_at(aead::NONCE_LEN);
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(salt_bytes);
+        let mut nonce = [0; aead::NONCE_LEN];
+        nonce.copy_from_slice(nonce_bytes);
+
+        let key = OpeningKey::new(&AES_256_GCM, &self.key(salt))
+            .map_err(|_| failed("while trying to make a key"))?;
+        let mut in_out = rest.to_vec();
+        let plaintext = aead::open_in_place(&key, Nonce::assume_unique_for_key(nonce),
+                                            Aad::empty(), 0, &mut in_out)
+            .map_err(|_| failed("while trying to decrypt, so the passphrase or keyfile may be \
+                                 wrong"))?;
+        let plaintext = String::from_utf8(plaintext.to_vec())
+            .map_err(|_| failed("while trying to decrypt"))?;
+
+        self.salt.lock().unwrap().get_or_insert(salt);
+        Ok(plaintext)
+    }
+}
+
+
+fn failed(when: &str) -> eva::Error {
+    eva::ErrorKind::Encryption(when.to_owned()).into()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_same_secret_decrypts_what_was_encrypted() {
+        let aes = Aes::new(Secret::Keyfile(b"a keyfile".to_vec()));
+        let ciphertext = aes.encrypt("Buy milk").unwrap();
+        assert!(!ciphertext.contains("milk"));
+        assert!(!ciphertext.contains(','));
+        assert_ne!(aes.encrypt("Buy milk").unwrap(), ciphertext);
+        assert_eq!(aes.decrypt(&ciphertext).unwrap(), "Buy milk");
+
+        let other = Aes::new(Secret::Keyfile(b"another keyfile".to_vec()));
+        assert!(other.decrypt(&ciphertext).is_err());
+    }
+}
@@ -0,0 +1,406 @@
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chrono::prelude::*;
+use chrono::Duration;
+use eva::configuration::Configuration;
+use serde_json::{self, Value};
+
+pub use self::errors::*;
+
+mod errors {
+    error_chain! {
+        errors {
+            UnknownFormat(format: String) {
+                description("unknown export format")
+                display("I don't know how to export to {}. You can add a format by putting a \
+                        program called {}{} on your PATH",
+                        format, super::PREFIX, format)
+            }
+            Run(program: String) {
+                description("exporter start error")
+                display("I could not run {}", program)
+            }
+            Failed(program: String, stderr: String) {
+                description("exporter failure")
+                display("{} failed: {}", program, stderr.trim())
+            }
+            Document(what: String) {
+                description("invalid document")
+                display("The document to export has no valid {}", what)
+            }
+        }
+    }
+}
+
+
+/// What the programs that export to other formats are called, followed by the name of the format.
+pub const PREFIX: &str = "eva-export-";
+
+
+/// Turns the tasks and the schedule into another format, like a weekly plan in LaTeX.
+pub trait Exporter {
+    /// Converts `document` (see `json::document`) to the bytes of a file in the format.
+    fn export(&self, document: &Value) -> Result<Vec<u8>>;
+}
+
+
+/// Exports the document as it is, as indented JSON.
+pub struct JsonExporter;
+
+impl Exporter for JsonExporter {
+    fn export(&self, document: &Value) -> Result<Vec<u8>> {
+        let mut bytes = serde_json::to_vec_pretty(document)
+            .expect("A JSON value can always be serialized");
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+}
+
+
+/// Exports the schedule as an iCalendar file, with an event for every slot. Every event has an
+/// alarm for each of the reminders of its task, or else for each of the `reminders` of the
+/// configuration, like the ones `eva daemon` sends. When the document has focus sessions, the
+/// events are the parts of the slots in the sessions instead, with an event for every break, and
+/// only the first part of a task has its alarms.
+pub struct IcsExporter {
+    pub reminders: Vec<Duration>,
+    /// When the file is made, which every event is stamped with.
+    pub now: DateTime<Utc>,
+}
+
+impl Exporter for IcsExporter {
+    fn export(&self, document: &Value) -> Result<Vec<u8>> {
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_owned(),
+            "VERSION:2.0".to_owned(),
+            "PRODID:-//eva//eva export ics//EN".to_owned(),
+        ];
+        match document["focus_sessions"].as_array() {
+            Some(sessions) => {
+                let invalid = || ErrorKind::Document("focus sessions".to_owned());
+                let mut started = vec![];
+                for session in sessions {
+                    for slot in session["slots"].as_array().ok_or_else(invalid)? {
+                        let id = slot["task"]["id"].as_u64().ok_or_else(invalid)?;
+                        lines.extend(self.event(slot, !started.contains(&id))?);
+                        started.push(id);
+                    }
+                    if !session["pause_until"].is_null() {
+                        let end = moment(&session["end"]).ok_or_else(invalid)?;
+                        let pause_until = moment(&session["pause_until"]).ok_or_else(invalid)?;
+                        lines.extend(self.pause(end, pause_until));
+                    }
+                }
+            },
+            None => {
+                let slots = document["schedule"].as_array().map(Vec::as_slice).unwrap_or_default();
+                for slot in slots {
+                    lines.extend(self.event(slot, true)?);
+                }
+            },
+        }
+        lines.push("END:VCALENDAR".to_owned());
+        let mut bytes = vec![];
+        for line in &lines {
+            bytes.extend_from_slice(fold(line).as_bytes());
+            bytes.extend_from_slice(b"\r\n");
+        }
+        Ok(bytes)
+    }
+}
+
+impl IcsExporter {
+    /// Turns a slot of the schedule (see `json::schedule`) into the lines of its event, with its
+    /// alarms if `alarms` is true.
+    fn event(&self, slot: &Value, alarms: bool) -> Result<Vec<String>> {
+        let invalid = || ErrorKind::Document("schedule".to_owned());
+        let task = &slot["task"];
+        let id = task["id"].as_u64().ok_or_else(invalid)?;
+        let content = task["content"].as_str().ok_or_else(invalid)?;
+        let start = moment(&slot["when"]).ok_or_else(invalid)?;
+        let end = task["duration"].as_i64()
+            .and_then(|duration| start.checked_add_signed(Duration::minutes(duration)))
+            .ok_or_else(invalid)?;
+        let own_reminders = task["reminders"].as_array()
+            .and_then(|reminders| {
+                reminders.iter()
+                    .map(|reminder| reminder.as_i64().map(Duration::minutes))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(invalid)?;
+        let reminders = match (alarms, own_reminders.is_empty()) {
+            (false, _) => &[][..],
+            (true, true) => &self.reminders[..],
+            (true, false) => &own_reminders[..],
+        };
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:{}-{}@eva", id, stamp(start)),
+            format!("DTSTAMP:{}", stamp(self.now)),
+            format!("DTSTART:{}", stamp(start)),
+            format!("DTEND:{}", stamp(end)),
+            format!("SUMMARY:{}", text(content)),
+        ];
+        if let Some(note) = slot["note"].as_str() {
+            lines.push(format!("DESCRIPTION:{}", text(note)));
+        }
+        for reminder in reminders {
+            lines.push("BEGIN:VALARM".to_owned());
+            lines.push("ACTION:DISPLAY".to_owned());
+            lines.push(format!("DESCRIPTION:{}", text(content)));
+            lines.push(format!("TRIGGER:-PT{}M", reminder.num_minutes()));
+            lines.push("END:VALARM".to_owned());
+        }
+        lines.push("END:VEVENT".to_owned());
+        Ok(lines)
+    }
+
+    /// Returns the lines of the event for a break from `start` until `end`.
+    fn pause(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<String> {
+        vec![
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:break-{}@eva", stamp(start)),
+            format!("DTSTAMP:{}", stamp(self.now)),
+            format!("DTSTART:{}", stamp(start)),
+            format!("DTEND:{}", stamp(end)),
+            "SUMMARY:Break".to_owned(),
+            "END:VEVENT".to_owned(),
+        ]
+    }
+}
+
+/// Reads a moment of the document, which `json::moment` wrote.
+fn moment(value: &Value) -> Option<DateTime<Utc>> {
+    value.as_str()
+        .and_then(|moment| DateTime::parse_from_rfc3339(moment).ok())
+        .map(|moment| moment.with_timezone(&Utc))
+}
+
+/// Writes `moment` like `20170802T140300Z`.
+fn stamp(moment: DateTime<Utc>) -> String {
+    moment.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes `text` for a text value of iCalendar.
+fn text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds `line` into lines of at most 75 bytes, which iCalendar continues with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+
+/// A program that is given the document as JSON on its standard input, and that writes the
+/// exported file to its standard output. It fails by exiting with a non-zero status, explaining
+/// why on its standard error.
+pub struct ExternalExporter {
+    pub program: PathBuf,
+}
+
+impl Exporter for ExternalExporter {
+    fn export(&self, document: &Value) -> Result<Vec<u8>> {
+        let program = self.program.display().to_string();
+        let mut child = Command::new(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .chain_err(|| ErrorKind::Run(program.clone()))?;
+        {
+            // Dropping stdin closes it, so that the program knows the whole document was sent
+            let mut stdin = child.stdin.take().unwrap();
+            stdin.write_all(document.to_string().as_bytes())
+                .chain_err(|| ErrorKind::Run(program.clone()))?;
+        }
+        let output = child.wait_with_output().chain_err(|| ErrorKind::Run(program.clone()))?;
+        ensure!(output.status.success(),
+                ErrorKind::Failed(program, String::from_utf8_lossy(&output.stderr).into_owned()));
+        Ok(output.stdout)
+    }
+}
+
+
+/// Returns the exporter to `format`: a built-in one, or else the program called `PREFIX` followed
+/// by the format on the PATH.
+pub fn exporter(format: &str, configuration: &Configuration) -> Result<Box<Exporter>> {
+    match format {
+        "json" => return Ok(Box::new(JsonExporter)),
+        "ics" => {
+            return Ok(Box::new(IcsExporter {
+                reminders: configuration.reminders.clone(),
+                now: configuration.now(),
+            }));
+        },
+        _ => {},
+    }
+    external_exporters().into_iter()
+        .find(|(name, _)| name == format)
+        .map(|(_, program)| Box::new(ExternalExporter { program: program }) as Box<Exporter>)
+        .ok_or_else(|| ErrorKind::UnknownFormat(format.to_owned()).into())
+}
+
+
+/// Returns the names of all formats that can be exported to, in alphabetical order.
+pub fn formats() -> Vec<String> {
+    let mut formats: Vec<String> = external_exporters().into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    formats.push("ics".to_owned());
+    formats.push("json".to_owned());
+    formats.sort();
+    formats.dedup();
+    formats
+}
+
+
+/// Finds the exporters on the PATH with their format. An exporter that appears in several
+/// directories is taken from the first one, like the shell does.
+fn external_exporters() -> Vec<(String, PathBuf)> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    let mut exporters: Vec<(String, PathBuf)> = vec![];
+    for directory in env::split_paths(&path) {
+        let entries = match directory.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let format = match format_of(Path::new(&entry.file_name())) {
+                Some(format) => format,
+                None => continue,
+            };
+            if !exporters.iter().any(|(name, _)| *name == format) {
+                exporters.push((format, entry.path()));
+            }
+        }
+    }
+    exporters
+}
+
+/// Returns the format that the program at `path` exports to, if it's an exporter.
+fn format_of(path: &Path) -> Option<String> {
+    // Leave out extensions like .exe on Windows
+    let name = path.file_stem()?.to_str()?;
+    if name.starts_with(PREFIX) && name.len() > PREFIX.len() {
+        Some(name[PREFIX.len()..].to_owned())
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn slot(id: u32, when: &str, reminders: &[i64]) -> Value {
+        json!({
+            "when": when,
+            "task": {
+                "id": id,
+                "content": "Call mom, then dad",
+                "duration": 90,
+                "reminders": reminders,
+            },
+            "note": null,
+        })
+    }
+
+    fn export(document: &Value) -> String {
+        let exporter = IcsExporter {
+            reminders: vec![Duration::minutes(15), Duration::days(1)],
+            now: Utc.ymd(2017, 8, 1).and_hms(12, 0, 0),
+        };
+        String::from_utf8(exporter.export(document).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn alarms_for_reminders() {
+        let document = json!({
+            "schedule": [
+                slot(1, "2017-08-02T14:00:00+00:00", &[]),
+                slot(2, "2017-08-03T09:30:00+00:00", &[60]),
+            ],
+        });
+        let ics = export(&document);
+        let events: Vec<&str> = ics.split("BEGIN:VEVENT").skip(1).collect();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].contains("DTSTART:20170802T140000Z\r\nDTEND:20170802T153000Z\r\n"));
+        assert!(events[0].contains("SUMMARY:Call mom\\, then dad\r\n"));
+        assert!(events[0].contains("TRIGGER:-PT15M\r\n"));
+        assert!(events[0].contains("TRIGGER:-PT1440M\r\n"));
+        assert_eq!(events[0].matches("BEGIN:VALARM").count(), 2);
+        assert!(events[1].contains("TRIGGER:-PT60M\r\n"));
+        assert_eq!(events[1].matches("BEGIN:VALARM").count(), 1);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn focus_sessions_and_breaks() {
+        let part = |when: &str, duration: i64| {
+            let mut part = slot(1, when, &[]);
+            part["task"]["duration"] = json!(duration);
+            part
+        };
+        let document = json!({
+            "schedule": [slot(1, "2017-08-02T14:00:00+00:00", &[])],
+            "focus_sessions": [
+                {
+                    "start": "2017-08-02T14:00:00+00:00",
+                    "end": "2017-08-02T14:25:00+00:00",
+                    "slots": [part("2017-08-02T14:00:00+00:00", 25)],
+                    "pause_until": "2017-08-02T14:30:00+00:00",
+                },
+                {
+                    "start": "2017-08-02T14:30:00+00:00",
+                    "end": "2017-08-02T14:55:00+00:00",
+                    "slots": [part("2017-08-02T14:30:00+00:00", 25)],
+                    "pause_until": null,
+                },
+            ],
+        });
+        let ics = export(&document);
+        let events: Vec<&str> = ics.split("BEGIN:VEVENT").skip(1).collect();
+        assert_eq!(events.len(), 3);
+        assert!(events[0].contains("DTSTART:20170802T140000Z\r\nDTEND:20170802T142500Z\r\n"));
+        assert_eq!(events[0].matches("BEGIN:VALARM").count(), 2);
+        assert!(events[1].contains("DTSTART:20170802T142500Z\r\nDTEND:20170802T143000Z\r\n"));
+        assert!(events[1].contains("SUMMARY:Break\r\n"));
+        assert!(events[2].contains("DTSTART:20170802T143000Z\r\nDTEND:20170802T145500Z\r\n"));
+        assert!(!events[2].contains("BEGIN:VALARM"));
+    }
+
+    #[test]
+    fn no_schedule() {
+        let ics = export(&json!({ "schedule": null }));
+        assert!(!ics.contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let line = format!("SUMMARY:{}", "\u{e9}".repeat(50));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|part| part.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+}
@@ -0,0 +1,44 @@
+use chrono::NaiveDate;
+use reqwest;
+use serde_json;
+
+pub use self::errors::*;
+
+mod errors {
+    error_chain! {
+        errors {
+            Download(country: String) {
+                description("holiday download error")
+                display("I could not download the public holidays of {}", country)
+            }
+            Format(country: String) {
+                description("holiday format error")
+                display("I could not make sense of the public holidays of {} I downloaded",
+                        country)
+            }
+        }
+    }
+}
+
+/// Where the public holidays are downloaded from, as JSON.
+const SOURCE: &str = "https://date.nager.at/api/v2/PublicHolidays";
+
+
+/// Downloads the public holidays of `country` in `year`. The country is given by its ISO 3166-1
+/// alpha-2 code, e.g. 'BE'.
+pub fn fetch(country: &str, year: i32) -> Result<Vec<NaiveDate>> {
+    let url = format!("{}/{}/{}", SOURCE, year, country);
+    let body = reqwest::get(&url)
+        .and_then(|response| response.error_for_status())
+        .and_then(|mut response| response.text())
+        .chain_err(|| ErrorKind::Download(country.to_owned()))?;
+    let holidays: Vec<serde_json::Value> = serde_json::from_str(&body)
+        .chain_err(|| ErrorKind::Format(country.to_owned()))?;
+    holidays.iter()
+        .map(|holiday| {
+            holiday["date"].as_str()
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                .ok_or_else(|| ErrorKind::Format(country.to_owned()).into())
+        })
+        .collect()
+}
@@ -0,0 +1,82 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use eva::{Schedule, Task};
+use serde_json::Value;
+
+use crate::json;
+
+pub use self::errors::*;
+
+mod errors {
+    error_chain! {
+        foreign_links {
+            Io(std::io::Error);
+        }
+        errors {
+            Hook(hook: String, command: String, reason: String) {
+                description("hook error")
+                display("I could not run the {} hook `{}` ({})", hook, command, reason)
+            }
+        }
+    }
+}
+
+
+/// The shell commands that are run when something happens to the tasks, like the hooks of
+/// Taskwarrior. Every command gets what happened as JSON on its standard input, in the format of
+/// the `json` module, and the name of the hook in `EVA_HOOK`.
+///
+/// The hooks run after the change is stored, so a hook that fails doesn't undo it or stop eva.
+/// It is reported on standard error instead.
+#[derive(Debug, Default)]
+pub struct Hooks {
+    /// The commands that get every task that is added.
+    pub on_add: Vec<String>,
+    /// The commands that get every task that is completed.
+    pub on_complete: Vec<String>,
+    /// The commands that get every schedule that is accepted, also by a rollover.
+    pub on_schedule: Vec<String>,
+}
+
+impl Hooks {
+    pub fn added(&self, task: &Task) {
+        run_all(&self.on_add, "on-add", &json::task(task));
+    }
+
+    pub fn completed(&self, task: &Task) {
+        run_all(&self.on_complete, "on-complete", &json::task(task));
+    }
+
+    pub fn scheduled(&self, schedule: &Schedule) {
+        run_all(&self.on_schedule, "on-schedule", &json::schedule(schedule));
+    }
+}
+
+
+fn run_all(commands: &[String], hook: &str, input: &Value) {
+    for command in commands {
+        if let Err(error) = run(command, hook, input) {
+            eprintln!("{}.", error);
+        }
+    }
+}
+
+
+fn run(command: &str, hook: &str, input: &Value) -> Result<()> {
+    let hook_error = |reason: String| ErrorKind::Hook(hook.to_owned(), command.to_owned(), reason);
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("EVA_HOOK", hook)
+        .stdin(Stdio::piped())
+        .spawn()
+        .chain_err(|| hook_error("it didn't start".to_owned()))?;
+    child.stdin.take().unwrap().write_all(input.to_string().as_bytes())
+        .chain_err(|| hook_error("it didn't take the JSON".to_owned()))?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(hook_error(format!("it exited with {}", status)));
+    }
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use eva::tracking::Session;
+use eva::{Days, FocusSession, NewTask, Schedule, ScheduleError, Task, Trigger, Window};
+use serde_json::{json, Value};
+
+pub use self::errors::*;
+
+mod errors {
+    error_chain! {
+        errors {
+            Field(name: String, how_it_should_be: String) {
+                description("invalid JSON field")
+                display("The {} of the task is missing or invalid. It should be {}",
+                        name, how_it_should_be)
+            }
+        }
+    }
+}
+
+
+/// The JSON Schema of the documents made by `document`, and of the tasks and schedules in them.
+pub const SCHEMA: &str = include_str!("../schema/eva.schema.json");
+
+/// The version of `SCHEMA`. It only changes when fields are removed or change meaning, so that
+/// other programs keep understanding the documents when fields are added.
+pub const VERSION: u32 = 1;
+
+
+/// Makes the document that is exported: the tasks that aren't completed yet, the schedule that
+/// was accepted last, if any, the work sessions, e.g. to bill them, and the focus sessions the
+/// accepted schedule is sliced into, if it is.
+pub fn document(tasks: &[Task], schedule: Option<&Schedule>, sessions: &[Session],
+                focus_sessions: Option<&[FocusSession]>)
+    -> Value
+{
+    json!({
+        "version": VERSION,
+        "tasks": self::tasks(tasks),
+        "schedule": schedule.map(self::schedule),
+        "sessions": sessions.iter().map(session).collect::<Vec<_>>(),
+        "focus_sessions": focus_sessions
+            .map(|focus_sessions| focus_sessions.iter().map(focus_session).collect::<Vec<_>>()),
+    })
+}
+
+
+/// Converts `task` to JSON. Moments are given in RFC 3339 and durations in whole minutes.
+pub fn task(task: &Task) -> Value {
+    json!({
+        "id": task.id,
+        "content": task.content,
+        "deadline": moment(task.deadline),
+        "duration": task.duration.num_minutes(),
+        "importance": task.importance,
+        "tags": task.tags,
+        "start_after": task.start_after.map(moment),
+        "reminders": task.reminders.iter().map(Duration::num_minutes).collect::<Vec<_>>(),
+        "buffer": task.buffer.map(|buffer| buffer.num_minutes()),
+        "horizon": task.horizon.map(|horizon| horizon.num_minutes()),
+        "daily_minimum": task.daily_minimum.map(|daily_minimum| daily_minimum.num_minutes()),
+        "attachments": task.attachments,
+        "completed": task.completed.map(moment),
+        "time_spent": task.time_spent.map(|time_spent| time_spent.num_minutes()),
+        "slipped": task.slipped,
+        "waiting_for": task.waiting_for.as_ref().map(trigger),
+        "preferred_window": task.preferred_window.as_ref().map(window),
+        "assignee": task.assignee,
+    })
+}
+
+
+/// Converts `window` to JSON, like `{"days": "weekends", "from": "18:00", "until": "22:00"}`.
+fn window(window: &Window) -> Value {
+    let days = match window.days {
+        Days::Every => "every",
+        Days::Weekdays => "weekdays",
+        Days::Weekends => "weekends",
+    };
+    json!({
+        "days": days,
+        "from": window.from.format("%H:%M").to_string(),
+        "until": window.until.map(|until| until.format("%H:%M").to_string()),
+    })
+}
+
+
+/// Converts `trigger` to JSON, like `{"at": "office"}` or `{"done": 12}`.
+fn trigger(trigger: &Trigger) -> Value {
+    match trigger {
+        Trigger::At(place) => json!({ "at": place }),
+        Trigger::Done(id) => json!({ "done": id }),
+    }
+}
+
+
+pub fn tasks(tasks: &[Task]) -> Value {
+    Value::Array(tasks.iter().map(task).collect())
+}
+
+
+/// Converts `session` to JSON, with how long it lasted so far when it's still going on.
+pub fn session(session: &Session) -> Value {
+    json!({
+        "task_id": session.task_id,
+        "start": moment(session.start),
+        "end": session.end.map(moment),
+        "duration": session.duration(Utc::now()).num_minutes(),
+    })
+}
+
+
+/// Converts `schedule` to JSON, as a list of the scheduled tasks with the moment they start and
+/// the note on their slot.
+pub fn schedule(schedule: &Schedule) -> Value {
+    Value::Array(schedule.0.iter()
+        .map(|scheduled_task| json!({
+            "when": moment(scheduled_task.when),
+            "task": task(&scheduled_task.task),
+            "note": scheduled_task.note,
+        }))
+        .collect())
+}
+
+
+/// Converts `focus_session` to JSON, with its slots like those of a schedule and the end of the
+/// break after it, if it has one.
+pub fn focus_session(focus_session: &FocusSession) -> Value {
+    json!({
+        "start": moment(focus_session.start),
+        "end": moment(focus_session.end),
+        "slots": schedule(&Schedule(focus_session.slots.clone())),
+        "pause_until": focus_session.pause_until.map(moment),
+    })
+}
+
+
+/// Converts `error` to JSON, with the task that couldn't be scheduled, how many minutes it lacked
+/// and the ids of the tasks that took up that time, where they are known.
+pub fn conflict(error: &ScheduleError) -> Value {
+    let (kind, shortfall) = match error {
+        ScheduleError::DeadlineMissed { shortfall, .. } => ("deadline_missed", Some(shortfall)),
+        ScheduleError::StartsTooLate { shortfall, .. } => ("starts_too_late", Some(shortfall)),
+        ScheduleError::NotEnoughTime { shortfall, .. } => ("not_enough_time", Some(shortfall)),
+        ScheduleError::TimeOff { shortfall, .. } => ("time_off", Some(shortfall)),
+        ScheduleError::Overflow { .. } => ("overflow", None),
+        ScheduleError::OverBudget { .. } => ("over_budget", None),
+        ScheduleError::InvalidDuration { .. } => ("invalid_duration", None),
+        ScheduleError::InvalidDailyMinimum { .. } => ("invalid_daily_minimum", None),
+        ScheduleError::ChainTooLong { shortfall, .. } => ("chain_too_long", Some(shortfall)),
+        ScheduleError::CircularWait { .. } => ("circular_wait", None),
+        _ => ("internal", None),
+    };
+    json!({
+        "kind": kind,
+        "task": error.task().map(task),
+        "shortfall": shortfall.map(Duration::num_minutes),
+        "conflicting": error.conflicting().iter().map(|task| task.id).collect::<Vec<_>>(),
+    })
+}
+
+
+/// Reads a new task from JSON in the format of `task`, without the id and the fields that are
+/// only known once it's completed. Only the content, deadline, duration and importance are
+/// required.
+pub fn new_task(value: &Value) -> Result<NewTask> {
+    Ok(NewTask {
+        content: value["content"].as_str()
+            .ok_or_else(|| field("content", "a string"))?
+            .to_owned(),
+        deadline: required(value, "deadline", as_moment, "a moment like '2017-08-02T14:03:00Z'")?,
+        duration: required(value, "duration", as_minutes, "a whole number of minutes")?,
+        importance: required(value, "importance", as_importance, "a whole number")?,
+        tags: optional(value, "tags", as_strings, "a list of strings")?.unwrap_or_default(),
+        start_after: optional(value, "start_after", as_moment,
+                              "a moment like '2017-08-02T14:03:00Z'")?,
+        reminders: optional(value, "reminders", as_minute_list,
+                            "a list of whole numbers of minutes")?.unwrap_or_default(),
+        buffer: optional(value, "buffer", as_minutes, "a whole number of minutes")?,
+        horizon: optional(value, "horizon", as_minutes, "a whole number of minutes")?,
+        daily_minimum: optional(value, "daily_minimum", as_minutes, "a whole number of minutes")?,
+        attachments: optional(value, "attachments", as_strings, "a list of strings")?
+            .unwrap_or_default(),
+        preferred_window: optional(value, "preferred_window", as_window,
+                                   "a window like {\"days\": \"every\", \"from\": \"18:00\", \
+                                    \"until\": \"22:00\"}")?,
+        assignee: optional(value, "assignee", as_string, "a string")?,
+    })
+}
+
+
+fn moment(moment: DateTime<Utc>) -> String {
+    moment.to_rfc3339()
+}
+
+fn required<T, F>(value: &Value, name: &str, convert: F, how_it_should_be: &str) -> Result<T>
+    where F: Fn(&Value) -> Option<T>
+{
+    convert(&value[name]).ok_or_else(|| field(name, how_it_should_be))
+}
+
+/// Like `required`, but a field that is missing or null is `None`.
+fn optional<T, F>(value: &Value, name: &str, convert: F, how_it_should_be: &str)
+    -> Result<Option<T>>
+    where F: Fn(&Value) -> Option<T>
+{
+    match value[name] {
+        Value::Null => Ok(None),
+        ref field_value => Ok(Some(convert(field_value)
+            .ok_or_else(|| field(name, how_it_should_be))?)),
+    }
+}
+
+fn field(name: &str, how_it_should_be: &str) -> Error {
+    ErrorKind::Field(name.to_owned(), how_it_should_be.to_owned()).into()
+}
+
+fn as_moment(value: &Value) -> Option<DateTime<Utc>> {
+    value.as_str()
+        .and_then(|moment| DateTime::parse_from_rfc3339(moment).ok())
+        .map(|moment| moment.with_timezone(&Utc))
+}
+
+fn as_minutes(value: &Value) -> Option<Duration> {
+    value.as_i64().filter(|&minutes| minutes >= 0).map(Duration::minutes)
+}
+
+fn as_minute_list(value: &Value) -> Option<Vec<Duration>> {
+    value.as_array()?.iter().map(as_minutes).collect()
+}
+
+fn as_importance(value: &Value) -> Option<u32> {
+    value.as_u64().filter(|&importance| importance <= u64::from(u32::max_value()))
+        .map(|importance| importance as u32)
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_owned)
+}
+
+fn as_strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(|string| string.as_str().map(str::to_owned)).collect()
+}
+
+fn as_window(value: &Value) -> Option<Window> {
+    let days = match value["days"].as_str()? {
+        "every" => Days::Every,
+        "weekdays" => Days::Weekdays,
+        "weekends" => Days::Weekends,
+        _ => return None,
+    };
+    let as_time = |time: &Value| NaiveTime::parse_from_str(time.as_str()?, "%H:%M").ok();
+    let until = match value["until"] {
+        Value::Null => None,
+        ref until => Some(as_time(until)?),
+    };
+    Some(Window {
+        days: days,
+        from: as_time(&value["from"])?,
+        until: until,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use eva::ScheduledTask;
+
+    use super::*;
+
+    /// Tells what in `value` doesn't follow the part of `SCHEMA` that is `schema`, if anything.
+    /// Only the keywords that `SCHEMA` uses are checked, apart from the formats and patterns of
+    /// strings.
+    fn violation(root: &Value, schema: &Value, value: &Value, path: &str) -> Option<String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let definition = reference.trim_start_matches("#/definitions/");
+            return match root["definitions"].get(definition) {
+                Some(schema) => violation(root, schema, value, path),
+                None => Some(format!("{}: {} refers to nothing", path, reference)),
+            };
+        }
+        if let Some(schemas) = schema["oneOf"].as_array() {
+            let matching = schemas.iter()
+                .filter(|schema| violation(root, schema, value, path).is_none())
+                .count();
+            if matching != 1 {
+                return Some(format!("{}: {} matches {} of oneOf", path, value, matching));
+            }
+        }
+        let expected_type = match schema["type"].as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("null") => value.is_null(),
+            Some(other) => return Some(format!("{}: unknown type {}", path, other)),
+            None => true,
+        };
+        if !expected_type {
+            return Some(format!("{}: {} isn't of type {}", path, value, schema["type"]));
+        }
+        if !schema["const"].is_null() && schema["const"] != *value {
+            return Some(format!("{}: {} isn't {}", path, value, schema["const"]));
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            if !values.contains(value) {
+                return Some(format!("{}: {} isn't one of {:?}", path, value, values));
+            }
+        }
+        if let (Some(minimum), Some(number)) = (schema["minimum"].as_i64(), value.as_i64()) {
+            if number < minimum {
+                return Some(format!("{}: {} is less than {}", path, number, minimum));
+            }
+        }
+        for name in schema["required"].as_array().into_iter().flatten() {
+            let name = name.as_str().unwrap();
+            if value.get(name).is_none() {
+                return Some(format!("{}: {} is missing", path, name));
+            }
+        }
+        if let Some(properties) = schema["properties"].as_object() {
+            for (name, property) in properties {
+                if let Some(field) = value.get(name) {
+                    let path = format!("{}.{}", path, name);
+                    if let Some(violation) = violation(root, property, field, &path) {
+                        return Some(violation);
+                    }
+                }
+            }
+        }
+        if let (Some(items), Some(elements)) = (schema.get("items"), value.as_array()) {
+            for (index, element) in elements.iter().enumerate() {
+                let path = format!("{}[{}]", path, index);
+                if let Some(violation) = violation(root, items, element, &path) {
+                    return Some(violation);
+                }
+            }
+        }
+        None
+    }
+
+    fn schema() -> Value {
+        serde_json::from_str(SCHEMA).unwrap()
+    }
+
+    /// The part of `SCHEMA` with the given name.
+    fn definition(name: &str) -> Value {
+        json!({ "$ref": format!("#/definitions/{}", name) })
+    }
+
+    fn assert_valid(schema: &Value, value: &Value) {
+        if let Some(violation) = violation(&self::schema(), schema, value, "$") {
+            panic!("{} doesn't follow the schema: {}", value, violation);
+        }
+    }
+
+    fn monday() -> DateTime<Utc> {
+        Utc.ymd(2019, 3, 4).and_hms(9, 0, 0)
+    }
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            Task {
+                id: 1,
+                content: "Call mom".to_owned(),
+                deadline: monday() + Duration::days(1),
+                duration: Duration::minutes(30),
+                importance: 5,
+                ..Default::default()
+            },
+            Task {
+                id: 2,
+                content: "Paint the shed".to_owned(),
+                deadline: monday() + Duration::days(7),
+                duration: Duration::hours(6),
+                importance: 3,
+                tags: vec!["home".to_owned()],
+                start_after: Some(monday()),
+                reminders: vec![Duration::minutes(15)],
+                buffer: Some(Duration::minutes(10)),
+                horizon: Some(Duration::days(3)),
+                daily_minimum: Some(Duration::hours(1)),
+                attachments: vec!["https://example.com/shed".to_owned()],
+                completed: Some(monday() + Duration::days(2)),
+                time_spent: Some(Duration::hours(5)),
+                slipped: 1,
+                waiting_for: Some(Trigger::Done(1)),
+                preferred_window: Some(Window {
+                    days: Days::Weekends,
+                    from: NaiveTime::from_hms(18, 0, 0),
+                    until: None,
+                }),
+                assignee: Some("alex".to_owned()),
+            },
+        ]
+    }
+
+    #[test]
+    fn schema_is_valid_json_of_the_current_version() {
+        let schema = schema();
+        assert_eq!(schema["properties"]["version"]["const"], json!(VERSION));
+        assert!(schema["definitions"].is_object());
+    }
+
+    #[test]
+    fn documents_follow_the_schema() {
+        let tasks = tasks();
+        let mut scheduled = ScheduledTask::new(tasks[0].clone(), monday());
+        scheduled.note = Some("From the car".to_owned());
+        let schedule = Schedule(vec![scheduled.clone()]);
+        let sessions = vec![
+            Session { task_id: 1, start: monday(), end: Some(monday() + Duration::minutes(20)) },
+            Session { task_id: 2, start: monday() + Duration::hours(1), end: None },
+        ];
+        let focus_sessions = vec![FocusSession {
+            start: monday(),
+            end: monday() + Duration::minutes(30),
+            slots: vec![scheduled],
+            pause_until: Some(monday() + Duration::minutes(35)),
+        }];
+        for document in &[
+            document(&tasks, Some(&schedule), &sessions, Some(&focus_sessions)),
+            document(&[], None, &[], None),
+        ] {
+            assert_valid(&schema(), document);
+        }
+        for task in &tasks {
+            assert_valid(&definition("task"), &self::task(task));
+        }
+    }
+
+    #[test]
+    fn errors_follow_the_schema() {
+        let tasks = tasks();
+        let errors = vec![
+            ScheduleError::NotEnoughTime {
+                task: tasks[0].clone(),
+                shortfall: Duration::minutes(10),
+                conflicting: vec![tasks[1].clone()],
+            },
+            ScheduleError::Internal("oops".to_owned()),
+        ];
+        for error in &errors {
+            assert_valid(&definition("conflict"), &conflict(error));
+            let answer = json!({ "error": error.to_string(), "conflict": conflict(error) });
+            assert_valid(&definition("error"), &answer);
+        }
+    }
+
+    #[test]
+    fn new_tasks_follow_the_schema() {
+        let value = json!({
+            "content": "Call mom",
+            "deadline": "2019-03-05T09:00:00+00:00",
+            "duration": 30,
+            "importance": 5,
+            "preferred_window": { "days": "every", "from": "18:00", "until": "22:00" },
+        });
+        assert_valid(&definition("new_task"), &value);
+        assert_eq!(new_task(&value).unwrap().duration, Duration::minutes(30));
+    }
+}
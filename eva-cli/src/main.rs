@@ -2,35 +2,119 @@
 extern crate error_chain;
 
 
+use std::cmp::{max, min};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::iter;
+
+use chrono::Datelike;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use error_chain::ChainedError;
 use eva::configuration::Configuration;
 use futures::executor::block_on;
 use itertools::Itertools;
 
 use crate::errors::*;
+use crate::hooks::Hooks;
 use crate::pretty_print::PrettyPrint;
 
 
+mod agenda;
+mod attachments;
+mod completions;
 mod configuration;
+mod daemon;
+mod demo;
+mod encryption;
+mod export;
+mod holidays;
+mod hooks;
+mod json;
 mod parse;
 mod pretty_print;
+mod progress;
+mod serve;
+mod timezone;
 
 mod errors {
+    use crate::attachments;
     use crate::configuration;
+    use crate::daemon;
+    use crate::demo;
+    use crate::export;
+    use crate::holidays;
     use crate::parse;
+    use crate::serve;
 
     error_chain! {
         links {
+            Attachments(attachments::Error, attachments::ErrorKind);
             Configuration(configuration::Error, configuration::ErrorKind);
+            Daemon(daemon::Error, daemon::ErrorKind);
+            Demo(demo::Error, demo::ErrorKind);
+            Export(export::Error, export::ErrorKind);
+            Holidays(holidays::Error, holidays::ErrorKind);
             Parse(parse::Error, parse::ErrorKind);
+            Serve(serve::Error, serve::ErrorKind);
         }
         foreign_links {
             EvaCore(eva::Error);
+            Io(std::io::Error);
+        }
+        errors {
+            OutOfRange(what: String) {
+                description("value out of range")
+                display("I could not calculate {}, because it would be too far out there", what)
+            }
+            NotInDemo(command: String) {
+                description("command not in demo")
+                display("`eva {}` can't be tried out with `eva demo`, which is about tasks and \
+                        schedules",
+                        command)
+            }
+            NotEncrypted(context: String) {
+                description("encryption not configured")
+                display("The database of {} isn't encrypted. You can set encryption_passphrase or \
+                        encryption_keyfile in your configuration file to encrypt it",
+                        context)
+            }
+            NoAttachment(id: u32, number: Option<usize>) {
+                description("attachment not found")
+                display("Task {} doesn't have {}. You can attach a file or a link to it with \
+                        `eva edit {} --attach <path or URL>`",
+                        id,
+                        number.map_or("any attachments".to_owned(),
+                                      |number| format!("an attachment {}", number)),
+                        id)
+            }
+            NotWorking {
+                description("not working on a task")
+                display("You aren't working on anything. You can start with \
+                        `eva start <task-id>`")
+            }
+            SnoozedUntilItself(id: u32) {
+                description("task snoozed until itself")
+                display("Task {} can't wait until it is done itself", id)
+            }
+            NotInTemplate(field: String, template: String) {
+                description("field missing from template")
+                display("Give the {} of the task, because the template {} doesn't have one",
+                        field, template)
+            }
         }
     }
 }
 
 
+/// How sure a suggested tag has to be to be added by `eva add --auto-tags`.
+const AUTO_TAG_CONFIDENCE: f64 = 0.8;
+
+/// The commands that `eva demo` can't run, because they don't go through `dispatch`.
+const NOT_IN_DEMO: &[&str] = &[
+    "completions", "schema", "tz", "place", "holidays", "off", "context", "serve", "daemon", "demo",
+];
+
+
 fn main() {
     if let Err(ref error) = run() {
         handle_error(error);
@@ -38,24 +122,159 @@ fn main() {
 }
 
 fn run() -> Result<()> {
-    let configuration = configuration::read()?;
-    let matches = cli(&configuration).get_matches();
-    dispatch(&matches, &configuration)
+    let matches = cli().get_matches();
+    if let ("completions", Some(submatches)) = matches.subcommand() {
+        let shell = submatches.value_of("shell").unwrap().parse().unwrap();
+        return Ok(completions::generate(&mut cli(), shell, &mut io::stdout())?);
+    }
+    if let ("schema", Some(_submatches)) = matches.subcommand() {
+        println!("{}", json::SCHEMA.trim_end());
+        return Ok(());
+    }
+    if let ("tz", Some(submatches)) = matches.subcommand() {
+        return manage_timezone(submatches);
+    }
+    if let ("place", Some(submatches)) = matches.subcommand() {
+        return manage_place(submatches);
+    }
+    configuration::activate_timezone()?;
+    if let ("holidays", Some(submatches)) = matches.subcommand() {
+        return manage_holidays(submatches);
+    }
+    if let ("off", Some(submatches)) = matches.subcommand() {
+        return manage_days_off(submatches);
+    }
+    if let ("demo", Some(submatches)) = matches.subcommand() {
+        return run_demo(submatches);
+    }
+    let context = chosen_context(&matches).map(parse::context).transpose()?;
+    if let ("context", Some(submatches)) = matches.subcommand() {
+        // Managing contexts doesn't need the configuration of any of them
+        return manage_contexts(submatches, context);
+    }
+    if let ("serve", Some(submatches)) = matches.subcommand() {
+        // Every thread of the server reads the configuration for itself
+        let address = submatches.value_of("address").unwrap();
+        let threads = parse::threads(submatches.value_of("threads").unwrap())?;
+        return Ok(serve::serve(context, address, threads)?);
+    }
+    if let ("daemon", Some(submatches)) = matches.subcommand() {
+        // The daemon reads the configuration again whenever it changes
+        return Ok(daemon::run(context, submatches.value_of("strategy"))?);
+    }
+    if let ("encrypt", Some(_submatches)) = matches.subcommand() {
+        // Without encryption, storing everything again would store it as it was
+        if !configuration::encrypts(context)? {
+            let context = match context {
+                Some(context) => context.to_owned(),
+                None => configuration::current_context()?,
+            };
+            bail!(ErrorKind::NotEncrypted(context));
+        }
+    }
+    let configuration = configuration::read(context)?;
+    let hooks = configuration::hooks(context)?;
+    dispatch(&matches, &configuration, &hooks)
 }
 
-fn cli<'a, 'b>(configuration: &Configuration) -> App<'a, 'b> {
+/// Runs the command in `inputs` on a demo profile with generated tasks (see
+/// `demo::configuration`).
+fn run_demo(inputs: &ArgMatches) -> Result<()> {
+    let count = parse::task_count(inputs.value_of("tasks").unwrap())?;
+    let seed = parse::seed(inputs.value_of("seed").unwrap())?;
+    let command: Vec<&str> = match inputs.values_of("command") {
+        Some(command) => command.collect(),
+        None => vec!["schedule"],
+    };
+    let matches = cli().get_matches_from(iter::once("eva").chain(command));
+    let name = matches.subcommand_name().unwrap_or_default();
+    if NOT_IN_DEMO.contains(&name) {
+        bail!(ErrorKind::NotInDemo(name.to_owned()));
+    }
+    let configuration = demo::configuration(count, seed)?;
+    // The hooks are left out, since they are meant for real tasks
+    dispatch(&matches, &configuration, &Hooks::default())
+}
+
+/// Returns the value of --context, which can be given before or after the subcommand.
+fn chosen_context<'a>(inputs: &'a ArgMatches) -> Option<&'a str> {
+    inputs.value_of("context").or_else(|| {
+        inputs.subcommand().1.and_then(chosen_context)
+    })
+}
+
+fn cli<'a, 'b>() -> App<'a, 'b> {
     let add = SubCommand::with_name("add")
         .about("Adds a task")
-        .arg(Arg::with_name("content").required(true)
+        .arg(Arg::with_name("content").required_unless("from")
              .help("What is it that you want to do?"))
-        .arg(Arg::with_name("deadline").required(true)
+        .arg(Arg::with_name("deadline").required_unless("from")
              .help("When should it be finished? \
                    Give it in the format of '2 Aug 2017 14:03'."))
-        .arg(Arg::with_name("duration").required(true)
+        .arg(Arg::with_name("duration").required_unless("from")
              .help("How long do you estimate it will take? \
-                   Give it in a (whole or decimal) number of hours."))
-        .arg(Arg::with_name("importance").required(true)
-             .help("How important is this task to you on a scale from 1 to 10?"));
+                   Give it in a (whole or decimal) number of hours, or give 'estimate' to get \
+                   a proposal based on similar tasks you completed."))
+        .arg(Arg::with_name("importance").required_unless("from")
+             .help("How important is this task to you on a scale from 1 to 10?"))
+        .arg(Arg::with_name("from").long("from").takes_value(true)
+             .help("The template to fill in the task from (see `eva template`). What you give \
+                   yourself overrides it."))
+        .arg(Arg::with_name("tags").long("tags").takes_value(true)
+             .help("Comma-separated tags to group this task with others, e.g. 'errand,home'."))
+        .arg(Arg::with_name("auto-tags").long("auto-tags")
+             .help("Adds the tags Eva suggests when it's quite sure about them, instead of \
+                   asking which ones to add. Useful when adding tasks from a script."))
+        .arg(start_after_arg())
+        .arg(remind_arg())
+        .arg(buffer_arg())
+        .arg(horizon_arg())
+        .arg(daily_minimum_arg())
+        .arg(window_arg())
+        .arg(assignee_arg())
+        .arg(attach_arg());
+    let template = SubCommand::with_name("template")
+        .about("Keeps templates to add tasks that come back often with `eva add --from`")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("add")
+                    .about("Adds a template, or replaces the one with the same name")
+                    .arg(Arg::with_name("name").required(true))
+                    .arg(Arg::with_name("content").long("content").takes_value(true)
+                         .help("What the tasks are about. '{date}', '{week}', '{month}' and \
+                               '{year}' are replaced by the day, the week number, the month and \
+                               the year the task is added, e.g. 'Review week {week}'."))
+                    .arg(Arg::with_name("due-in").long("due-in").takes_value(true)
+                         .help("How long after they are added the tasks are due, e.g. '2d'."))
+                    .arg(Arg::with_name("duration").long("duration").takes_value(true)
+                         .help("How long the tasks take, e.g. '1.5', '90m' or '2d'."))
+                    .arg(Arg::with_name("importance").long("importance").takes_value(true)
+                         .help("How important the tasks are on a scale from 1 to 10."))
+                    .arg(Arg::with_name("tags").long("tags").takes_value(true)
+                         .help("Comma-separated tags of the tasks, e.g. 'errand,home'."))
+                    .arg(remind_arg().help("How long before the tasks are scheduled to remind \
+                                           you of them, e.g. '1d' and '1h'."))
+                    .arg(buffer_arg())
+                    .arg(horizon_arg())
+                    .arg(daily_minimum_arg()))
+        .subcommand(SubCommand::with_name("list")
+                    .about("Lists all templates"))
+        .subcommand(SubCommand::with_name("rm")
+                    .about("Removes a template")
+                    .arg(Arg::with_name("name").required(true)));
+    let done = SubCommand::with_name("done")
+        .about("Marks a task as completed")
+        .arg(Arg::with_name("task-id").required(true))
+        .arg(Arg::with_name("spent").long("spent").takes_value(true)
+             .help("How much time did it take? E.g. '1.5', '90m' or '2d'. This is compared with \
+                   your estimate in `eva stats`. Without it, the time you tracked with \
+                   `eva start` counts."));
+    let start = SubCommand::with_name("start")
+        .about("Starts working on a task, until you stop or start working on another one")
+        .arg(Arg::with_name("task-id").required(true));
+    let stop = SubCommand::with_name("stop")
+        .about("Stops working on the task you started, adding the time to the time spent on it");
+    let status = SubCommand::with_name("status")
+        .about("Shows what you're working on, next to what the accepted schedule planned for now");
     let rm = SubCommand::with_name("rm")
         .about("Removes a task")
         .arg(Arg::with_name("task-id").required(true));
@@ -65,41 +284,510 @@ fn cli<'a, 'b>(configuration: &Configuration) -> App<'a, 'b> {
              .possible_values(&["content", "deadline", "duration", "importance"]))
         .arg(Arg::with_name("task-id").required(true))
         .arg(Arg::with_name("value").required(true));
+    let edit = SubCommand::with_name("edit")
+        .about("Changes the deadline, duration, importance or tags of several tasks at once")
+        .arg(selection_arg())
+        .arg(Arg::with_name("deadline").long("deadline").takes_value(true)
+             .help("The new deadline, in the format of '2 Aug 2017 14:03'."))
+        .arg(Arg::with_name("duration").long("duration").takes_value(true)
+             .help("The new duration, e.g. '1.5', '90m' or '2d'."))
+        .arg(Arg::with_name("importance").long("importance").takes_value(true)
+             .help("The new importance on a scale from 1 to 10."))
+        .arg(start_after_arg())
+        .arg(buffer_arg())
+        .arg(horizon_arg())
+        .arg(daily_minimum_arg())
+        .arg(window_arg())
+        .arg(assignee_arg())
+        .arg(remind_arg().help("Replaces the reminders of the tasks, e.g. '1d' and '1h' to be \
+                               reminded 1 day and 1 hour before they are scheduled."))
+        .arg(Arg::with_name("tag").long("tag").takes_value(true)
+             .multiple(true).number_of_values(1)
+             .help("Adds a tag to the tasks."))
+        .arg(Arg::with_name("untag").long("untag").takes_value(true)
+             .multiple(true).number_of_values(1)
+             .help("Removes a tag from the tasks."))
+        .arg(attach_arg())
+        .arg(Arg::with_name("detach").long("detach").takes_value(true)
+             .multiple(true).number_of_values(1)
+             .help("Removes an attachment from the tasks, given like it was attached."));
+    let postpone = SubCommand::with_name("postpone")
+        .about("Moves the deadline of one or more tasks")
+        .arg(selection_arg())
+        .arg(Arg::with_name("by").long("by").takes_value(true)
+             .required_unless("to").conflicts_with("to")
+             .help("How much later should the deadline be? E.g. '5h', '2d' or '1w'."))
+        .arg(Arg::with_name("to").long("to").takes_value(true)
+             .help("The new deadline, in the format of '2 Aug 2017 14:03'."));
+    let snooze = SubCommand::with_name("snooze")
+        .about("Keeps one or more tasks out of the schedule for a while, without changing their \
+               deadline")
+        .arg(selection_arg())
+        .arg(Arg::with_name("until").long("until").takes_value(true)
+             .conflicts_with_all(&["until-at", "until-done"])
+             .help("Until when should the tasks wait, in the format of '2 Aug 2017 14:03'? \
+                   Defaults to the start of tomorrow."))
+        .arg(Arg::with_name("until-at").long("until-at").takes_value(true)
+             .conflicts_with("until-done")
+             .help("Makes the tasks wait until you are at a place, e.g. '@office'. \
+                   `eva place set` tells Eva where you are."))
+        .arg(Arg::with_name("until-done").long("until-done").takes_value(true)
+             .value_name("task-id")
+             .help("Makes the tasks wait until another task is done."));
+    let bump = SubCommand::with_name("bump")
+        .about("Makes one or more tasks more (or less) important")
+        .arg(selection_arg())
+        .arg(Arg::with_name("importance").long("importance").takes_value(true).required(true)
+             .allow_hyphen_values(true)
+             .help("How much should the importance change? E.g. '+2' or '-1'. It stays on the \
+                   scale from 1 to 10."));
+    let open = SubCommand::with_name("open")
+        .about("Opens the files and links attached to a task")
+        .arg(Arg::with_name("task-id").required(true))
+        .arg(Arg::with_name("attachment")
+             .help("The number of the attachment to open, as listed by `eva tasks`. Without it, \
+                   all attachments of the task are opened."));
+    let required = SubCommand::with_name("required")
+        .about("Tells how many hours per week a task needs to be finished before its deadline")
+        .arg(Arg::with_name("task-id").required(true));
+    let explain = SubCommand::with_name("explain")
+        .about("Tells why a task is where it is in the accepted schedule")
+        .arg(Arg::with_name("task-id").required(true));
     let list = SubCommand::with_name("tasks")
-        .about("Lists your tasks in the order you added them");
+        .about("Lists your tasks in the order you added them")
+        .arg(assignee_filter_arg());
+    let history = SubCommand::with_name("history")
+        .about("Lists the tasks you completed, which are kept apart from the others in an archive")
+        .arg(Arg::with_name("since").long("since").takes_value(true)
+             .help("Only lists the tasks that were completed on or after this day, e.g. \
+                   '2024-01-01'."))
+        .arg(Arg::with_name("tag").long("tag").takes_value(true)
+             .help("Only lists the tasks with this tag, e.g. 'work'."));
+    let stats = SubCommand::with_name("stats")
+        .about("Shows how your accepted schedules worked out, how good your estimates were and \
+               how much of their weekly budget your tags use");
+    let insights = SubCommand::with_name("insights")
+        .about("Looks for patterns in a month of your history, like the days on which you plan \
+               too much, the tags you estimate worst and the tasks that keep slipping")
+        .arg(Arg::with_name("month")
+             .long("month")
+             .takes_value(true)
+             .help("The month to look at, like '2019-03'. Defaults to the current month."));
     let schedule = SubCommand::with_name("schedule")
         .about("Lets Eva suggest a schedule for your tasks")
-        .arg(Arg::with_name("strategy")
-             .long("strategy")
+        .arg(strategy_arg())
+        .arg(Arg::with_name("fair")
+             .long("fair")
+             .help("When there isn't enough time for everything, leaves out tasks so that the \
+                   projects in your configuration share the time according to their weights, \
+                   instead of failing."))
+        .arg(Arg::with_name("propose")
+             .long("propose")
+             .conflicts_with_all(&["fair", "accepted"])
+             .help("Stores the new schedule as a proposal, which only replaces the schedule you \
+                   committed to once you accept it with `eva accept`."))
+        .arg(Arg::with_name("accepted")
+             .long("accepted")
+             .conflicts_with("fair")
+             .help("Shows the schedule you accepted last instead of making a new one."))
+        .arg(Arg::with_name("sessions")
+             .long("sessions")
              .takes_value(true)
-             .possible_values(&["importance", "urgency"])
-             .default_value(configuration.scheduling_strategy.as_str()));
+             .min_values(0)
+             .max_values(1)
+             .value_name("lengths")
+             .help("Slices the schedule into focus sessions with breaks after them, e.g. '25/5' \
+                   for 25 minutes of focus and 5 minute breaks or '50/10'. Without lengths, \
+                   the focus_sessions in your configuration or 25/5 are used."))
+        .arg(assignee_filter_arg());
+    let accept = SubCommand::with_name("accept")
+        .about("Accepts the proposed schedule, making it the one you are committed to");
+    let note = SubCommand::with_name("note")
+        .about("Puts a note on a slot of a task in the accepted schedule, like where to do it")
+        .arg(Arg::with_name("task-id").required(true))
+        .arg(Arg::with_name("note").required_unless("clear")
+             .help("The note, e.g. 'At the library'."))
+        .arg(Arg::with_name("at").long("at").takes_value(true)
+             .help("When the slot starts, in the format of '2 Aug 2017 14:03'. Defaults to the \
+                   first slot of the task that hasn't ended yet."))
+        .arg(Arg::with_name("clear").long("clear").conflicts_with("note")
+             .help("Removes the note from the slot instead."));
+    let rollover = SubCommand::with_name("rollover")
+        .about("Closes off the day: the tasks that were scheduled to be done by now but aren't are \
+               marked as slipped, and a new schedule for them and the others is accepted")
+        .arg(strategy_arg());
+    let daemon = SubCommand::with_name("daemon")
+        .about("Rolls over the day every day at the rollover_time in your configuration, and \
+               sends a summary to your notify_command")
+        .arg(strategy_arg());
+    let export = SubCommand::with_name("export")
+        .about("Exports your tasks and the schedule you accepted to another format")
+        .arg(Arg::with_name("format").required_unless("list")
+             .help("The format to export to, e.g. 'json', or 'ics' for a calendar. Other formats \
+                   are exported by programs called eva-export-<format> on your PATH, which get \
+                   the tasks and the schedule in JSON on their standard input and write the \
+                   result to their standard output."))
+        .arg(Arg::with_name("output").long("output").short("o").takes_value(true)
+             .help("The file to write to. Defaults to the standard output."))
+        .arg(Arg::with_name("list").long("list").conflicts_with("format")
+             .help("Lists the formats you can export to."));
+
+    let context = SubCommand::with_name("context")
+        .about("Lists or switches between contexts, which each have their own tasks")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("list")
+                    .about("Lists all contexts, marking the current one with an asterisk"))
+        .subcommand(SubCommand::with_name("switch")
+                    .about("Makes another context the current one")
+                    .arg(Arg::with_name("name").required(true)));
+
+    let tz = SubCommand::with_name("tz")
+        .about("Shows and reads dates and times in another timezone for a while, e.g. when \
+               travelling")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("set")
+                    .about("Uses another timezone instead of the local one")
+                    .arg(Arg::with_name("zone").required(true)
+                         .help("The name of the timezone, e.g. 'Europe/Lisbon'."))
+                    .arg(Arg::with_name("until").long("until").takes_value(true)
+                         .help("When to go back to the local timezone, e.g. '2024-09-10'. \
+                               Without it, the timezone is used until you unset it.")))
+        .subcommand(SubCommand::with_name("unset")
+                    .about("Goes back to the local timezone"))
+        .subcommand(SubCommand::with_name("show")
+                    .about("Shows which timezone is used instead of the local one, if any"));
+
+    let place = SubCommand::with_name("place")
+        .about("Tells Eva where you are, for the tasks that are snoozed until you're there")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("set")
+                    .about("Says that you are at a place")
+                    .arg(Arg::with_name("place").required(true)
+                         .help("The name of the place, e.g. 'office' or '@office'.")))
+        .subcommand(SubCommand::with_name("unset")
+                    .about("Forgets where you are"))
+        .subcommand(SubCommand::with_name("show")
+                    .about("Shows where you are, if you said so"));
+
+    let holidays = SubCommand::with_name("holidays")
+        .about("Downloads public holidays, on which Eva doesn't schedule anything")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("fetch")
+                    .about("Downloads the public holidays of one or more countries")
+                    .arg(country_arg())
+                    .arg(Arg::with_name("year").long("year").takes_value(true).multiple(true)
+                         .number_of_values(1)
+                         .help("Which year to download the holidays of. Can be given more than \
+                               once. Defaults to this year and the next one.")))
+        .subcommand(SubCommand::with_name("list")
+                    .about("Lists the upcoming public holidays of every country you downloaded"))
+        .subcommand(SubCommand::with_name("forget")
+                    .about("Forgets the public holidays of one or more countries")
+                    .arg(country_arg()));
+
+    let off = SubCommand::with_name("off")
+        .about("Takes days off, e.g. for a holiday or a trip, on which Eva doesn't schedule \
+               anything")
+        .arg(Arg::with_name("days").required_unless("list")
+             .help("The days to take off, e.g. '2024-08-01..2024-08-14' (including both ends) or \
+                   '2024-08-01'."))
+        .arg(Arg::with_name("cancel").long("cancel")
+             .help("Cancels days off you took before, given the same way."))
+        .arg(Arg::with_name("list").long("list").conflicts_with_all(&["days", "cancel"])
+             .help("Lists the days off that haven't passed yet."));
+
+    let completions = SubCommand::with_name("completions")
+        .about("Prints a script that completes Eva's subcommands, options, task ids and tags in \
+               your shell")
+        .arg(Arg::with_name("shell").required(true)
+             .possible_values(&clap::Shell::variants())
+             .help("Task ids and tags are only completed in bash, zsh and fish."));
+    let porcelain = SubCommand::with_name("porcelain")
+        .about("Lists your tasks or tags in a stable format for scripts, like the shell \
+               completions")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("tasks")
+                    .about("Prints the id and the content of every pending task, separated by a \
+                           tab, one task per line"))
+        .subcommand(SubCommand::with_name("tags")
+                    .about("Prints the tags of the pending tasks, one per line, in alphabetical \
+                           order"));
+
+    let encrypt = SubCommand::with_name("encrypt")
+        .about("Encrypts everything that was stored before encryption was set up in your \
+               configuration. What's stored after that is encrypted anyway");
+
+    let demo = SubCommand::with_name("demo")
+        .about("Runs a command on generated tasks instead of yours, to try eva out. The tasks and \
+               whatever the command does with them are gone when it's done")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(Arg::with_name("tasks").long("tasks").takes_value(true).default_value("12")
+             .help("How many tasks to generate."))
+        .arg(Arg::with_name("seed").long("seed").takes_value(true).default_value("42")
+             .help("Which tasks to generate. The same seed always gives the same tasks, due \
+                   relative to now, so you can share what you ran into."))
+        .arg(Arg::with_name("command").multiple(true).allow_hyphen_values(true)
+             .help("The command to run, e.g. 'schedule --fair' or 'explain 3'. Defaults to \
+                   'schedule'."));
+
+    let schema = SubCommand::with_name("schema")
+        .about("Prints the JSON Schema of what `eva export json` and `eva serve` give, so other \
+               programs can check that they understand it");
+
+    let serve = SubCommand::with_name("serve")
+        .about("Serves your tasks and schedule as JSON over HTTP, for other programs to use")
+        .arg(Arg::with_name("address").long("address").takes_value(true)
+             .default_value("127.0.0.1:8080")
+             .help("Where to listen for requests. Only listen on other addresses than \
+                   localhost if you trust everyone who can reach them."))
+        .arg(Arg::with_name("threads").long("threads").takes_value(true)
+             .default_value("4")
+             .help("How many requests can be answered at the same time."));
 
     App::new("eva")
         .version(env!("CARGO_PKG_VERSION"))
         .global_setting(AppSettings::ColoredHelp)
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("plain")
+             .long("plain")
+             .global(true)
+             .help("Leaves out colors. Setting NO_COLOR does the same."))
+        .arg(Arg::with_name("context")
+             .long("context")
+             .takes_value(true)
+             .env("EVA_CONTEXT")
+             .global(true)
+             .help("Which context to use instead of the current one, e.g. 'work' or 'home'."))
         .subcommand(add)
+        .subcommand(template)
+        .subcommand(done)
+        .subcommand(start)
+        .subcommand(stop)
+        .subcommand(status)
         .subcommand(rm)
         .subcommand(set)
+        .subcommand(edit)
+        .subcommand(postpone)
+        .subcommand(snooze)
+        .subcommand(bump)
+        .subcommand(open)
+        .subcommand(required)
+        .subcommand(explain)
         .subcommand(list)
+        .subcommand(history)
+        .subcommand(stats)
+        .subcommand(insights)
         .subcommand(schedule)
+        .subcommand(accept)
+        .subcommand(note)
+        .subcommand(rollover)
+        .subcommand(daemon)
+        .subcommand(export)
+        .subcommand(context)
+        .subcommand(tz)
+        .subcommand(place)
+        .subcommand(holidays)
+        .subcommand(off)
+        .subcommand(serve)
+        .subcommand(encrypt)
+        .subcommand(demo)
+        .subcommand(schema)
+        .subcommand(completions)
+        .subcommand(porcelain)
+}
+
+fn country_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("country").long("country").takes_value(true).multiple(true)
+        .number_of_values(1).required(true)
+        .help("The two-letter code of the country, e.g. 'BE'. Can be given more than once, e.g. \
+              for a team that is spread over several countries.")
+}
+
+fn strategy_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("strategy").long("strategy").takes_value(true)
+        .possible_values(&["importance", "urgency", "optimal"])
+        .help("Defaults to the scheduling strategy in your configuration.")
+}
+
+fn selection_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("tasks").required(true).multiple(true)
+        .help("Ids of the tasks, or tags prefixed with a plus sign to select all tasks with that \
+              tag, e.g. '1 4 7' or '+errand'.")
+}
+
+fn start_after_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("start-after").long("start-after").takes_value(true)
+        .help("Don't schedule the task before this moment, in the format of '2 Aug 2017 14:03'.")
+}
+
+fn buffer_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("buffer").long("buffer").takes_value(true)
+        .help("How much time to keep free after the task, e.g. '15m'. Overrides the buffer in \
+              your configuration.")
 }
 
-fn dispatch(inputs: &ArgMatches, configuration: &Configuration) -> Result<()> {
+fn horizon_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("horizon").long("horizon").takes_value(true)
+        .help("How soon the work on the task has to start for it to be scheduled, e.g. '4w'. \
+              Until then, it's listed under 'Later'. Overrides the horizon in your \
+              configuration.")
+}
+
+fn daily_minimum_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("daily-minimum").long("daily-minimum").takes_value(true)
+        .help("How much of the task to do every day until it's done, e.g. '1h'. This is planned \
+              every day before the rest of your work is spread out.")
+}
+
+fn window_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("window").long("window").takes_value(true)
+        .help("When you would rather do the task, e.g. 'evenings', 'weekends', 'after 20:00' or \
+              'weekdays 09:00-17:00'. The schedule keeps to it when there is time.")
+}
+
+fn assignee_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("assignee").long("assignee").takes_value(true)
+        .help("Who does the task, e.g. 'alice'. Without one, the task goes to whoever of the \
+              assignees in your configuration has the least work and time for it.")
+}
+
+fn assignee_filter_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("assignee").long("assignee").takes_value(true)
+        .help("Only shows the tasks of this assignee, e.g. 'alice'.")
+}
+
+fn attach_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("attach").long("attach").takes_value(true).multiple(true).number_of_values(1)
+        .help("Attaches a file or a link to the task, e.g. '~/notes/plan.md' or \
+              'https://example.org'. Only the path or the URL is stored. Can be given more than \
+              once.")
+}
+
+fn remind_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("remind").long("remind").takes_value(true)
+        .multiple(true).number_of_values(1)
+        .help("How long before the task is scheduled to remind you of it, e.g. '1d' and '1h' to \
+              be reminded 1 day and 1 hour before. Overrides the reminders in your configuration.")
+}
+
+/// Tells whether --plain was given, before or after the subcommand.
+fn plain(inputs: &ArgMatches) -> bool {
+    inputs.is_present("plain") || inputs.subcommand().1.map_or(false, plain)
+}
+
+fn dispatch(inputs: &ArgMatches, configuration: &Configuration, hooks: &Hooks) -> Result<()> {
+    let agenda = agenda::Agenda {
+        now: configuration.now(),
+        color: agenda::use_color(plain(inputs)),
+    };
     match inputs.subcommand() {
         ("add", Some(submatches)) => {
-            let content = submatches.value_of("content").unwrap();
-            let deadline = submatches.value_of("deadline").unwrap();
-            let duration = submatches.value_of("duration").unwrap();
-            let importance = submatches.value_of("importance").unwrap();
+            let from = submatches.value_of("from");
+            let template = match from {
+                Some(name) => block_on(eva::template(configuration, name))?,
+                None => eva::Template::default(),
+            };
+            // Only reached with --from, because clap requires the fields otherwise
+            let missing = |field: &str| {
+                ErrorKind::NotInTemplate(field.to_owned(), template.name.clone())
+            };
+            let content = match (submatches.value_of("content"), &template.content) {
+                (Some(content), _) => content.to_owned(),
+                (None, Some(content)) => content.clone(),
+                (None, None) => bail!(missing("content")),
+            };
+            let content = match from {
+                Some(_) => {
+                    let today = timezone::to_local(configuration.now()).date().naive_local();
+                    eva::Template::fill_in(&content, today)
+                },
+                None => content,
+            };
+            let deadline = match (submatches.value_of("deadline"), template.due_in) {
+                (Some(deadline), _) => parse::deadline(deadline)?,
+                (None, Some(due_in)) => configuration.now().checked_add_signed(due_in)
+                    .ok_or_else(|| ErrorKind::OutOfRange("the deadline".to_owned()))?,
+                (None, None) => bail!(missing("deadline")),
+            };
+            let importance = match (submatches.value_of("importance"), template.importance) {
+                (Some(importance), _) => parse::importance(importance)?,
+                (None, Some(importance)) => importance,
+                (None, None) => bail!(missing("importance")),
+            };
+            let mut tags = match submatches.value_of("tags") {
+                Some(tags) => parse::tags(tags),
+                None => template.tags.clone(),
+            };
+            let suggestions = block_on(eva::suggest_tags(configuration, &content, &tags))?;
+            if submatches.is_present("auto-tags") {
+                tags.extend(suggestions.into_iter()
+                            .filter(|suggestion| suggestion.confidence >= AUTO_TAG_CONFIDENCE)
+                            .map(|suggestion| suggestion.tag));
+            } else if !suggestions.is_empty() && atty::is(atty::Stream::Stdin) {
+                // Scripts don't get tags they didn't ask for
+                tags.extend(confirm_tags(&suggestions)?);
+            }
+            let duration = match (submatches.value_of("duration"), template.duration) {
+                (Some("estimate"), _) => estimate_duration(configuration, &content, &tags)?,
+                (Some(duration), _) => parse::duration(duration)?,
+                (None, Some(duration)) => duration,
+                (None, None) => bail!(missing("duration")),
+            };
             let new_task = eva::NewTask {
-                content: content.to_owned(),
-                deadline: parse::deadline(deadline)?,
-                duration: parse::duration(duration)?,
-                importance: parse::importance(importance)?,
+                content: content,
+                deadline: deadline,
+                duration: duration,
+                importance: importance,
+                tags: tags,
+                start_after: submatches.value_of("start-after").map(parse::start_after).transpose()?,
+                reminders: reminders(submatches)?.unwrap_or_else(|| template.reminders.clone()),
+                buffer: submatches.value_of("buffer").map(parse::buffer).transpose()?
+                    .or(template.buffer),
+                horizon: submatches.value_of("horizon").map(parse::duration).transpose()?
+                    .or(template.horizon),
+                daily_minimum: submatches.value_of("daily-minimum").map(parse::duration)
+                    .transpose()?.or(template.daily_minimum),
+                preferred_window: submatches.value_of("window").map(parse::window).transpose()?,
+                assignee: submatches.value_of("assignee").map(str::to_owned),
+                attachments: submatches.values_of("attach").into_iter().flatten()
+                    .map(parse::attachment)
+                    .collect::<parse::Result<_>>()?,
             };
-            let _task = block_on(eva::add(configuration, new_task))?;
+            let task = block_on(eva::add(configuration, new_task))?;
+            hooks.added(&task);
+            Ok(())
+        },
+        ("template", Some(submatches)) => manage_templates(configuration, submatches),
+        ("encrypt", Some(_submatches)) => {
+            Ok(progress::while_storing("Encrypting", || {
+                block_on(eva::store_everything_again(configuration))
+            })?)
+        },
+        ("done", Some(submatches)) => {
+            let id = parse::id(submatches.value_of("task-id").unwrap())?;
+            let time_spent = submatches.value_of("spent").map(parse::duration).transpose()?;
+            let task = block_on(eva::complete(configuration, id, time_spent))?;
+            hooks.completed(&task);
+            Ok(())
+        },
+        ("start", Some(submatches)) => {
+            let id = parse::id(submatches.value_of("task-id").unwrap())?;
+            if let Some(stopped) = block_on(eva::start(configuration, id))? {
+                let task = block_on(eva::get(configuration, stopped.task_id))?;
+                println!("Stopped working on {}. {} after {}.",
+                         task.id, task, stopped.duration(configuration.now()).pretty_print());
+            }
+            Ok(())
+        },
+        ("stop", Some(_submatches)) => {
+            let stopped = block_on(eva::stop(configuration))?.ok_or(ErrorKind::NotWorking)?;
+            let task = block_on(eva::get(configuration, stopped.task_id))?;
+            println!("Worked on {}. {} for {}.",
+                     task.id, task, stopped.duration(configuration.now()).pretty_print());
+            Ok(())
+        },
+        ("status", Some(_submatches)) => {
+            println!("{}", block_on(eva::status(configuration))?.pretty_print());
             Ok(())
         },
         ("rm", Some(submatches)) => {
@@ -114,25 +802,659 @@ fn dispatch(inputs: &ArgMatches, configuration: &Configuration) -> Result<()> {
             let id = parse::id(id)?;
             Ok(set_field(configuration, field, id, value)?)
         },
-        ("tasks", Some(_submatches)) => {
+        ("edit", Some(submatches)) => {
+            let ids = select_ids(configuration, submatches.values_of("tasks").unwrap())?;
+            let deadline = submatches.value_of("deadline").map(parse::deadline).transpose()?;
+            let duration = submatches.value_of("duration").map(parse::duration).transpose()?;
+            let importance = submatches.value_of("importance").map(parse::importance).transpose()?;
+            let start_after = submatches.value_of("start-after").map(parse::start_after)
+                .transpose()?;
+            let buffer = submatches.value_of("buffer").map(parse::buffer).transpose()?;
+            let horizon = submatches.value_of("horizon").map(parse::duration).transpose()?;
+            let daily_minimum = submatches.value_of("daily-minimum").map(parse::duration)
+                .transpose()?;
+            let window = submatches.value_of("window").map(parse::window).transpose()?;
+            let assignee = submatches.value_of("assignee");
+            let reminders = reminders(submatches)?;
+            let tags = submatches.values_of("tag").into_iter().flatten().map(parse::tag)
+                .collect::<parse::Result<Vec<_>>>()?;
+            let untags: Vec<&str> = submatches.values_of("untag").into_iter().flatten().collect();
+            let attachments = submatches.values_of("attach").into_iter().flatten()
+                .map(parse::attachment)
+                .collect::<parse::Result<Vec<_>>>()?;
+            // What was attached by a relative path is stored as an absolute one
+            let detachments = submatches.values_of("detach").into_iter().flatten()
+                .map(|attachment| Ok((attachment, parse::attachment(attachment)?)))
+                .collect::<parse::Result<Vec<_>>>()?;
+            block_on(eva::modify_all(configuration, ids, |task| {
+                if let Some(deadline) = deadline {
+                    task.deadline = deadline;
+                }
+                if let Some(duration) = duration {
+                    task.duration = duration;
+                }
+                if let Some(importance) = importance {
+                    task.importance = importance;
+                }
+                if let Some(start_after) = start_after {
+                    task.start_after = Some(start_after);
+                }
+                if let Some(buffer) = buffer {
+                    task.buffer = Some(buffer);
+                }
+                if let Some(horizon) = horizon {
+                    task.horizon = Some(horizon);
+                }
+                if let Some(daily_minimum) = daily_minimum {
+                    task.daily_minimum = Some(daily_minimum);
+                }
+                if let Some(window) = window {
+                    task.preferred_window = Some(window);
+                }
+                if let Some(assignee) = assignee {
+                    task.assignee = Some(assignee.to_owned());
+                }
+                if let Some(reminders) = &reminders {
+                    task.reminders = reminders.clone();
+                }
+                for tag in &tags {
+                    if !task.has_tag(tag) {
+                        task.tags.push(tag.clone());
+                    }
+                }
+                task.tags.retain(|own_tag| !untags.contains(&own_tag.as_str()));
+                for attachment in &attachments {
+                    if !task.attachments.contains(attachment) {
+                        task.attachments.push(attachment.clone());
+                    }
+                }
+                for (attachment, absolute) in &detachments {
+                    task.attachments.retain(|own| own != attachment && own != absolute);
+                }
+                Ok(())
+            }))?;
+            Ok(())
+        },
+        ("open", Some(submatches)) => {
+            let id = parse::id(submatches.value_of("task-id").unwrap())?;
+            let task = block_on(eva::get(configuration, id))?;
+            let attachments = match submatches.value_of("attachment") {
+                Some(number) => {
+                    let number = parse::attachment_number(number)?;
+                    let attachment = number.checked_sub(1)
+                        .and_then(|index| task.attachments.get(index))
+                        .ok_or_else(|| ErrorKind::NoAttachment(id, Some(number)))?;
+                    vec![attachment.clone()]
+                },
+                None => task.attachments,
+            };
+            ensure!(!attachments.is_empty(), ErrorKind::NoAttachment(id, None));
+            for attachment in attachments {
+                attachments::open(&attachment)?;
+            }
+            Ok(())
+        },
+        ("postpone", Some(submatches)) => {
+            let ids = select_ids(configuration, submatches.values_of("tasks").unwrap())?;
+            if let Some(deadline) = submatches.value_of("to") {
+                let deadline = parse::deadline(deadline)?;
+                block_on(eva::modify_all(configuration, ids, |task| {
+                    task.deadline = deadline;
+                    Ok(())
+                }))?;
+            } else {
+                let delay = parse::duration(submatches.value_of("by").unwrap())?;
+                block_on(eva::modify_all(configuration, ids, |task| {
+                    task.deadline = task.deadline.checked_add_signed(delay)
+                        .ok_or_else(|| {
+                            ErrorKind::OutOfRange(format!("the new deadline of {}", task))
+                                .to_string()
+                        })?;
+                    Ok(())
+                }))?;
+            }
+            Ok(())
+        },
+        ("snooze", Some(submatches)) => {
+            let ids = select_ids(configuration, submatches.values_of("tasks").unwrap())?;
+            let trigger = if let Some(place) = submatches.value_of("until-at") {
+                Some(eva::Trigger::At(parse::place(place)?))
+            } else if let Some(id) = submatches.value_of("until-done") {
+                let id = parse::id(id)?;
+                ensure!(!ids.contains(&id), ErrorKind::SnoozedUntilItself(id));
+                // Look the task up, so that a wrong id isn't a trigger that goes off right away
+                block_on(eva::get(configuration, id))?;
+                Some(eva::Trigger::Done(id))
+            } else {
+                None
+            };
+            match trigger {
+                Some(trigger) => {
+                    block_on(eva::modify_all(configuration, ids, |task| {
+                        task.waiting_for = Some(trigger.clone());
+                        Ok(())
+                    }))?;
+                },
+                None => {
+                    let until = match submatches.value_of("until") {
+                        Some(until) => parse::start_after(until)?,
+                        None => timezone::start_of_tomorrow(),
+                    };
+                    block_on(eva::modify_all(configuration, ids, |task| {
+                        task.start_after = Some(until);
+                        Ok(())
+                    }))?;
+                },
+            }
+            Ok(())
+        },
+        ("bump", Some(submatches)) => {
+            let ids = select_ids(configuration, submatches.values_of("tasks").unwrap())?;
+            let delta = parse::importance_delta(submatches.value_of("importance").unwrap())?;
+            block_on(eva::modify_all(configuration, ids, |task| {
+                // The importance is read in the same transaction, so no other bump gets lost
+                let importance = i64::from(task.importance).saturating_add(delta);
+                task.importance = min(max(1, importance), 10) as u32;
+                Ok(())
+            }))?;
+            Ok(())
+        },
+        ("required", Some(submatches)) => {
+            let id = submatches.value_of("task-id").unwrap();
+            let id = parse::id(id)?;
+            let pace = block_on(eva::required_pace(configuration, id))?;
+            println!("{}", pace.pretty_print());
+            Ok(())
+        },
+        ("explain", Some(submatches)) => {
+            let id = parse::id(submatches.value_of("task-id").unwrap())?;
+            let explanations = block_on(eva::explain(configuration, id))?;
+            println!("{}", explanations.iter().map(PrettyPrint::pretty_print).join("\n"));
+            Ok(())
+        },
+        ("accept", Some(_submatches)) => {
+            let schedule = progress::while_running("Scheduling", || {
+                block_on(eva::accept_schedule(configuration))
+            })?;
+            hooks.scheduled(&schedule);
+            println!("{}", agenda.render(&schedule));
+            Ok(())
+        },
+        ("note", Some(submatches)) => {
+            let id = parse::id(submatches.value_of("task-id").unwrap())?;
+            let when = submatches.value_of("at").map(parse::slot_start).transpose()?;
+            let note = submatches.value_of("note").map(str::to_owned);
+            let slot = block_on(eva::annotate(configuration, id, when, note))?;
+            println!("{}", agenda.render(&eva::Schedule(vec![slot])));
+            Ok(())
+        },
+        ("rollover", Some(submatches)) => {
+            let strategy = submatches.value_of("strategy")
+                .unwrap_or_else(|| configuration.scheduling_strategy.as_str());
+            let rollover = progress::while_storing("Rolling over", || {
+                block_on(eva::roll_over(configuration, strategy))
+            })?;
+            hooks.scheduled(&rollover.schedule);
+            println!("{}", daemon::summary(&rollover, &agenda));
+            Ok(())
+        },
+        ("export", Some(submatches)) => {
+            if submatches.is_present("list") {
+                for format in export::formats() {
+                    println!("{}", format);
+                }
+                return Ok(());
+            }
+            let format = submatches.value_of("format").unwrap();
+            let exporter = export::exporter(format, configuration)?;
             let tasks = block_on(eva::all(configuration))?;
+            let schedule = block_on(eva::accepted_schedule(configuration))?;
+            let sessions = block_on(eva::sessions(configuration))?;
+            let focus_sessions = match (&schedule, configuration.focus_sessions) {
+                (Some(schedule), Some(settings)) => {
+                    Some(schedule.focus_sessions(settings,
+                                                 |assignee| configuration.unavailable_to(assignee)))
+                },
+                _ => None,
+            };
+            let document = json::document(&tasks, schedule.as_ref(), &sessions,
+                                          focus_sessions.as_ref().map(Vec::as_slice));
+            let exported = exporter.export(&document)?;
+            match submatches.value_of("output") {
+                Some(path) => std::fs::write(path, exported)?,
+                None => io::stdout().write_all(&exported)?,
+            }
+            Ok(())
+        },
+        ("tasks", Some(submatches)) => {
+            let tasks = block_on(eva::all(configuration))?;
+            let assignee = submatches.value_of("assignee");
             println!("Tasks:");
-            for task in &tasks {
+            let assigned = |task: &&eva::Task| {
+                assignee.map_or(true, |assignee| task.assignee.as_ref().map_or(false, |own| {
+                    own == assignee
+                }))
+            };
+            for task in tasks.iter().filter(assigned) {
                 // Indent all lines of task.pretty_print() by two spaces
                 println!("  {}", task.pretty_print().split("\n").join("\n  "));
             }
             Ok(())
         },
+        ("history", Some(submatches)) => {
+            let query = eva::HistoryQuery {
+                since: submatches.value_of("since").map(parse::date).transpose()?
+                    .map(|since| timezone::day(since).start),
+                tag: submatches.value_of("tag").map(str::to_owned),
+            };
+            let tasks = block_on(eva::history(configuration, query))?;
+            if tasks.is_empty() {
+                println!("You haven't completed any tasks like that.");
+                return Ok(());
+            }
+            println!("Completed:");
+            for task in &tasks {
+                println!("  {}", task.pretty_print().split("\n").join("\n  "));
+            }
+            Ok(())
+        },
+        ("stats", Some(_submatches)) => {
+            let (statistics, budget_uses) = progress::while_running("Counting", || {
+                let statistics = block_on(eva::statistics(configuration))?;
+                let budget_uses = block_on(eva::budget_use(configuration))?;
+                Ok::<_, eva::Error>((statistics, budget_uses))
+            })?;
+            println!("{}", statistics.pretty_print());
+            if !budget_uses.is_empty() {
+                println!("\nBudgets this week:\n  {}",
+                         budget_uses.iter().map(PrettyPrint::pretty_print).join("\n  "));
+            }
+            Ok(())
+        },
+        ("insights", Some(submatches)) => {
+            let month = match submatches.value_of("month") {
+                Some(month) => parse::month(month)?,
+                None => configuration.now().date(),
+            };
+            let insights = progress::while_running("Looking for patterns", || {
+                block_on(eva::insights(configuration, month))
+            })?;
+            println!("{}", insights.pretty_print());
+            Ok(())
+        },
         ("schedule", Some(submatches)) => {
-            let strategy = submatches.value_of("strategy").unwrap().to_owned();
-            let schedule = block_on(eva::schedule(configuration, &strategy))?;
-            println!("{}", schedule.pretty_print());
+            let strategy = submatches.value_of("strategy")
+                .unwrap_or_else(|| configuration.scheduling_strategy.as_str())
+                .to_owned();
+            let focus_sessions = match submatches.value_of("sessions") {
+                Some(lengths) => Some(parse::focus_sessions(lengths)?),
+                None if submatches.is_present("sessions") => {
+                    Some(configuration.focus_sessions.unwrap_or_default())
+                },
+                None => configuration.focus_sessions,
+            };
+            let assignee = submatches.value_of("assignee");
+            let render = |schedule: &eva::Schedule| {
+                let schedule = match assignee {
+                    Some(assignee) => schedule.assigned_to(Some(assignee)),
+                    None => schedule.clone(),
+                };
+                match focus_sessions {
+                    Some(settings) => {
+                        let sessions = schedule.focus_sessions(
+                            settings, |assignee| configuration.unavailable_to(assignee));
+                        agenda.render_sessions(&sessions)
+                    },
+                    None => agenda.render(&schedule),
+                }
+            };
+            if submatches.is_present("accepted") {
+                match block_on(eva::accepted_schedule(configuration))? {
+                    Some(schedule) => println!("{}", render(&schedule)),
+                    None => println!("You haven't accepted a schedule yet."),
+                }
+            } else if submatches.is_present("propose") {
+                let schedule = progress::while_running("Scheduling", || {
+                    block_on(eva::propose_schedule(configuration, &strategy))
+                })?;
+                println!("{}", render(&schedule));
+                print_later(configuration)?;
+                print_stuck(configuration)?;
+                println!("\nThis is only a proposal. Run `eva accept` to commit to it.");
+            } else if submatches.is_present("fair") {
+                let fair_schedule = progress::while_running("Scheduling", || {
+                    block_on(eva::schedule_fairly(configuration, &strategy))
+                })?;
+                println!("{}", render(&fair_schedule.schedule));
+                let shortfalls = fair_schedule.shortfalls.iter()
+                    .filter(|shortfall| !shortfall.dropped.is_empty())
+                    .map(PrettyPrint::pretty_print)
+                    .join("\n  ");
+                if !shortfalls.is_empty() {
+                    println!("\nThere wasn't enough time for everything:\n  {}", shortfalls);
+                }
+                print_later(configuration)?;
+                print_stuck(configuration)?;
+            } else {
+                let schedule = progress::while_running("Scheduling", || {
+                    block_on(eva::schedule(configuration, &strategy))
+                })?;
+                println!("{}", render(&schedule));
+                print_later(configuration)?;
+                print_stuck(configuration)?;
+            }
             Ok(())
         },
+        ("porcelain", Some(submatches)) => porcelain(configuration, submatches),
         _ => unreachable!(),
     }
 }
 
+/// Lists the tasks that were left out of the schedule because they can wait until after their
+/// horizon, if there are any.
+fn print_later(configuration: &Configuration) -> Result<()> {
+    let tasks = block_on(eva::deferred(configuration))?;
+    if !tasks.is_empty() {
+        println!("\nLater:");
+        for task in &tasks {
+            println!("  {}", task.pretty_print().split("\n").join("\n  "));
+        }
+    }
+    Ok(())
+}
+
+/// Tells why the snoozed tasks that wait for others can't be done in time, if there are any.
+fn print_stuck(configuration: &Configuration) -> Result<()> {
+    let errors = block_on(eva::stuck(configuration))?;
+    if !errors.is_empty() {
+        println!("\n{}", daemon::stuck(&errors));
+    }
+    Ok(())
+}
+
+/// Prints tasks or tags in a format that scripts can rely on. Only add to it in a way that
+/// existing scripts won't notice.
+fn porcelain(configuration: &Configuration, inputs: &ArgMatches) -> Result<()> {
+    let tasks = block_on(eva::all(configuration))?;
+    match inputs.subcommand() {
+        ("tasks", Some(_submatches)) => {
+            for task in &tasks {
+                // Keep every task on one line
+                let content = task.content.replace(|c: char| c == '\t' || c == '\n', " ");
+                println!("{}\t{}", task.id, content);
+            }
+        },
+        ("tags", Some(_submatches)) => {
+            let tags: BTreeSet<&str> = tasks.iter()
+                .flat_map(|task| &task.tags)
+                .map(String::as_str)
+                .collect();
+            for tag in tags {
+                println!("{}", tag);
+            }
+        },
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn manage_templates(configuration: &Configuration, inputs: &ArgMatches) -> Result<()> {
+    match inputs.subcommand() {
+        ("add", Some(submatches)) => {
+            let template = eva::Template {
+                name: submatches.value_of("name").unwrap().to_owned(),
+                content: submatches.value_of("content").map(str::to_owned),
+                due_in: submatches.value_of("due-in").map(parse::duration).transpose()?,
+                duration: submatches.value_of("duration").map(parse::duration).transpose()?,
+                importance: submatches.value_of("importance").map(parse::importance)
+                    .transpose()?,
+                tags: submatches.value_of("tags").map(parse::tags).unwrap_or_default(),
+                reminders: reminders(submatches)?.unwrap_or_default(),
+                buffer: submatches.value_of("buffer").map(parse::buffer).transpose()?,
+                horizon: submatches.value_of("horizon").map(parse::duration).transpose()?,
+                daily_minimum: submatches.value_of("daily-minimum").map(parse::duration)
+                    .transpose()?,
+            };
+            Ok(block_on(eva::save_template(configuration, template))?)
+        },
+        ("list", Some(_submatches)) => {
+            for template in block_on(eva::templates(configuration))? {
+                println!("{}", template.pretty_print());
+            }
+            Ok(())
+        },
+        ("rm", Some(submatches)) => {
+            let name = submatches.value_of("name").unwrap();
+            Ok(block_on(eva::remove_template(configuration, name))?)
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn manage_contexts(inputs: &ArgMatches, chosen_context: Option<&str>) -> Result<()> {
+    match inputs.subcommand() {
+        ("list", Some(_submatches)) => {
+            let current_context = match chosen_context {
+                Some(context) => context.to_owned(),
+                None => configuration::current_context()?,
+            };
+            for context in configuration::contexts()? {
+                let marker = if context == current_context { "*" } else { " " };
+                println!("{} {}", marker, context);
+            }
+            Ok(())
+        },
+        ("switch", Some(submatches)) => {
+            let name = parse::context(submatches.value_of("name").unwrap())?;
+            Ok(configuration::switch_context(name)?)
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn manage_timezone(inputs: &ArgMatches) -> Result<()> {
+    match inputs.subcommand() {
+        ("set", Some(submatches)) => {
+            let timezone_override = configuration::TimezoneOverride {
+                zone: parse::timezone(submatches.value_of("zone").unwrap())?,
+                until: submatches.value_of("until").map(parse::date).transpose()?,
+            };
+            Ok(configuration::override_timezone(&timezone_override)?)
+        },
+        ("unset", Some(_submatches)) => Ok(configuration::remove_timezone_override()?),
+        ("show", Some(_submatches)) => {
+            match configuration::timezone_override()? {
+                Some(configuration::TimezoneOverride { zone, until: Some(until) }) => {
+                    println!("{} until {}", zone.name(), until.format("%a %-d %b %Y"))
+                },
+                Some(configuration::TimezoneOverride { zone, until: None }) => {
+                    println!("{}", zone.name())
+                },
+                None => println!("The local timezone"),
+            }
+            Ok(())
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn manage_place(inputs: &ArgMatches) -> Result<()> {
+    match inputs.subcommand() {
+        ("set", Some(submatches)) => {
+            let place = parse::place(submatches.value_of("place").unwrap())?;
+            Ok(configuration::set_place(&place)?)
+        },
+        ("unset", Some(_submatches)) => Ok(configuration::clear_place()?),
+        ("show", Some(_submatches)) => {
+            match configuration::place()? {
+                Some(place) => println!("At {}", place),
+                None => println!("Nowhere in particular"),
+            }
+            Ok(())
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn manage_holidays(inputs: &ArgMatches) -> Result<()> {
+    match inputs.subcommand() {
+        ("fetch", Some(submatches)) => {
+            let years = match submatches.values_of("year") {
+                Some(years) => years.map(parse::year).collect::<parse::Result<Vec<_>>>()?,
+                None => {
+                    let this_year = timezone::to_local(chrono::Utc::now()).year();
+                    vec![this_year, this_year + 1]
+                },
+            };
+            for country in submatches.values_of("country").unwrap() {
+                let country = parse::country(country)?;
+                let message = format!("Downloading the public holidays of {}", country);
+                let dates = progress::while_running(&message, || {
+                    let mut dates = vec![];
+                    for &year in &years {
+                        dates.extend(holidays::fetch(&country, year)?);
+                    }
+                    Ok::<_, holidays::Error>(dates)
+                })?;
+                configuration::add_holidays(&country, &dates)?;
+                println!("Downloaded {} public holidays of {}", dates.len(), country);
+            }
+            Ok(())
+        },
+        ("list", Some(_submatches)) => {
+            let today = timezone::to_local(chrono::Utc::now()).date().naive_local();
+            for (country, dates) in configuration::holidays()? {
+                println!("{}:", country);
+                for date in dates.iter().filter(|&&date| today <= date) {
+                    println!("  {}", date.format("%a %-d %b %Y"));
+                }
+            }
+            Ok(())
+        },
+        ("forget", Some(submatches)) => {
+            for country in submatches.values_of("country").unwrap() {
+                configuration::forget_holidays(&parse::country(country)?)?;
+            }
+            Ok(())
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn manage_days_off(inputs: &ArgMatches) -> Result<()> {
+    if inputs.is_present("list") {
+        let today = timezone::to_local(chrono::Utc::now()).date().naive_local();
+        for days_off in configuration::days_off()? {
+            if days_off.last < today {
+                continue;
+            }
+            if days_off.first == days_off.last {
+                println!("{}", days_off.first.format("%a %-d %b %Y"));
+            } else {
+                println!("{} up to and including {}",
+                         days_off.first.format("%a %-d %b %Y"),
+                         days_off.last.format("%a %-d %b %Y"));
+            }
+        }
+        return Ok(());
+    }
+    let (first, last) = parse::date_range(inputs.value_of("days").unwrap())?;
+    let days_off = configuration::DaysOff { first: first, last: last };
+    if inputs.is_present("cancel") {
+        Ok(configuration::cancel_days_off(days_off)?)
+    } else {
+        Ok(configuration::take_days_off(days_off)?)
+    }
+}
+
+/// Proposes a duration for a new task based on the similar tasks that were completed, and lets
+/// the user accept or override it.
+fn estimate_duration(configuration: &Configuration, content: &str, tags: &[String])
+    -> Result<chrono::Duration>
+{
+    let answer = match block_on(eva::estimate(configuration, content, tags))? {
+        Some(estimate) => {
+            println!("Similar tasks you completed took {} (the median of):",
+                     estimate.duration.pretty_print());
+            for task in &estimate.similar_tasks {
+                println!("  {}: {}",
+                         task.content,
+                         task.time_spent.unwrap_or(task.duration).pretty_print());
+            }
+            let answer = ask("How long do you estimate it will take? Press enter to accept the \
+                             proposal, or give another duration: ")?;
+            if answer.is_empty() {
+                return Ok(estimate.duration);
+            }
+            answer
+        },
+        None => {
+            println!("I couldn't find any similar tasks you completed.");
+            ask("How long do you estimate it will take? ")?
+        },
+    };
+    Ok(parse::duration(&answer)?)
+}
+
+/// Asks which of the suggested tags to add, and returns them.
+fn confirm_tags(suggestions: &[eva::TagSuggestion]) -> Result<Vec<String>> {
+    println!("Suggested tags: {}",
+             suggestions.iter()
+             .map(|suggestion| {
+                 format!("{} ({:.0}%)", suggestion.tag, suggestion.confidence * 100.0)
+             })
+             .join(", "));
+    let answer = ask("Press enter to add them all, give the ones to add separated by commas, or \
+                     give '-' to add none: ")?;
+    Ok(match answer.as_str() {
+        "" => suggestions.iter().map(|suggestion| suggestion.tag.clone()).collect(),
+        "-" => vec![],
+        answer => parse::tags(answer),
+    })
+}
+
+/// Asks `question` on the terminal and returns the answer without surrounding whitespace.
+fn ask(question: &str) -> Result<String> {
+    print!("{}", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_owned())
+}
+
+/// Finds the tasks that match any of the given selectors (see `parse::Selector`), without
+/// duplicates.
+/// Returns the ids of the tasks that the `selectors` select, each only once. The tasks are read
+/// again when they are modified, so that nothing that changed them in the meantime gets lost.
+fn select_ids<'a, I>(configuration: &Configuration, selectors: I) -> Result<Vec<u32>>
+    where I: IntoIterator<Item = &'a str>
+{
+    let all_tasks = block_on(eva::all(configuration))?;
+    let mut selected_ids: Vec<u32> = vec![];
+    for selector in selectors {
+        let matching_ids = match parse::selector(selector)? {
+            parse::Selector::Id(id) => vec![block_on(eva::get(configuration, id))?.id],
+            parse::Selector::Tag(tag) => {
+                all_tasks.iter().filter(|task| task.has_tag(&tag)).map(|task| task.id).collect()
+            },
+        };
+        for id in matching_ids {
+            if !selected_ids.contains(&id) {
+                selected_ids.push(id);
+            }
+        }
+    }
+    Ok(selected_ids)
+}
+
+/// Parses the values of --remind, if it was given.
+fn reminders(inputs: &ArgMatches) -> Result<Option<Vec<chrono::Duration>>> {
+    match inputs.values_of("remind") {
+        Some(reminders) => Ok(Some(reminders.map(parse::duration).collect::<parse::Result<_>>()?)),
+        None => Ok(None),
+    }
+}
+
 fn set_field(configuration: &Configuration, field: &str, id: u32, value: &str) -> Result<()> {
     let mut task = block_on(eva::get(configuration, id))?;
     match field {
@@ -145,6 +1467,12 @@ fn set_field(configuration: &Configuration, field: &str, id: u32, value: &str) -
     Ok(block_on(eva::update(configuration, task))?)
 }
 
+/// Describes `error` and what caused it in one sentence, for the daemon's notifications and the
+/// server's responses.
+fn describe<E: ChainedError>(error: &E) -> String {
+    error.iter().map(ToString::to_string).join(". ")
+}
+
 fn handle_error(error: &Error) {
     let chain = error.iter().skip(1)
         .map(|x| x.to_string())
@@ -1,5 +1,13 @@
+use std::env;
+use std::path::Path;
+
 use chrono::prelude::*;
 use chrono::Duration;
+use chrono_tz::Tz;
+use eva::configuration::FocusSessions;
+use eva::{Days, Window};
+
+use crate::timezone;
 
 
 pub use self::errors::*;
@@ -33,27 +41,349 @@ pub fn importance(importance_str: &str) -> Result<u32> {
             "Try entering a valid integer.".to_owned()))
 }
 
-pub fn duration(duration_hours: &str) -> Result<Duration> {
-    let hours: f64 = duration_hours.parse()
+/// Parses a relative change in importance, like `+2` or `-1`.
+pub fn importance_delta(delta_str: &str) -> Result<i64> {
+    delta_str.trim_start_matches('+').parse()
+        .chain_err(|| ErrorKind::Parse(
+            "change in importance".to_owned(),
+            delta_str.to_owned(),
+            "Try entering something like '+2' or '-1'.".to_owned()))
+}
+
+pub fn tags(tags_str: &str) -> Vec<String> {
+    tags_str.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Parses a single tag, as given to `--tag`. Tags are stored separated by commas, so a tag can't
+/// contain one itself.
+pub fn tag(tag_str: &str) -> Result<String> {
+    let tag = tag_str.trim();
+    ensure!(!tag.is_empty() && !tag.contains(','), ErrorKind::Parse(
+        "tag".to_owned(),
+        tag_str.to_owned(),
+        "Try entering a single tag without commas, like 'errand'.".to_owned()));
+    Ok(tag.to_owned())
+}
+
+/// Checks the name of a context, as given to `--context`, `EVA_CONTEXT` or
+/// `eva context switch`. The name ends up in the keys of the configuration and in the filename of
+/// the database, so it can only contain letters, digits, dashes and underscores.
+pub fn context(context_str: &str) -> Result<&str> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    ensure!(!context_str.is_empty() && context_str.chars().all(valid), ErrorKind::Parse(
+        "context".to_owned(),
+        context_str.to_owned(),
+        "Try entering a name with only letters, digits, dashes and underscores, like \
+         'work'.".to_owned()));
+    Ok(context_str)
+}
+
+/// Selects tasks either by their id or by a tag prefixed with a plus sign, e.g. `4` or `+errand`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    Id(u32),
+    Tag(String),
+}
+
+pub fn selector(selector_str: &str) -> Result<Selector> {
+    if selector_str.starts_with('+') {
+        let tag = &selector_str[1..];
+        ensure!(!tag.is_empty(), ErrorKind::Parse(
+            "tag".to_owned(),
+            selector_str.to_owned(),
+            "Try entering a tag after the plus sign, like '+errand'.".to_owned()));
+        Ok(Selector::Tag(tag.to_owned()))
+    } else {
+        id(selector_str).map(Selector::Id)
+    }
+}
+
+/// Parses an attachment, which is a URL like `https://example.org/plan` or the path of a file. A
+/// relative path is made absolute, so that the file can be opened from anywhere. A path that
+/// starts with `~` is kept like that, so that it works for the same file on another computer.
+pub fn attachment(attachment_str: &str) -> Result<String> {
+    let invalid = || ErrorKind::Parse(
+        "attachment".to_owned(),
+        attachment_str.to_owned(),
+        "Try entering a path to a file or a URL, on a single line.".to_owned());
+    ensure!(!attachment_str.trim().is_empty() && !attachment_str.contains('\n'), invalid());
+    if attachment_str.contains("://") || attachment_str.starts_with('~')
+        || Path::new(attachment_str).is_absolute() {
+        return Ok(attachment_str.to_owned());
+    }
+    let path = env::current_dir().chain_err(invalid)?.join(attachment_str);
+    path.to_str().map(str::to_owned).ok_or_else(|| invalid().into())
+}
+
+/// Parses the number of an attachment, counting from 1.
+pub fn attachment_number(number_str: &str) -> Result<usize> {
+    number_str.parse()
+        .chain_err(|| ErrorKind::Parse(
+            "number of the attachment".to_owned(),
+            number_str.to_owned(),
+            "Try entering the number it has in `eva tasks`, like '1'.".to_owned()))
+}
+
+/// Parses a number of hours, or a number followed by one of the units `m`, `h`, `d` or `w`, e.g.
+/// `1.5`, `90m` or `2d`.
+pub fn duration(duration_str: &str) -> Result<Duration> {
+    let (number, minutes_per_unit) = match duration_str.chars().last() {
+        Some('m') => (&duration_str[..duration_str.len() - 1], 1.0),
+        Some('h') => (&duration_str[..duration_str.len() - 1], 60.0),
+        Some('d') => (&duration_str[..duration_str.len() - 1], 24.0 * 60.0),
+        Some('w') => (&duration_str[..duration_str.len() - 1], 7.0 * 24.0 * 60.0),
+        _ => (duration_str, 60.0),
+    };
+    let amount: f64 = number.parse()
         .chain_err(|| ErrorKind::Parse(
             "duration".to_owned(),
-            duration_hours.to_owned(),
-            "Try entering a valid, real number.".to_owned()))?;
+            duration_str.to_owned(),
+            "Try entering a valid, real number of hours or something like '90m' or '2d'."
+                .to_owned()))?;
 
-    ensure!(hours > 0.0, ErrorKind::Parse(
+    ensure!(amount > 0.0, ErrorKind::Parse(
         "duration".to_owned(),
-        duration_hours.to_owned(),
+        duration_str.to_owned(),
         "Try entering a positive number.".to_owned()));
 
-    Ok(Duration::minutes((60.0 * hours) as i64))
+    // chrono panics on durations that don't fit in an i64 of milliseconds
+    let minutes = minutes_per_unit * amount;
+    ensure!(minutes < (i64::max_value() / 60_000) as f64, ErrorKind::Parse(
+        "duration".to_owned(),
+        duration_str.to_owned(),
+        "Try entering a duration that is a bit more down to earth.".to_owned()));
+
+    Ok(Duration::minutes(minutes as i64))
+}
+
+/// Parses the lengths of focus sessions and the breaks after them in minutes, like `25/5`.
+pub fn focus_sessions(sessions_str: &str) -> Result<FocusSessions> {
+    let invalid = || ErrorKind::Parse(
+        "lengths of the focus sessions".to_owned(),
+        sessions_str.to_owned(),
+        "Try entering the minutes of focus and of the breaks, like '25/5' or '50/10'.".to_owned());
+    let slash = sessions_str.find('/').ok_or_else(invalid)?;
+    let length = |minutes_str: &str| match minutes_str.trim().parse() {
+        Ok(minutes) if minutes > 0 && minutes < 24 * 60 => Ok(Duration::minutes(minutes)),
+        _ => Err(invalid()),
+    };
+    Ok(FocusSessions {
+        focus: length(&sessions_str[..slash])?,
+        pause: length(&sessions_str[slash + 1..])?,
+    })
+}
+
+/// Parses a buffer like a duration, except that it can be zero, e.g. `0` or `15m`.
+pub fn buffer(buffer_str: &str) -> Result<Duration> {
+    let number = buffer_str.trim_end_matches(|unit| "mhdw".contains(unit));
+    if number.parse::<f64>().ok() == Some(0.0) {
+        Ok(Duration::zero())
+    } else {
+        duration(buffer_str)
+    }
 }
 
 pub fn deadline(datetime: &str) -> Result<DateTime<Utc>> {
-    Local.datetime_from_str(datetime, "%-d %b %Y %-H:%M")
+    local_datetime("deadline", datetime)
+}
+
+/// Parses the moment before which a task can't be started.
+pub fn start_after(datetime: &str) -> Result<DateTime<Utc>> {
+    local_datetime("start", datetime)
+}
+
+/// Parses the moment at which a slot of the schedule starts.
+pub fn slot_start(datetime: &str) -> Result<DateTime<Utc>> {
+    local_datetime("start of the slot", datetime)
+}
+
+fn local_datetime(type_: &str, datetime: &str) -> Result<DateTime<Utc>> {
+    timezone::datetime_from_str(datetime, "%-d %b %Y %-H:%M")
         .chain_err(|| ErrorKind::Parse(
-            "deadline".to_owned(),
+            type_.to_owned(),
             datetime.to_owned(),
             "Try entering something like '4 Jul 2017 6:05'.".to_owned())
         )
-        .map(|local_datetime| local_datetime.with_timezone(&Utc))
+}
+
+pub fn date(date_str: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .chain_err(|| ErrorKind::Parse(
+            "date".to_owned(),
+            date_str.to_owned(),
+            "Try entering something like '2017-07-04'.".to_owned()))
+}
+
+pub fn timezone(timezone_str: &str) -> Result<Tz> {
+    timezone_str.parse()
+        .map_err(|_| ErrorKind::Parse(
+            "timezone".to_owned(),
+            timezone_str.to_owned(),
+            "Try entering the name of a timezone like 'Europe/Lisbon'.".to_owned()).into())
+}
+
+pub fn year(year_str: &str) -> Result<i32> {
+    year_str.parse()
+        .chain_err(|| ErrorKind::Parse(
+            "year".to_owned(),
+            year_str.to_owned(),
+            "Try entering something like '2019'.".to_owned()))
+}
+
+/// Parses a two-letter country code like `BE`, in upper case.
+pub fn country(country_str: &str) -> Result<String> {
+    ensure!(country_str.len() == 2 && country_str.chars().all(|c| c.is_ascii_alphabetic()),
+            ErrorKind::Parse(
+                "country".to_owned(),
+                country_str.to_owned(),
+                "Try entering a two-letter country code like 'BE'.".to_owned()));
+    Ok(country_str.to_ascii_uppercase())
+}
+
+/// Parses a place like `office` or `@office`, without the at sign.
+pub fn place(place_str: &str) -> Result<String> {
+    let place = place_str.trim().trim_start_matches('@');
+    ensure!(!place.is_empty() && !place.contains('\n'),
+            ErrorKind::Parse(
+                "place".to_owned(),
+                place_str.to_owned(),
+                "Try entering something like 'office'.".to_owned()));
+    Ok(place.to_owned())
+}
+
+/// Parses how many threads to use, which is at least one.
+pub fn threads(threads_str: &str) -> Result<usize> {
+    match threads_str.parse() {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => bail!(ErrorKind::Parse(
+            "number of threads".to_owned(),
+            threads_str.to_owned(),
+            "Try entering a positive integer.".to_owned())),
+    }
+}
+
+/// Parses how many tasks to generate, which is at least one.
+pub fn task_count(count_str: &str) -> Result<u32> {
+    match count_str.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => bail!(ErrorKind::Parse(
+            "number of tasks".to_owned(),
+            count_str.to_owned(),
+            "Try entering a positive integer.".to_owned())),
+    }
+}
+
+pub fn seed(seed_str: &str) -> Result<u64> {
+    seed_str.parse()
+        .chain_err(|| ErrorKind::Parse(
+            "seed".to_owned(),
+            seed_str.to_owned(),
+            "Try entering a natural number like '42'.".to_owned()))
+}
+
+/// Parses a month like `2019-03`, returning its first day.
+pub fn month(month_str: &str) -> Result<Date<Utc>> {
+    NaiveDate::parse_from_str(&format!("{}-01", month_str), "%Y-%m-%d")
+        .map(|date| Utc.from_utc_date(&date))
+        .chain_err(|| ErrorKind::Parse(
+            "month".to_owned(),
+            month_str.to_owned(),
+            "Try entering something like '2019-03'.".to_owned()))
+}
+
+/// Parses a time of day like `23:00`.
+pub fn time(time_str: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time_str, "%H:%M")
+        .chain_err(|| ErrorKind::Parse(
+            "time".to_owned(),
+            time_str.to_owned(),
+            "Try entering something like '23:00'.".to_owned()))
+}
+
+/// Parses a range of dates like `2024-08-01..2024-08-14`, which includes both ends, or a single
+/// date. Returns the first and the last date.
+pub fn date_range(range_str: &str) -> Result<(NaiveDate, NaiveDate)> {
+    let suggestion = "Try entering something like '2024-08-01..2024-08-14'.";
+    let invalid = || ErrorKind::Parse(
+        "range of dates".to_owned(),
+        range_str.to_owned(),
+        suggestion.to_owned());
+    let (first, last) = match range_str.find("..") {
+        Some(position) => (&range_str[..position], &range_str[position + 2..]),
+        None => (range_str, range_str),
+    };
+    let first = date(first).chain_err(invalid)?;
+    let last = date(last).chain_err(invalid)?;
+    ensure!(first <= last, invalid());
+    Ok((first, last))
+}
+
+/// Parses a preferred window like `evenings`, `weekends`, `after 20:00`, `before 10:00` or
+/// `09:00-17:00`, which can follow `weekdays` or `weekends`, as in `weekdays mornings`. Business
+/// hours are from 9:00 until 17:00 on weekdays.
+pub fn window(window_str: &str) -> Result<Window> {
+    let invalid = || ErrorKind::Parse(
+        "window".to_owned(),
+        window_str.to_owned(),
+        "Try entering something like 'evenings', 'weekends after 20:00' or \
+         'weekdays 09:00-17:00'.".to_owned());
+    let hour = |hour| NaiveTime::from_hms(hour, 0, 0);
+    let lowercase = window_str.trim().to_lowercase();
+    let lowercase = match lowercase.as_str() {
+        "business hours" => "weekdays 09:00-17:00",
+        other => other,
+    };
+    let (days, time_str) = match lowercase.find(' ').map(|space| lowercase.split_at(space)) {
+        Some(("weekdays", rest)) => (Days::Weekdays, rest.trim()),
+        Some(("weekends", rest)) | Some(("weekend", rest)) => (Days::Weekends, rest.trim()),
+        _ => match lowercase {
+            "weekdays" => (Days::Weekdays, ""),
+            "weekends" | "weekend" => (Days::Weekends, ""),
+            other => (Days::Every, other),
+        },
+    };
+    let (from, until) = match time_str {
+        "" | "any time" => (hour(0), None),
+        "mornings" | "morning" => (hour(8), Some(hour(12))),
+        "afternoons" | "afternoon" => (hour(12), Some(hour(17))),
+        "evenings" | "evening" => (hour(18), Some(hour(22))),
+        other if other.starts_with("after ") => {
+            (time(other["after ".len()..].trim()).chain_err(invalid)?, None)
+        },
+        other if other.starts_with("before ") => {
+            (hour(0), Some(time(other["before ".len()..].trim()).chain_err(invalid)?))
+        },
+        other => {
+            let dash = other.find('-').ok_or_else(invalid)?;
+            let from = time(other[..dash].trim()).chain_err(invalid)?;
+            let until = time(other[dash + 1..].trim()).chain_err(invalid)?;
+            ensure!(from != until, invalid());
+            (from, Some(until))
+        },
+    };
+    Ok(Window {
+        days: days,
+        from: from,
+        until: until,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_names() {
+        for name in &["default", "work", "side-project", "home_2"] {
+            assert_eq!(context(name).unwrap(), *name);
+        }
+        for name in &["", "a.b", "../x", "work/home", "with space", "caf\u{e9}"] {
+            assert!(context(name).is_err(), "{:?} was accepted", name);
+        }
+    }
 }
@@ -1,37 +1,304 @@
 use chrono::prelude::*;
 use itertools::Itertools;
 
+use crate::timezone;
+
 
 pub(crate) trait PrettyPrint {
     fn pretty_print(&self) -> String;
 }
 
 
-impl PrettyPrint for eva::Schedule {
+impl PrettyPrint for eva::Shortfall {
+    fn pretty_print(&self) -> String {
+        let project = match self.project {
+            Some(ref project) => format!("Project {} (weight {})", project, self.weight),
+            None => "Tasks outside of projects".to_owned(),
+        };
+        let dropped = self.dropped.iter()
+            .map(|task| format!("{}. {}", task.id, task.content))
+            .join("\n    ");
+        format!("{} got {} of the {} it needed and was shorted {}. Left out:\n    {}",
+                project,
+                self.allocated.pretty_print(),
+                self.requested.pretty_print(),
+                self.shorted().pretty_print(),
+                dropped)
+    }
+}
+
+
+impl PrettyPrint for eva::Pace {
+    fn pretty_print(&self) -> String {
+        let pace = format!("To finish \"{}\" before {}, you need to spend {} per week on it.\n\
+                            After the other tasks that are due before then, you have {} per week \
+                            left.",
+                           self.task.content,
+                           self.task.deadline.pretty_print(),
+                           self.per_week.pretty_print(),
+                           self.free_per_week.pretty_print());
+        if self.is_feasible() {
+            pace
+        } else {
+            format!("{}\nThat is not enough, so you might want to relax some deadlines.", pace)
+        }
+    }
+}
+
+
+impl PrettyPrint for eva::Explanation {
+    fn pretty_print(&self) -> String {
+        let task = &self.slot.task;
+        let sooner = match self.held_back_by {
+            Some(eva::Constraint::StartAfter(start_after)) => {
+                format!("It can't start any sooner, because the task can't be started before {}.",
+                        start_after.pretty_print())
+            },
+            Some(eva::Constraint::TaskAhead(ref other)) => {
+                let why = if other.importance > task.importance {
+                    " because it's more important"
+                } else if other.deadline < task.deadline {
+                    " because it's due sooner"
+                } else {
+                    ""
+                };
+                format!("It can't start any sooner, because {}. {} comes right before it{}.",
+                        other.id, other.content, why)
+            },
+            Some(eva::Constraint::Unavailable(ref period)) => {
+                format!("It can't start any sooner, because you're not available from {} until \
+                         {}.",
+                        period.start.pretty_print(), period.end.pretty_print())
+            },
+            Some(eva::Constraint::DailyMinimum) => {
+                "It can't start any sooner, because it's the daily minimum of that day.".to_owned()
+            },
+            Some(eva::Constraint::Budget(ref tag)) => {
+                format!("It can't start any sooner, because the weekly budget of {} was used up \
+                         the week before.",
+                        tag)
+            },
+            Some(eva::Constraint::Window(ref window)) => {
+                format!("It can't start any sooner, because you would rather do it {}.", window)
+            },
+            None => "Nothing before it holds it back, so it was planned as soon as it could be \
+                     when the schedule was made.".to_owned(),
+        };
+        let later = if self.at_deadline {
+            "\n  It ends right at the deadline, so it can't be any later either."
+        } else {
+            ""
+        };
+        format!("{}: {}. {}\n  {}{}",
+                self.slot.when.pretty_print(), task.id, task.content, sooner, later)
+    }
+}
+
+
+impl PrettyPrint for eva::tracking::Status {
     fn pretty_print(&self) -> String {
-        format!("Schedule:\n  {}",
-                self.0.iter().map(PrettyPrint::pretty_print).join("\n  "))
+        let now = Utc::now();
+        let first_line = |task: &eva::Task| {
+            task.content.lines().next().unwrap_or_default().to_owned()
+        };
+        let tracking = match self.tracking {
+            Some((ref session, ref task)) => {
+                format!("You're working on {}. {}, for {} now.",
+                        task.id, first_line(task), session.duration(now).pretty_print())
+            },
+            None => "You're not working on anything.".to_owned(),
+        };
+        let planned = match self.planned {
+            Some(ref slot) if self.on_track() => {
+                format!("That's what the schedule planned until {}.",
+                        (slot.when + slot.task.duration).pretty_print())
+            },
+            Some(ref slot) => {
+                format!("The schedule planned {}. {} until {} instead. `eva start {}` switches to \
+                         it.",
+                        slot.task.id, first_line(&slot.task),
+                        (slot.when + slot.task.duration).pretty_print(), slot.task.id)
+            },
+            None => "The schedule doesn't plan anything right now.".to_owned(),
+        };
+        let next = match self.next {
+            Some(ref slot) => {
+                format!("\nNext up at {}: {}. {}",
+                        slot.when.pretty_print(), slot.task.id, first_line(&slot.task))
+            },
+            None => String::new(),
+        };
+        format!("{}\n{}{}", tracking, planned, next)
     }
 }
 
 
-impl PrettyPrint for eva::ScheduledTask {
+impl PrettyPrint for eva::Statistics {
     fn pretty_print(&self) -> String {
-        format!("{}: {}",
-                self.when.pretty_print(),
-                self.task.pretty_print())
+        if self.weeks.is_empty() {
+            return "There is nothing to report yet. Accept a schedule and complete some tasks \
+                    first.".to_owned();
+        }
+        let weeks = self.weeks.iter()
+            .map(|week| format!("Week of {}: planned {}, completed {}",
+                                week.monday.format("%a %-d %b %Y"),
+                                week.planned.pretty_print(),
+                                week.completed.pretty_print()))
+            .join("\n  ");
+        let mut report = format!("Hours per week:\n  {}", weeks);
+        if let Some(on_time_rate) = self.on_time_rate() {
+            report += &format!("\n\nCompleted {} tasks, {} of them ({:.0}%) before their deadline.",
+                               self.completed,
+                               self.completed_on_time,
+                               on_time_rate * 100.0);
+        }
+        if !self.completed_per_tag.is_empty() {
+            let tags = self.completed_per_tag.iter()
+                .map(|(tag, completed)| format!("{}: {}", tag, completed))
+                .join("\n  ");
+            report += &format!("\n\nCompleted tasks per tag:\n  {}", tags);
+        }
+        if let Some(estimate_ratio) = self.estimate_ratio() {
+            report += &format!("\n\nThe tasks you logged time for were estimated at {} and took \
+                                {}, which is {:.0}% of the estimate.",
+                               self.estimated.pretty_print(),
+                               self.spent.pretty_print(),
+                               estimate_ratio * 100.0);
+        }
+        report
+    }
+}
+
+
+/// How far off the estimates for a tag can be before the insights suggest to change them, as the
+/// most that was spent compared to the estimates, either way.
+const ESTIMATE_TOLERANCE: f64 = 1.25;
+
+/// How many tags with bad estimates the insights show at most.
+const WORST_ESTIMATES: usize = 3;
+
+impl PrettyPrint for eva::statistics::Insights {
+    fn pretty_print(&self) -> String {
+        let month = self.month.format("%B %Y");
+        let mut sections = vec![];
+
+        // The days of the week on which less than planned gets done more often than not
+        let overcommitted = self.workloads.iter()
+            .filter(|workload| workload.overcommitted * 2 > workload.days)
+            .filter_map(|workload| {
+                let completion_rate = workload.completion_rate()?;
+                let done_per_day = workload.completed / workload.days as i32;
+                Some(format!("On {}s you did less than planned {} out of {} times, {:.0}% of \
+                              what was planned overall. Try planning about {} on them.",
+                             weekday_name(workload.weekday),
+                             workload.overcommitted,
+                             workload.days,
+                             completion_rate * 100.0,
+                             done_per_day.pretty_print()))
+            })
+            .join("\n  ");
+        if !overcommitted.is_empty() {
+            sections.push(format!("Days you overcommit:\n  {}", overcommitted));
+        }
+
+        let estimates = self.estimates.iter()
+            .filter(|estimate| {
+                let ratio = estimate.ratio();
+                ratio > ESTIMATE_TOLERANCE || ratio < 1.0 / ESTIMATE_TOLERANCE
+            })
+            .take(WORST_ESTIMATES)
+            .map(|estimate| {
+                let advice = if estimate.ratio() > 1.0 { "longer" } else { "shorter" };
+                format!("+{} took {} instead of {} over {} tasks ({:.0}%). Try estimating them \
+                         {}.",
+                        estimate.tag,
+                        estimate.spent.pretty_print(),
+                        estimate.estimated.pretty_print(),
+                        estimate.tasks,
+                        estimate.ratio() * 100.0,
+                        advice)
+            })
+            .join("\n  ");
+        if !estimates.is_empty() {
+            sections.push(format!("Tags with the worst estimates:\n  {}", estimates));
+        }
+
+        if let Some(average_slips) = self.average_slips() {
+            sections.push(format!("The {} tasks you completed slipped {:.1} times on average \
+                                   before they were done.",
+                                  self.completed,
+                                  average_slips));
+        }
+        if !self.most_slipped.is_empty() {
+            let tasks = self.most_slipped.iter()
+                .map(|task| format!("{}. {} (slipped {} times)", task.id, task.content,
+                                    task.slipped))
+                .join("\n  ");
+            sections.push(format!("These tasks keep slipping. Consider splitting them up, \
+                                   giving them a later start or dropping them:\n  {}",
+                                  tasks));
+        }
+
+        if sections.is_empty() {
+            format!("There is nothing to report for {} yet. Accept schedules and complete some \
+                     tasks first.", month)
+        } else {
+            format!("Insights for {}\n\n{}", month, sections.join("\n\n"))
+        }
+    }
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+
+impl PrettyPrint for eva::statistics::BudgetUse {
+    fn pretty_print(&self) -> String {
+        let used = self.spent + self.planned;
+        let mut limits = vec![];
+        if let Some(minimum) = self.budget.minimum {
+            limits.push(format!("at least {}", minimum.pretty_print()));
+        }
+        if let Some(maximum) = self.budget.maximum {
+            limits.push(format!("at most {}", maximum.pretty_print()));
+        }
+        let verdict = match (self.budget.minimum, self.budget.maximum) {
+            (_, Some(maximum)) if used > maximum => {
+                format!(", {} over budget", (used - maximum).pretty_print())
+            },
+            (Some(minimum), _) if used < minimum => {
+                format!(", {} short", (minimum - used).pretty_print())
+            },
+            _ => String::new(),
+        };
+        format!("{}: spent {} and planned {} of {}{}",
+                self.tag,
+                self.spent.pretty_print(),
+                self.planned.pretty_print(),
+                limits.join(" and "),
+                verdict)
     }
 }
 
 
 impl PrettyPrint for DateTime<Utc> {
     fn pretty_print(&self) -> String {
-        let format = if self.year() == Utc::now().year() {
+        let local_datetime = timezone::to_local(*self);
+        let format = if local_datetime.year() == timezone::to_local(Utc::now()).year() {
             "%a %-d %b %-H:%M"
         } else {
             "%a %-d %b %Y %-H:%M"
         };
-        self.format(format).to_string()
+        local_datetime.format(format).to_string()
     }
 }
 
@@ -39,13 +306,120 @@ impl PrettyPrint for DateTime<Utc> {
 impl PrettyPrint for eva::Task {
     fn pretty_print(&self) -> String {
         let prefix = format!("{}. ", self.id);
-        format!("{}{}\n{}(deadline: {}, duration: {}, importance: {})",
+        let tags = if self.tags.is_empty() {
+            String::new()
+        } else {
+            format!(", tags: {}", self.tags.join(", "))
+        };
+        let start_after = match self.start_after {
+            Some(start_after) => format!(", start after: {}", start_after.pretty_print()),
+            None => String::new(),
+        };
+        let buffer = match self.buffer {
+            Some(buffer) => format!(", buffer: {}", buffer.pretty_print()),
+            None => String::new(),
+        };
+        let horizon = match self.horizon {
+            Some(horizon) => format!(", horizon: {}", horizon.pretty_print()),
+            None => String::new(),
+        };
+        let daily_minimum = match self.daily_minimum {
+            Some(daily_minimum) => format!(", daily minimum: {}", daily_minimum.pretty_print()),
+            None => String::new(),
+        };
+        let window = match self.preferred_window {
+            Some(window) => format!(", window: {}", window),
+            None => String::new(),
+        };
+        let assignee = match self.assignee {
+            Some(ref assignee) => format!(", assignee: {}", assignee),
+            None => String::new(),
+        };
+        let reminders = if self.reminders.is_empty() {
+            String::new()
+        } else {
+            format!(", reminders: {} before",
+                    self.reminders.iter().map(PrettyPrint::pretty_print).join(", "))
+        };
+        let completed = match self.completed {
+            Some(completed) => format!(", completed: {}", completed.pretty_print()),
+            None => String::new(),
+        };
+        let slipped = match self.slipped {
+            0 => String::new(),
+            1 => ", slipped once".to_owned(),
+            slipped => format!(", slipped {} times", slipped),
+        };
+        let waiting_for = match self.waiting_for {
+            Some(eva::Trigger::At(ref place)) => format!(", waiting until at {}", place),
+            Some(eva::Trigger::Done(id)) => format!(", waiting until {} is done", id),
+            None => String::new(),
+        };
+        let indent = " ".repeat(prefix.len());
+        // Content that runs over several lines stays aligned with its first line
+        let content = self.content.lines().join(&format!("\n{}", indent));
+        let attachments = self.attachments.iter().enumerate()
+            .map(|(index, attachment)| format!("\n{}[{}] {}", indent, index + 1, attachment))
+            .join("");
+        format!("{}{}\n{}(deadline: {}, duration: {}, importance: {}{}{}{}{}{}{}{}{}{}{}{}){}",
                 prefix,
-                self.content,
-                " ".repeat(prefix.len()),
+                content,
+                indent,
                 self.deadline.pretty_print(),
                 self.duration.pretty_print(),
-                self.importance)
+                self.importance,
+                tags,
+                start_after,
+                buffer,
+                horizon,
+                daily_minimum,
+                window,
+                assignee,
+                reminders,
+                completed,
+                slipped,
+                waiting_for,
+                attachments)
+    }
+}
+
+
+impl PrettyPrint for eva::Template {
+    fn pretty_print(&self) -> String {
+        let mut fields = vec![];
+        if let Some(ref content) = self.content {
+            fields.push(format!("content: {}", content));
+        }
+        if let Some(due_in) = self.due_in {
+            fields.push(format!("due in: {}", due_in.pretty_print()));
+        }
+        if let Some(duration) = self.duration {
+            fields.push(format!("duration: {}", duration.pretty_print()));
+        }
+        if let Some(importance) = self.importance {
+            fields.push(format!("importance: {}", importance));
+        }
+        if !self.tags.is_empty() {
+            fields.push(format!("tags: {}", self.tags.join(", ")));
+        }
+        if let Some(buffer) = self.buffer {
+            fields.push(format!("buffer: {}", buffer.pretty_print()));
+        }
+        if let Some(horizon) = self.horizon {
+            fields.push(format!("horizon: {}", horizon.pretty_print()));
+        }
+        if let Some(daily_minimum) = self.daily_minimum {
+            fields.push(format!("daily minimum: {}", daily_minimum.pretty_print()));
+        }
+        if !self.reminders.is_empty() {
+            fields.push(format!("reminders: {} before",
+                                self.reminders.iter().map(PrettyPrint::pretty_print).join(", ")));
+        }
+        if fields.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}\n  ({})", self.name, fields.join(", "))
+        }
     }
 }
 
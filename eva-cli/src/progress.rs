@@ -0,0 +1,85 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+use atty;
+use ctrlc;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// The exit code of a program that was interrupted by Ctrl-C (128 + SIGINT).
+const INTERRUPTED: i32 = 130;
+
+static HANDLE_INTERRUPTS: Once = Once::new();
+static SPINNING: AtomicBool = AtomicBool::new(false);
+static STORING: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+
+/// Runs `operation` while a spinner with `message` turns on standard error, if that's a terminal.
+///
+/// Ctrl-C stops the operation right away. That undoes the transaction that was being stored, if
+/// any, but keeps the ones that were stored before it. So only use this for operations of which
+/// every transaction is a whole change on its own, like unsnoozing the tasks before scheduling
+/// them, and use `while_storing` for the others.
+pub fn while_running<T, F: FnOnce() -> T>(message: &str, operation: F) -> T {
+    handle_interrupts();
+    spin(message, operation)
+}
+
+
+/// Runs `operation`, which stores its changes in more than one transaction, like `while_running`.
+///
+/// Ctrl-C doesn't stop the operation halfway through, because that could leave the database with
+/// only some of the changes. It cancels the command instead, which stops eva as soon as the
+/// operation is done.
+pub fn while_storing<T, F: FnOnce() -> T>(message: &str, operation: F) -> T {
+    handle_interrupts();
+    STORING.store(true, Ordering::SeqCst);
+    let result = spin(message, operation);
+    STORING.store(false, Ordering::SeqCst);
+    if CANCELLED.load(Ordering::SeqCst) {
+        eprintln!("Interrupted");
+        process::exit(INTERRUPTED);
+    }
+    result
+}
+
+
+fn handle_interrupts() {
+    HANDLE_INTERRUPTS.call_once(|| {
+        // Without the handler, Ctrl-C still stops eva, but may leave the spinner on the screen
+        let _ = ctrlc::set_handler(|| {
+            if STORING.load(Ordering::SeqCst) {
+                if !CANCELLED.swap(true, Ordering::SeqCst) {
+                    eprint!("\r\x1b[2K");
+                    eprintln!("Stopping once the changes are stored");
+                }
+                return;
+            }
+            if SPINNING.load(Ordering::SeqCst) {
+                eprint!("\r\x1b[2K");
+            }
+            eprintln!("Interrupted");
+            process::exit(INTERRUPTED);
+        });
+    });
+}
+
+
+fn spin<T, F: FnOnce() -> T>(message: &str, operation: F) -> T {
+    let spinner = if atty::is(atty::Stream::Stderr) {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
+    spinner.set_style(ProgressStyle::default_spinner().template("{spinner} {msg} {elapsed}"));
+    spinner.set_message(message);
+    spinner.enable_steady_tick(100);
+    SPINNING.store(true, Ordering::SeqCst);
+
+    let result = operation();
+
+    SPINNING.store(false, Ordering::SeqCst);
+    spinner.finish_and_clear();
+    result
+}
@@ -0,0 +1,255 @@
+use std::io::Read;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use eva::configuration::Configuration;
+use futures::executor::block_on;
+use serde_json::{self, json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::configuration;
+use crate::describe;
+use crate::hooks::Hooks;
+use crate::json;
+use crate::parse;
+
+pub use self::errors::*;
+
+mod errors {
+    use crate::configuration;
+    use crate::json;
+    use crate::parse;
+
+    error_chain! {
+        links {
+            Configuration(configuration::Error, configuration::ErrorKind);
+            Json(json::Error, json::ErrorKind);
+            Parse(parse::Error, parse::ErrorKind);
+        }
+        foreign_links {
+            EvaCore(eva::Error);
+            Io(std::io::Error);
+        }
+        errors {
+            Start(address: String, reason: String) {
+                description("server start error")
+                display("I could not start serving on {} ({})", address, reason)
+            }
+            BadRequest(reason: String) {
+                description("bad request")
+                display("I could not make sense of the request: {}", reason)
+            }
+            NotFound(method: String, path: String) {
+                description("unknown route")
+                display("There is nothing at {} {}", method, path)
+            }
+            UnknownTask(id: u32) {
+                description("unknown task")
+                display("There is no task with id {}", id)
+            }
+            NoSchedule {
+                description("no accepted schedule")
+                display("You haven't accepted a schedule yet")
+            }
+        }
+    }
+}
+
+
+/// Serves the tasks of `context` over HTTP on `address`, answering requests on several threads
+/// at once. Every thread has a connection to the database of its own.
+///
+/// These are the routes, which all answer with JSON in the format of the `json` module:
+///
+/// * `GET /tasks` lists the tasks that haven't been completed yet.
+/// * `POST /tasks` adds the task in the body and answers with it, including its id.
+/// * `GET /schedule` gives the schedule that was accepted last.
+/// * `POST /tasks/<id>/done` marks a task as completed. The body can give the time spent on it,
+///   like `{"time_spent": 90}`.
+/// * `GET /schema` gives the JSON Schema of all of the above.
+///
+/// Adding and completing tasks runs the hooks of the context, like the same commands do.
+///
+/// This only returns when one of the threads fails.
+pub fn serve(context: Option<&str>, address: &str, threads: usize) -> Result<()> {
+    let server = Server::http(address)
+        .map_err(|error| ErrorKind::Start(address.to_owned(), error.to_string()))?;
+    let server = Arc::new(server);
+    println!("Serving on http://{}", address);
+    let (sender, failures) = mpsc::channel();
+    for _ in 0..threads {
+        let server = Arc::clone(&server);
+        let context = context.map(str::to_owned);
+        let sender = sender.clone();
+        thread::spawn(move || {
+            // Nobody listens anymore once another thread failed first
+            let _ = sender.send(work(&server, context.as_ref().map(String::as_str)));
+        });
+    }
+    // The threads only stop when they fail, so the first one to stop ends the server
+    drop(sender);
+    failures.recv().expect("The threads of the server panicked")
+}
+
+
+/// Answers the requests that come in on `server` for as long as it can.
+fn work(server: &Server, context: Option<&str>) -> Result<()> {
+    // The timezone that dates and times are shown in is kept per thread
+    configuration::activate_timezone()?;
+    let configuration = configuration::read(context)?;
+    let hooks = configuration::hooks(context)?;
+    loop {
+        // A client that went away shouldn't bring the server down
+        if let Err(error) = answer(&configuration, &hooks, server.recv()?) {
+            eprintln!("{}.", describe(&error));
+        }
+    }
+}
+
+
+fn answer(configuration: &Configuration, hooks: &Hooks, mut request: Request) -> Result<()> {
+    let (status, body) = match route(configuration, hooks, &mut request) {
+        Ok(answer) => answer,
+        Err(error) => {
+            let mut body = json!({ "error": describe(&error) });
+            if let Some(schedule_error) = schedule_error(&error) {
+                body["conflict"] = json::conflict(schedule_error);
+            }
+            (status_of(&error), body)
+        },
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    Ok(request.respond(response)?)
+}
+
+/// Handles `request`, returning the status code and the body of the answer.
+fn route(configuration: &Configuration, hooks: &Hooks, request: &mut Request)
+    -> Result<(u16, Value)>
+{
+    let method = request.method().clone();
+    let url = request.url().to_owned();
+    let path: Vec<&str> = url.split('?').next().unwrap().trim_matches('/').split('/').collect();
+    match (&method, path.as_slice()) {
+        (Method::Get, ["tasks"]) => {
+            let tasks = block_on(eva::all(configuration))?;
+            Ok((200, json::tasks(&tasks)))
+        },
+        (Method::Post, ["tasks"]) => {
+            let new_task = json::new_task(&body(request)?)?;
+            let task = block_on(eva::add(configuration, new_task))?;
+            hooks.added(&task);
+            Ok((201, json::task(&task)))
+        },
+        (Method::Get, ["schedule"]) => {
+            match block_on(eva::accepted_schedule(configuration))? {
+                Some(schedule) => Ok((200, json::schedule(&schedule))),
+                None => bail!(ErrorKind::NoSchedule),
+            }
+        },
+        (Method::Get, ["schema"]) => {
+            let schema = serde_json::from_str(json::SCHEMA)
+                .expect("The JSON Schema that comes with Eva is valid JSON");
+            Ok((200, schema))
+        },
+        (Method::Post, ["tasks", id, "done"]) => {
+            let id = parse::id(id)?;
+            let time_spent = match body(request)?["time_spent"] {
+                Value::Null => None,
+                ref minutes => match minutes.as_i64() {
+                    Some(minutes) if minutes >= 0 => Some(chrono::Duration::minutes(minutes)),
+                    _ => bail!(ErrorKind::BadRequest(
+                        "the time spent should be a whole number of minutes".to_owned())),
+                },
+            };
+            let tasks = block_on(eva::all(configuration))?;
+            ensure!(tasks.iter().any(|task| task.id == id), ErrorKind::UnknownTask(id));
+            let task = block_on(eva::complete(configuration, id, time_spent))?;
+            hooks.completed(&task);
+            Ok((200, json::task(&task)))
+        },
+        _ => bail!(ErrorKind::NotFound(method.to_string(), url.clone())),
+    }
+}
+
+/// Reads the body of `request` as JSON. An empty body is `null`.
+fn body(request: &mut Request) -> Result<Value> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    if body.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&body)
+        .map_err(|error| ErrorKind::BadRequest(error.to_string()).into())
+}
+
+fn status_of(error: &Error) -> u16 {
+    match error.kind() {
+        ErrorKind::BadRequest(_) | ErrorKind::Json(_) | ErrorKind::Parse(_) => 400,
+        ErrorKind::NotFound(_, _) | ErrorKind::UnknownTask(_) | ErrorKind::NoSchedule => 404,
+        _ => 500,
+    }
+}
+
+/// Returns why the tasks couldn't be scheduled, if that is what `error` comes down to.
+fn schedule_error(error: &Error) -> Option<&eva::ScheduleError> {
+    match error.kind() {
+        ErrorKind::EvaCore(eva_error) => match eva_error.kind() {
+            eva::ErrorKind::Schedule(schedule_error) => Some(schedule_error),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::demo;
+
+    /// Sends `request` to a server and routes it, returning the status code of the answer.
+    fn status(configuration: &Configuration, request: &str) -> u16 {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.server_addr()).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let mut request = server.recv().unwrap();
+        match route(configuration, &Hooks::default(), &mut request) {
+            Ok((status, _)) => status,
+            Err(error) => status_of(&error),
+        }
+    }
+
+    fn get(path: &str) -> String {
+        format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)
+    }
+
+    fn post(path: &str, body: &str) -> String {
+        format!("POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+                path, body.len(), body)
+    }
+
+    #[test]
+    fn status_codes() {
+        let configuration = demo::configuration(3, 1).unwrap();
+        assert_eq!(status(&configuration, &get("/tasks")), 200);
+        assert_eq!(status(&configuration, &get("/schedule")), 200);
+        assert_eq!(status(&configuration, &get("/schema")), 200);
+        let new_task = r#"{"content": "Call mom", "deadline": "2100-08-02T14:03:00Z",
+                           "duration": 30, "importance": 5}"#;
+        assert_eq!(status(&configuration, &post("/tasks", new_task)), 201);
+        assert_eq!(status(&configuration, &post("/tasks", r#"{"content": "Call mom"}"#)), 400);
+        assert_eq!(status(&configuration, &post("/tasks", "not json")), 400);
+        assert_eq!(status(&configuration, &post("/tasks/1/done", r#"{"time_spent": -5}"#)), 400);
+        assert_eq!(status(&configuration, &post("/tasks/one/done", "")), 400);
+        assert_eq!(status(&configuration, &post("/tasks/999/done", "")), 404);
+        assert_eq!(status(&configuration, &post("/tasks/1/done", "")), 200);
+        assert_eq!(status(&configuration, &get("/nowhere")), 404);
+    }
+}
@@ -0,0 +1,200 @@
+use std::cell::Cell;
+use std::ops::Range;
+
+use chrono::offset::Offset;
+use chrono::prelude::*;
+use chrono::{Duration, ParseResult};
+use chrono_tz::Tz;
+use eva::configuration::LocalTime;
+
+
+thread_local! {
+    /// The timezone that replaces the local one, if any, and the date on which the local one takes
+    /// over again. Every thread has one of its own, so a thread that is spawned shows and reads
+    /// dates and times in the local timezone until it calls `activate` itself.
+    static OVERRIDE: Cell<Option<(Tz, Option<NaiveDate>)>> = Cell::new(None);
+}
+
+
+/// Makes all dates and times be shown and read in `zone` instead of in the local timezone, or in
+/// the local timezone again when `zone` is `None`. When `until` is given, the moments from when
+/// that date starts in `zone` are in the local timezone again. This only applies to the current
+/// thread.
+pub fn activate(zone: Option<Tz>, until: Option<NaiveDate>) {
+    OVERRIDE.with(|override_| override_.set(zone.map(|zone| (zone, until))));
+}
+
+
+/// The timezone in which dates and times are shown on a thread, as it was when `shown` was
+/// called there. This is the local time that the scheduler follows.
+#[derive(Clone, Copy)]
+pub struct Shown(Option<(Tz, Option<NaiveDate>)>);
+
+
+/// Returns the timezone in which dates and times are shown on the current thread.
+pub fn shown() -> Shown {
+    Shown(OVERRIDE.with(Cell::get))
+}
+
+
+impl LocalTime for Shown {
+    fn offset_at(&self, moment: DateTime<Utc>) -> FixedOffset {
+        match self.zone_at(moment) {
+            Some(zone) => moment.with_timezone(&zone).offset().fix(),
+            None => moment.with_timezone(&Local).offset().fix(),
+        }
+    }
+
+    /// Up to and including the end of an override, this is in its timezone, so that its last day
+    /// ends where the local timezone takes over.
+    fn moment_at(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        if let Some((zone, until)) = self.0 {
+            let moment = moment_in(&zone, local);
+            if end_of_override(&zone, until).map_or(true, |end| moment <= end) {
+                return moment;
+            }
+        }
+        moment_in(&Local, local)
+    }
+}
+
+
+impl Shown {
+    /// Returns the timezone that replaces the local one at `moment`, if any.
+    fn zone_at(&self, moment: DateTime<Utc>) -> Option<Tz> {
+        let (zone, until) = self.0?;
+        match end_of_override(&zone, until) {
+            Some(end) if end <= moment => None,
+            _ => Some(zone),
+        }
+    }
+}
+
+
+/// Converts `datetime` to the timezone in which dates and times are shown.
+pub fn to_local(datetime: DateTime<Utc>) -> DateTime<FixedOffset> {
+    datetime.with_timezone(&shown().offset_at(datetime))
+}
+
+
+/// Parses a date and time in the timezone in which dates and times are read.
+pub fn datetime_from_str(datetime: &str, format: &str) -> ParseResult<DateTime<Utc>> {
+    if let Some((zone, until)) = OVERRIDE.with(Cell::get) {
+        let moment = zone.datetime_from_str(datetime, format)?.with_timezone(&Utc);
+        if end_of_override(&zone, until).map_or(true, |end| moment <= end) {
+            return Ok(moment);
+        }
+    }
+    Local.datetime_from_str(datetime, format).map(|datetime| datetime.with_timezone(&Utc))
+}
+
+
+/// Returns the moment tomorrow starts in the timezone in which dates and times are shown.
+pub fn start_of_tomorrow() -> DateTime<Utc> {
+    let tomorrow = to_local(Utc::now()).naive_local().date().succ();
+    day(tomorrow).start
+}
+
+
+/// Returns the first moment after `after` at which the clocks show `time` in the timezone in
+/// which dates and times are shown. On a day where the clocks skip `time`, that is the moment
+/// they skip it.
+pub fn next_occurrence(time: NaiveTime, after: DateTime<Utc>) -> DateTime<Utc> {
+    let today = to_local(after).date().naive_local();
+    [today, today.succ(), today.succ().succ()].iter()
+        .map(|&date| moment(date.and_time(time)))
+        .find(|&moment| after < moment)
+        .unwrap()
+}
+
+
+/// Returns today's date in `zone`.
+pub fn today_in(zone: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&zone).naive_local().date()
+}
+
+
+/// Returns the period that `date` covers in the timezone in which dates and times are shown.
+/// Where the clocks skip midnight, the day starts at the first moment they do show.
+pub fn day(date: NaiveDate) -> Range<DateTime<Utc>> {
+    moment(date.and_hms(0, 0, 0))..moment(date.succ().and_hms(0, 0, 0))
+}
+
+
+/// Returns the moment at which the clocks show `datetime` in the timezone in which dates and
+/// times are read.
+fn moment(datetime: NaiveDateTime) -> DateTime<Utc> {
+    shown().moment_at(datetime)
+}
+
+
+/// Returns when an override of the local timezone by `zone` until `until` ends, if it ends.
+fn end_of_override(zone: &Tz, until: Option<NaiveDate>) -> Option<DateTime<Utc>> {
+    until.map(|until| moment_in(zone, until.and_hms(0, 0, 0)))
+}
+
+
+fn moment_in<Z: TimeZone>(zone: &Z, datetime: NaiveDateTime) -> DateTime<Utc> {
+    // Where the clocks skip the moment, take the first one after it that they do show
+    (0..=24 * 60)
+        .filter_map(|minutes| {
+            let datetime = datetime.checked_add_signed(Duration::minutes(minutes))?;
+            zone.from_local_datetime(&datetime).earliest()
+        })
+        .next()
+        .unwrap_or_else(|| zone.from_utc_datetime(&datetime))
+        .with_timezone(&Utc)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_where_the_clocks_skip_midnight() {
+        // Cuba moved its clocks from midnight to one o'clock on 10 March 2019
+        activate(Some(Tz::America__Havana), None);
+        let skipped = day(NaiveDate::from_ymd(2019, 3, 10));
+        assert_eq!(skipped.start, Utc.ymd(2019, 3, 10).and_hms(5, 0, 0));
+        assert_eq!(skipped.end, Utc.ymd(2019, 3, 11).and_hms(4, 0, 0));
+        assert_eq!(day(NaiveDate::from_ymd(2019, 3, 9)).end, skipped.start);
+        activate(None, None);
+    }
+
+    #[test]
+    fn times_the_clocks_skip() {
+        // Belgium moved its clocks from two to three o'clock on 31 March 2019, at 01:00 in UTC
+        activate(Some(Tz::Europe__Brussels), None);
+        let half_past_two = NaiveTime::from_hms(2, 30, 0);
+        let after = Utc.ymd(2019, 3, 30).and_hms(12, 0, 0);
+        assert_eq!(next_occurrence(half_past_two, after), Utc.ymd(2019, 3, 31).and_hms(1, 0, 0));
+        let after = Utc.ymd(2019, 3, 31).and_hms(12, 0, 0);
+        assert_eq!(next_occurrence(half_past_two, after), Utc.ymd(2019, 4, 1).and_hms(0, 30, 0));
+        activate(None, None);
+    }
+
+    #[test]
+    fn the_shown_timezone_follows_daylight_saving_time() {
+        activate(Some(Tz::Europe__Brussels), None);
+        let brussels = shown();
+        activate(None, None);
+        let offset_at = |moment| brussels.offset_at(moment).local_minus_utc();
+        assert_eq!(offset_at(Utc.ymd(2019, 3, 30).and_hms(12, 0, 0)), 3600);
+        assert_eq!(offset_at(Utc.ymd(2019, 3, 31).and_hms(12, 0, 0)), 7200);
+        let monday_morning = NaiveDate::from_ymd(2019, 4, 1).and_hms(9, 0, 0);
+        assert_eq!(brussels.moment_at(monday_morning), Utc.ymd(2019, 4, 1).and_hms(7, 0, 0));
+    }
+
+    #[test]
+    fn overrides_end_when_their_date_starts() {
+        let until = NaiveDate::from_ymd(2019, 9, 10);
+        activate(Some(Tz::Asia__Tokyo), Some(until));
+        let end = Utc.ymd(2019, 9, 9).and_hms(15, 0, 0);
+        assert_eq!(day(until.pred()), Utc.ymd(2019, 9, 8).and_hms(15, 0, 0)..end);
+        assert_eq!(to_local(end - Duration::minutes(1)).offset().local_minus_utc(), 9 * 3600);
+        assert_eq!(to_local(end).offset(), &Local.offset_from_utc_datetime(&end.naive_utc()).fix());
+        assert_eq!(day(until), end..moment_in(&Local, until.succ().and_hms(0, 0, 0)));
+        activate(None, None);
+    }
+}
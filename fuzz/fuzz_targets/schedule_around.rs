@@ -0,0 +1,147 @@
+//! Schedules tasks and unavailable periods that are read from the input with every strategy, and
+//! checks that the schedules keep to the deadlines without overlaps, like the properties of the
+//! scheduling module do for generated task sets. Run it with `cargo fuzz run schedule_around`.
+
+#![no_main]
+
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use libfuzzer_sys::fuzz_target;
+
+use eva::configuration::{SchedulingStrategy, UrgencyCoefficients};
+use eva::{Schedule, ScheduleError, ScheduledTask, Task};
+
+const STRATEGIES: [SchedulingStrategy; 3] = [
+    SchedulingStrategy::Importance,
+    SchedulingStrategy::Urgency,
+    SchedulingStrategy::Optimal,
+];
+
+/// Sunday evening, a minute before the first moment a task can be scheduled at 18:00, so that
+/// the daily minimums run into the next day.
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2019, 3, 10).and_hms(17, 59, 0)
+}
+
+/// The moment `quarters` quarters of an hour after the first moment a task can be scheduled.
+fn after(quarters: i64) -> DateTime<Utc> {
+    start() + Duration::minutes(1 + 15 * quarters)
+}
+
+/// The bytes of the input, read one at a time. Once they run out, only zeroes are left.
+struct Input<'a>(std::slice::Iter<'a, u8>);
+
+impl<'a> Input<'a> {
+    /// Reads a number below `bound`.
+    fn below(&mut self, bound: u8) -> i64 {
+        i64::from(self.0.next().map_or(0, |byte| byte % bound))
+    }
+
+    /// Reads a number from 1 up to `most`, or `None` for 0.
+    fn optional(&mut self, most: u8) -> Option<i64> {
+        Some(self.below(most + 1)).filter(|&number| number > 0)
+    }
+
+    /// Reads up to 6 tasks of at most 2 hours, with a deadline in the first 24 hours, some of
+    /// which have a buffer, can't start right away or have a daily minimum of at most an hour.
+    fn tasks(&mut self) -> Vec<Task> {
+        (0..self.below(7))
+            .map(|id| Task {
+                id: id as u32,
+                content: format!("task {}", id),
+                duration: Duration::minutes(15 * self.below(9)),
+                importance: 1 + self.below(10) as u32,
+                deadline: after(self.below(97)),
+                buffer: self.optional(2).map(|buffer| Duration::minutes(15 * buffer)),
+                start_after: self.optional(96).map(after),
+                daily_minimum: self.optional(4).map(|minimum| Duration::minutes(15 * minimum)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Reads up to 3 unavailable periods of at most 2 hours, which can overlap or touch.
+    fn unavailable(&mut self) -> Vec<Range<DateTime<Utc>>> {
+        (0..self.below(4))
+            .map(|_| {
+                let from = self.below(97);
+                after(from)..after(from + 1 + self.below(8))
+            })
+            .collect()
+    }
+}
+
+/// Checks that `schedule` has every one of the `tasks` in full, in one slot unless it has a daily
+/// minimum, and that the slots start when they can, end by their deadline and don't overlap with
+/// each other or with the `unavailable` periods.
+fn check(tasks: &[Task], unavailable: &[Range<DateTime<Utc>>], schedule: &Schedule) {
+    for task in tasks {
+        let slots: Vec<&ScheduledTask> = schedule
+            .0
+            .iter()
+            .filter(|slot| slot.task.id == task.id)
+            .collect();
+        assert!(slots.len() == 1 || (task.daily_minimum.is_some() && !slots.is_empty()));
+        let planned = slots
+            .iter()
+            .fold(Duration::zero(), |planned, slot| planned + slot.task.duration);
+        assert_eq!(planned, task.duration, "task {} isn't planned in full", task.id);
+    }
+    assert!(schedule
+        .0
+        .iter()
+        .all(|slot| tasks.iter().any(|task| task.id == slot.task.id)));
+    for (index, slot) in schedule.0.iter().enumerate() {
+        let end = slot.when + slot.task.duration;
+        assert!(after(0) <= slot.when, "task {} starts too soon", slot.task.id);
+        assert!(
+            slot.task.start_after.map_or(true, |start_after| start_after <= slot.when),
+            "task {} starts before it can",
+            slot.task.id
+        );
+        assert!(end <= slot.task.deadline, "task {} ends too late", slot.task.id);
+        assert!(
+            unavailable
+                .iter()
+                .all(|period| end <= period.start || period.end <= slot.when),
+            "task {} is scheduled in an unavailable period",
+            slot.task.id
+        );
+        for other in &schedule.0[index + 1..] {
+            let occupied = |slot: &ScheduledTask| slot.task.duration_with_buffer().unwrap();
+            assert!(
+                other.when + occupied(other) <= slot.when
+                    || slot.when + occupied(slot) <= other.when,
+                "tasks {} and {} overlap",
+                slot.task.id,
+                other.task.id
+            );
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input(data.iter());
+    let tasks = input.tasks();
+    let unavailable = input.unavailable();
+    for &strategy in &STRATEGIES {
+        let schedule = Schedule::schedule_around(
+            start(),
+            tasks.clone(),
+            strategy,
+            &unavailable,
+            &UrgencyCoefficients::default(),
+            &Utc,
+        );
+        match schedule {
+            Ok(schedule) => check(&tasks, &unavailable, &schedule),
+            Err(ScheduleError::Internal(more_info)) => {
+                panic!("{:?} failed internally: {}", strategy, more_info)
+            }
+            // Not every input can be scheduled
+            Err(_) => {}
+        }
+    }
+});
@@ -0,0 +1,10 @@
+CREATE TABLE tasks_without_tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL
+);
+INSERT INTO tasks_without_tags SELECT id, content, deadline, duration, importance FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_tags RENAME TO tasks
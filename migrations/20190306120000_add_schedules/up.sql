@@ -0,0 +1,10 @@
+CREATE TABLE schedules (
+    kind TEXT PRIMARY KEY NOT NULL
+);
+
+CREATE TABLE scheduled_tasks (
+    kind TEXT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    PRIMARY KEY (kind, task_id)
+)
@@ -0,0 +1,11 @@
+CREATE TABLE tasks_without_not_before (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT ''
+);
+INSERT INTO tasks_without_not_before SELECT id, content, deadline, duration, importance, tags FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_not_before RENAME TO tasks
@@ -0,0 +1,12 @@
+CREATE TABLE tasks_without_reminders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    not_before INTEGER
+);
+INSERT INTO tasks_without_reminders SELECT id, content, deadline, duration, importance, tags, not_before FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_reminders RENAME TO tasks
@@ -0,0 +1,16 @@
+DROP TABLE snapshot_tasks;
+DROP TABLE snapshots;
+CREATE TABLE tasks_without_statistics (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    not_before INTEGER,
+    reminders TEXT NOT NULL DEFAULT ''
+);
+INSERT INTO tasks_without_statistics
+    SELECT id, content, deadline, duration, importance, tags, not_before, reminders FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_statistics RENAME TO tasks
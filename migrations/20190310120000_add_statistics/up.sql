@@ -0,0 +1,13 @@
+ALTER TABLE tasks ADD COLUMN completed INTEGER;
+ALTER TABLE tasks ADD COLUMN time_spent INTEGER;
+CREATE TABLE snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    taken INTEGER NOT NULL
+);
+CREATE TABLE snapshot_tasks (
+    snapshot_id INTEGER NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, task_id)
+)
@@ -0,0 +1,18 @@
+CREATE TABLE tasks_without_buffers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    not_before INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER
+);
+INSERT INTO tasks_without_buffers
+    SELECT id, content, deadline, duration, importance, tags, not_before, reminders, completed,
+        time_spent
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_buffers RENAME TO tasks
@@ -0,0 +1,19 @@
+CREATE TABLE tasks_with_not_before (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    not_before INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER,
+    buffer INTEGER
+);
+INSERT INTO tasks_with_not_before
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_with_not_before RENAME TO tasks
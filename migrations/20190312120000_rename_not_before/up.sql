@@ -0,0 +1,19 @@
+CREATE TABLE tasks_with_start_after (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    start_after INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER,
+    buffer INTEGER
+);
+INSERT INTO tasks_with_start_after
+    SELECT id, content, deadline, duration, importance, tags, not_before, reminders, completed,
+        time_spent, buffer
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_with_start_after RENAME TO tasks
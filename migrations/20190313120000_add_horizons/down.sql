@@ -0,0 +1,19 @@
+CREATE TABLE tasks_without_horizons (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    start_after INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER,
+    buffer INTEGER
+);
+INSERT INTO tasks_without_horizons
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_horizons RENAME TO tasks
@@ -0,0 +1 @@
+ALTER TABLE tasks ADD COLUMN slipped INTEGER NOT NULL DEFAULT 0
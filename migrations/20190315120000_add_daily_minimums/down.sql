@@ -0,0 +1,43 @@
+CREATE TABLE snapshot_tasks_without_parts (
+    snapshot_id INTEGER NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, task_id)
+);
+INSERT INTO snapshot_tasks_without_parts
+    SELECT snapshot_id, task_id, MIN(start), SUM(duration)
+    FROM snapshot_tasks GROUP BY snapshot_id, task_id;
+DROP TABLE snapshot_tasks;
+ALTER TABLE snapshot_tasks_without_parts RENAME TO snapshot_tasks;
+CREATE TABLE scheduled_tasks_without_parts (
+    kind TEXT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    PRIMARY KEY (kind, task_id)
+);
+INSERT INTO scheduled_tasks_without_parts
+    SELECT kind, task_id, MIN(start) FROM scheduled_tasks GROUP BY kind, task_id;
+DROP TABLE scheduled_tasks;
+ALTER TABLE scheduled_tasks_without_parts RENAME TO scheduled_tasks;
+CREATE TABLE tasks_without_daily_minimums (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    start_after INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER,
+    buffer INTEGER,
+    horizon INTEGER,
+    slipped INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO tasks_without_daily_minimums
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer, horizon, slipped
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_daily_minimums RENAME TO tasks
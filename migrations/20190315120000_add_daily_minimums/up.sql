@@ -0,0 +1,23 @@
+ALTER TABLE tasks ADD COLUMN daily_minimum INTEGER;
+CREATE TABLE scheduled_parts (
+    kind TEXT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    PRIMARY KEY (kind, task_id, start)
+);
+INSERT INTO scheduled_parts
+    SELECT scheduled_tasks.kind, scheduled_tasks.task_id, scheduled_tasks.start, tasks.duration
+    FROM scheduled_tasks JOIN tasks ON tasks.id = scheduled_tasks.task_id;
+DROP TABLE scheduled_tasks;
+ALTER TABLE scheduled_parts RENAME TO scheduled_tasks;
+CREATE TABLE snapshot_parts (
+    snapshot_id INTEGER NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, task_id, start)
+);
+INSERT INTO snapshot_parts SELECT snapshot_id, task_id, start, duration FROM snapshot_tasks;
+DROP TABLE snapshot_tasks;
+ALTER TABLE snapshot_parts RENAME TO snapshot_tasks
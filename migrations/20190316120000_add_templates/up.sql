@@ -0,0 +1,12 @@
+CREATE TABLE templates (
+    name TEXT PRIMARY KEY NOT NULL,
+    content TEXT,
+    due_in INTEGER,
+    duration INTEGER,
+    importance INTEGER,
+    tags TEXT NOT NULL DEFAULT '',
+    reminders TEXT NOT NULL DEFAULT '',
+    buffer INTEGER,
+    horizon INTEGER,
+    daily_minimum INTEGER
+)
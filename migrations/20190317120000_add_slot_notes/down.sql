@@ -0,0 +1,11 @@
+CREATE TABLE scheduled_tasks_without_notes (
+    kind TEXT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    PRIMARY KEY (kind, task_id, start)
+);
+INSERT INTO scheduled_tasks_without_notes
+    SELECT kind, task_id, start, duration FROM scheduled_tasks;
+DROP TABLE scheduled_tasks;
+ALTER TABLE scheduled_tasks_without_notes RENAME TO scheduled_tasks
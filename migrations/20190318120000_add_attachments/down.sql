@@ -0,0 +1,22 @@
+CREATE TABLE tasks_without_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    start_after INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER,
+    buffer INTEGER,
+    horizon INTEGER,
+    slipped INTEGER NOT NULL DEFAULT 0,
+    daily_minimum INTEGER
+);
+INSERT INTO tasks_without_attachments
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer, horizon, slipped, daily_minimum
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_attachments RENAME TO tasks
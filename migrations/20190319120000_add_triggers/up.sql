@@ -0,0 +1 @@
+ALTER TABLE tasks ADD COLUMN waiting_for TEXT
\ No newline at end of file
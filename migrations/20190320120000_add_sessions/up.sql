@@ -0,0 +1,6 @@
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    stop INTEGER
+)
\ No newline at end of file
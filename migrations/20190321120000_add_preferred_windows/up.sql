@@ -0,0 +1 @@
+ALTER TABLE tasks ADD COLUMN preferred_window TEXT
\ No newline at end of file
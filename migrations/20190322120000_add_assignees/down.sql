@@ -0,0 +1,38 @@
+CREATE TABLE tasks_without_assignees (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    start_after INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER,
+    time_spent INTEGER,
+    buffer INTEGER,
+    horizon INTEGER,
+    slipped INTEGER NOT NULL DEFAULT 0,
+    daily_minimum INTEGER,
+    attachments TEXT NOT NULL DEFAULT '',
+    waiting_for TEXT,
+    preferred_window TEXT
+);
+INSERT INTO tasks_without_assignees
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer, horizon, slipped, daily_minimum, attachments, waiting_for,
+        preferred_window
+    FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_without_assignees RENAME TO tasks;
+CREATE TABLE scheduled_tasks_without_assignees (
+    kind TEXT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    note TEXT,
+    PRIMARY KEY (kind, task_id, start)
+);
+INSERT INTO scheduled_tasks_without_assignees
+    SELECT kind, task_id, start, duration, note FROM scheduled_tasks;
+DROP TABLE scheduled_tasks;
+ALTER TABLE scheduled_tasks_without_assignees RENAME TO scheduled_tasks
\ No newline at end of file
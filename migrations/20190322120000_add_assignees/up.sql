@@ -0,0 +1,2 @@
+ALTER TABLE tasks ADD COLUMN assignee TEXT;
+ALTER TABLE scheduled_tasks ADD COLUMN assignee TEXT
\ No newline at end of file
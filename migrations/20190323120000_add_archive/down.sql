@@ -0,0 +1,6 @@
+INSERT INTO tasks
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer, horizon, slipped, daily_minimum, attachments, waiting_for,
+        preferred_window, assignee
+    FROM archived_tasks;
+DROP TABLE archived_tasks
\ No newline at end of file
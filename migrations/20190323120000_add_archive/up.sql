@@ -0,0 +1,27 @@
+CREATE TABLE archived_tasks (
+    id INTEGER PRIMARY KEY NOT NULL,
+    content TEXT NOT NULL,
+    deadline TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    importance INTEGER NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    start_after INTEGER,
+    reminders TEXT NOT NULL DEFAULT '',
+    completed INTEGER NOT NULL,
+    time_spent INTEGER,
+    buffer INTEGER,
+    horizon INTEGER,
+    slipped INTEGER NOT NULL DEFAULT 0,
+    daily_minimum INTEGER,
+    attachments TEXT NOT NULL DEFAULT '',
+    waiting_for TEXT,
+    preferred_window TEXT,
+    assignee TEXT
+);
+INSERT INTO archived_tasks
+    SELECT id, content, deadline, duration, importance, tags, start_after, reminders, completed,
+        time_spent, buffer, horizon, slipped, daily_minimum, attachments, waiting_for,
+        preferred_window, assignee
+    FROM tasks
+    WHERE completed IS NOT NULL;
+DELETE FROM tasks WHERE completed IS NOT NULL
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE scheduled_tasks_without_constraints (
+    kind TEXT NOT NULL,
+    task_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    note TEXT,
+    assignee TEXT,
+    PRIMARY KEY (kind, task_id, start)
+);
+INSERT INTO scheduled_tasks_without_constraints
+    SELECT kind, task_id, start, duration, note, assignee FROM scheduled_tasks;
+DROP TABLE scheduled_tasks;
+ALTER TABLE scheduled_tasks_without_constraints RENAME TO scheduled_tasks
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE scheduled_tasks ADD COLUMN held_back_by TEXT
\ No newline at end of file
@@ -0,0 +1,454 @@
+#!/usr/bin/env python3
+"""Checks the public API of the eva crate against the snapshot in api/eva.txt.
+
+The API is read from the rustdoc JSON of the crate, with its default features, and listed as one
+line per public item: the modules, types, fields, variants, functions and methods, traits and
+their items, and the trait implementations, at every path they can be reached by from the crate
+root. A change to any of them shows up as a diff against the snapshot, so that it can be checked
+against the semantic versioning policy in the documentation of the crate.
+
+    scripts/public_api.py          fails with the diff when the API differs from the snapshot
+    scripts/public_api.py --bless  writes the current API to the snapshot
+
+It needs a nightly toolchain, since rustdoc only writes JSON behind `-Z unstable-options`.
+"""
+
+import difflib
+import json
+import os
+import subprocess
+import sys
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+SNAPSHOT = os.path.join(ROOT, "api", "eva.txt")
+# The versions of the rustdoc JSON format that this script can read
+FORMAT_VERSIONS = range(56, 58)
+
+
+def rustdoc_json():
+    subprocess.run(
+        [
+            "cargo",
+            "+nightly",
+            "rustdoc",
+            "--lib",
+            "--quiet",
+            "--",
+            "-Z",
+            "unstable-options",
+            "--output-format",
+            "json",
+        ],
+        cwd=ROOT,
+        check=True,
+    )
+    target = os.environ.get("CARGO_TARGET_DIR", os.path.join(ROOT, "target"))
+    with open(os.path.join(target, "doc", "eva.json")) as json_file:
+        return json.load(json_file)
+
+
+class Api:
+    def __init__(self, crate):
+        if crate["format_version"] not in FORMAT_VERSIONS:
+            sys.exit(
+                "The rustdoc JSON has format version {}, which this script can't read yet".format(
+                    crate["format_version"]
+                )
+            )
+        self.index = crate["index"]
+        self.paths = crate["paths"]
+        self.visiting = set()
+        # The shortest public path of every local item, which names it wherever it is used
+        self.public_paths = {}
+        root = self.item(crate["root"])
+        # The first walk finds the public paths, the second one lists the items with them
+        for _ in range(2):
+            self.lines = set()
+            self.module(root, root["name"])
+
+    def item(self, id):
+        return self.index.get(str(id))
+
+    def kind(self, item):
+        return next(iter(item["inner"]))
+
+    # Walking the items
+
+    def module(self, module, path):
+        if module["id"] in self.visiting:
+            return
+        self.visiting.add(module["id"])
+        for id in module["inner"]["module"]["items"]:
+            item = self.item(id)
+            if item is not None and item["visibility"] == "public":
+                self.public(item, path)
+        self.visiting.remove(module["id"])
+
+    def public(self, item, parent, name=None):
+        kind = self.kind(item)
+        if kind == "use":
+            self.reexport(item["inner"]["use"], parent)
+            return
+        path = "{}::{}".format(parent, name or item["name"])
+        known = self.public_paths.get(item["id"])
+        if known is None or (known.count("::"), known) > (path.count("::"), path):
+            self.public_paths[item["id"]] = path
+        inner = item["inner"][kind]
+        if kind == "module":
+            self.add("pub mod {}".format(path))
+            self.module(item, path)
+        elif kind == "struct":
+            self.add("{}pub struct {}{}".format(self.attrs(item), path, self.generics(inner["generics"])))
+            self.fields(inner["kind"], path)
+            self.impls(inner["impls"])
+        elif kind == "enum":
+            self.add("{}pub enum {}{}".format(self.attrs(item), path, self.generics(inner["generics"])))
+            for variant in inner["variants"]:
+                self.variant(self.item(variant), path)
+            self.impls(inner["impls"])
+        elif kind == "trait":
+            self.trait(item, path)
+        elif kind == "function":
+            self.add("pub " + self.function(inner, path))
+        elif kind == "constant":
+            self.add("pub const {}: {}".format(path, self.type(inner["type"])))
+        elif kind == "static":
+            mutable = "mut " if inner["is_mutable"] else ""
+            self.add("pub static {}{}: {}".format(mutable, path, self.type(inner["type"])))
+        elif kind == "type_alias":
+            self.add(
+                "pub type {}{} = {}".format(
+                    path, self.generics(inner["generics"]), self.type(inner["type"])
+                )
+            )
+        elif kind == "macro":
+            self.add("pub macro {}!".format(path))
+        else:
+            self.add("pub {} {}".format(kind, path))
+
+    def reexport(self, reexport, parent):
+        target = self.item(reexport["id"]) if reexport["id"] is not None else None
+        if target is None:
+            glob = "::*" if reexport["is_glob"] else ""
+            self.add("pub use {}{}".format(reexport["source"], glob))
+        elif reexport["is_glob"]:
+            self.module(target, parent)
+        else:
+            self.public(target, parent, reexport["name"])
+
+    def fields(self, kind, path):
+        if kind == "unit":
+            return
+        if "tuple" in kind:
+            fields = [(str(index), id) for index, id in enumerate(kind["tuple"]) if id is not None]
+        else:
+            fields = [(None, id) for id in kind["plain"]["fields"]]
+        for name, id in fields:
+            field = self.item(id)
+            if field is not None and field["visibility"] == "public":
+                self.add(
+                    "pub {}::{}: {}".format(
+                        path, name or field["name"], self.type(field["inner"]["struct_field"])
+                    )
+                )
+
+    def variant(self, variant, path):
+        path = "{}::{}".format(path, variant["name"])
+        kind = variant["inner"]["variant"]["kind"]
+        if kind == "plain":
+            self.add("{}pub {}".format(self.attrs(variant), path))
+        elif "tuple" in kind:
+            types = [
+                self.type(self.item(id)["inner"]["struct_field"]) if id is not None else "_"
+                for id in kind["tuple"]
+            ]
+            self.add("{}pub {}({})".format(self.attrs(variant), path, ", ".join(types)))
+        else:
+            self.add("{}pub {}".format(self.attrs(variant), path))
+            for id in kind["struct"]["fields"]:
+                field = self.item(id)
+                self.add(
+                    "pub {}::{}: {}".format(
+                        path, field["name"], self.type(field["inner"]["struct_field"])
+                    )
+                )
+
+    def impls(self, ids):
+        for id in ids:
+            inner = self.item(id)["inner"]["impl"]
+            if inner["is_synthetic"] or inner["blanket_impl"] is not None:
+                continue
+            if inner["trait"] is not None:
+                self.add(self.impl(inner))
+                continue
+            for item_id in inner["items"]:
+                item = self.item(item_id)
+                if item is None or item["visibility"] != "public":
+                    continue
+                kind = self.kind(item)
+                item_path = "{}::{}".format(self.type(inner["for"]), item["name"])
+                if kind == "function":
+                    self.add("pub " + self.function(item["inner"]["function"], item_path))
+                elif kind == "assoc_const":
+                    self.add("pub const {}: {}".format(item_path, self.type(item["inner"][kind]["type"])))
+
+    def impl(self, inner):
+        negative = "!" if inner["is_negative"] else ""
+        unsafe = "unsafe " if inner["is_unsafe"] else ""
+        return "{}impl{} {}{} for {}{}".format(
+            unsafe,
+            self.generics(inner["generics"]),
+            negative,
+            self.path(inner["trait"]),
+            self.type(inner["for"]),
+            self.where_clause(inner["generics"]),
+        )
+
+    def trait(self, item, path):
+        inner = item["inner"]["trait"]
+        unsafe = "unsafe " if inner["is_unsafe"] else ""
+        bounds = ": " + self.bounds(inner["bounds"]) if inner["bounds"] else ""
+        self.add(
+            "pub {}trait {}{}{}{}".format(
+                unsafe,
+                path,
+                self.generics(inner["generics"]),
+                bounds,
+                self.where_clause(inner["generics"]),
+            )
+        )
+        for id in inner["items"]:
+            trait_item = self.item(id)
+            kind = self.kind(trait_item)
+            item_path = "{}::{}".format(path, trait_item["name"])
+            trait_inner = trait_item["inner"][kind]
+            if kind == "function":
+                self.add("pub " + self.function(trait_inner, item_path))
+            elif kind == "assoc_type":
+                bounds = ": " + self.bounds(trait_inner["bounds"]) if trait_inner["bounds"] else ""
+                self.add("pub type {}{}".format(item_path, bounds))
+            elif kind == "assoc_const":
+                self.add("pub const {}: {}".format(item_path, self.type(trait_inner["type"])))
+        for id in inner["implementations"]:
+            impl = self.item(id)["inner"]["impl"]
+            if not impl["is_synthetic"] and impl["blanket_impl"] is None:
+                self.add(self.impl(impl))
+
+    def attrs(self, item):
+        return "#[non_exhaustive] " if "non_exhaustive" in item["attrs"] else ""
+
+    def add(self, line):
+        self.lines.add(line)
+
+    # Rendering signatures
+
+    def function(self, inner, path):
+        header = inner["header"]
+        qualifiers = "".join(
+            qualifier + " "
+            for qualifier, present in [
+                ("const", header["is_const"]),
+                ("async", header["is_async"]),
+                ("unsafe", header["is_unsafe"]),
+            ]
+            if present
+        )
+        sig = inner["sig"]
+        output = " -> " + self.type(sig["output"]) if sig["output"] is not None else ""
+        return "{}fn {}{}({}){}{}".format(
+            qualifiers,
+            path,
+            self.generics(inner["generics"]),
+            ", ".join(self.input(name, type) for name, type in sig["inputs"]),
+            output,
+            self.where_clause(inner["generics"]),
+        )
+
+    def input(self, name, type):
+        if name == "self":
+            if type == {"generic": "Self"}:
+                return "self"
+            reference = type.get("borrowed_ref")
+            if reference is not None and reference["type"] == {"generic": "Self"}:
+                return "&{}{}self".format(self.lifetime(reference["lifetime"]), self.mutable(reference))
+        return "{}: {}".format(name, self.type(type))
+
+    def generics(self, generics):
+        params = []
+        for param in generics["params"]:
+            kind = param["kind"]
+            if "lifetime" in kind:
+                outlives = kind["lifetime"]["outlives"]
+                params.append(param["name"] + (": " + " + ".join(outlives) if outlives else ""))
+            elif "type" in kind:
+                if kind["type"]["is_synthetic"]:
+                    continue
+                bounds = kind["type"]["bounds"]
+                default = kind["type"]["default"]
+                params.append(
+                    param["name"]
+                    + (": " + self.bounds(bounds) if bounds else "")
+                    + (" = " + self.type(default) if default is not None else "")
+                )
+            else:
+                params.append("const {}: {}".format(param["name"], self.type(kind["const"]["type"])))
+        return "<{}>".format(", ".join(params)) if params else ""
+
+    def where_clause(self, generics):
+        predicates = []
+        for predicate in generics["where_predicates"]:
+            if "bound_predicate" in predicate:
+                bound = predicate["bound_predicate"]
+                if bound["type"].get("generic", "").startswith("impl "):
+                    continue
+                predicates.append(
+                    "{}{}: {}".format(
+                        self.higher_ranked(bound["generic_params"]),
+                        self.type(bound["type"]),
+                        self.bounds(bound["bounds"]),
+                    )
+                )
+            elif "lifetime_predicate" in predicate:
+                lifetime = predicate["lifetime_predicate"]
+                predicates.append("{}: {}".format(lifetime["lifetime"], " + ".join(lifetime["outlives"])))
+            else:
+                equality = predicate["eq_predicate"]
+                predicates.append("{} = {}".format(self.type(equality["lhs"]), self.term(equality["rhs"])))
+        return " where " + ", ".join(predicates) if predicates else ""
+
+    def higher_ranked(self, params):
+        return "for<{}> ".format(", ".join(param["name"] for param in params)) if params else ""
+
+    def bounds(self, bounds):
+        rendered = []
+        for bound in bounds:
+            if "trait_bound" in bound:
+                trait_bound = bound["trait_bound"]
+                maybe = "?" if trait_bound["modifier"] == "maybe" else ""
+                rendered.append(
+                    self.higher_ranked(trait_bound["generic_params"]) + maybe + self.path(trait_bound["trait"])
+                )
+            elif "outlives" in bound:
+                rendered.append(bound["outlives"])
+            else:
+                rendered.append("use<{}>".format(", ".join(bound["use"])))
+        return " + ".join(rendered)
+
+    def path(self, path):
+        name = self.public_paths.get(path["id"])
+        if name is None:
+            summary = self.paths.get(str(path["id"]))
+            name = "::".join(summary["path"]) if summary is not None else path["path"]
+        return name + self.args(path["args"])
+
+    def args(self, args):
+        if args is None:
+            return ""
+        if "parenthesized" in args:
+            parenthesized = args["parenthesized"]
+            output = parenthesized["output"]
+            return "({}){}".format(
+                ", ".join(self.type(input) for input in parenthesized["inputs"]),
+                " -> " + self.type(output) if output is not None else "",
+            )
+        if "return_type_notation" in args:
+            return "(..)"
+        angle_bracketed = args["angle_bracketed"]
+        rendered = []
+        for arg in angle_bracketed["args"]:
+            if arg == "infer":
+                rendered.append("_")
+            elif "lifetime" in arg:
+                rendered.append(arg["lifetime"])
+            elif "type" in arg:
+                rendered.append(self.type(arg["type"]))
+            else:
+                rendered.append(arg["const"]["expr"])
+        for constraint in angle_bracketed["constraints"]:
+            name = constraint["name"] + self.args(constraint["args"])
+            binding = constraint["binding"]
+            if "equality" in binding:
+                rendered.append("{} = {}".format(name, self.term(binding["equality"])))
+            else:
+                rendered.append("{}: {}".format(name, self.bounds(binding["constraint"])))
+        return "<{}>".format(", ".join(rendered)) if rendered else ""
+
+    def term(self, term):
+        return self.type(term["type"]) if "type" in term else term["constant"]["expr"]
+
+    def lifetime(self, lifetime):
+        return lifetime + " " if lifetime is not None else ""
+
+    def mutable(self, reference):
+        return "mut " if reference["is_mutable"] else ""
+
+    def type(self, type):
+        if type == "infer":
+            return "_"
+        kind, inner = next(iter(type.items()))
+        if kind == "resolved_path":
+            return self.path(inner)
+        if kind in ("generic", "primitive"):
+            return inner
+        if kind == "borrowed_ref":
+            return "&{}{}{}".format(self.lifetime(inner["lifetime"]), self.mutable(inner), self.type(inner["type"]))
+        if kind == "raw_pointer":
+            return "*{} {}".format("mut" if inner["is_mutable"] else "const", self.type(inner["type"]))
+        if kind == "tuple":
+            types = [self.type(element) for element in inner]
+            return "({},)".format(types[0]) if len(types) == 1 else "({})".format(", ".join(types))
+        if kind == "slice":
+            return "[{}]".format(self.type(inner))
+        if kind == "array":
+            return "[{}; {}]".format(self.type(inner["type"]), inner["len"])
+        if kind == "impl_trait":
+            return "impl " + self.bounds(inner)
+        if kind == "dyn_trait":
+            traits = [
+                self.higher_ranked(poly["generic_params"]) + self.path(poly["trait"]) for poly in inner["traits"]
+            ]
+            if inner["lifetime"] is not None:
+                traits.append(inner["lifetime"])
+            return "dyn " + " + ".join(traits)
+        if kind == "qualified_path":
+            self_type = self.type(inner["self_type"])
+            if inner["trait"] is None:
+                return "{}::{}{}".format(self_type, inner["name"], self.args(inner["args"]))
+            return "<{} as {}>::{}{}".format(
+                self_type, self.path(inner["trait"]), inner["name"], self.args(inner["args"])
+            )
+        if kind == "function_pointer":
+            sig = inner["sig"]
+            output = " -> " + self.type(sig["output"]) if sig["output"] is not None else ""
+            return "{}fn({}){}".format(
+                self.higher_ranked(inner["generic_params"]),
+                ", ".join(self.type(input) for _, input in sig["inputs"]),
+                output,
+            )
+        if kind == "pat":
+            return self.type(inner["type"])
+        sys.exit("The rustdoc JSON has a type this script can't read: {}".format(kind))
+
+
+def main():
+    api = "".join(line + "\n" for line in sorted(Api(rustdoc_json()).lines))
+    if sys.argv[1:] == ["--bless"]:
+        with open(SNAPSHOT, "w") as snapshot:
+            snapshot.write(api)
+        return
+    with open(SNAPSHOT) as snapshot:
+        expected = snapshot.read()
+    if api != expected:
+        sys.stdout.writelines(
+            difflib.unified_diff(
+                expected.splitlines(True), api.splitlines(True), "api/eva.txt", "the current API"
+            )
+        )
+        sys.exit(
+            "\nThe public API changed. Check the change against the semantic versioning policy "
+            "in src/lib.rs, and run scripts/public_api.py --bless to update the snapshot."
+        )
+
+
+if __name__ == "__main__":
+    main()
@@ -1,7 +1,14 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
 use cfg_if::cfg_if;
-use chrono::{DateTime, Utc};
+use std::fmt;
+
+use chrono::offset::{Offset, TimeZone};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Utc};
 
 use crate::database::Database;
+use crate::Task;
 
 cfg_if! {
     if #[cfg(feature = "clock")] {
@@ -9,21 +16,99 @@ cfg_if! {
         pub struct Configuration {
             pub database: Box<Database>,
             pub scheduling_strategy: SchedulingStrategy,
+            /// The weights of the projects (by tag) that share the available time when there isn't
+            /// enough of it.
+            pub project_weights: HashMap<String, u32>,
+            /// How long before its scheduled start to be reminded of a task that doesn't have
+            /// reminders of its own.
+            pub reminders: Vec<Duration>,
+            /// The breathing room to keep free after every task that doesn't have a buffer of its
+            /// own.
+            pub buffer: Duration,
+            /// The periods in which nothing can be scheduled, like public holidays.
+            pub unavailable: Vec<Range<DateTime<Utc>>>,
+            /// The people that tasks are shared between, with the periods in which each of them
+            /// is unavailable on top of `unavailable`. The tasks without an assignee are assigned
+            /// to one of them when there are any.
+            pub assignees: HashMap<String, Vec<Range<DateTime<Utc>>>>,
+            /// How soon the work on a task has to start for it to be scheduled, unless it has a
+            /// horizon of its own. Without one, all tasks are scheduled.
+            pub horizon: Option<Duration>,
+            /// How much time a week goes to the tasks with a tag, by tag. Every assignee has
+            /// these budgets for themselves.
+            pub budgets: HashMap<String, Budget>,
+            pub urgency: UrgencyCoefficients,
+            /// The keywords in the content of a task that say it should get a tag, by tag.
+            pub tag_rules: HashMap<String, Vec<String>>,
+            /// Where the user is now, like the office, for the tasks that are snoozed until then.
+            pub place: Option<String>,
+            /// The local time of the preferred windows of tasks.
+            pub local_time: Box<LocalTime>,
+            /// How long the focus sessions are that schedules can be sliced into, with the breaks
+            /// after them, if there are any.
+            pub focus_sessions: Option<FocusSessions>,
         }
     } else {
         #[derive(Debug)]
         pub struct Configuration {
             pub database: Box<Database>,
             pub scheduling_strategy: SchedulingStrategy,
+            /// The weights of the projects (by tag) that share the available time when there isn't
+            /// enough of it.
+            pub project_weights: HashMap<String, u32>,
+            /// How long before its scheduled start to be reminded of a task that doesn't have
+            /// reminders of its own.
+            pub reminders: Vec<Duration>,
+            /// The breathing room to keep free after every task that doesn't have a buffer of its
+            /// own.
+            pub buffer: Duration,
+            /// The periods in which nothing can be scheduled, like public holidays.
+            pub unavailable: Vec<Range<DateTime<Utc>>>,
+            /// The people that tasks are shared between, with the periods in which each of them
+            /// is unavailable on top of `unavailable`. The tasks without an assignee are assigned
+            /// to one of them when there are any.
+            pub assignees: HashMap<String, Vec<Range<DateTime<Utc>>>>,
+            /// How soon the work on a task has to start for it to be scheduled, unless it has a
+            /// horizon of its own. Without one, all tasks are scheduled.
+            pub horizon: Option<Duration>,
+            /// How much time a week goes to the tasks with a tag, by tag. Every assignee has
+            /// these budgets for themselves.
+            pub budgets: HashMap<String, Budget>,
+            pub urgency: UrgencyCoefficients,
+            /// The keywords in the content of a task that say it should get a tag, by tag.
+            pub tag_rules: HashMap<String, Vec<String>>,
+            /// Where the user is now, like the office, for the tasks that are snoozed until then.
+            pub place: Option<String>,
+            /// The local time of the preferred windows of tasks.
+            pub local_time: Box<LocalTime>,
+            /// How long the focus sessions are that schedules can be sliced into, with the breaks
+            /// after them, if there are any.
+            pub focus_sessions: Option<FocusSessions>,
             pub time_context: Box<TimeContext>,
         }
     }
 }
 
-#[derive(Debug)]
+impl Configuration {
+    /// Returns the periods in which `assignee` is unavailable, or in which the tasks without an
+    /// assignee can't be scheduled when `assignee` is `None`.
+    pub fn unavailable_to(&self, assignee: Option<&str>) -> Vec<Range<DateTime<Utc>>> {
+        let mut unavailable = self.unavailable.clone();
+        if let Some(own) = assignee.and_then(|assignee| self.assignees.get(assignee)) {
+            unavailable.extend(own.iter().cloned());
+        }
+        unavailable
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum SchedulingStrategy {
     Importance,
     Urgency,
+    /// Searches for the order that minimizes the weighted lateness of the tasks, for small sets of
+    /// tasks. Larger ones are scheduled according to importance.
+    Optimal,
 }
 
 impl SchedulingStrategy {
@@ -31,10 +116,122 @@ impl SchedulingStrategy {
         match *self {
             SchedulingStrategy::Importance => "importance",
             SchedulingStrategy::Urgency => "urgency",
+            SchedulingStrategy::Optimal => "optimal",
+        }
+    }
+}
+
+/// How much time a week should go to the tasks with a tag. Weeks run from Monday to Sunday in
+/// UTC, and a task counts in the week it starts in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The time that schedules plan for the tag in a week never exceeds this.
+    pub maximum: Option<Duration>,
+    /// The time that schedules try to plan for the tag in the current week.
+    pub minimum: Option<Duration>,
+}
+
+/// How long the sessions of focused work that schedules are sliced into last, and the breaks
+/// between them, like the 25 minutes of work and 5 minute break of the Pomodoro technique. These
+/// are the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusSessions {
+    pub focus: Duration,
+    pub pause: Duration,
+}
+
+impl Default for FocusSessions {
+    fn default() -> Self {
+        FocusSessions {
+            focus: Duration::minutes(25),
+            pause: Duration::minutes(5),
+        }
+    }
+}
+
+/// How the importance of a task and the nearness of its deadline add up to its urgency, like the
+/// urgency coefficients of Taskwarrior. The importance strategy schedules the most urgent tasks
+/// first.
+///
+/// The default coefficients make the urgency of a task equal to its importance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UrgencyCoefficients {
+    /// How much every point of importance adds to the urgency.
+    pub importance: f64,
+    /// How much is added to the urgency of a task that is due. This grows linearly from nothing
+    /// when the deadline is `horizon` away.
+    pub due: f64,
+    pub horizon: Duration,
+}
+
+impl UrgencyCoefficients {
+    /// Returns how urgent `task` is at `now`.
+    pub fn urgency(&self, task: &Task, now: DateTime<Utc>) -> f64 {
+        let time_left = task.deadline.signed_duration_since(now);
+        let nearness = if time_left >= self.horizon {
+            0.0
+        } else if time_left <= Duration::zero() {
+            1.0
+        } else {
+            1.0 - time_left.num_seconds() as f64 / self.horizon.num_seconds() as f64
+        };
+        self.importance * f64::from(task.importance) + self.due * nearness
+    }
+}
+
+impl Default for UrgencyCoefficients {
+    fn default() -> Self {
+        UrgencyCoefficients {
+            importance: 1.0,
+            due: 0.0,
+            horizon: Duration::weeks(2),
         }
     }
 }
 
+/// The time that the clocks show where the user is. How far it is ahead of UTC can change, like
+/// when daylight saving time starts or ends. Every `TimeZone` of chrono is one, like `Utc` or a
+/// `FixedOffset`.
+pub trait LocalTime: Send + Sync {
+    /// Returns how far local time is ahead of UTC at `moment`.
+    fn offset_at(&self, moment: DateTime<Utc>) -> FixedOffset;
+
+    /// Returns the moment at which the clocks show `local`. Where they skip it, that is the first
+    /// moment after it that they do show.
+    fn moment_at(&self, local: NaiveDateTime) -> DateTime<Utc>;
+
+    /// Returns what the clocks show at `moment`.
+    fn local(&self, moment: DateTime<Utc>) -> NaiveDateTime {
+        moment.naive_utc() + self.offset_at(moment)
+    }
+}
+
+impl<Z: TimeZone + Send + Sync> LocalTime for Z {
+    fn offset_at(&self, moment: DateTime<Utc>) -> FixedOffset {
+        self.offset_from_utc_datetime(&moment.naive_utc()).fix()
+    }
+
+    fn moment_at(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        // Where the clocks skip `local`, take the first minute after it that they show
+        (0..=24 * 60)
+            .filter_map(|minutes| {
+                let local = local.checked_add_signed(Duration::minutes(minutes))?;
+                self.from_local_datetime(&local).earliest()
+            })
+            .next()
+            .map_or_else(
+                || DateTime::from_utc(local, Utc),
+                |moment| moment.with_timezone(&Utc),
+            )
+    }
+}
+
+impl fmt::Debug for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<local time>")
+    }
+}
+
 cfg_if! {
     if #[cfg(feature = "clock")] {
         impl Configuration {
@@ -43,9 +240,7 @@ cfg_if! {
             }
         }
     } else {
-        use std::fmt;
-
-        pub trait TimeContext {
+        pub trait TimeContext: Send {
             fn now(&self) -> DateTime<Utc>;
         }
 
@@ -62,3 +257,30 @@ cfg_if! {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn urgency_grows_as_the_deadline_approaches() {
+        let now = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+        let task = |days_left| Task {
+            deadline: now + Duration::days(days_left),
+            importance: 5,
+            ..Default::default()
+        };
+        let coefficients = UrgencyCoefficients {
+            importance: 2.0,
+            due: 12.0,
+            horizon: Duration::days(4),
+        };
+        assert_eq!(coefficients.urgency(&task(10), now), 10.0);
+        assert_eq!(coefficients.urgency(&task(4), now), 10.0);
+        assert_eq!(coefficients.urgency(&task(1), now), 19.0);
+        assert_eq!(coefficients.urgency(&task(-1), now), 22.0);
+        assert_eq!(UrgencyCoefficients::default().urgency(&task(1), now), 5.0);
+    }
+}
@@ -0,0 +1,498 @@
+//! Encryption at rest for any `Database`: `Encrypted` encrypts the contents, tags, attachments
+//! and assignees of the tasks and the places that they wait for, the templates, and the notes on
+//! the schedule and the tags whose budget its slots wait for before they are stored, and decrypts
+//! them when they are loaded. The ids, moments
+//! and durations are stored as they are, so that the database can still sort and look them up.
+//! The names of the templates and the work sessions are stored as they are as well.
+
+use chrono::{DateTime, Utc};
+use futures::future;
+use futures::future::LocalFutureObj;
+use futures::prelude::*;
+
+use super::{Database, ScheduleKind};
+use crate::errors::*;
+use crate::statistics::Snapshot;
+use crate::tracking::Session;
+use crate::{Constraint, NewTask, Schedule, ScheduledTask, Task, Template, Trigger};
+
+/// What every encrypted value starts with, to tell it apart from the values that were stored
+/// before the database was encrypted. Those are loaded as they are.
+const ENCRYPTED_PREFIX: &str = "encrypted:";
+
+/// Encrypts and decrypts the values that `Encrypted` stores.
+pub trait Cipher: Send {
+    /// Encrypts `plaintext`. The result may only contain the characters of Base64, because some
+    /// values are stored in lists that are separated by commas.
+    fn encrypt(&self, plaintext: &str) -> Result<String>;
+    /// Decrypts what `encrypt` returned.
+    fn decrypt(&self, ciphertext: &str) -> Result<String>;
+}
+
+/// A database that encrypts what it stores in `database` with `cipher`.
+#[derive(Debug)]
+pub struct Encrypted<D, C> {
+    database: D,
+    cipher: C,
+}
+
+impl<D, C: Cipher> Encrypted<D, C> {
+    pub fn new(database: D, cipher: C) -> Encrypted<D, C> {
+        Encrypted { database, cipher }
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String> {
+        Ok(format!(
+            "{}{}",
+            ENCRYPTED_PREFIX,
+            self.cipher.encrypt(plaintext)?
+        ))
+    }
+
+    fn decrypt(&self, value: &str) -> Result<String> {
+        if value.starts_with(ENCRYPTED_PREFIX) {
+            self.cipher.decrypt(&value[ENCRYPTED_PREFIX.len()..])
+        } else {
+            Ok(value.to_owned())
+        }
+    }
+
+    fn encrypt_all(&self, values: &[String]) -> Result<Vec<String>> {
+        values.iter().map(|value| self.encrypt(value)).collect()
+    }
+
+    fn decrypt_all(&self, values: &[String]) -> Result<Vec<String>> {
+        values.iter().map(|value| self.decrypt(value)).collect()
+    }
+
+    fn encrypt_new_task(&self, mut task: NewTask) -> Result<NewTask> {
+        task.content = self.encrypt(&task.content)?;
+        task.tags = self.encrypt_all(&task.tags)?;
+        task.attachments = self.encrypt_all(&task.attachments)?;
+        task.assignee = task
+            .assignee
+            .map(|assignee| self.encrypt(&assignee))
+            .transpose()?;
+        Ok(task)
+    }
+
+    fn encrypt_task(&self, mut task: Task) -> Result<Task> {
+        task.content = self.encrypt(&task.content)?;
+        task.tags = self.encrypt_all(&task.tags)?;
+        task.attachments = self.encrypt_all(&task.attachments)?;
+        if let Some(Trigger::At(place)) = &task.waiting_for {
+            task.waiting_for = Some(Trigger::At(self.encrypt(place)?));
+        }
+        task.assignee = task
+            .assignee
+            .map(|assignee| self.encrypt(&assignee))
+            .transpose()?;
+        Ok(task)
+    }
+
+    fn decrypt_task(&self, mut task: Task) -> Result<Task> {
+        task.content = self.decrypt(&task.content)?;
+        task.tags = self.decrypt_all(&task.tags)?;
+        task.attachments = self.decrypt_all(&task.attachments)?;
+        if let Some(Trigger::At(place)) = &task.waiting_for {
+            task.waiting_for = Some(Trigger::At(self.decrypt(place)?));
+        }
+        task.assignee = task
+            .assignee
+            .map(|assignee| self.decrypt(&assignee))
+            .transpose()?;
+        Ok(task)
+    }
+
+    fn encrypt_tasks(&self, tasks: Vec<Task>) -> Result<Vec<Task>> {
+        tasks
+            .into_iter()
+            .map(|task| self.encrypt_task(task))
+            .collect()
+    }
+
+    fn decrypt_tasks(&self, tasks: Vec<Task>) -> Result<Vec<Task>> {
+        tasks
+            .into_iter()
+            .map(|task| self.decrypt_task(task))
+            .collect()
+    }
+
+    fn encrypt_schedule(&self, schedule: Schedule) -> Result<Schedule> {
+        let scheduled_tasks = schedule
+            .0
+            .into_iter()
+            .map(|scheduled_task| {
+                Ok(ScheduledTask {
+                    task: self.encrypt_task(scheduled_task.task)?,
+                    when: scheduled_task.when,
+                    note: scheduled_task
+                        .note
+                        .map(|note| self.encrypt(&note))
+                        .transpose()?,
+                    held_back_by: scheduled_task
+                        .held_back_by
+                        .map(|constraint| self.encrypt_constraint(constraint))
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Schedule(scheduled_tasks))
+    }
+
+    fn decrypt_schedule(&self, schedule: Schedule) -> Result<Schedule> {
+        let scheduled_tasks = schedule
+            .0
+            .into_iter()
+            .map(|scheduled_task| {
+                Ok(ScheduledTask {
+                    task: self.decrypt_task(scheduled_task.task)?,
+                    when: scheduled_task.when,
+                    note: scheduled_task
+                        .note
+                        .map(|note| self.decrypt(&note))
+                        .transpose()?,
+                    held_back_by: scheduled_task
+                        .held_back_by
+                        .map(|constraint| self.decrypt_constraint(constraint))
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Schedule(scheduled_tasks))
+    }
+
+    /// Encrypts the task ahead of a slot and the tag whose budget it waits for.
+    fn encrypt_constraint(&self, constraint: Constraint) -> Result<Constraint> {
+        Ok(match constraint {
+            Constraint::TaskAhead(task) => Constraint::TaskAhead(self.encrypt_task(task)?),
+            Constraint::Budget(tag) => Constraint::Budget(self.encrypt(&tag)?),
+            constraint => constraint,
+        })
+    }
+
+    fn decrypt_constraint(&self, constraint: Constraint) -> Result<Constraint> {
+        Ok(match constraint {
+            Constraint::TaskAhead(task) => Constraint::TaskAhead(self.decrypt_task(task)?),
+            Constraint::Budget(tag) => Constraint::Budget(self.decrypt(&tag)?),
+            constraint => constraint,
+        })
+    }
+
+    fn encrypt_template(&self, mut template: Template) -> Result<Template> {
+        template.content = template
+            .content
+            .map(|content| self.encrypt(&content))
+            .transpose()?;
+        template.tags = self.encrypt_all(&template.tags)?;
+        Ok(template)
+    }
+
+    fn decrypt_template(&self, mut template: Template) -> Result<Template> {
+        template.content = template
+            .content
+            .map(|content| self.decrypt(&content))
+            .transpose()?;
+        template.tags = self.decrypt_all(&template.tags)?;
+        Ok(template)
+    }
+}
+
+impl<D: Database, C: Cipher> Database for Encrypted<D, C> {
+    fn add_task<'a: 'b, 'b>(&'a self, task: NewTask) -> LocalFutureObj<'b, Result<Task>> {
+        let future = future::ready(self.encrypt_new_task(task).chain_err(encrypting("a task")))
+            .and_then(move |task| self.database.add_task(task))
+            .and_then(move |task| {
+                future::ready(self.decrypt_task(task).chain_err(decrypting("a task")))
+            });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn remove_task<'a: 'b, 'b>(&'a self, id: u32) -> LocalFutureObj<'b, Result<()>> {
+        self.database.remove_task(id)
+    }
+
+    fn find_task<'a: 'b, 'b>(&'a self, id: u32) -> LocalFutureObj<'b, Result<Task>> {
+        let future = self.database.find_task(id).and_then(move |task| {
+            future::ready(self.decrypt_task(task).chain_err(decrypting("a task")))
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn update_task<'a: 'b, 'b>(&'a self, task: Task) -> LocalFutureObj<'b, Result<()>> {
+        let future = future::ready(self.encrypt_task(task).chain_err(encrypting("a task")))
+            .and_then(move |task| self.database.update_task(task));
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn update_tasks<'a: 'b, 'b>(&'a self, tasks: Vec<Task>) -> LocalFutureObj<'b, Result<()>> {
+        let future = future::ready(self.encrypt_tasks(tasks).chain_err(encrypting("the tasks")))
+            .and_then(move |tasks| self.database.update_tasks(tasks));
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn modify_tasks<'a: 'b, 'b>(
+        &'a self,
+        ids: Vec<u32>,
+        mut modify: Box<FnMut(&mut Task) -> Result<()> + 'b>,
+    ) -> LocalFutureObj<'b, Result<Vec<Task>>> {
+        // The tasks are only modified while they are decrypted
+        let modify_decrypted = move |task: &mut Task| {
+            let mut decrypted = self
+                .decrypt_task(task.clone())
+                .chain_err(decrypting("a task"))?;
+            modify(&mut decrypted)?;
+            *task = self
+                .encrypt_task(decrypted)
+                .chain_err(encrypting("a task"))?;
+            Ok(())
+        };
+        let future = self
+            .database
+            .modify_tasks(ids, Box::new(modify_decrypted))
+            .and_then(move |tasks| {
+                future::ready(self.decrypt_tasks(tasks).chain_err(decrypting("the tasks")))
+            });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn all_tasks<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Task>>> {
+        let future = self.database.all_tasks().and_then(move |tasks| {
+            future::ready(self.decrypt_tasks(tasks).chain_err(decrypting("the tasks")))
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn archive_task<'a: 'b, 'b>(&'a self, task: Task) -> LocalFutureObj<'b, Result<()>> {
+        let future = future::ready(self.encrypt_task(task).chain_err(encrypting("a task")))
+            .and_then(move |task| self.database.archive_task(task));
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn completed_tasks<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Task>>> {
+        let future = self.database.completed_tasks().and_then(move |tasks| {
+            future::ready(
+                self.decrypt_tasks(tasks)
+                    .chain_err(decrypting("the completed tasks")),
+            )
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn save_schedule<'a: 'b, 'b>(
+        &'a self,
+        kind: ScheduleKind,
+        schedule: Schedule,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let future = future::ready(
+            self.encrypt_schedule(schedule)
+                .chain_err(encrypting("a schedule")),
+        )
+        .and_then(move |schedule| self.database.save_schedule(kind, schedule));
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn find_schedule<'a: 'b, 'b>(
+        &'a self,
+        kind: ScheduleKind,
+    ) -> LocalFutureObj<'b, Result<Option<Schedule>>> {
+        let future = self.database.find_schedule(kind).and_then(move |schedule| {
+            future::ready(
+                schedule
+                    .map(|schedule| self.decrypt_schedule(schedule))
+                    .transpose()
+                    .chain_err(decrypting("a schedule")),
+            )
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn remove_schedule<'a: 'b, 'b>(&'a self, kind: ScheduleKind) -> LocalFutureObj<'b, Result<()>> {
+        self.database.remove_schedule(kind)
+    }
+
+    fn accept_schedule<'a: 'b, 'b>(
+        &'a self,
+        schedule: Schedule,
+        snapshot: Snapshot,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let future = future::ready(
+            self.encrypt_schedule(schedule)
+                .chain_err(encrypting("a schedule")),
+        )
+        .and_then(move |schedule| self.database.accept_schedule(schedule, snapshot));
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn roll_over<'a: 'b, 'b>(
+        &'a self,
+        slipped: Vec<Task>,
+        schedule: Schedule,
+        snapshot: Snapshot,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let encrypted: Result<_> = try {
+            let slipped = self.encrypt_tasks(slipped).chain_err(encrypting("the tasks"))?;
+            let schedule = self
+                .encrypt_schedule(schedule)
+                .chain_err(encrypting("a schedule"))?;
+            (slipped, schedule)
+        };
+        let future = future::ready(encrypted).and_then(move |(slipped, schedule)| {
+            self.database.roll_over(slipped, schedule, snapshot)
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn add_snapshot<'a: 'b, 'b>(&'a self, snapshot: Snapshot) -> LocalFutureObj<'b, Result<()>> {
+        self.database.add_snapshot(snapshot)
+    }
+
+    fn snapshots<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Snapshot>>> {
+        self.database.snapshots()
+    }
+
+    fn save_template<'a: 'b, 'b>(&'a self, template: Template) -> LocalFutureObj<'b, Result<()>> {
+        let future = future::ready(
+            self.encrypt_template(template)
+                .chain_err(encrypting("a template")),
+        )
+        .and_then(move |template| self.database.save_template(template));
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn find_template<'a: 'b, 'b>(
+        &'a self,
+        name: &'a str,
+    ) -> LocalFutureObj<'b, Result<Option<Template>>> {
+        let future = self.database.find_template(name).and_then(move |template| {
+            future::ready(
+                template
+                    .map(|template| self.decrypt_template(template))
+                    .transpose()
+                    .chain_err(decrypting("a template")),
+            )
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn remove_template<'a: 'b, 'b>(&'a self, name: &'a str) -> LocalFutureObj<'b, Result<()>> {
+        self.database.remove_template(name)
+    }
+
+    fn templates<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Template>>> {
+        let future = self.database.templates().and_then(move |templates| {
+            future::ready(
+                templates
+                    .into_iter()
+                    .map(|template| self.decrypt_template(template))
+                    .collect::<Result<_>>()
+                    .chain_err(decrypting("the templates")),
+            )
+        });
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn add_session<'a: 'b, 'b>(&'a self, session: Session) -> LocalFutureObj<'b, Result<()>> {
+        self.database.add_session(session)
+    }
+
+    fn end_session<'a: 'b, 'b>(
+        &'a self,
+        end: DateTime<Utc>,
+    ) -> LocalFutureObj<'b, Result<Option<Session>>> {
+        self.database.end_session(end)
+    }
+
+    fn sessions<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Session>>> {
+        self.database.sessions()
+    }
+}
+
+fn encrypting(what: &'static str) -> impl FnOnce() -> ErrorKind {
+    move || ErrorKind::Encryption(format!("while trying to encrypt {}", what))
+}
+
+fn decrypting(what: &'static str) -> impl FnOnce() -> ErrorKind {
+    move || ErrorKind::Encryption(format!("while trying to decrypt {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use super::*;
+
+    /// Stands in for a real cipher by reversing the text, which keeps it recognizable.
+    struct Reverse;
+
+    impl Cipher for Reverse {
+        fn encrypt(&self, plaintext: &str) -> Result<String> {
+            Ok(plaintext.chars().rev().collect())
+        }
+
+        fn decrypt(&self, ciphertext: &str) -> Result<String> {
+            Ok(ciphertext.chars().rev().collect())
+        }
+    }
+
+    /// Only encrypts and decrypts, without a database to store anything in.
+    fn encrypted() -> Encrypted<(), Reverse> {
+        Encrypted::new((), Reverse)
+    }
+
+    fn task() -> Task {
+        Task {
+            id: 1,
+            content: "Call Alice".to_owned(),
+            deadline: Utc.ymd(2019, 3, 4).and_hms(9, 0, 0),
+            tags: vec!["clients".to_owned(), "phone".to_owned()],
+            attachments: vec!["~/alice.vcf".to_owned()],
+            waiting_for: Some(Trigger::At("office".to_owned())),
+            assignee: Some("sam".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn everything_that_is_private_is_encrypted() {
+        let encrypted = encrypted();
+        let stored = encrypted.encrypt_task(task()).unwrap();
+        assert_eq!(
+            stored,
+            Task {
+                content: "encrypted:ecilA llaC".to_owned(),
+                tags: vec!["encrypted:stneilc".to_owned(), "encrypted:enohp".to_owned()],
+                attachments: vec!["encrypted:fcv.ecila/~".to_owned()],
+                waiting_for: Some(Trigger::At("encrypted:eciffo".to_owned())),
+                assignee: Some("encrypted:mas".to_owned()),
+                ..task()
+            }
+        );
+        assert_eq!(encrypted.decrypt_task(stored).unwrap(), task());
+
+        let schedule = Schedule(vec![ScheduledTask {
+            note: Some("At the office".to_owned()),
+            ..ScheduledTask::new(task(), task().deadline)
+        }]);
+        let stored = encrypted.encrypt_schedule(schedule.clone()).unwrap();
+        assert_eq!(stored.0[0].note, Some("encrypted:eciffo eht tA".to_owned()));
+        assert_eq!(stored.0[0].task.content, "encrypted:ecilA llaC");
+        assert_eq!(encrypted.decrypt_schedule(stored).unwrap(), schedule);
+
+        let template = Template {
+            name: "call".to_owned(),
+            content: Some("Call {date}".to_owned()),
+            tags: vec!["phone".to_owned()],
+            ..Default::default()
+        };
+        let stored = encrypted.encrypt_template(template.clone()).unwrap();
+        assert_eq!(stored.name, "call");
+        assert_eq!(stored.content, Some("encrypted:}etad{ llaC".to_owned()));
+        assert_eq!(encrypted.decrypt_template(stored).unwrap(), template);
+    }
+
+    #[test]
+    fn values_from_before_the_encryption_are_loaded_as_they_are() {
+        assert_eq!(encrypted().decrypt_task(task()).unwrap(), task());
+    }
+}
@@ -1,19 +1,115 @@
 use std::fmt;
 
+use chrono::{DateTime, Utc};
 use futures::future::LocalFutureObj;
 
 use crate::errors::*;
-use crate::{NewTask, Task};
+use crate::statistics::Snapshot;
+use crate::tracking::Session;
+use crate::{NewTask, Schedule, Task, Template};
 
+pub mod encrypted;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
 
-pub trait Database {
+/// A store of tasks, an archive of the completed ones, schedules, snapshots, templates and work
+/// sessions. It can be moved to another thread, so that every thread of a server can have a
+/// connection of its own.
+pub trait Database: Send {
     fn add_task<'a: 'b, 'b>(&'a self, task: NewTask) -> LocalFutureObj<'b, Result<Task>>;
     fn remove_task<'a: 'b, 'b>(&'a self, id: u32) -> LocalFutureObj<'b, Result<()>>;
+    /// Finds the task with the given id, also when it's in the archive.
     fn find_task<'a: 'b, 'b>(&'a self, id: u32) -> LocalFutureObj<'b, Result<Task>>;
     fn update_task<'a: 'b, 'b>(&'a self, task: Task) -> LocalFutureObj<'b, Result<()>>;
+    /// Updates several tasks in one transaction: when one of the updates fails, none of the tasks
+    /// are modified.
+    fn update_tasks<'a: 'b, 'b>(&'a self, tasks: Vec<Task>) -> LocalFutureObj<'b, Result<()>>;
+    /// Finds the tasks with the given ids, changes every one of them with `modify` and updates
+    /// them, all in one transaction, so that nothing else can change them in between. When one of
+    /// the tasks can't be found or `modify` fails, none of the tasks are modified. Returns the
+    /// modified tasks, in the order of `ids`. Since the transaction can be tried again when the
+    /// database is busy, `modify` can be called more than once for the same task.
+    fn modify_tasks<'a: 'b, 'b>(
+        &'a self,
+        ids: Vec<u32>,
+        modify: Box<FnMut(&mut Task) -> Result<()> + 'b>,
+    ) -> LocalFutureObj<'b, Result<Vec<Task>>>;
+    /// Returns the tasks that haven't been completed yet.
     fn all_tasks<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Task>>>;
+    /// Moves `task`, which was completed, out of the tasks and into the archive in one
+    /// transaction. It keeps its id there, which is never given to another task. A task that is
+    /// already in the archive is replaced by `task`.
+    fn archive_task<'a: 'b, 'b>(&'a self, task: Task) -> LocalFutureObj<'b, Result<()>>;
+    /// Returns the tasks in the archive, which are the ones that were completed.
+    fn completed_tasks<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Task>>>;
+    /// Stores `schedule` as the schedule of the given kind, replacing the previous one.
+    fn save_schedule<'a: 'b, 'b>(
+        &'a self,
+        kind: ScheduleKind,
+        schedule: Schedule,
+    ) -> LocalFutureObj<'b, Result<()>>;
+    /// Returns the stored schedule of the given kind, if there is one. Tasks that were removed or
+    /// archived in the meantime are left out of it, and the other tasks are as they are now.
+    fn find_schedule<'a: 'b, 'b>(
+        &'a self,
+        kind: ScheduleKind,
+    ) -> LocalFutureObj<'b, Result<Option<Schedule>>>;
+    fn remove_schedule<'a: 'b, 'b>(&'a self, kind: ScheduleKind) -> LocalFutureObj<'b, Result<()>>;
+    /// Stores `schedule` as the accepted schedule, removes the proposed one and adds `snapshot`,
+    /// all in one transaction: when one of them fails, nothing changes.
+    fn accept_schedule<'a: 'b, 'b>(
+        &'a self,
+        schedule: Schedule,
+        snapshot: Snapshot,
+    ) -> LocalFutureObj<'b, Result<()>>;
+    /// Updates the `slipped` tasks like `update_tasks` and accepts `schedule` like
+    /// `accept_schedule`, all in one transaction: when one of them fails, nothing changes.
+    fn roll_over<'a: 'b, 'b>(
+        &'a self,
+        slipped: Vec<Task>,
+        schedule: Schedule,
+        snapshot: Snapshot,
+    ) -> LocalFutureObj<'b, Result<()>>;
+    fn add_snapshot<'a: 'b, 'b>(&'a self, snapshot: Snapshot) -> LocalFutureObj<'b, Result<()>>;
+    /// Returns all snapshots, in the order they were taken.
+    fn snapshots<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Snapshot>>>;
+    /// Stores `template`, replacing the one with the same name if there is one.
+    fn save_template<'a: 'b, 'b>(&'a self, template: Template) -> LocalFutureObj<'b, Result<()>>;
+    fn find_template<'a: 'b, 'b>(
+        &'a self,
+        name: &'a str,
+    ) -> LocalFutureObj<'b, Result<Option<Template>>>;
+    fn remove_template<'a: 'b, 'b>(&'a self, name: &'a str) -> LocalFutureObj<'b, Result<()>>;
+    /// Returns all templates, in the order of their names.
+    fn templates<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Template>>>;
+    fn add_session<'a: 'b, 'b>(&'a self, session: Session) -> LocalFutureObj<'b, Result<()>>;
+    /// Ends the session that is still going on at `end`, and returns it as it's stored now. When
+    /// no session is going on, nothing changes.
+    fn end_session<'a: 'b, 'b>(
+        &'a self,
+        end: DateTime<Utc>,
+    ) -> LocalFutureObj<'b, Result<Option<Session>>>;
+    /// Returns all sessions, in the order they started.
+    fn sessions<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Session>>>;
+}
+
+/// The schedules that can be stored in a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScheduleKind {
+    /// A schedule that was generated, but that still has to be accepted.
+    Proposed,
+    /// The schedule that was accepted last, i.e. the plan that is committed to.
+    Accepted,
+}
+
+impl ScheduleKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ScheduleKind::Proposed => "proposed",
+            ScheduleKind::Accepted => "accepted",
+        }
+    }
 }
 
 impl fmt::Debug for Database {
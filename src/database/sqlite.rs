@@ -1,47 +1,279 @@
+use std::collections::HashMap;
 use std::io;
+use std::thread;
+use std::time;
 
 use chrono::prelude::*;
 use chrono::Duration;
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
 use futures::future;
 use futures::future::LocalFutureObj;
 
-use super::Database;
+use super::{Database, ScheduleKind};
 use crate::errors::*;
+use crate::statistics::{PlannedTask, Snapshot};
+use crate::{Constraint, Days, Trigger, Window};
 
+use self::archived_tasks::dsl::archived_tasks as archived_task_table;
+use self::scheduled_tasks::dsl::scheduled_tasks as scheduled_task_table;
+use self::schedules::dsl::schedules as schedule_table;
+use self::sessions::dsl::sessions as session_table;
+use self::snapshot_tasks::dsl::snapshot_tasks as snapshot_task_table;
+use self::snapshots::dsl::snapshots as snapshot_table;
 use self::tasks::dsl::tasks as task_table;
+use self::templates::dsl::templates as template_table;
 
 #[derive(Debug, Clone, PartialEq, Queryable, Identifiable, AsChangeset)]
 #[table_name = "tasks"]
+#[changeset_options(treat_none_as_null = "true")]
 struct Task {
     pub id: i32,
     pub content: String,
-    pub deadline: i32,
-    pub duration: i32,
+    pub deadline: i64,
+    pub duration: i64,
     pub importance: i32,
+    pub tags: String,
+    pub start_after: Option<i64>,
+    pub reminders: String,
+    pub completed: Option<i64>,
+    pub time_spent: Option<i64>,
+    pub buffer: Option<i64>,
+    pub horizon: Option<i64>,
+    pub slipped: i32,
+    pub daily_minimum: Option<i64>,
+    pub attachments: String,
+    pub waiting_for: Option<String>,
+    pub preferred_window: Option<String>,
+    pub assignee: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
 #[table_name = "tasks"]
 struct NewTask {
     pub content: String,
-    pub deadline: i32,
-    pub duration: i32,
+    pub deadline: i64,
+    pub duration: i64,
     pub importance: i32,
+    pub tags: String,
+    pub start_after: Option<i64>,
+    pub reminders: String,
+    pub buffer: Option<i64>,
+    pub horizon: Option<i64>,
+    pub daily_minimum: Option<i64>,
+    pub attachments: String,
+    pub preferred_window: Option<String>,
+    pub assignee: Option<String>,
+}
+
+/// A completed task in the archive, which has the same columns as the tasks. Its `completed` is
+/// never null.
+#[derive(Debug, Clone, PartialEq, Queryable, Insertable)]
+#[table_name = "archived_tasks"]
+struct ArchivedTask {
+    pub id: i32,
+    pub content: String,
+    pub deadline: i64,
+    pub duration: i64,
+    pub importance: i32,
+    pub tags: String,
+    pub start_after: Option<i64>,
+    pub reminders: String,
+    pub completed: Option<i64>,
+    pub time_spent: Option<i64>,
+    pub buffer: Option<i64>,
+    pub horizon: Option<i64>,
+    pub slipped: i32,
+    pub daily_minimum: Option<i64>,
+    pub attachments: String,
+    pub waiting_for: Option<String>,
+    pub preferred_window: Option<String>,
+    pub assignee: Option<String>,
+}
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "templates"]
+struct Template {
+    pub name: String,
+    pub content: Option<String>,
+    pub due_in: Option<i64>,
+    pub duration: Option<i64>,
+    pub importance: Option<i32>,
+    pub tags: String,
+    pub reminders: String,
+    pub buffer: Option<i64>,
+    pub horizon: Option<i64>,
+    pub daily_minimum: Option<i64>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "snapshots"]
+struct NewSnapshot {
+    pub taken: i64,
+}
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "snapshot_tasks"]
+struct SnapshotTask {
+    pub snapshot_id: i32,
+    pub task_id: i32,
+    pub start: i64,
+    pub duration: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "schedules"]
+struct Schedule {
+    pub kind: String,
+}
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "scheduled_tasks"]
+struct ScheduledTask {
+    pub kind: String,
+    pub task_id: i32,
+    pub start: i64,
+    pub duration: i64,
+    pub note: Option<String>,
+    pub assignee: Option<String>,
+    pub held_back_by: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, Identifiable, AsChangeset)]
+#[table_name = "sessions"]
+#[changeset_options(treat_none_as_null = "true")]
+struct Session {
+    pub id: i32,
+    pub task_id: i32,
+    pub start: i64,
+    pub stop: Option<i64>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "sessions"]
+struct NewSession {
+    pub task_id: i32,
+    pub start: i64,
+    pub stop: Option<i64>,
 }
 
 table! {
     tasks (id) {
         id -> Integer,
         content -> Text,
-        deadline -> Integer,
-        duration -> Integer,
+        deadline -> BigInt,
+        duration -> BigInt,
         importance -> Integer,
+        tags -> Text,
+        start_after -> Nullable<BigInt>,
+        reminders -> Text,
+        completed -> Nullable<BigInt>,
+        time_spent -> Nullable<BigInt>,
+        buffer -> Nullable<BigInt>,
+        horizon -> Nullable<BigInt>,
+        slipped -> Integer,
+        daily_minimum -> Nullable<BigInt>,
+        attachments -> Text,
+        waiting_for -> Nullable<Text>,
+        preferred_window -> Nullable<Text>,
+        assignee -> Nullable<Text>,
+    }
+}
+
+table! {
+    archived_tasks (id) {
+        id -> Integer,
+        content -> Text,
+        deadline -> BigInt,
+        duration -> BigInt,
+        importance -> Integer,
+        tags -> Text,
+        start_after -> Nullable<BigInt>,
+        reminders -> Text,
+        completed -> Nullable<BigInt>,
+        time_spent -> Nullable<BigInt>,
+        buffer -> Nullable<BigInt>,
+        horizon -> Nullable<BigInt>,
+        slipped -> Integer,
+        daily_minimum -> Nullable<BigInt>,
+        attachments -> Text,
+        waiting_for -> Nullable<Text>,
+        preferred_window -> Nullable<Text>,
+        assignee -> Nullable<Text>,
+    }
+}
+
+table! {
+    schedules (kind) {
+        kind -> Text,
+    }
+}
+
+table! {
+    scheduled_tasks (kind, task_id, start) {
+        kind -> Text,
+        task_id -> Integer,
+        start -> BigInt,
+        duration -> BigInt,
+        note -> Nullable<Text>,
+        assignee -> Nullable<Text>,
+        held_back_by -> Nullable<Text>,
+    }
+}
+
+table! {
+    templates (name) {
+        name -> Text,
+        content -> Nullable<Text>,
+        due_in -> Nullable<BigInt>,
+        duration -> Nullable<BigInt>,
+        importance -> Nullable<Integer>,
+        tags -> Text,
+        reminders -> Text,
+        buffer -> Nullable<BigInt>,
+        horizon -> Nullable<BigInt>,
+        daily_minimum -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    snapshots (id) {
+        id -> Integer,
+        taken -> BigInt,
+    }
+}
+
+table! {
+    snapshot_tasks (snapshot_id, task_id, start) {
+        snapshot_id -> Integer,
+        task_id -> Integer,
+        start -> BigInt,
+        duration -> BigInt,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Integer,
+        task_id -> Integer,
+        start -> BigInt,
+        stop -> Nullable<BigInt>,
     }
 }
 
 embed_migrations!();
 
+/// How many milliseconds to wait for a database that another connection is writing to.
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// How many times to try a write again when the database stayed locked for longer than the busy
+/// timeout, e.g. because another process was holding on to it.
+const WRITE_RETRIES: u32 = 4;
+
+/// How many milliseconds to wait before trying a write again the first time. The wait doubles
+/// with every retry.
+const FIRST_RETRY_DELAY_MS: u64 = 100;
+
 no_arg_sql_function!(last_insert_rowid, diesel::sql_types::Integer);
 
 impl Database for SqliteConnection {
@@ -49,16 +281,15 @@ impl Database for SqliteConnection {
         &'a self,
         task: crate::NewTask,
     ) -> LocalFutureObj<'b, Result<crate::Task>> {
+        let db_task = NewTask::from(task);
         let future_task = async move {
-            diesel::insert_into(task_table)
-                .values(&NewTask::from(task))
-                .execute(self)
-                .chain_err(|| ErrorKind::Database("while trying to add a task".into()))?;
-            let id = diesel::select(last_insert_rowid)
-                .get_result::<i32>(self)
-                .chain_err(|| {
-                    ErrorKind::Database("while trying to fetch the id of the new task".into())
-                })?;
+            let id = write(self, || {
+                diesel::insert_into(task_table)
+                    .values(&db_task)
+                    .execute(self)?;
+                diesel::select(last_insert_rowid).get_result::<i32>(self)
+            })
+            .chain_err(|| ErrorKind::Database("while trying to add a task".into()))?;
             let task = await!(self.find_task(id as u32)).chain_err(|| {
                 ErrorKind::Database("while trying to fetch the newly created task".into())
             })?;
@@ -69,9 +300,10 @@ impl Database for SqliteConnection {
 
     fn remove_task<'a: 'b, 'b>(&'a self, id: u32) -> LocalFutureObj<'b, Result<()>> {
         let future = async move {
-            let amount_deleted = diesel::delete(task_table.find(id as i32))
-                .execute(self)
-                .chain_err(|| ErrorKind::Database("while trying to remove a task".to_owned()))?;
+            let amount_deleted = write(self, || {
+                diesel::delete(task_table.find(id as i32)).execute(self)
+            })
+            .chain_err(|| ErrorKind::Database("while trying to remove a task".to_owned()))?;
             ensure!(
                 amount_deleted == 1,
                 ErrorKind::Database("while trying to remove a task".to_owned())
@@ -86,6 +318,14 @@ impl Database for SqliteConnection {
             let db_task = task_table
                 .find(id as i32)
                 .get_result::<Task>(self)
+                .optional()
+                .and_then(|db_task| match db_task {
+                    Some(db_task) => Ok(db_task),
+                    None => archived_task_table
+                        .find(id as i32)
+                        .get_result::<ArchivedTask>(self)
+                        .map(Task::from),
+                })
                 .chain_err(|| ErrorKind::Database("while trying to find a task".to_owned()))?;
             crate::Task::from(db_task)
         };
@@ -95,10 +335,10 @@ impl Database for SqliteConnection {
     fn update_task<'a: 'b, 'b>(&'a self, task: crate::Task) -> LocalFutureObj<'b, Result<()>> {
         let db_task = Task::from(task);
         let future = async move {
-            let amount_updated = diesel::update(&db_task)
-                .set(&db_task)
-                .execute(self)
-                .chain_err(|| ErrorKind::Database("while trying to update a task".to_owned()))?;
+            let amount_updated = write(self, || {
+                diesel::update(&db_task).set(&db_task).execute(self)
+            })
+            .chain_err(|| ErrorKind::Database("while trying to update a task".to_owned()))?;
             ensure!(
                 amount_updated == 1,
                 ErrorKind::Database("while trying to remove a task".to_owned())
@@ -108,39 +348,392 @@ impl Database for SqliteConnection {
         LocalFutureObj::new(Box::new(future))
     }
 
+    fn update_tasks<'a: 'b, 'b>(
+        &'a self,
+        tasks: Vec<crate::Task>,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let db_tasks: Vec<Task> = tasks.into_iter().map(Task::from).collect();
+        let result = write(self, || update_each(self, &db_tasks))
+            .chain_err(|| ErrorKind::Database("while trying to update several tasks".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn modify_tasks<'a: 'b, 'b>(
+        &'a self,
+        ids: Vec<u32>,
+        mut modify: Box<FnMut(&mut crate::Task) -> Result<()> + 'b>,
+    ) -> LocalFutureObj<'b, Result<Vec<crate::Task>>> {
+        // Why `modify` failed, since only a database error gets out of the transaction
+        let mut failure = None;
+        let result = write(self, || {
+            let mut tasks = Vec::with_capacity(ids.len());
+            for &id in &ids {
+                let db_task = task_table.find(id as i32).get_result::<Task>(self)?;
+                let mut task = crate::Task::from(db_task);
+                if let Err(error) = modify(&mut task) {
+                    failure = Some(error);
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+                tasks.push(task);
+            }
+            let db_tasks: Vec<Task> = tasks.iter().cloned().map(Task::from).collect();
+            update_each(self, &db_tasks)?;
+            Ok(tasks)
+        });
+        let result = match failure {
+            Some(error) => Err(error),
+            None => result.chain_err(|| {
+                ErrorKind::Database("while trying to modify several tasks".to_owned())
+            }),
+        };
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
     fn all_tasks<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<crate::Task>>> {
         let tasks_result = try {
             let db_tasks = task_table
+                .filter(tasks::completed.is_null())
                 .load::<Task>(self)
                 .chain_err(|| ErrorKind::Database("while trying to retrieve tasks".to_owned()))?;
             db_tasks.into_iter().map(crate::Task::from).collect()
         };
         LocalFutureObj::new(Box::new(future::ready(tasks_result)))
     }
+
+    fn archive_task<'a: 'b, 'b>(&'a self, task: crate::Task) -> LocalFutureObj<'b, Result<()>> {
+        let db_task = ArchivedTask::from(Task::from(task));
+        let result = if db_task.completed.is_none() {
+            Err(ErrorKind::Internal("only completed tasks can be archived".to_owned()).into())
+        } else {
+            write(self, || {
+                diesel::replace_into(archived_task_table)
+                    .values(&db_task)
+                    .execute(self)?;
+                diesel::delete(task_table.find(db_task.id)).execute(self)?;
+                Ok(())
+            })
+            .chain_err(|| ErrorKind::Database("while trying to archive a task".to_owned()))
+        };
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn completed_tasks<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<crate::Task>>> {
+        let tasks_result = try {
+            let db_tasks = archived_task_table
+                .load::<ArchivedTask>(self)
+                .chain_err(|| {
+                    ErrorKind::Database("while trying to retrieve the completed tasks".to_owned())
+                })?;
+            db_tasks
+                .into_iter()
+                .map(|db_task| crate::Task::from(Task::from(db_task)))
+                .collect()
+        };
+        LocalFutureObj::new(Box::new(future::ready(tasks_result)))
+    }
+
+    fn save_schedule<'a: 'b, 'b>(
+        &'a self,
+        kind: ScheduleKind,
+        schedule: crate::Schedule,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let db_scheduled_tasks = to_db_scheduled_tasks(kind, schedule);
+        let result = write(self, || insert_schedule(self, kind, &db_scheduled_tasks))
+            .chain_err(|| ErrorKind::Database("while trying to save a schedule".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn find_schedule<'a: 'b, 'b>(
+        &'a self,
+        kind: ScheduleKind,
+    ) -> LocalFutureObj<'b, Result<Option<crate::Schedule>>> {
+        let kind = kind.as_str();
+        let schedule_result = self
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let exists = schedule_table
+                    .find(kind)
+                    .select(schedules::kind)
+                    .get_result::<String>(self)
+                    .optional()?
+                    .is_some();
+                if !exists {
+                    return Ok(None);
+                }
+                let db_scheduled_tasks = scheduled_task_table
+                    .filter(scheduled_tasks::kind.eq(kind))
+                    .order(scheduled_tasks::start.asc())
+                    .load::<ScheduledTask>(self)?;
+                let tasks: HashMap<i32, Task> = task_table
+                    .load::<Task>(self)?
+                    .into_iter()
+                    .map(|task| (task.id, task))
+                    .collect();
+                let schedule = db_scheduled_tasks
+                    .into_iter()
+                    .filter_map(|db_scheduled_task| {
+                        // A task that is scheduled in parts has the duration of its part, and one
+                        // that was assigned by the schedule has the assignee of its slot
+                        let task = tasks.get(&db_scheduled_task.task_id)?.clone();
+                        let task = crate::Task::from(task);
+                        let held_back_by = db_scheduled_task
+                            .held_back_by
+                            .and_then(|constraint| deserialize_constraint(&constraint, &tasks));
+                        Some(crate::ScheduledTask {
+                            task: crate::Task {
+                                duration: Duration::seconds(db_scheduled_task.duration),
+                                assignee: db_scheduled_task.assignee.or(task.assignee),
+                                ..task
+                            },
+                            when: from_timestamp(db_scheduled_task.start),
+                            note: db_scheduled_task.note,
+                            held_back_by,
+                        })
+                    })
+                    .collect();
+                Ok(Some(crate::Schedule(schedule)))
+            })
+            .chain_err(|| ErrorKind::Database("while trying to find a schedule".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(schedule_result)))
+    }
+
+    fn remove_schedule<'a: 'b, 'b>(&'a self, kind: ScheduleKind) -> LocalFutureObj<'b, Result<()>> {
+        let result = write(self, || delete_schedule(self, kind))
+            .chain_err(|| ErrorKind::Database("while trying to remove a schedule".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn accept_schedule<'a: 'b, 'b>(
+        &'a self,
+        schedule: crate::Schedule,
+        snapshot: Snapshot,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let db_scheduled_tasks = to_db_scheduled_tasks(ScheduleKind::Accepted, schedule);
+        let result = write(self, || {
+            insert_schedule(self, ScheduleKind::Accepted, &db_scheduled_tasks)?;
+            delete_schedule(self, ScheduleKind::Proposed)?;
+            insert_snapshot(self, &snapshot)
+        })
+        .chain_err(|| ErrorKind::Database("while trying to accept a schedule".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn roll_over<'a: 'b, 'b>(
+        &'a self,
+        slipped: Vec<crate::Task>,
+        schedule: crate::Schedule,
+        snapshot: Snapshot,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let db_tasks: Vec<Task> = slipped.into_iter().map(Task::from).collect();
+        let db_scheduled_tasks = to_db_scheduled_tasks(ScheduleKind::Accepted, schedule);
+        let result = write(self, || {
+            update_each(self, &db_tasks)?;
+            insert_schedule(self, ScheduleKind::Accepted, &db_scheduled_tasks)?;
+            delete_schedule(self, ScheduleKind::Proposed)?;
+            insert_snapshot(self, &snapshot)
+        })
+        .chain_err(|| ErrorKind::Database("while trying to roll over".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn add_snapshot<'a: 'b, 'b>(&'a self, snapshot: Snapshot) -> LocalFutureObj<'b, Result<()>> {
+        let result = write(self, || insert_snapshot(self, &snapshot))
+            .chain_err(|| ErrorKind::Database("while trying to save a snapshot".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn snapshots<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<Snapshot>>> {
+        let snapshots_result = self
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let db_snapshots = snapshot_table
+                    .order(snapshots::taken.asc())
+                    .load::<(i32, i64)>(self)?;
+                let mut planned_tasks: HashMap<i32, Vec<PlannedTask>> = HashMap::new();
+                let db_snapshot_tasks = snapshot_task_table
+                    .order(snapshot_tasks::start.asc())
+                    .load::<SnapshotTask>(self)?;
+                for db_snapshot_task in db_snapshot_tasks {
+                    planned_tasks
+                        .entry(db_snapshot_task.snapshot_id)
+                        .or_insert_with(Vec::new)
+                        .push(PlannedTask {
+                            task_id: db_snapshot_task.task_id as u32,
+                            when: from_timestamp(db_snapshot_task.start),
+                            duration: Duration::seconds(db_snapshot_task.duration),
+                        });
+                }
+                Ok(db_snapshots
+                    .into_iter()
+                    .map(|(id, taken)| Snapshot {
+                        taken: from_timestamp(taken),
+                        planned: planned_tasks.remove(&id).unwrap_or_default(),
+                    })
+                    .collect())
+            })
+            .chain_err(|| ErrorKind::Database("while trying to retrieve snapshots".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(snapshots_result)))
+    }
+
+    fn save_template<'a: 'b, 'b>(
+        &'a self,
+        template: crate::Template,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let db_template = Template::from(template);
+        let result = write(self, || {
+            diesel::replace_into(template_table)
+                .values(&db_template)
+                .execute(self)
+        })
+        .map(|_amount_saved| ())
+        .chain_err(|| ErrorKind::Database("while trying to save a template".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn find_template<'a: 'b, 'b>(
+        &'a self,
+        name: &'a str,
+    ) -> LocalFutureObj<'b, Result<Option<crate::Template>>> {
+        let template_result = template_table
+            .find(name)
+            .get_result::<Template>(self)
+            .optional()
+            .map(|db_template| db_template.map(crate::Template::from))
+            .chain_err(|| ErrorKind::Database("while trying to find a template".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(template_result)))
+    }
+
+    fn remove_template<'a: 'b, 'b>(&'a self, name: &'a str) -> LocalFutureObj<'b, Result<()>> {
+        let future = async move {
+            let amount_deleted = write(self, || {
+                diesel::delete(template_table.find(name)).execute(self)
+            })
+            .chain_err(|| ErrorKind::Database("while trying to remove a template".to_owned()))?;
+            ensure!(
+                amount_deleted == 1,
+                ErrorKind::Database("while trying to remove a template".to_owned())
+            );
+            Ok(())
+        };
+        LocalFutureObj::new(Box::new(future))
+    }
+
+    fn templates<'a: 'b, 'b>(&'a self) -> LocalFutureObj<'b, Result<Vec<crate::Template>>> {
+        let templates_result = template_table
+            .order(templates::name.asc())
+            .load::<Template>(self)
+            .map(|db_templates| {
+                db_templates
+                    .into_iter()
+                    .map(crate::Template::from)
+                    .collect()
+            })
+            .chain_err(|| ErrorKind::Database("while trying to retrieve templates".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(templates_result)))
+    }
+
+    fn add_session<'a: 'b, 'b>(
+        &'a self,
+        session: crate::tracking::Session,
+    ) -> LocalFutureObj<'b, Result<()>> {
+        let db_session = NewSession::from(session);
+        let result = write(self, || {
+            diesel::insert_into(session_table)
+                .values(&db_session)
+                .execute(self)
+        })
+        .map(|_amount_added| ())
+        .chain_err(|| ErrorKind::Database("while trying to add a session".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn end_session<'a: 'b, 'b>(
+        &'a self,
+        end: DateTime<Utc>,
+    ) -> LocalFutureObj<'b, Result<Option<crate::tracking::Session>>> {
+        let result = write(self, || {
+            let running = session_table
+                .filter(sessions::stop.is_null())
+                .order(sessions::start.desc())
+                .first::<Session>(self)
+                .optional()?;
+            match running {
+                Some(mut db_session) => {
+                    db_session.stop = Some(end.timestamp());
+                    diesel::update(&db_session).set(&db_session).execute(self)?;
+                    Ok(Some(crate::tracking::Session::from(db_session)))
+                }
+                None => Ok(None),
+            }
+        })
+        .chain_err(|| ErrorKind::Database("while trying to end a session".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(result)))
+    }
+
+    fn sessions<'a: 'b, 'b>(
+        &'a self,
+    ) -> LocalFutureObj<'b, Result<Vec<crate::tracking::Session>>> {
+        let sessions_result = session_table
+            .order(sessions::start.asc())
+            .load::<Session>(self)
+            .map(|db_sessions| {
+                db_sessions
+                    .into_iter()
+                    .map(crate::tracking::Session::from)
+                    .collect()
+            })
+            .chain_err(|| ErrorKind::Database("while trying to retrieve sessions".to_owned()));
+        LocalFutureObj::new(Box::new(future::ready(sessions_result)))
+    }
 }
 
 impl From<crate::NewTask> for NewTask {
     fn from(task: crate::NewTask) -> NewTask {
         NewTask {
             content: task.content,
-            deadline: task.deadline.timestamp() as i32,
-            duration: task.duration.num_seconds() as i32,
+            deadline: task.deadline.timestamp(),
+            duration: task.duration.num_seconds(),
             importance: task.importance as i32,
+            tags: serialize_tags(&task.tags),
+            start_after: task.start_after.map(|start_after| start_after.timestamp()),
+            reminders: serialize_reminders(&task.reminders),
+            buffer: task.buffer.map(|buffer| buffer.num_seconds()),
+            horizon: task.horizon.map(|horizon| horizon.num_seconds()),
+            daily_minimum: task
+                .daily_minimum
+                .map(|daily_minimum| daily_minimum.num_seconds()),
+            attachments: serialize_attachments(&task.attachments),
+            preferred_window: task.preferred_window.as_ref().map(serialize_window),
+            assignee: task.assignee,
         }
     }
 }
 
 impl From<Task> for crate::Task {
     fn from(task: Task) -> crate::Task {
-        let naive_deadline = NaiveDateTime::from_timestamp(i64::from(task.deadline), 0);
-        let deadline = Utc.from_utc_datetime(&naive_deadline);
-        let duration = Duration::seconds(i64::from(task.duration));
+        let deadline = from_timestamp(task.deadline);
+        let duration = Duration::seconds(task.duration);
         crate::Task {
             id: task.id as u32,
             content: task.content,
             deadline,
             duration,
             importance: task.importance as u32,
+            tags: deserialize_tags(&task.tags),
+            start_after: task.start_after.map(from_timestamp),
+            reminders: deserialize_reminders(&task.reminders),
+            completed: task.completed.map(from_timestamp),
+            time_spent: task.time_spent.map(Duration::seconds),
+            buffer: task.buffer.map(Duration::seconds),
+            horizon: task.horizon.map(Duration::seconds),
+            slipped: task.slipped as u32,
+            daily_minimum: task.daily_minimum.map(Duration::seconds),
+            attachments: deserialize_attachments(&task.attachments),
+            waiting_for: task
+                .waiting_for
+                .and_then(|trigger| deserialize_trigger(&trigger)),
+            preferred_window: task
+                .preferred_window
+                .and_then(|window| deserialize_window(&window)),
+            assignee: task.assignee,
         }
     }
 }
@@ -150,19 +743,415 @@ impl From<crate::Task> for Task {
         Task {
             id: task.id as i32,
             content: task.content,
-            deadline: task.deadline.timestamp() as i32,
-            duration: task.duration.num_seconds() as i32,
+            deadline: task.deadline.timestamp(),
+            duration: task.duration.num_seconds(),
             importance: task.importance as i32,
+            tags: serialize_tags(&task.tags),
+            start_after: task.start_after.map(|start_after| start_after.timestamp()),
+            reminders: serialize_reminders(&task.reminders),
+            completed: task.completed.map(|completed| completed.timestamp()),
+            time_spent: task.time_spent.map(|time_spent| time_spent.num_seconds()),
+            buffer: task.buffer.map(|buffer| buffer.num_seconds()),
+            horizon: task.horizon.map(|horizon| horizon.num_seconds()),
+            slipped: task.slipped as i32,
+            daily_minimum: task
+                .daily_minimum
+                .map(|daily_minimum| daily_minimum.num_seconds()),
+            attachments: serialize_attachments(&task.attachments),
+            waiting_for: task.waiting_for.as_ref().map(serialize_trigger),
+            preferred_window: task.preferred_window.as_ref().map(serialize_window),
+            assignee: task.assignee,
         }
     }
 }
 
+impl From<Task> for ArchivedTask {
+    fn from(task: Task) -> ArchivedTask {
+        ArchivedTask {
+            id: task.id,
+            content: task.content,
+            deadline: task.deadline,
+            duration: task.duration,
+            importance: task.importance,
+            tags: task.tags,
+            start_after: task.start_after,
+            reminders: task.reminders,
+            completed: task.completed,
+            time_spent: task.time_spent,
+            buffer: task.buffer,
+            horizon: task.horizon,
+            slipped: task.slipped,
+            daily_minimum: task.daily_minimum,
+            attachments: task.attachments,
+            waiting_for: task.waiting_for,
+            preferred_window: task.preferred_window,
+            assignee: task.assignee,
+        }
+    }
+}
+
+impl From<ArchivedTask> for Task {
+    fn from(task: ArchivedTask) -> Task {
+        Task {
+            id: task.id,
+            content: task.content,
+            deadline: task.deadline,
+            duration: task.duration,
+            importance: task.importance,
+            tags: task.tags,
+            start_after: task.start_after,
+            reminders: task.reminders,
+            completed: task.completed,
+            time_spent: task.time_spent,
+            buffer: task.buffer,
+            horizon: task.horizon,
+            slipped: task.slipped,
+            daily_minimum: task.daily_minimum,
+            attachments: task.attachments,
+            waiting_for: task.waiting_for,
+            preferred_window: task.preferred_window,
+            assignee: task.assignee,
+        }
+    }
+}
+
+impl From<crate::Template> for Template {
+    fn from(template: crate::Template) -> Template {
+        let seconds = |duration: Duration| duration.num_seconds();
+        Template {
+            name: template.name,
+            content: template.content,
+            due_in: template.due_in.map(seconds),
+            duration: template.duration.map(seconds),
+            importance: template.importance.map(|importance| importance as i32),
+            tags: serialize_tags(&template.tags),
+            reminders: serialize_reminders(&template.reminders),
+            buffer: template.buffer.map(seconds),
+            horizon: template.horizon.map(seconds),
+            daily_minimum: template.daily_minimum.map(seconds),
+        }
+    }
+}
+
+impl From<Template> for crate::Template {
+    fn from(template: Template) -> crate::Template {
+        crate::Template {
+            name: template.name,
+            content: template.content,
+            due_in: template.due_in.map(Duration::seconds),
+            duration: template.duration.map(Duration::seconds),
+            importance: template.importance.map(|importance| importance as u32),
+            tags: deserialize_tags(&template.tags),
+            reminders: deserialize_reminders(&template.reminders),
+            buffer: template.buffer.map(Duration::seconds),
+            horizon: template.horizon.map(Duration::seconds),
+            daily_minimum: template.daily_minimum.map(Duration::seconds),
+        }
+    }
+}
+
+impl From<crate::tracking::Session> for NewSession {
+    fn from(session: crate::tracking::Session) -> NewSession {
+        NewSession {
+            task_id: session.task_id as i32,
+            start: session.start.timestamp(),
+            stop: session.end.map(|end| end.timestamp()),
+        }
+    }
+}
+
+impl From<Session> for crate::tracking::Session {
+    fn from(session: Session) -> crate::tracking::Session {
+        crate::tracking::Session {
+            task_id: session.task_id as u32,
+            start: from_timestamp(session.start),
+            end: session.stop.map(from_timestamp),
+        }
+    }
+}
+
+fn from_timestamp(timestamp: i64) -> DateTime<Utc> {
+    let naive_date_time = NaiveDateTime::from_timestamp(timestamp, 0);
+    Utc.from_utc_datetime(&naive_date_time)
+}
+
+fn serialize_tags(tags: &[String]) -> String {
+    tags.join(",")
+}
+
+fn deserialize_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Stores attachments on a line each, since paths and URLs can contain commas.
+fn serialize_attachments(attachments: &[String]) -> String {
+    attachments.join("\n")
+}
+
+fn deserialize_attachments(attachments: &str) -> Vec<String> {
+    attachments
+        .lines()
+        .filter(|attachment| !attachment.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Stores triggers like `at:office` and `done:12`.
+fn serialize_trigger(trigger: &Trigger) -> String {
+    match trigger {
+        Trigger::At(place) => format!("at:{}", place),
+        Trigger::Done(id) => format!("done:{}", id),
+    }
+}
+
+/// Reads a trigger stored by `serialize_trigger`. A trigger that can't be read is left out, so
+/// that the task is scheduled again.
+fn deserialize_trigger(trigger: &str) -> Option<Trigger> {
+    let mut parts = trigger.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("at"), Some(place)) => Some(Trigger::At(place.to_owned())),
+        (Some("done"), Some(id)) => id.parse().ok().map(Trigger::Done),
+        _ => None,
+    }
+}
+
+/// Stores windows like `weekdays|09:00|17:00` and `every|20:00|`, where the last time is left
+/// out when the window stays open until midnight.
+fn serialize_window(window: &Window) -> String {
+    let days = match window.days {
+        Days::Every => "every",
+        Days::Weekdays => "weekdays",
+        Days::Weekends => "weekends",
+    };
+    let until = window
+        .until
+        .map_or_else(String::new, |until| until.format("%H:%M").to_string());
+    format!("{}|{}|{}", days, window.from.format("%H:%M"), until)
+}
+
+/// Reads a window stored by `serialize_window`. A window that can't be read is left out, so that
+/// the task can be scheduled at any time.
+fn deserialize_window(window: &str) -> Option<Window> {
+    let parts: Vec<&str> = window.split('|').collect();
+    let (days, from, until) = match parts.as_slice() {
+        [days, from, until] => (days, from, until),
+        _ => return None,
+    };
+    let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M").ok();
+    Some(Window {
+        days: match *days {
+            "every" => Days::Every,
+            "weekdays" => Days::Weekdays,
+            "weekends" => Days::Weekends,
+            _ => return None,
+        },
+        from: time(from)?,
+        until: match *until {
+            "" => None,
+            until => Some(time(until)?),
+        },
+    })
+}
+
+/// Stores what holds a slot back like `start_after:1551430800`, `task:12:3600` (the id of the task
+/// ahead and the duration of its part), `unavailable:1551430800:1551434400`, `daily_minimum`,
+/// `budget:work` and `window:weekdays|09:00|17:00`.
+fn serialize_constraint(constraint: &Constraint) -> String {
+    match constraint {
+        Constraint::StartAfter(start_after) => format!("start_after:{}", start_after.timestamp()),
+        Constraint::TaskAhead(task) => {
+            format!("task:{}:{}", task.id, task.duration.num_seconds())
+        }
+        Constraint::Unavailable(period) => format!(
+            "unavailable:{}:{}",
+            period.start.timestamp(),
+            period.end.timestamp()
+        ),
+        Constraint::DailyMinimum => "daily_minimum".to_owned(),
+        Constraint::Budget(tag) => format!("budget:{}", tag),
+        Constraint::Window(window) => format!("window:{}", serialize_window(window)),
+    }
+}
+
+/// Reads a constraint stored by `serialize_constraint`, looking up the task ahead in `tasks`. A
+/// constraint that can't be read, or whose task is gone, is left out, so that the slot is just
+/// not explained.
+fn deserialize_constraint(constraint: &str, tasks: &HashMap<i32, Task>) -> Option<Constraint> {
+    let mut parts = constraint.splitn(2, ':');
+    let timestamp = |timestamp: &str| timestamp.parse().ok().map(from_timestamp);
+    match (parts.next(), parts.next()) {
+        (Some("start_after"), Some(start_after)) => {
+            timestamp(start_after).map(Constraint::StartAfter)
+        }
+        (Some("task"), Some(task)) => {
+            let mut task = task.splitn(2, ':');
+            let id: i32 = task.next()?.parse().ok()?;
+            let duration = Duration::seconds(task.next()?.parse().ok()?);
+            let task = crate::Task::from(tasks.get(&id)?.clone());
+            Some(Constraint::TaskAhead(crate::Task { duration, ..task }))
+        }
+        (Some("unavailable"), Some(period)) => {
+            let mut period = period.splitn(2, ':');
+            let start = timestamp(period.next()?)?;
+            let end = timestamp(period.next()?)?;
+            Some(Constraint::Unavailable(start..end))
+        }
+        (Some("daily_minimum"), None) => Some(Constraint::DailyMinimum),
+        (Some("budget"), Some(tag)) => Some(Constraint::Budget(tag.to_owned())),
+        (Some("window"), Some(window)) => deserialize_window(window).map(Constraint::Window),
+        _ => None,
+    }
+}
+
+/// Stores reminders as a comma-separated list of seconds.
+fn serialize_reminders(reminders: &[Duration]) -> String {
+    reminders
+        .iter()
+        .map(|reminder| reminder.num_seconds().to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn deserialize_reminders(reminders: &str) -> Vec<Duration> {
+    reminders
+        .split(',')
+        .filter_map(|seconds| seconds.parse().ok())
+        .map(Duration::seconds)
+        .collect()
+}
+
+fn to_db_scheduled_tasks(kind: ScheduleKind, schedule: crate::Schedule) -> Vec<ScheduledTask> {
+    schedule
+        .0
+        .into_iter()
+        .map(|scheduled_task| ScheduledTask {
+            kind: kind.as_str().to_owned(),
+            task_id: scheduled_task.task.id as i32,
+            start: scheduled_task.when.timestamp(),
+            duration: scheduled_task.task.duration.num_seconds(),
+            note: scheduled_task.note,
+            assignee: scheduled_task.task.assignee,
+            held_back_by: scheduled_task.held_back_by.as_ref().map(serialize_constraint),
+        })
+        .collect()
+}
+
+/// Updates every one of `db_tasks`, and fails when one of them doesn't exist, so that the
+/// transaction it's in is rolled back.
+fn update_each(connection: &SqliteConnection, db_tasks: &[Task]) -> QueryResult<()> {
+    for db_task in db_tasks {
+        let amount_updated = diesel::update(db_task).set(db_task).execute(connection)?;
+        if amount_updated != 1 {
+            return Err(diesel::result::Error::NotFound);
+        }
+    }
+    Ok(())
+}
+
+/// Stores the schedule of the given kind, replacing the previous one. It's meant to be run in a
+/// transaction, like the other queries below, so that a schedule is never seen half stored.
+fn insert_schedule(
+    connection: &SqliteConnection,
+    kind: ScheduleKind,
+    db_scheduled_tasks: &[ScheduledTask],
+) -> QueryResult<()> {
+    delete_schedule(connection, kind)?;
+    diesel::insert_into(schedule_table)
+        .values(&Schedule {
+            kind: kind.as_str().to_owned(),
+        })
+        .execute(connection)?;
+    for db_scheduled_task in db_scheduled_tasks {
+        diesel::insert_into(scheduled_task_table)
+            .values(db_scheduled_task)
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+fn delete_schedule(connection: &SqliteConnection, kind: ScheduleKind) -> QueryResult<()> {
+    let kind = kind.as_str();
+    diesel::delete(scheduled_task_table.filter(scheduled_tasks::kind.eq(kind)))
+        .execute(connection)?;
+    diesel::delete(schedule_table.find(kind)).execute(connection)?;
+    Ok(())
+}
+
+fn insert_snapshot(connection: &SqliteConnection, snapshot: &Snapshot) -> QueryResult<()> {
+    diesel::insert_into(snapshot_table)
+        .values(&NewSnapshot {
+            taken: snapshot.taken.timestamp(),
+        })
+        .execute(connection)?;
+    let snapshot_id = diesel::select(last_insert_rowid).get_result::<i32>(connection)?;
+    for planned_task in &snapshot.planned {
+        diesel::insert_into(snapshot_task_table)
+            .values(&SnapshotTask {
+                snapshot_id,
+                task_id: planned_task.task_id as i32,
+                start: planned_task.when.timestamp(),
+                duration: planned_task.duration.num_seconds(),
+            })
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Runs `queries` in a transaction that locks the database for other writers from the start,
+/// so that another connection can't change what they read before they write. When the database
+/// stays locked for longer than the busy timeout, they are tried again a few times, waiting
+/// longer every time.
+fn write<T, F>(connection: &SqliteConnection, mut queries: F) -> QueryResult<T>
+where
+    F: FnMut() -> QueryResult<T>,
+{
+    let mut delay = time::Duration::from_millis(FIRST_RETRY_DELAY_MS);
+    for _ in 0..WRITE_RETRIES {
+        match connection.immediate_transaction(&mut queries) {
+            Err(ref error) if is_locked(error) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    connection.immediate_transaction(queries)
+}
+
+/// Tells whether `error` means that another connection was holding a lock on the database.
+fn is_locked(error: &diesel::result::Error) -> bool {
+    match error {
+        diesel::result::Error::DatabaseError(_kind, information) => {
+            let message = information.message();
+            message.contains("database is locked") || message.contains("database table is locked")
+        }
+        _ => false,
+    }
+}
+
 pub fn make_connection(database_url: &str) -> Result<SqliteConnection> {
     let connection = SqliteConnection::establish(database_url).chain_err(|| {
         ErrorKind::Database(format!("while trying to connect to {}", database_url))
     })?;
+    // Wait a while for other connections to the same database, e.g. those of other threads,
+    // instead of failing right away when they are writing. With write-ahead logging, reading
+    // doesn't have to wait for writing at all.
+    connection
+        .batch_execute(&format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL;",
+            BUSY_TIMEOUT_MS
+        ))
+        .chain_err(|| ErrorKind::Database("while configuring the connection".to_owned()))?;
+    // Keep other connections out while migrating, so that two processes that start at the same
+    // time don't both run the same migrations
     // TODO run instead of run_with_output
-    embedded_migrations::run_with_output(&connection, &mut io::stderr())
+    connection
+        .exclusive_transaction(|| {
+            embedded_migrations::run_with_output(&connection, &mut io::stderr())
+        })
         .chain_err(|| ErrorKind::Database("while running migrations".to_owned()))?;
     Ok(connection)
 }
@@ -189,6 +1178,16 @@ mod tests {
         assert_eq!(tasks[0].deadline.timestamp(), new_task.deadline.timestamp());
         assert_eq!(tasks[0].duration, new_task.duration);
         assert_eq!(tasks[0].importance, new_task.importance);
+        assert_eq!(tasks[0].start_after, new_task.start_after);
+        assert_eq!(tasks[0].reminders, new_task.reminders);
+        assert_eq!(tasks[0].buffer, new_task.buffer);
+        assert_eq!(tasks[0].horizon, new_task.horizon);
+        assert_eq!(tasks[0].slipped, 0);
+        assert_eq!(tasks[0].daily_minimum, new_task.daily_minimum);
+        assert_eq!(tasks[0].attachments, new_task.attachments);
+        assert_eq!(tasks[0].preferred_window, new_task.preferred_window);
+        assert_eq!(tasks[0].assignee, new_task.assignee);
+        assert_eq!(tasks[0].waiting_for, None);
         let same_task = block_on(connection.find_task(tasks[0].id)).unwrap();
         assert_eq!(same_task.content, new_task.content);
         assert_eq!(
@@ -219,6 +1218,7 @@ mod tests {
         task.deadline = deadline;
         task.duration = Duration::minutes(7);
         task.importance = 100;
+        task.start_after = None;
         block_on(connection.update_task(task.clone())).unwrap();
 
         let task_from_db = block_on(connection.find_task(task.id)).unwrap();
@@ -227,6 +1227,515 @@ mod tests {
         assert_eq!(task.deadline, deadline);
         assert_eq!(task.duration, Duration::minutes(7));
         assert_eq!(task.importance, 100);
+        assert_eq!(task.start_after, None);
+    }
+
+    #[test]
+    fn test_update_several_tasks() {
+        let connection = make_connection(":memory:").unwrap();
+
+        block_on(connection.add_task(test_task())).unwrap();
+        block_on(connection.add_task(test_task())).unwrap();
+        let mut tasks = block_on(connection.all_tasks()).unwrap();
+        for task in &mut tasks {
+            task.importance += 1;
+            task.tags.push("errand".to_owned());
+            task.slipped += 1;
+        }
+        tasks[0].waiting_for = Some(Trigger::At("office: 2nd floor".to_owned()));
+        tasks[1].waiting_for = Some(Trigger::Done(tasks[0].id));
+        tasks[1].preferred_window = Some(Window {
+            days: Days::Weekends,
+            from: NaiveTime::from_hms(20, 0, 0),
+            until: None,
+        });
+        block_on(connection.update_tasks(tasks.clone())).unwrap();
+        assert_eq!(block_on(connection.all_tasks()).unwrap(), tasks);
+    }
+
+    #[test]
+    fn test_modify_several_tasks() {
+        let connection = make_connection(":memory:").unwrap();
+
+        let first = block_on(connection.add_task(test_task())).unwrap();
+        let second = block_on(connection.add_task(test_task())).unwrap();
+        let ids = vec![second.id, first.id];
+        let modified = block_on(connection.modify_tasks(
+            ids.clone(),
+            Box::new(|task| {
+                task.importance += 1;
+                Ok(())
+            }),
+        ))
+        .unwrap();
+        assert_eq!(modified.iter().map(|task| task.id).collect::<Vec<_>>(), ids);
+        assert_eq!(block_on(connection.find_task(first.id)).unwrap().importance, 6);
+        assert_eq!(block_on(connection.find_task(second.id)).unwrap().importance, 6);
+
+        // When one of the tasks can't be changed, none of them are
+        let result = block_on(connection.modify_tasks(
+            ids,
+            Box::new(|task| {
+                ensure!(task.id == second.id, "only the second task can be changed");
+                task.importance += 1;
+                Ok(())
+            }),
+        ));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "only the second task can be changed"
+        );
+        assert_eq!(block_on(connection.find_task(second.id)).unwrap().importance, 6);
+        let missing = vec![first.id, second.id + 1];
+        assert!(block_on(connection.modify_tasks(missing, Box::new(|_task| Ok(())))).is_err());
+    }
+
+    #[test]
+    fn test_absurd_durations_and_moments_come_back_unchanged() {
+        let connection = make_connection(":memory:").unwrap();
+
+        // Way beyond what fits in 32 bits of seconds
+        let century = Duration::days(100 * 365);
+        let new_task = crate::NewTask {
+            deadline: Utc.ymd(3000, 1, 1).and_hms(0, 0, 0),
+            duration: century,
+            start_after: Some(Utc.ymd(1000, 1, 1).and_hms(0, 0, 0)),
+            buffer: Some(century),
+            horizon: Some(century * 10),
+            daily_minimum: Some(century),
+            ..test_task()
+        };
+        let mut task = block_on(connection.add_task(new_task.clone())).unwrap();
+        assert_eq!(task.deadline, new_task.deadline);
+        assert_eq!(task.duration, century);
+        assert_eq!(task.start_after, new_task.start_after);
+        assert_eq!(task.buffer, new_task.buffer);
+        assert_eq!(task.horizon, new_task.horizon);
+        assert_eq!(task.daily_minimum, new_task.daily_minimum);
+        task.time_spent = Some(century);
+        block_on(connection.update_task(task.clone())).unwrap();
+        assert_eq!(block_on(connection.find_task(task.id)).unwrap(), task);
+
+        let schedule = crate::Schedule(vec![crate::ScheduledTask::new(
+            task.clone(),
+            task.deadline - century,
+        )]);
+        block_on(connection.save_schedule(ScheduleKind::Accepted, schedule.clone())).unwrap();
+        let found = block_on(connection.find_schedule(ScheduleKind::Accepted)).unwrap();
+        assert_eq!(found, Some(schedule));
+    }
+
+    #[test]
+    fn test_failing_bulk_update_modifies_nothing() {
+        let connection = make_connection(":memory:").unwrap();
+
+        block_on(connection.add_task(test_task())).unwrap();
+        let original_tasks = block_on(connection.all_tasks()).unwrap();
+        let mut existing_task = original_tasks[0].clone();
+        existing_task.content = "changed".to_owned();
+        let mut missing_task = existing_task.clone();
+        missing_task.id += 1;
+        assert!(block_on(connection.update_tasks(vec![existing_task, missing_task])).is_err());
+        assert_eq!(block_on(connection.all_tasks()).unwrap(), original_tasks);
+    }
+
+    #[test]
+    fn test_save_find_and_remove_schedules() {
+        let connection = make_connection(":memory:").unwrap();
+
+        assert!(block_on(connection.find_schedule(ScheduleKind::Proposed))
+            .unwrap()
+            .is_none());
+        block_on(connection.add_task(test_task())).unwrap();
+        block_on(connection.add_task(test_task())).unwrap();
+        let tasks = block_on(connection.all_tasks()).unwrap();
+        let when = Utc.timestamp(1_551_430_800, 0);
+        let window = Window {
+            days: Days::Weekdays,
+            from: NaiveTime::from_hms(9, 0, 0),
+            until: Some(NaiveTime::from_hms(17, 0, 0)),
+        };
+        let schedule = crate::Schedule(vec![
+            crate::ScheduledTask {
+                held_back_by: Some(Constraint::Budget("work".to_owned())),
+                ..crate::ScheduledTask::new(tasks[1].clone(), when)
+            },
+            crate::ScheduledTask {
+                held_back_by: Some(Constraint::Window(window)),
+                ..crate::ScheduledTask::new(tasks[0].clone(), when + Duration::hours(1))
+            },
+        ]);
+        block_on(connection.save_schedule(ScheduleKind::Proposed, schedule.clone())).unwrap();
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Proposed)).unwrap(),
+            Some(schedule.clone())
+        );
+        // Schedules of different kinds are kept apart
+        assert!(block_on(connection.find_schedule(ScheduleKind::Accepted))
+            .unwrap()
+            .is_none());
+        block_on(connection.save_schedule(ScheduleKind::Accepted, crate::Schedule(vec![])))
+            .unwrap();
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Accepted)).unwrap(),
+            Some(crate::Schedule(vec![]))
+        );
+
+        // A task can be scheduled in parts, and every slot can have a note and what holds it back
+        let part = |hours| crate::Task {
+            duration: Duration::hours(hours),
+            ..tasks[0].clone()
+        };
+        let schedule = crate::Schedule(vec![
+            crate::ScheduledTask {
+                held_back_by: Some(Constraint::Unavailable(when - Duration::hours(2)..when)),
+                ..crate::ScheduledTask::new(part(1), when)
+            },
+            crate::ScheduledTask {
+                held_back_by: Some(Constraint::TaskAhead(part(1))),
+                ..crate::ScheduledTask::new(tasks[1].clone(), when + Duration::hours(1))
+            },
+            crate::ScheduledTask {
+                note: Some("at the library".to_owned()),
+                held_back_by: Some(Constraint::DailyMinimum),
+                ..crate::ScheduledTask::new(part(2), when + Duration::days(1))
+            },
+            crate::ScheduledTask {
+                held_back_by: Some(Constraint::StartAfter(when + Duration::days(2))),
+                ..crate::ScheduledTask::new(tasks[1].clone(), when + Duration::days(2))
+            },
+        ]);
+        block_on(connection.save_schedule(ScheduleKind::Accepted, schedule.clone())).unwrap();
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Accepted)).unwrap(),
+            Some(schedule)
+        );
+
+        // Removed tasks disappear from the schedule
+        block_on(connection.remove_task(tasks[0].id)).unwrap();
+        let schedule = block_on(connection.find_schedule(ScheduleKind::Proposed))
+            .unwrap()
+            .unwrap();
+        assert_eq!(schedule.0.len(), 1);
+        assert_eq!(schedule.0[0].task, tasks[1]);
+        // and so does what they held back
+        let schedule = block_on(connection.find_schedule(ScheduleKind::Accepted))
+            .unwrap()
+            .unwrap();
+        assert_eq!(schedule.0.len(), 2);
+        assert_eq!(schedule.0[0].held_back_by, None);
+
+        block_on(connection.remove_schedule(ScheduleKind::Proposed)).unwrap();
+        assert!(block_on(connection.find_schedule(ScheduleKind::Proposed))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_accepting_a_schedule_is_all_or_nothing() {
+        let connection = make_connection(":memory:").unwrap();
+
+        let task = block_on(connection.add_task(test_task())).unwrap();
+        let when = Utc.timestamp(1_551_430_800, 0);
+        let schedule = crate::Schedule(vec![crate::ScheduledTask::new(task.clone(), when)]);
+        block_on(connection.save_schedule(ScheduleKind::Proposed, schedule.clone())).unwrap();
+
+        // A snapshot that plans the same task twice at the same moment can't be stored
+        let mut snapshot = Snapshot::of(when, &schedule);
+        snapshot.planned.push(snapshot.planned[0].clone());
+        assert!(block_on(connection.accept_schedule(schedule.clone(), snapshot)).is_err());
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Proposed)).unwrap(),
+            Some(schedule.clone())
+        );
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Accepted)).unwrap(),
+            None
+        );
+        assert!(block_on(connection.snapshots()).unwrap().is_empty());
+
+        let snapshot = Snapshot::of(when, &schedule);
+        block_on(connection.accept_schedule(schedule.clone(), snapshot.clone())).unwrap();
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Proposed)).unwrap(),
+            None
+        );
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Accepted)).unwrap(),
+            Some(schedule)
+        );
+        assert_eq!(block_on(connection.snapshots()).unwrap(), vec![snapshot]);
+    }
+
+    #[test]
+    fn test_rolling_over_is_all_or_nothing() {
+        let connection = make_connection(":memory:").unwrap();
+
+        let mut task = block_on(connection.add_task(test_task())).unwrap();
+        let when = Utc.timestamp(1_551_430_800, 0);
+        let schedule = crate::Schedule(vec![crate::ScheduledTask::new(task.clone(), when)]);
+        task.slipped += 1;
+
+        // A task that was removed can't be updated
+        let mut removed = task.clone();
+        removed.id += 1;
+        let snapshot = Snapshot::of(when, &schedule);
+        let result = connection.roll_over(vec![task.clone(), removed], schedule.clone(), snapshot);
+        assert!(block_on(result).is_err());
+        assert_eq!(block_on(connection.find_task(task.id)).unwrap().slipped, 0);
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Accepted)).unwrap(),
+            None
+        );
+        assert!(block_on(connection.snapshots()).unwrap().is_empty());
+
+        let snapshot = Snapshot::of(when, &schedule);
+        block_on(connection.roll_over(vec![task.clone()], schedule, snapshot.clone())).unwrap();
+        assert_eq!(block_on(connection.find_task(task.id)).unwrap(), task);
+        assert_eq!(
+            block_on(connection.find_schedule(ScheduleKind::Accepted))
+                .unwrap()
+                .unwrap()
+                .0
+                .len(),
+            1
+        );
+        assert_eq!(block_on(connection.snapshots()).unwrap(), vec![snapshot]);
+    }
+
+    #[test]
+    fn test_completed_tasks_and_snapshots() {
+        let connection = make_connection(":memory:").unwrap();
+
+        block_on(connection.add_task(test_task())).unwrap();
+        block_on(connection.add_task(test_task())).unwrap();
+        let mut tasks = block_on(connection.all_tasks()).unwrap();
+        let when = Utc.timestamp(1_551_430_800, 0);
+        tasks[0].completed = Some(when);
+        tasks[0].time_spent = Some(Duration::minutes(5));
+        block_on(connection.archive_task(tasks[0].clone())).unwrap();
+        // Completed tasks are kept apart from the ones still to be done, in the archive
+        assert_eq!(block_on(connection.all_tasks()).unwrap(), vec![tasks[1].clone()]);
+        assert_eq!(block_on(connection.completed_tasks()).unwrap(), vec![tasks[0].clone()]);
+        assert_eq!(block_on(connection.find_task(tasks[0].id)).unwrap(), tasks[0]);
+        // Archiving it again replaces it, but only completed tasks can be archived
+        tasks[0].time_spent = Some(Duration::minutes(10));
+        block_on(connection.archive_task(tasks[0].clone())).unwrap();
+        assert_eq!(block_on(connection.completed_tasks()).unwrap(), vec![tasks[0].clone()]);
+        assert!(block_on(connection.archive_task(tasks[1].clone())).is_err());
+
+        assert!(block_on(connection.snapshots()).unwrap().is_empty());
+        let schedule = crate::Schedule(vec![
+            crate::ScheduledTask::new(tasks[1].clone(), when),
+            crate::ScheduledTask::new(tasks[0].clone(), when + Duration::hours(1)),
+        ]);
+        let snapshots = vec![
+            Snapshot::of(when, &schedule),
+            Snapshot::of(when + Duration::days(1), &crate::Schedule(vec![])),
+        ];
+        block_on(connection.add_snapshot(snapshots[1].clone())).unwrap();
+        block_on(connection.add_snapshot(snapshots[0].clone())).unwrap();
+        assert_eq!(block_on(connection.snapshots()).unwrap(), snapshots);
+
+        // The id of an archived task isn't given to a new one, not even when it was the last one
+        let mut last_task = block_on(connection.add_task(test_task())).unwrap();
+        last_task.completed = Some(when);
+        block_on(connection.archive_task(last_task.clone())).unwrap();
+        assert!(block_on(connection.add_task(test_task())).unwrap().id > last_task.id);
+    }
+
+    #[test]
+    fn test_save_find_and_remove_templates() {
+        let connection = make_connection(":memory:").unwrap();
+
+        assert!(block_on(connection.templates()).unwrap().is_empty());
+        let template = crate::Template {
+            name: "weekly-review".to_owned(),
+            content: Some("Review week {week}".to_owned()),
+            due_in: Some(Duration::days(2)),
+            duration: Some(Duration::hours(1)),
+            importance: Some(7),
+            tags: vec!["admin".to_owned()],
+            reminders: vec![Duration::hours(1)],
+            ..Default::default()
+        };
+        let other = crate::Template {
+            name: "errand".to_owned(),
+            ..Default::default()
+        };
+        block_on(connection.save_template(template.clone())).unwrap();
+        block_on(connection.save_template(other.clone())).unwrap();
+        assert_eq!(
+            block_on(connection.find_template("weekly-review")).unwrap(),
+            Some(template.clone())
+        );
+        assert_eq!(
+            block_on(connection.templates()).unwrap(),
+            vec![other.clone(), template.clone()]
+        );
+
+        // Saving a template with the same name replaces it
+        let changed = crate::Template {
+            importance: Some(3),
+            ..template
+        };
+        block_on(connection.save_template(changed.clone())).unwrap();
+        assert_eq!(
+            block_on(connection.templates()).unwrap(),
+            vec![other.clone(), changed]
+        );
+
+        block_on(connection.remove_template("weekly-review")).unwrap();
+        assert!(block_on(connection.find_template("weekly-review"))
+            .unwrap()
+            .is_none());
+        assert!(block_on(connection.remove_template("weekly-review")).is_err());
+        assert_eq!(block_on(connection.templates()).unwrap(), vec![other]);
+    }
+
+    #[test]
+    fn test_concurrent_connections() {
+        let database_url = temporary_database("threads");
+        let threads = 8;
+        let tasks_per_thread = 20;
+        // Start all at once, so that the migrations are run at the same time as well
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(threads));
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let database_url = database_url.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let connection = make_connection(&database_url).unwrap();
+                    for _ in 0..tasks_per_thread {
+                        let task = block_on(connection.add_task(test_task())).unwrap();
+                        let tasks = block_on(connection.all_tasks()).unwrap();
+                        block_on(connection.update_tasks(tasks)).unwrap();
+                        let schedule = crate::Schedule(
+                            (0..3)
+                                .map(|hour| crate::ScheduledTask {
+                                    task: task.clone(),
+                                    when: Utc.ymd(2019, 3, 4).and_hms(hour, 0, 0),
+                                    note: None,
+                                    held_back_by: None,
+                                })
+                                .collect(),
+                        );
+                        block_on(connection.save_schedule(ScheduleKind::Proposed, schedule))
+                            .unwrap();
+                        // A schedule is never seen half saved
+                        let schedule = block_on(connection.find_schedule(ScheduleKind::Proposed))
+                            .unwrap()
+                            .unwrap();
+                        assert_eq!(schedule.0.len(), 3);
+                        assert!(schedule
+                            .0
+                            .iter()
+                            .all(|scheduled_task| scheduled_task.task.id == schedule.0[0].task.id));
+                    }
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(thread::JoinHandle::join).collect();
+
+        let connection = make_connection(&database_url).unwrap();
+        let tasks = block_on(connection.all_tasks()).unwrap();
+        remove_database(&database_url);
+        assert!(results.iter().all(std::result::Result::is_ok));
+        let mut ids: Vec<u32> = tasks.iter().map(|task| task.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), threads * tasks_per_thread);
+    }
+
+    /// The environment variable that turns a run of `test_concurrent_processes` into one of the
+    /// processes that write to the database at this url.
+    const WRITER_DATABASE: &str = "EVA_TEST_WRITER_DATABASE";
+
+    #[test]
+    fn test_concurrent_processes() {
+        let processes = 4;
+        let tasks_per_process = 20;
+        // The test runs itself in every writing process, where it only writes
+        if let Ok(database_url) = std::env::var(WRITER_DATABASE) {
+            let connection = make_connection(&database_url).unwrap();
+            for _ in 0..tasks_per_process {
+                block_on(connection.add_task(test_task())).unwrap();
+                let tasks = block_on(connection.all_tasks()).unwrap();
+                block_on(connection.update_tasks(tasks)).unwrap();
+            }
+            return;
+        }
+
+        let database_url = temporary_database("processes");
+        // Start all at once, so that the migrations are run at the same time as well
+        let children: Vec<_> = (0..processes)
+            .map(|_| {
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(&[
+                        "--exact",
+                        "database::sqlite::tests::test_concurrent_processes",
+                        "--test-threads=1",
+                        "--quiet",
+                    ])
+                    .env(WRITER_DATABASE, &database_url)
+                    .stdout(std::process::Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        let statuses: Vec<_> = children
+            .into_iter()
+            .map(|mut child| child.wait().unwrap())
+            .collect();
+
+        let connection = make_connection(&database_url).unwrap();
+        let tasks = block_on(connection.all_tasks()).unwrap();
+        remove_database(&database_url);
+        assert!(statuses.iter().all(std::process::ExitStatus::success));
+        // No task was lost, and no id was given out twice
+        let mut ids: Vec<u32> = tasks.iter().map(|task| task.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), processes * tasks_per_process);
+    }
+
+    /// Returns the url of a new database file in the temporary directory, which every connection
+    /// opens by itself, like the connections of separate processes.
+    fn temporary_database(name: &str) -> String {
+        let file_name = format!("eva-test-{}-{}.sqlite", name, std::process::id());
+        let database_url = std::env::temp_dir()
+            .join(file_name)
+            .to_str()
+            .unwrap()
+            .to_owned();
+        remove_database(&database_url);
+        database_url
+    }
+
+    fn remove_database(database_url: &str) {
+        for suffix in &["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", database_url, suffix));
+        }
+    }
+
+    #[test]
+    fn test_start_and_end_sessions() {
+        let connection = make_connection(":memory:").unwrap();
+        let morning = Utc.ymd(2019, 3, 20).and_hms(9, 0, 0);
+
+        assert_eq!(block_on(connection.end_session(morning)).unwrap(), None);
+        let first = crate::tracking::Session::new(1, morning);
+        block_on(connection.add_session(first.clone())).unwrap();
+        let ended = block_on(connection.end_session(morning + Duration::hours(1))).unwrap();
+        let first = crate::tracking::Session {
+            end: Some(morning + Duration::hours(1)),
+            ..first
+        };
+        assert_eq!(ended, Some(first.clone()));
+        let second = crate::tracking::Session::new(2, morning + Duration::hours(2));
+        block_on(connection.add_session(second.clone())).unwrap();
+        assert_eq!(block_on(connection.sessions()).unwrap(), vec![first, second]);
     }
 
     fn test_task() -> crate::NewTask {
@@ -235,6 +1744,22 @@ mod tests {
             deadline: Utc::now(),
             duration: Duration::seconds(6),
             importance: 42,
+            tags: vec!["home".to_string(), "pc".to_string()],
+            start_after: Some(Utc.ymd(2019, 3, 1).and_hms(9, 0, 0)),
+            reminders: vec![Duration::days(1), Duration::hours(1)],
+            buffer: Some(Duration::minutes(10)),
+            horizon: Some(Duration::weeks(3)),
+            daily_minimum: Some(Duration::hours(1)),
+            attachments: vec![
+                "~/notes/white, pure white.md".to_owned(),
+                "https://example.org/robes".to_owned(),
+            ],
+            preferred_window: Some(Window {
+                days: Days::Weekdays,
+                from: NaiveTime::from_hms(9, 0, 0),
+                until: Some(NaiveTime::from_hms(17, 0, 0)),
+            }),
+            assignee: Some("alice".to_owned()),
         }
     }
 }
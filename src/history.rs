@@ -0,0 +1,95 @@
+//! Looks through the archive of completed tasks. Completing a task moves it to the archive (see
+//! `complete`), where the schedules don't see it anymore, but the statistics, the insights and
+//! the suggestions still learn from it.
+
+use chrono::prelude::*;
+
+use crate::Task;
+
+/// Which of the archived tasks to look at. The default query is about all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryQuery {
+    /// Only the tasks that were completed at or after this moment.
+    pub since: Option<DateTime<Utc>>,
+    /// Only the tasks with this tag.
+    pub tag: Option<String>,
+}
+
+impl HistoryQuery {
+    /// Tells whether `task` is one of the tasks the query is about. Tasks that aren't completed
+    /// never are when it asks for the ones since a moment.
+    pub fn matches(&self, task: &Task) -> bool {
+        let completed_since = match (self.since, task.completed) {
+            (Some(since), Some(completed)) => since <= completed,
+            (Some(_since), None) => false,
+            (None, _) => true,
+        };
+        completed_since && self.tag.as_ref().map_or(true, |tag| task.has_tag(tag))
+    }
+
+    /// Returns the `archived` tasks that the query is about, in the order they were completed.
+    pub fn apply(&self, archived: Vec<Task>) -> Vec<Task> {
+        let mut tasks: Vec<Task> = archived
+            .into_iter()
+            .filter(|task| self.matches(task))
+            .collect();
+        tasks.sort_by_key(|task| (task.completed, task.id));
+        tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn completed(id: u32, days_ago: i64, tags: &[&str]) -> Task {
+        let now = Utc.ymd(2019, 3, 23).and_hms(12, 0, 0);
+        Task {
+            id,
+            content: format!("task {}", id),
+            tags: tags.iter().map(|&tag| tag.to_owned()).collect(),
+            completed: Some(now - Duration::days(days_ago)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn queries_filter_by_completion_and_tag() {
+        let archived = vec![
+            completed(1, 3, &["work"]),
+            completed(2, 10, &["work", "clients"]),
+            completed(3, 1, &["home"]),
+            completed(4, 2, &["work"]),
+        ];
+        let ids = |query: HistoryQuery| -> Vec<u32> {
+            query
+                .apply(archived.clone())
+                .iter()
+                .map(|task| task.id)
+                .collect()
+        };
+        assert_eq!(ids(HistoryQuery::default()), vec![2, 1, 4, 3]);
+        let since = Utc.ymd(2019, 3, 20).and_hms(0, 0, 0);
+        assert_eq!(
+            ids(HistoryQuery {
+                since: Some(since),
+                tag: Some("work".to_owned()),
+            }),
+            vec![1, 4]
+        );
+        assert_eq!(
+            ids(HistoryQuery {
+                tag: Some("clients".to_owned()),
+                ..Default::default()
+            }),
+            vec![2]
+        );
+        let query = HistoryQuery {
+            since: Some(since),
+            tag: None,
+        };
+        assert!(!query.matches(&Task::default()));
+    }
+}
@@ -1,7 +1,47 @@
+//! Eva's scheduling engine and task model.
+//!
+//! The crate is split in three parts, which front ends can use independently:
+//!
+//! * the task model in `task`,
+//! * the scheduling engine (`Schedule`, `FairSchedule` and `Pace`), which works on plain tasks
+//!   without touching any storage,
+//! * persistence through the `Database` trait in `database`, with an SQLite implementation
+//!   behind the `sqlite` feature.
+//!
+//! On top of that, `statistics` compares the accepted plans with what was actually done,
+//! `suggestions` learns from the completed tasks how to fill in new ones, `rollover` carries
+//! the unfinished work of a day over to the next, `tracking` keeps the time that is actually
+//! spent on the tasks and `history` looks through the archive of completed tasks.
+//!
+//! The functions at the crate root tie these together for a `Configuration`. All types of the
+//! task model and the schedules are `Send` and `Sync`, so the scheduler can run on any thread.
+//!
+//! # Stability
+//!
+//! The public API of this crate consists of everything that is reachable from the crate root:
+//! the functions and types defined or re-exported there (including `ScheduleError`), the error
+//! types in `errors`, and the `configuration`, `database`, `history`, `rollover`, `statistics`,
+//! `suggestions`, `task` and `tracking` modules. Anything else is an implementation detail.
+//!
+//! Starting from version 0.1, this surface follows semantic versioning:
+//!
+//! * Removing or renaming a public item or changing the signature of a public function requires
+//!   a new major version (or a new minor version as long as we are below 1.0).
+//! * New public fields on `Task` and `NewTask` may be added in any release. Construct them with
+//!   `..Default::default()` to stay compatible.
+//! * New variants may be added to `SchedulingStrategy` and to the error kinds in any release, so
+//!   matches on them need a wildcard arm.
+//!
+//! The surface with the default features is recorded in `api/eva.txt`, and CI fails when it no
+//! longer matches. A change to the public API updates the snapshot with
+//! `scripts/public_api.py --bless` in the same commit, so that the diff shows which of the rules
+//! above applies to it.
+
 #![feature(box_patterns)]
 #![feature(futures_api)]
 #![feature(async_await, await_macro)]
 #![feature(try_blocks)]
+#![feature(non_exhaustive)]
 
 #[macro_use]
 extern crate error_chain;
@@ -13,29 +53,49 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
-use chrono::prelude::*;
-use chrono::Duration;
-use derive_new::new;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use chrono::{Date, DateTime, Duration, Utc};
 use futures::prelude::*;
+use futures::stream;
 
 use crate::configuration::{Configuration, SchedulingStrategy};
+use crate::database::ScheduleKind;
+use crate::statistics::{BudgetUse, Insights, Snapshot};
+use crate::tracking::{Session, Status};
 
 pub use crate::errors::*;
-pub use crate::scheduling::{Schedule, ScheduledTask};
+pub use crate::history::HistoryQuery;
+pub use crate::rollover::Rollover;
+pub use crate::scheduling::{
+    Constraint, Error as ScheduleError, Explanation, FairSchedule, FocusSession, Pace, Schedule,
+    ScheduledTask, Shortfall,
+};
+pub use crate::statistics::Statistics;
+pub use crate::suggestions::{Estimate, TagSuggestion};
+pub use crate::task::{Days, NewTask, Task, Template, Trigger, Window};
 
 #[macro_use]
 mod util;
 
 pub mod configuration;
 pub mod database;
+pub mod history;
+pub mod rollover;
 mod scheduling;
+pub mod statistics;
+pub mod suggestions;
+pub mod task;
+pub mod tracking;
 
 pub mod errors {
     use crate::scheduling;
 
     error_chain! {
-        links {
-            Schedule(scheduling::Error, scheduling::ErrorKind);
+        foreign_links {
+            Schedule(scheduling::Error);
         }
         errors {
             Parse(what: String, how_it_should_be: String) {
@@ -46,31 +106,46 @@ pub mod errors {
                 description("database error")
                 display("A database error occurred {}", when)
             }
+            Encryption(when: String) {
+                description("encryption error")
+                display("An encryption error occurred {}", when)
+            }
             Internal(more_info: String) {
                 description("internal error")
                 display("An internal error occurred (This shouldn't happen.): {}", more_info)
             }
+            NoProposedSchedule {
+                description("no proposed schedule")
+                display("There is no proposed schedule to accept. You can propose one first")
+            }
+            UnknownTemplate(name: String) {
+                description("unknown template")
+                display("There is no template called {}", name)
+            }
+            NoAcceptedSchedule {
+                description("no accepted schedule")
+                display("There is no accepted schedule yet. You can propose one and accept it")
+            }
+            NotScheduled(id: u32) {
+                description("task not scheduled")
+                display("Task {} isn't scheduled then in the accepted schedule", id)
+            }
+            NotInSchedule(id: u32) {
+                description("task not in schedule")
+                display("Task {} isn't in the accepted schedule", id)
+            }
+            WaitingFor(id: u32, other: u32) {
+                description("task waits for another")
+                display("Task {} isn't scheduled until task {} is done", id, other)
+            }
+            AlreadyCompleted(id: u32) {
+                description("task already completed")
+                display("Task {} is already done", id)
+            }
         }
     }
 }
 
-#[derive(Debug, new, Clone)]
-pub struct NewTask {
-    pub content: String,
-    pub deadline: DateTime<Utc>,
-    pub duration: Duration,
-    pub importance: u32,
-}
-
-#[derive(Debug, Eq, PartialEq, Clone, Hash)]
-pub struct Task {
-    pub id: u32,
-    pub content: String,
-    pub deadline: DateTime<Utc>,
-    pub duration: Duration,
-    pub importance: u32,
-}
-
 pub fn add<'a: 'b, 'b>(
     configuration: &'a Configuration,
     new_task: NewTask,
@@ -78,11 +153,15 @@ pub fn add<'a: 'b, 'b>(
     configuration.database.add_task(new_task)
 }
 
+/// Removes the task with the given id. When it's being worked on, its work session is stopped
+/// first.
 pub fn remove<'a: 'b, 'b>(
     configuration: &'a Configuration,
     id: u32,
 ) -> impl Future<Output = Result<()>> + 'b {
-    configuration.database.remove_task(id)
+    let now = configuration.now();
+    end_session_of(configuration, id, now)
+        .and_then(move |()| configuration.database.remove_task(id))
 }
 
 pub fn get<'a: 'b, 'b>(
@@ -99,24 +178,1078 @@ pub fn update<'a: 'b, 'b>(
     configuration.database.update_task(task)
 }
 
+/// Updates all given tasks at once. Either all of them are updated or, when something goes wrong,
+/// none of them are.
+pub fn update_all<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    tasks: Vec<Task>,
+) -> impl Future<Output = Result<()>> + 'b {
+    configuration.database.update_tasks(tasks)
+}
+
+/// Changes the tasks with the given ids with `modify` and updates them at once, so that nothing
+/// else can change them in between. Either all of them are modified or, when something goes wrong,
+/// none of them are. Returns the modified tasks, in the order of `ids`.
+pub fn modify_all<'a: 'b, 'b, F>(
+    configuration: &'a Configuration,
+    ids: Vec<u32>,
+    modify: F,
+) -> impl Future<Output = Result<Vec<Task>>> + 'b
+where
+    F: FnMut(&mut Task) -> Result<()> + 'b,
+{
+    configuration.database.modify_tasks(ids, Box::new(modify))
+}
+
+/// Returns the tasks that haven't been completed yet.
 pub fn all<'a: 'b, 'b>(
     configuration: &'a Configuration,
 ) -> impl Future<Output = Result<Vec<Task>>> + 'b {
     configuration.database.all_tasks()
 }
 
+/// Marks the task with the given id as completed now, moves it to the archive and returns it.
+/// Archived tasks aren't scheduled or listed with the other tasks anymore, but still count in the
+/// statistics and can be looked up with `history`.
+///
+/// When the task is being worked on, its work session is stopped. The time spent on the task is
+/// the time of its work sessions, unless `time_spent` says otherwise. A task can only be
+/// completed once.
+pub fn complete<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    id: u32,
+    time_spent: Option<Duration>,
+) -> impl Future<Output = Result<Task>> + 'b {
+    let now = configuration.now();
+    let database = &configuration.database;
+    database
+        .find_task(id)
+        .and_then(move |task| {
+            future::ready(match task.completed {
+                Some(_completed) => Err(Error::from(ErrorKind::AlreadyCompleted(id))),
+                None => Ok(()),
+            })
+        })
+        .and_then(move |()| end_session_of(configuration, id, now))
+        .and_then(move |()| database.find_task(id))
+        .and_then(move |mut task| {
+            task.completed = Some(now);
+            task.time_spent = time_spent.or(task.time_spent);
+            database.archive_task(task.clone()).map_ok(|()| task)
+        })
+}
+
+/// Returns the archived tasks that `query` is about, in the order they were completed. See
+/// `HistoryQuery`.
+pub fn history<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    query: HistoryQuery,
+) -> impl Future<Output = Result<Vec<Task>>> + 'b {
+    configuration
+        .database
+        .completed_tasks()
+        .map_ok(move |archived| query.apply(archived))
+}
+
+/// Starts working on the task with the given id now, in a work session that goes on until it's
+/// stopped. Only one task is worked on at a time, so the session that was going on is stopped
+/// first, and returned.
+pub fn start<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    id: u32,
+) -> impl Future<Output = Result<Option<Session>>> + 'b {
+    let now = configuration.now();
+    let database = &configuration.database;
+    database
+        .find_task(id)
+        .and_then(move |task| {
+            future::ready(match task.completed {
+                Some(_completed) => Err(Error::from(ErrorKind::AlreadyCompleted(id))),
+                None => Ok(()),
+            })
+        })
+        .and_then(move |()| end_session(configuration, now))
+        .and_then(move |stopped| {
+            database
+                .add_session(Session::new(id, now))
+                .map_ok(move |()| stopped)
+        })
+}
+
+/// Stops the work session that is going on now and returns it, or `None` when nothing is being
+/// worked on. The time of the session is added to the time spent on its task.
+pub fn stop<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Option<Session>>> + 'b {
+    end_session(configuration, configuration.now())
+}
+
+/// Tells what is being worked on now, next to what the accepted schedule planned for now. See
+/// `Status`.
+pub fn status<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Status>> + 'b {
+    let now = configuration.now();
+    let database = &configuration.database;
+    database
+        .sessions()
+        .and_then(move |sessions| {
+            match sessions.into_iter().find(|session| session.end.is_none()) {
+                Some(session) => future::Either::Left(
+                    database
+                        .find_task(session.task_id)
+                        .map_ok(move |task| Some((session, task))),
+                ),
+                None => future::Either::Right(future::ready(Ok(None))),
+            }
+        })
+        .and_then(move |tracking| {
+            database
+                .find_schedule(ScheduleKind::Accepted)
+                .map_ok(move |accepted| {
+                    let accepted = accepted.unwrap_or_else(|| Schedule(vec![]));
+                    Status::of(now, &accepted, tracking)
+                })
+        })
+}
+
+/// Returns all work sessions, in the order they started, e.g. to bill the time spent on the
+/// tasks.
+pub fn sessions<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Vec<Session>>> + 'b {
+    configuration.database.sessions()
+}
+
+/// Estimates how long a task with the given content and tags will take from the completed tasks
+/// that are most like it. See `Estimate::from_history`.
+pub fn estimate<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    content: &'b str,
+    tags: &'b [String],
+) -> impl Future<Output = Result<Option<Estimate>>> + 'c {
+    configuration
+        .database
+        .completed_tasks()
+        .map_ok(move |completed_tasks| Estimate::from_history(content, tags, &completed_tasks))
+}
+
+/// Suggests tags for a task with the given content and tags, using the tag rules of the
+/// configuration and all tasks so far. See `TagSuggestion::suggest`.
+pub fn suggest_tags<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    content: &'b str,
+    tags: &'b [String],
+) -> impl Future<Output = Result<Vec<TagSuggestion>>> + 'c {
+    let database = &configuration.database;
+    database.all_tasks().and_then(move |mut history| {
+        database.completed_tasks().map_ok(move |completed_tasks| {
+            history.extend(completed_tasks);
+            TagSuggestion::suggest(content, tags, &configuration.tag_rules, &history)
+        })
+    })
+}
+
+/// Gathers statistics about the completed tasks and the schedules that were accepted. See
+/// `Statistics::compute`.
+pub fn statistics<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Statistics>> + 'b {
+    let database = &configuration.database;
+    database.completed_tasks().and_then(move |completed_tasks| {
+        database
+            .snapshots()
+            .map_ok(move |snapshots| Statistics::compute(&completed_tasks, &snapshots))
+    })
+}
+
+/// Looks for patterns in the history of the month that `month` falls in: on which days of the
+/// week more is planned than done, which tags are estimated worst and which tasks keep slipping.
+/// See `Insights::compute`.
+pub fn insights<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    month: Date<Utc>,
+) -> impl Future<Output = Result<Insights>> + 'b {
+    let database = &configuration.database;
+    database.completed_tasks().and_then(move |completed_tasks| {
+        database.all_tasks().and_then(move |pending_tasks| {
+            database.snapshots().map_ok(move |snapshots| {
+                Insights::compute(month, &completed_tasks, &pending_tasks, &snapshots)
+            })
+        })
+    })
+}
+
+/// Stores `template`, replacing the one with the same name if there is one.
+pub fn save_template<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    template: Template,
+) -> impl Future<Output = Result<()>> + 'b {
+    configuration.database.save_template(template)
+}
+
+/// Returns the template with the given name.
+pub fn template<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    name: &'b str,
+) -> impl Future<Output = Result<Template>> + 'c {
+    let unknown = move || Error::from(ErrorKind::UnknownTemplate(name.to_owned()));
+    configuration
+        .database
+        .find_template(name)
+        .and_then(move |template| future::ready(template.ok_or_else(unknown)))
+}
+
+/// Returns all templates, in the order of their names.
+pub fn templates<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Vec<Template>>> + 'b {
+    configuration.database.templates()
+}
+
+/// Removes the template with the given name.
+pub fn remove_template<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    name: &'b str,
+) -> impl Future<Output = Result<()>> + 'c {
+    template(configuration, name)
+        .and_then(move |_template| configuration.database.remove_template(name))
+}
+
+/// Tells how every tag with a budget uses it this week: how much time was spent on it and how
+/// much the accepted schedule still plans for it. See `BudgetUse::this_week`.
+pub fn budget_use<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Vec<BudgetUse>>> + 'b {
+    let now = configuration.now();
+    let database = &configuration.database;
+    database.completed_tasks().and_then(move |completed_tasks| {
+        database
+            .find_schedule(ScheduleKind::Accepted)
+            .map_ok(move |accepted| {
+                BudgetUse::this_week(
+                    now,
+                    &configuration.budgets,
+                    &completed_tasks,
+                    accepted.as_ref(),
+                )
+            })
+    })
+}
+
+/// Schedules the tasks that have to be started within their horizon (see `Task::beyond_horizon`)
+/// according to the given strategy. The others are left for later, see `deferred`. The time that
+/// is planned for a tag stays within its budget, counting the time spent on it this week. See
+/// `Schedule::schedule_within_budgets`. Tasks are planned in their preferred window when there is
+/// time for them in it, see `Schedule::keep_to_windows`.
+///
+/// Every assignee gets a timeline of their own (see `Configuration::assignees`), with budgets of
+/// their own: the budgets hold for every person separately.
+///
+/// Snoozed tasks are left out until what they wait for goes off (see `Trigger`), at the place of
+/// the configuration. Those that can't be done before their deadline after the tasks they wait
+/// for don't keep the others from being scheduled, see `stuck`. But when a task that is waited
+/// for can't be scheduled, the error is about the whole chain of tasks that can't be done in time.
 pub fn schedule<'a: 'c, 'b: 'c, 'c>(
     configuration: &'a Configuration,
     strategy: &'b str,
 ) -> impl Future<Output = Result<Schedule>> + 'c {
-    let strategy = match strategy {
-        "importance" => SchedulingStrategy::Importance,
-        "urgency" => SchedulingStrategy::Urgency,
-        _ => panic!("Unsupported scheduling strategy provided"),
+    let start = configuration.now();
+
+    let database = &configuration.database;
+
+    future::ready(parse_strategy(strategy)).and_then(move |strategy| {
+        unsnoozed_tasks(configuration).and_then(move |(tasks, snoozed)| {
+            database.completed_tasks().and_then(move |completed_tasks| {
+                let pending: Vec<Task> = tasks.iter().chain(&snoozed).cloned().collect();
+                future::ready(
+                    plan(configuration, start, strategy, tasks, &completed_tasks).map_err(
+                        |error| blame_chain(error, &stuck_tasks(configuration, start, pending)),
+                    ),
+                )
+            })
+        })
+    })
+}
+
+/// Tells which tasks can't be done before their deadline after the tasks they wait for, when
+/// these are scheduled right away, and which tasks wait for each other in a circle. See
+/// `Schedule::check_dependencies`.
+pub fn stuck<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Vec<ScheduleError>>> + 'b {
+    let now = configuration.now();
+    configuration
+        .database
+        .all_tasks()
+        .map_ok(move |tasks| stuck_tasks(configuration, now, tasks))
+}
+
+/// Returns the tasks that new schedules leave for later, because the work on them doesn't have to
+/// start within their horizon.
+pub fn deferred<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Vec<Task>>> + 'b {
+    let now = configuration.now();
+    configuration.database.all_tasks().map_ok(move |tasks| {
+        let beyond = beyond_horizons(configuration, now, &tasks);
+        tasks
+            .into_iter()
+            .zip(beyond)
+            .filter(|&(_, beyond)| beyond)
+            .map(|(task, _)| task)
+            .collect()
+    })
+}
+
+/// Generates a schedule like `schedule` and stores it as the proposed schedule, without touching
+/// the accepted one. It only replaces the accepted schedule once it is accepted with
+/// `accept_schedule`.
+pub fn propose_schedule<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    strategy: &'b str,
+) -> impl Future<Output = Result<Schedule>> + 'c {
+    schedule(configuration, strategy).and_then(move |schedule| {
+        configuration
+            .database
+            .save_schedule(ScheduleKind::Proposed, schedule.clone())
+            .map_ok(|()| schedule)
+    })
+}
+
+/// Makes the proposed schedule the accepted one, and returns it. A snapshot of it is kept for the
+/// statistics. The notes on the slots of the schedule that was accepted before are kept where the
+/// slots stay the same, see `Schedule::keep_notes`.
+pub fn accept_schedule<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Schedule>> + 'b {
+    let now = configuration.now();
+    let database = &configuration.database;
+    database
+        .find_schedule(ScheduleKind::Proposed)
+        .and_then(|proposed_schedule| {
+            future::ready(proposed_schedule.ok_or_else(|| ErrorKind::NoProposedSchedule.into()))
+        })
+        .and_then(move |mut schedule| {
+            database
+                .find_schedule(ScheduleKind::Accepted)
+                .map_ok(move |accepted| {
+                    if let Some(accepted) = accepted {
+                        schedule.keep_notes(&accepted);
+                    }
+                    schedule
+                })
+        })
+        .and_then(move |schedule| {
+            let snapshot = Snapshot::of(now, &schedule);
+            database
+                .accept_schedule(schedule.clone(), snapshot)
+                .map_ok(|()| schedule)
+        })
+}
+
+/// Returns the schedule that was accepted last, if any.
+pub fn accepted_schedule<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<Option<Schedule>>> + 'b {
+    configuration.database.find_schedule(ScheduleKind::Accepted)
+}
+
+/// Puts `note` on a slot of the task with the given id in the accepted schedule, or removes the
+/// note from it when `note` is `None`, and returns the slot. It's the slot that starts at `when`,
+/// or the first one that hasn't ended yet when `when` isn't given.
+pub fn annotate<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    id: u32,
+    when: Option<DateTime<Utc>>,
+    note: Option<String>,
+) -> impl Future<Output = Result<ScheduledTask>> + 'b {
+    let now = configuration.now();
+    let database = &configuration.database;
+
+    // Look the task up first, so a wrong id results in the same error as elsewhere
+    database
+        .find_task(id)
+        .and_then(move |_task| database.find_schedule(ScheduleKind::Accepted))
+        .and_then(move |accepted| future::ready(annotated(accepted, now, id, when, note)))
+        .and_then(move |(schedule, slot)| {
+            database
+                .save_schedule(ScheduleKind::Accepted, schedule)
+                .map_ok(move |()| slot)
+        })
+}
+
+/// Explains why every slot of the task with the given id is where it is in the accepted schedule.
+/// See `Schedule::explain`. A task that isn't scheduled because it waits for another task to be
+/// done (see `Trigger::Done`) gets an `ErrorKind::WaitingFor`.
+pub fn explain<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    id: u32,
+) -> impl Future<Output = Result<Vec<Explanation>>> + 'b {
+    let database = &configuration.database;
+
+    // Look the task up first, so a wrong id results in the same error as elsewhere
+    database
+        .find_task(id)
+        .and_then(move |task| {
+            database
+                .find_schedule(ScheduleKind::Accepted)
+                .map_ok(move |accepted| (task, accepted))
+        })
+        .and_then(move |(task, accepted)| future::ready(explained(&task, accepted)))
+}
+
+/// Schedules as many tasks as possible when there isn't enough time for all of them, sharing the
+/// time between the configured projects proportionally to their weight. See
+/// `Schedule::schedule_fairly`. Every assignee gets a timeline of their own, like with
+/// `schedule`, and the time is shared between the projects on each of them. Snoozed tasks are
+/// left out like with `schedule`.
+pub fn schedule_fairly<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    strategy: &'b str,
+) -> impl Future<Output = Result<FairSchedule>> + 'c {
+    let start = configuration.now();
+
+    future::ready(parse_strategy(strategy)).and_then(move |strategy| {
+        unsnoozed_tasks(configuration).and_then(move |(tasks, snoozed)| {
+            let pending: Vec<Task> = tasks.iter().chain(&snoozed).cloned().collect();
+            future::ready(
+                plan_fairly(configuration, start, strategy, tasks).map_err(|error| {
+                    blame_chain(error, &stuck_tasks(configuration, start, pending))
+                }),
+            )
+        })
+    })
+}
+
+/// Rolls over the day: the tasks that the accepted schedule planned to be finished by now but
+/// that aren't completed are marked as slipped, and a new schedule is accepted in which they get
+/// the time that is left of them (see `Rollover::time_left`). It is generated like `schedule`,
+/// replaces the proposed schedule if there is one and is kept in a snapshot and keeps notes like
+/// with `accept_schedule`. The rollover also tells which snoozed tasks are stuck, like `stuck`
+/// does.
+///
+/// The slipped tasks and the new schedule are stored in one transaction. When no new schedule can
+/// be generated, nothing is changed, except that the snoozed tasks whose trigger went off aren't
+/// snoozed anymore.
+pub fn roll_over<'a: 'c, 'b: 'c, 'c>(
+    configuration: &'a Configuration,
+    strategy: &'b str,
+) -> impl Future<Output = Result<Rollover>> + 'c {
+    let now = configuration.now();
+    let database = &configuration.database;
+
+    future::ready(parse_strategy(strategy)).and_then(move |strategy| {
+        database
+            .find_schedule(ScheduleKind::Accepted)
+            .and_then(move |accepted| {
+                database
+                    .sessions()
+                    .map_ok(move |sessions| (accepted, sessions))
+            })
+            .and_then(move |(accepted, sessions)| {
+                unsnoozed_tasks(configuration).map_ok(move |(tasks, snoozed)| {
+                    let accepted = accepted.unwrap_or_else(|| Schedule(vec![]));
+                    let mut slipped = Rollover::slipped_tasks(now, &accepted, &tasks);
+                    for task in &mut slipped {
+                        task.slipped += 1;
+                    }
+                    let pending = tasks.iter().chain(&snoozed).cloned().collect();
+                    let stuck = stuck_tasks(configuration, now, pending);
+                    // The slipped tasks are stored as they are, but only what is left of them is
+                    // planned
+                    let tasks: Vec<Task> = tasks
+                        .into_iter()
+                        .map(|task| {
+                            match slipped.iter().find(|slipped_task| slipped_task.id == task.id) {
+                                Some(slipped_task) => Task {
+                                    duration: Rollover::time_left(slipped_task, &sessions, now),
+                                    ..slipped_task.clone()
+                                },
+                                None => task,
+                            }
+                        })
+                        .collect();
+                    (slipped, stuck, tasks, accepted)
+                })
+            })
+            .and_then(move |(slipped, stuck, tasks, accepted)| {
+                database.completed_tasks().and_then(move |completed_tasks| {
+                    future::ready(
+                        plan(configuration, now, strategy, tasks, &completed_tasks)
+                            .map_err(|error| blame_chain(error, &stuck)),
+                    )
+                    .map_ok(move |mut schedule| {
+                        schedule.keep_notes(&accepted);
+                        (slipped, stuck, schedule)
+                    })
+                })
+            })
+            .and_then(move |(slipped, stuck, schedule)| {
+                let snapshot = Snapshot::of(now, &schedule);
+                database
+                    .roll_over(slipped.clone(), schedule.clone(), snapshot)
+                    .map_ok(move |()| Rollover {
+                        slipped,
+                        schedule,
+                        stuck,
+                    })
+            })
+    })
+}
+
+/// Stores every task, archived task, template and schedule again, as they are. When the database
+/// encrypts what it stores (see `database::encrypted`), this encrypts what was stored before it
+/// did.
+pub fn store_everything_again<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<()>> + 'b {
+    let database = &configuration.database;
+    let store_schedule_again = move |kind| {
+        database
+            .find_schedule(kind)
+            .and_then(move |schedule| match schedule {
+                Some(schedule) => future::Either::Left(database.save_schedule(kind, schedule)),
+                None => future::Either::Right(future::ready(Ok(()))),
+            })
     };
+
+    database
+        .all_tasks()
+        .and_then(move |tasks| database.update_tasks(tasks))
+        .and_then(move |()| database.completed_tasks())
+        .and_then(move |completed_tasks| {
+            stream::iter(completed_tasks)
+                .map(Ok)
+                .try_for_each(move |task| database.archive_task(task))
+        })
+        .and_then(move |()| database.templates())
+        .and_then(move |templates| {
+            stream::iter(templates)
+                .map(Ok)
+                .try_for_each(move |template| database.save_template(template))
+        })
+        .and_then(move |()| store_schedule_again(ScheduleKind::Proposed))
+        .and_then(move |()| store_schedule_again(ScheduleKind::Accepted))
+}
+
+/// Calculates how much time per week has to be spent on the task with the given id to finish it
+/// before its deadline. See `Pace::required`.
+pub fn required_pace<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    id: u32,
+) -> impl Future<Output = Result<Pace>> + 'b {
     let start = configuration.now();
 
-    configuration.database.all_tasks().and_then(move |tasks| {
-        future::ready(Schedule::schedule(start, tasks, strategy)).map_err(Error::from)
+    // Look the task up first, so a wrong id results in the same error as elsewhere
+    configuration.database.find_task(id).and_then(move |_task| {
+        configuration.database.all_tasks().and_then(move |tasks| {
+            let tasks = with_default_buffer(configuration, tasks);
+            future::ready(Pace::required(start, tasks, id)).map_err(Error::from)
+        })
+    })
+}
+
+/// Schedules those of `tasks` that are within their horizon from `start`, like `schedule` does.
+///
+/// Every assignee gets a timeline of their own, see `timelines`. The budgets are per person:
+/// they hold on every timeline separately, and the time that was spent this week on the tasks
+/// that are assigned to someone else doesn't count towards them. The time spent on the tasks
+/// without an assignee counts for everyone, since it isn't known who spent it.
+fn plan(
+    configuration: &Configuration,
+    start: DateTime<Utc>,
+    strategy: SchedulingStrategy,
+    tasks: Vec<Task>,
+    completed_tasks: &[Task],
+) -> Result<Schedule> {
+    let tasks = within_horizon(configuration, start, tasks);
+    let tasks = with_default_buffer(configuration, tasks);
+    let plan_timeline = |assignee: Option<&str>, tasks| {
+        let completed_by: Vec<Task> = completed_tasks
+            .iter()
+            .filter(|task| {
+                task.assignee.is_none() || task.assignee.as_ref().map(String::as_str) == assignee
+            })
+            .cloned()
+            .collect();
+        let spent = statistics::spent_per_tag(start, &completed_by);
+        let unavailable = configuration.unavailable_to(assignee);
+        plan_timeline(configuration, start, strategy, tasks, &spent, &unavailable)
+    };
+    let fits = |assignee: Option<&str>, tasks| plan_timeline(assignee, tasks).is_ok();
+    let mut scheduled_tasks = vec![];
+    for (assignee, tasks) in timelines(configuration, tasks, fits)? {
+        let schedule = plan_timeline(assignee.as_ref().map(String::as_str), tasks)?;
+        scheduled_tasks.extend(schedule.0);
+    }
+    scheduled_tasks.sort_by_key(|scheduled_task| scheduled_task.when);
+    Ok(Schedule(scheduled_tasks))
+}
+
+/// Schedules as many of those of `tasks` that are within their horizon from `start` as possible,
+/// like `schedule_fairly` does. Every assignee gets a timeline of their own, like with `plan`,
+/// and the time on each of them is shared between the projects separately. The shortfalls of a
+/// project on all timelines are added up.
+fn plan_fairly(
+    configuration: &Configuration,
+    start: DateTime<Utc>,
+    strategy: SchedulingStrategy,
+    tasks: Vec<Task>,
+) -> Result<FairSchedule> {
+    let tasks = within_horizon(configuration, start, tasks);
+    let tasks = with_default_buffer(configuration, tasks);
+    let plan_timeline = |assignee: Option<&str>, tasks| -> Result<FairSchedule> {
+        let unavailable = configuration.unavailable_to(assignee);
+        let mut fair_schedule = Schedule::schedule_fairly(
+            start,
+            tasks,
+            strategy,
+            &configuration.project_weights,
+            &unavailable,
+            &configuration.urgency,
+            &*configuration.local_time,
+        )?;
+        fair_schedule
+            .schedule
+            .keep_to_windows(start, &unavailable, &*configuration.local_time);
+        Ok(fair_schedule)
+    };
+    // A task only fits on a timeline when nothing has to be left out for it
+    let fits = |assignee: Option<&str>, tasks| {
+        plan_timeline(assignee, tasks).map_or(false, |fair_schedule| {
+            fair_schedule
+                .shortfalls
+                .iter()
+                .all(|shortfall| shortfall.dropped.is_empty())
+        })
+    };
+    let mut scheduled_tasks = vec![];
+    let mut shortfalls: Vec<Shortfall> = vec![];
+    for (assignee, tasks) in timelines(configuration, tasks, fits)? {
+        let fair_schedule = plan_timeline(assignee.as_ref().map(String::as_str), tasks)?;
+        scheduled_tasks.extend(fair_schedule.schedule.0);
+        for shortfall in fair_schedule.shortfalls {
+            match shortfalls
+                .iter_mut()
+                .find(|total| total.project == shortfall.project)
+            {
+                Some(total) => {
+                    total.requested = total.requested + shortfall.requested;
+                    total.allocated = total.allocated + shortfall.allocated;
+                    total.dropped.extend(shortfall.dropped);
+                }
+                None => shortfalls.push(shortfall),
+            }
+        }
+    }
+    scheduled_tasks.sort_by_key(|scheduled_task| scheduled_task.when);
+    Ok(FairSchedule {
+        schedule: Schedule(scheduled_tasks),
+        shortfalls,
+    })
+}
+
+/// Divides `tasks` between the timelines of the assignees, by assignee.
+///
+/// Every assignee gets a timeline of their own, which is unavailable in their own unavailable
+/// periods as well (see `Configuration::unavailable_to`). So do the tasks without an assignee,
+/// as long as no assignees are configured. When there are, each of those tasks is assigned to
+/// whoever has the least work and still has time for it, the most important tasks first. `fits`
+/// tells whether the tasks of a timeline can be scheduled on it.
+fn timelines<F>(
+    configuration: &Configuration,
+    tasks: Vec<Task>,
+    fits: F,
+) -> Result<BTreeMap<Option<String>, Vec<Task>>>
+where
+    F: Fn(Option<&str>, Vec<Task>) -> bool,
+{
+    let mut timelines: BTreeMap<Option<String>, Vec<Task>> = configuration
+        .assignees
+        .keys()
+        .map(|assignee| (Some(assignee.clone()), vec![]))
+        .collect();
+    let (mut unassigned, assigned): (Vec<Task>, Vec<Task>) =
+        tasks.into_iter().partition(|task| task.assignee.is_none());
+    for task in assigned {
+        timelines
+            .entry(task.assignee.clone())
+            .or_default()
+            .push(task);
+    }
+    if configuration.assignees.is_empty() {
+        if !unassigned.is_empty() {
+            timelines.insert(None, unassigned);
+        }
+        return Ok(timelines);
+    }
+    unassigned.sort_by_key(|task| (Reverse(task.importance), task.deadline, task.id));
+    for task in unassigned {
+        let mut candidates: Vec<(Duration, &String)> = configuration
+            .assignees
+            .keys()
+            .map(|assignee| {
+                let work = timelines[&Some(assignee.clone())].iter().try_fold(
+                    Duration::zero(),
+                    |work, task| {
+                        task.duration_with_buffer()
+                            .and_then(|occupied| work.checked_add(&occupied))
+                            .ok_or_else(|| scheduling::Error::Overflow { task: task.clone() })
+                    },
+                )?;
+                Ok((work, assignee))
+            })
+            .collect::<Result<_>>()?;
+        candidates.sort();
+        let assigned_to = |assignee: &String| Task {
+            assignee: Some(assignee.clone()),
+            ..task.clone()
+        };
+        let fits_on = |assignee: &String| {
+            let mut tasks = timelines[&Some(assignee.clone())].clone();
+            tasks.push(assigned_to(assignee));
+            fits(Some(assignee), tasks)
+        };
+        // When it fits nowhere, scheduling it on the least busy timeline tells why
+        let assignee = candidates
+            .iter()
+            .map(|&(_, assignee)| assignee)
+            .find(|assignee| fits_on(assignee))
+            .unwrap_or(candidates[0].1);
+        let task = assigned_to(assignee);
+        timelines.get_mut(&task.assignee).unwrap().push(task);
+    }
+    Ok(timelines)
+}
+
+/// Schedules `tasks` on a single timeline that is unavailable in the `unavailable` periods, with
+/// the time that was `spent` on the tags this week counting towards their budgets.
+///
+/// When there isn't enough time for a task, but there would be without the unavailable periods,
+/// the error says that it's because of the time off. The tasks keep to their preferred windows
+/// where they can (see `Schedule::keep_to_windows`).
+fn plan_timeline(
+    configuration: &Configuration,
+    start: DateTime<Utc>,
+    strategy: SchedulingStrategy,
+    tasks: Vec<Task>,
+    spent: &HashMap<String, Duration>,
+    unavailable: &[Range<DateTime<Utc>>],
+) -> Result<Schedule> {
+    let schedule = |tasks, unavailable| {
+        Schedule::schedule_within_budgets(
+            start,
+            tasks,
+            strategy,
+            &configuration.budgets,
+            spent,
+            unavailable,
+            &configuration.urgency,
+            &*configuration.local_time,
+        )
+    };
+    let result = schedule(tasks.clone(), unavailable);
+    if let Err(ScheduleError::NotEnoughTime {
+        ref task,
+        shortfall,
+        ref conflicting,
+    }) = result
+    {
+        if !unavailable.is_empty() && schedule(tasks, &[]).is_ok() {
+            bail!(ScheduleError::TimeOff {
+                task: task.clone(),
+                shortfall,
+                conflicting: conflicting.clone(),
+            });
+        }
+    }
+    let mut schedule = result?;
+    schedule.keep_to_windows(start, unavailable, &*configuration.local_time);
+    Ok(schedule)
+}
+
+/// Puts `note` on the slot of the task with the given id in the `accepted` schedule, as described
+/// in `annotate`, and returns the changed schedule and the slot.
+fn annotated(
+    accepted: Option<Schedule>,
+    now: DateTime<Utc>,
+    id: u32,
+    when: Option<DateTime<Utc>>,
+    note: Option<String>,
+) -> Result<(Schedule, ScheduledTask)> {
+    let mut schedule = accepted.ok_or(ErrorKind::NoAcceptedSchedule)?;
+    let slot = schedule
+        .0
+        .iter_mut()
+        .filter(|scheduled_task| scheduled_task.task.id == id)
+        .find(|scheduled_task| match when {
+            Some(when) => scheduled_task.when == when,
+            // A slot whose end is out of range isn't over yet
+            None => scheduled_task
+                .when
+                .checked_add_signed(scheduled_task.task.duration)
+                .map_or(true, |end| now < end),
+        })
+        .ok_or(ErrorKind::NotScheduled(id))?;
+    slot.note = note;
+    let slot = slot.clone();
+    Ok((schedule, slot))
+}
+
+fn explained(task: &Task, accepted: Option<Schedule>) -> Result<Vec<Explanation>> {
+    let accepted = accepted.ok_or(ErrorKind::NoAcceptedSchedule)?;
+    let explanations = accepted.explain(task.id);
+    if explanations.is_empty() {
+        if let Some(Trigger::Done(other)) = task.waiting_for {
+            bail!(ErrorKind::WaitingFor(task.id, other));
+        }
+        bail!(ErrorKind::NotInSchedule(task.id));
+    }
+    Ok(explanations)
+}
+
+/// Ends the work session that is going on at `end`, if any, and adds its time to the time spent
+/// on its task.
+fn end_session<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    end: DateTime<Utc>,
+) -> impl Future<Output = Result<Option<Session>>> + 'b {
+    let database = &configuration.database;
+    let add_to_task = move |session: Session| {
+        database
+            .find_task(session.task_id)
+            .and_then(move |mut task| {
+                session.add_to(&mut task);
+                database.update_task(task).map_ok(move |()| Some(session))
+            })
+    };
+
+    database
+        .end_session(end)
+        .and_then(move |ended| match ended {
+            Some(session) => future::Either::Left(add_to_task(session)),
+            None => future::Either::Right(future::ready(Ok(None))),
+        })
+}
+
+/// Ends the work session that is going on at `end` if it's on the task with the given id, like
+/// `end_session`.
+fn end_session_of<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+    id: u32,
+    end: DateTime<Utc>,
+) -> impl Future<Output = Result<()>> + 'b {
+    configuration.database.sessions().and_then(move |sessions| {
+        let working_on_it = sessions
+            .iter()
+            .any(|session| session.end.is_none() && session.task_id == id);
+        if working_on_it {
+            future::Either::Left(end_session(configuration, end).map_ok(|_session| ()))
+        } else {
+            future::Either::Right(future::ready(Ok(())))
+        }
+    })
+}
+
+/// Returns the tasks that aren't done yet and don't wait for a trigger anymore, and those that
+/// still do. The tasks whose trigger went off have it cleared and are stored like that, so they
+/// are scheduled from now on, also when the user isn't at the place of the trigger anymore.
+fn unsnoozed_tasks<'a: 'b, 'b>(
+    configuration: &'a Configuration,
+) -> impl Future<Output = Result<(Vec<Task>, Vec<Task>)>> + 'b {
+    let database = &configuration.database;
+    let place = configuration.place.as_ref().map(String::as_str);
+
+    database.all_tasks().and_then(move |tasks| {
+        let went_off: Vec<Task> = tasks
+            .iter()
+            .filter(|task| {
+                task.waiting_for
+                    .as_ref()
+                    .map_or(false, |trigger| trigger.went_off(place, &tasks))
+            })
+            .map(|task| Task {
+                waiting_for: None,
+                ..task.clone()
+            })
+            .collect();
+        let (waiting, tasks): (Vec<Task>, Vec<Task>) = tasks
+            .into_iter()
+            .partition(|task| task.waiting_for.is_some());
+        let snoozed: Vec<Task> = waiting
+            .into_iter()
+            .filter(|task| went_off.iter().all(|unsnoozed| unsnoozed.id != task.id))
+            .collect();
+        let tasks: Vec<Task> = tasks.into_iter().chain(went_off.iter().cloned()).collect();
+        if went_off.is_empty() {
+            future::Either::Left(future::ready(Ok((tasks, snoozed))))
+        } else {
+            future::Either::Right(
+                database
+                    .update_tasks(went_off)
+                    .map_ok(move |()| (tasks, snoozed)),
+            )
+        }
     })
 }
+
+/// Returns why those of the `pending` tasks that wait for others can't be done before their
+/// deadline, when the others are done from `start` on. See `Schedule::check_dependencies`.
+fn stuck_tasks(
+    configuration: &Configuration,
+    start: DateTime<Utc>,
+    pending: Vec<Task>,
+) -> Vec<ScheduleError> {
+    let pending = with_default_buffer(configuration, pending);
+    Schedule::check_dependencies(start, &pending, |assignee| {
+        configuration.unavailable_to(assignee)
+    })
+}
+
+/// Replaces `error` by the reason why a task is `stuck` when the task that couldn't be scheduled
+/// is part of that chain of tasks that wait for each other, since then the whole chain lacks time
+/// rather than the task on its own.
+fn blame_chain(error: Error, stuck: &[ScheduleError]) -> Error {
+    let id = match error.kind() {
+        ErrorKind::Schedule(schedule_error) => match schedule_error.task() {
+            Some(task) => task.id,
+            None => return error,
+        },
+        _ => return error,
+    };
+    let in_chain = |stuck: &&ScheduleError| {
+        stuck.task().map_or(false, |task| task.id == id)
+            || stuck.conflicting().iter().any(|task| task.id == id)
+    };
+    match stuck.iter().find(in_chain) {
+        Some(stuck) => Error::from(stuck.clone()),
+        None => error,
+    }
+}
+
+/// Leaves out the tasks that don't have to be started within their horizon from `start`.
+fn within_horizon(
+    configuration: &Configuration,
+    start: DateTime<Utc>,
+    tasks: Vec<Task>,
+) -> Vec<Task> {
+    let beyond = beyond_horizons(configuration, start, &tasks);
+    tasks
+        .into_iter()
+        .zip(beyond)
+        .filter(|&(_, beyond)| !beyond)
+        .map(|(task, _)| task)
+        .collect()
+}
+
+/// Tells of each of `tasks` whether the work on it doesn't have to start within its horizon from
+/// `start`, when all of them are done before their deadlines (see `Schedule::latest_starts`).
+fn beyond_horizons(
+    configuration: &Configuration,
+    start: DateTime<Utc>,
+    tasks: &[Task],
+) -> Vec<bool> {
+    Schedule::latest_starts(tasks, &configuration.unavailable)
+        .into_iter()
+        .zip(tasks)
+        .map(|(latest_start, task)| {
+            latest_start.map_or(false, |latest_start| {
+                task.beyond_horizon(start, latest_start, configuration.horizon)
+            })
+        })
+        .collect()
+}
+
+/// Gives the tasks that don't have a buffer of their own the one of the configuration.
+fn with_default_buffer(configuration: &Configuration, mut tasks: Vec<Task>) -> Vec<Task> {
+    for task in &mut tasks {
+        task.buffer = task.buffer.or(Some(configuration.buffer));
+    }
+    tasks
+}
+
+fn parse_strategy(strategy: &str) -> Result<SchedulingStrategy> {
+    match strategy {
+        "importance" => Ok(SchedulingStrategy::Importance),
+        "urgency" => Ok(SchedulingStrategy::Urgency),
+        "optimal" => Ok(SchedulingStrategy::Optimal),
+        _ => Err(Error::from(ErrorKind::Parse(
+            "scheduling strategy".to_owned(),
+            "It should be 'importance', 'urgency' or 'optimal'.".to_owned(),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::configuration::{Budget, FocusSessions, SchedulingStrategy};
+    use crate::task::fixtures::monday;
+
+    #[test]
+    fn thread_safe_types() {
+        fn is_send_and_sync<T: Send + Sync>() {}
+        fn is_send<T: Send>() {}
+        is_send_and_sync::<Task>();
+        is_send_and_sync::<NewTask>();
+        is_send_and_sync::<Template>();
+        is_send_and_sync::<Trigger>();
+        is_send_and_sync::<Window>();
+        is_send_and_sync::<Schedule>();
+        is_send_and_sync::<HistoryQuery>();
+        is_send_and_sync::<ScheduledTask>();
+        is_send_and_sync::<FocusSession>();
+        is_send_and_sync::<FocusSessions>();
+        is_send_and_sync::<FairSchedule>();
+        is_send_and_sync::<Shortfall>();
+        is_send_and_sync::<Pace>();
+        is_send_and_sync::<Explanation>();
+        is_send_and_sync::<Rollover>();
+        is_send_and_sync::<Statistics>();
+        is_send_and_sync::<Snapshot>();
+        is_send_and_sync::<BudgetUse>();
+        is_send_and_sync::<Insights>();
+        is_send_and_sync::<Session>();
+        is_send_and_sync::<Status>();
+        is_send_and_sync::<Budget>();
+        is_send_and_sync::<Estimate>();
+        is_send_and_sync::<TagSuggestion>();
+        is_send_and_sync::<SchedulingStrategy>();
+        is_send_and_sync::<ScheduleKind>();
+        is_send::<Configuration>();
+        is_send::<Error>();
+        is_send::<ScheduleError>();
+    }
+
+    #[test]
+    fn scheduling_errors_blame_the_chain_of_their_task() {
+        let task = |id| Task {
+            id,
+            ..Default::default()
+        };
+        let stuck = ScheduleError::ChainTooLong {
+            task: task(3),
+            chain: vec![task(1), task(2)],
+            duration: Duration::hours(5),
+            window: monday()..monday() + Duration::hours(4),
+            shortfall: Duration::hours(1),
+        };
+        let not_enough_time = |id| {
+            Error::from(ScheduleError::NotEnoughTime {
+                task: task(id),
+                shortfall: Duration::hours(1),
+                conflicting: vec![],
+            })
+        };
+        let blamed = |error| match blame_chain(error, &[stuck.clone()]).kind() {
+            ErrorKind::Schedule(ScheduleError::ChainTooLong { task, .. }) => Some(task.id),
+            _ => None,
+        };
+        assert_eq!(blamed(not_enough_time(1)), Some(3));
+        assert_eq!(blamed(not_enough_time(3)), Some(3));
+        assert_eq!(blamed(not_enough_time(4)), None);
+        assert_eq!(blamed(ErrorKind::NoAcceptedSchedule.into()), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! Closes off a day: the tasks that were scheduled to be finished by then but weren't are marked
+//! as slipped, and are carried over into a new schedule.
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::tracking::Session;
+use crate::{Schedule, ScheduleError, Task};
+
+/// What rolling over a day did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rollover {
+    /// The tasks that slipped, as they are now: with their `slipped` count increased.
+    pub slipped: Vec<Task>,
+    /// The schedule that replaced the accepted one.
+    pub schedule: Schedule,
+    /// Why the snoozed tasks that wait for others can't be done in time, see `crate::stuck`.
+    pub stuck: Vec<ScheduleError>,
+}
+
+impl Rollover {
+    /// Finds the tasks that `accepted` planned to be finished by `now`, but which are still among
+    /// the `pending` tasks. They are taken from `pending`, since the copies in the schedule are as
+    /// old as the schedule, and come in the order they were scheduled in. A task that is
+    /// scheduled in parts only slips once its last part is over.
+    pub fn slipped_tasks(now: DateTime<Utc>, accepted: &Schedule, pending: &[Task]) -> Vec<Task> {
+        let last_parts = accepted.0.iter().filter(|scheduled_task| {
+            accepted.0.iter().all(|other| {
+                other.task.id != scheduled_task.task.id || other.when <= scheduled_task.when
+            })
+        });
+        last_parts
+            .filter(|scheduled_task| scheduled_task.when + scheduled_task.task.duration <= now)
+            .filter_map(|scheduled_task| {
+                pending
+                    .iter()
+                    .find(|task| task.id == scheduled_task.task.id)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// Returns how much of `task` is left to do at `now`: its duration minus the time spent on
+    /// it, including the session on it that is still going on among `sessions`, if any. Nothing
+    /// is left of a task that took longer than it was supposed to.
+    pub fn time_left(task: &Task, sessions: &[Session], now: DateTime<Utc>) -> Duration {
+        let going_on = sessions
+            .iter()
+            .filter(|session| session.task_id == task.id && session.end.is_none())
+            .fold(Duration::zero(), |total, session| total + session.duration(now));
+        let time_spent = task.time_spent.unwrap_or_else(Duration::zero) + going_on;
+        if time_spent < task.duration {
+            task.duration - time_spent
+        } else {
+            Duration::zero()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::fixtures::{monday, task};
+    use crate::ScheduledTask;
+
+    /// Monday evening, when the day is rolled over.
+    fn evening() -> DateTime<Utc> {
+        monday() + Duration::hours(14)
+    }
+
+    fn scheduled(task: Task, hours_before_evening: i64) -> ScheduledTask {
+        ScheduledTask {
+            task,
+            when: evening() - Duration::hours(hours_before_evening),
+            note: None,
+            held_back_by: None,
+        }
+    }
+
+    #[test]
+    fn unfinished_tasks_that_were_due_slip() {
+        let deadline = evening() + Duration::days(3);
+        let accepted = Schedule(vec![
+            scheduled(task(1, 2, deadline), 6),
+            // Completed, so it isn't pending anymore
+            scheduled(task(2, 1, deadline), 4),
+            scheduled(task(3, 3, deadline), 3),
+            // Still going on
+            scheduled(task(4, 2, deadline), 1),
+            scheduled(task(5, 1, deadline), -10),
+        ]);
+        let mut edited = task(1, 2, deadline);
+        edited.content = "edited since".to_owned();
+        let pending = vec![
+            task(5, 1, deadline),
+            task(4, 2, deadline),
+            task(3, 3, deadline),
+            edited.clone(),
+        ];
+        assert_eq!(
+            Rollover::slipped_tasks(evening(), &accepted, &pending),
+            vec![edited, task(3, 3, deadline)]
+        );
+    }
+
+    #[test]
+    fn tasks_in_parts_slip_after_their_last_part() {
+        let deadline = evening() + Duration::days(3);
+        let accepted = Schedule(vec![
+            scheduled(task(1, 1, deadline), 30),
+            scheduled(task(2, 1, deadline), 28),
+            scheduled(task(1, 1, deadline), 6),
+            scheduled(task(2, 1, deadline), -20),
+        ]);
+        let pending = vec![task(1, 2, deadline), task(2, 2, deadline)];
+        assert_eq!(
+            Rollover::slipped_tasks(evening(), &accepted, &pending),
+            vec![task(1, 2, deadline)]
+        );
+    }
+
+    #[test]
+    fn nothing_slips_when_everything_is_done() {
+        let deadline = evening() + Duration::days(3);
+        let accepted = Schedule(vec![
+            scheduled(task(1, 2, deadline), 6),
+            scheduled(task(2, 1, deadline), 4),
+        ]);
+        assert_eq!(
+            Rollover::slipped_tasks(evening(), &accepted, &[]),
+            Vec::<Task>::new()
+        );
+    }
+
+    #[test]
+    fn the_time_spent_is_taken_off() {
+        let deadline = evening() + Duration::days(3);
+        let mut worked_on = task(1, 3, deadline);
+        worked_on.time_spent = Some(Duration::hours(1));
+        let sessions = vec![
+            // Already in the time spent
+            Session {
+                end: Some(evening() - Duration::hours(3)),
+                ..Session::new(1, evening() - Duration::hours(4))
+            },
+            Session::new(2, evening() - Duration::hours(2)),
+            Session::new(1, evening() - Duration::minutes(30)),
+        ];
+        assert_eq!(
+            Rollover::time_left(&worked_on, &sessions, evening()),
+            Duration::minutes(90)
+        );
+        assert_eq!(
+            Rollover::time_left(&task(3, 2, deadline), &sessions, evening()),
+            Duration::hours(2)
+        );
+        worked_on.time_spent = Some(Duration::hours(4));
+        assert_eq!(
+            Rollover::time_left(&worked_on, &sessions, evening()),
+            Duration::zero()
+        );
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::configuration::{Budget, LocalTime, SchedulingStrategy, UrgencyCoefficients};
+use crate::util::monday_of;
+use crate::Task;
+
+use super::{Constraint, Error, Result, Schedule};
+
+impl Schedule {
+    /// Schedules tasks like `schedule_around`, but within the weekly `budgets` of their tags.
+    ///
+    /// No week gets more time planned for a tag than its maximum. When the tasks of a tag don't
+    /// fit in the budget of a week, the least important of them waits until the next week. When
+    /// it can't wait that long, or its waiting leaves too little time for another task, the next
+    /// least important one waits instead, and only when none of them can does the schedule fail
+    /// with `OverBudget`. Since the project of a task is one
+    /// of its tags, this also caps the time of a project. The schedule also tries to plan the
+    /// minimum of every tag in the week of `start`, by planning enough of its tasks that are due
+    /// first by the end of that week. When that doesn't work out, they are scheduled as usual.
+    ///
+    /// `spent` is the time that was already spent on every tag in the week of `start`, which
+    /// counts towards both its maximum and its minimum.
+    pub fn schedule_within_budgets<I>(
+        start: DateTime<Utc>,
+        tasks: I,
+        strategy: SchedulingStrategy,
+        budgets: &HashMap<String, Budget>,
+        spent: &HashMap<String, Duration>,
+        unavailable: &[Range<DateTime<Utc>>],
+        urgency: &UrgencyCoefficients,
+        local_time: &LocalTime,
+    ) -> Result<Schedule>
+    where
+        I: IntoIterator<Item = Task>,
+    {
+        let tasks: Vec<Task> = tasks.into_iter().collect();
+        let schedule = |tasks: Vec<Task>| {
+            within_maximums(
+                start,
+                tasks,
+                strategy,
+                budgets,
+                spent,
+                unavailable,
+                urgency,
+                local_time,
+            )
+        };
+        let towards_minimums = towards_minimums(start, &tasks, budgets, spent);
+        if towards_minimums != tasks {
+            if let Ok(schedule) = schedule(towards_minimums) {
+                return Ok(restore(schedule, &tasks));
+            }
+        }
+        schedule(tasks.clone()).map(|schedule| restore(schedule, &tasks))
+    }
+}
+
+/// Schedules `tasks`, making the least important ones that exceed the maximum of a tag in a week
+/// wait until the next week, until none of them do.
+fn within_maximums(
+    start: DateTime<Utc>,
+    mut tasks: Vec<Task>,
+    strategy: SchedulingStrategy,
+    budgets: &HashMap<String, Budget>,
+    spent: &HashMap<String, Duration>,
+    unavailable: &[Range<DateTime<Utc>>],
+    urgency: &UrgencyCoefficients,
+    local_time: &LocalTime,
+) -> Result<Schedule> {
+    for task in &tasks {
+        if let Some(tag) = task
+            .tags
+            .iter()
+            .find(|tag| maximum(budgets, tag).map_or(false, |maximum| task.duration > maximum))
+        {
+            bail!(Error::OverBudget {
+                task: task.clone(),
+                tag: tag.clone(),
+            });
+        }
+    }
+    // The ids of the tasks that were made to wait, when they could start before that and the tag
+    // they wait for, from the one that waited first to the one that waited last
+    let mut waiting: Vec<(u32, Option<DateTime<Utc>>, String)> = vec![];
+    // The tasks that missed their deadline when they waited
+    let mut cant_wait: HashSet<u32> = HashSet::new();
+    loop {
+        let attempt = Schedule::schedule_around(
+            start,
+            tasks.clone(),
+            strategy,
+            unavailable,
+            urgency,
+            local_time,
+        );
+        let schedule = match attempt {
+            Ok(schedule) => schedule,
+            Err(Error::DeadlineMissed { task, .. })
+            | Err(Error::StartsTooLate { task, .. })
+            | Err(Error::NotEnoughTime { task, .. })
+                if !waiting.is_empty() =>
+            {
+                // Let the task go back to where it was, so that another task can wait instead.
+                // When it didn't wait, the task that waited last might have pushed it out.
+                let position = waiting
+                    .iter()
+                    .position(|&(id, _, _)| id == task.id)
+                    .unwrap_or(waiting.len() - 1);
+                let (id, start_after, _) = waiting.remove(position);
+                for task in tasks.iter_mut().filter(|task| task.id == id) {
+                    task.start_after = start_after;
+                }
+                cant_wait.insert(id);
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let over_budget = match first_over_budget(start, &schedule, budgets, spent) {
+            Some(over_budget) => over_budget,
+            None => return Ok(held_back_by_budgets(schedule, &waiting)),
+        };
+        let id = match over_budget
+            .candidates
+            .iter()
+            .find(|id| !cant_wait.contains(id))
+        {
+            Some(&id) => id,
+            None => bail!(Error::OverBudget {
+                task: over_budget.task,
+                tag: over_budget.tag,
+            }),
+        };
+        // When it already waited a week, it can still go back to where it was at first
+        let start_after = match waiting
+            .iter()
+            .position(|&(waiting_id, _, _)| waiting_id == id)
+        {
+            Some(position) => waiting.remove(position).1,
+            None => tasks
+                .iter()
+                .find(|task| task.id == id)
+                .and_then(|task| task.start_after),
+        };
+        waiting.push((id, start_after, over_budget.tag));
+        for task in tasks.iter_mut().filter(|task| task.id == id) {
+            task.start_after = Some(over_budget.next_week);
+        }
+    }
+}
+
+/// Marks the slots of the `waiting` tasks that start right when they can, in the week they wait
+/// for, as held back by the budget of their tag.
+fn held_back_by_budgets(
+    mut schedule: Schedule,
+    waiting: &[(u32, Option<DateTime<Utc>>, String)],
+) -> Schedule {
+    for scheduled_task in &mut schedule.0 {
+        let tag = match waiting.iter().find(|(id, _, _)| *id == scheduled_task.task.id) {
+            Some((_, _, tag)) => tag,
+            None => continue,
+        };
+        match scheduled_task.held_back_by {
+            Some(Constraint::StartAfter(start_after))
+                if scheduled_task.task.start_after == Some(start_after) =>
+            {
+                scheduled_task.held_back_by = Some(Constraint::Budget(tag.clone()));
+            }
+            _ => (),
+        }
+    }
+    schedule
+}
+
+/// A week in which the tasks of a tag exceed its maximum.
+struct OverBudget {
+    tag: String,
+    /// The scheduled task that exceeded the maximum.
+    task: Task,
+    /// The tasks with the tag in that week that could wait until the next one, by their id, from
+    /// the least important to the most important. Of equally important tasks, the one that is
+    /// scheduled last comes first.
+    candidates: Vec<u32>,
+    next_week: DateTime<Utc>,
+}
+
+/// Finds the first scheduled task that makes a tag exceed its maximum in a week.
+fn first_over_budget(
+    start: DateTime<Utc>,
+    schedule: &Schedule,
+    budgets: &HashMap<String, Budget>,
+    spent: &HashMap<String, Duration>,
+) -> Option<OverBudget> {
+    let this_week = monday_of(start);
+    let mut planned: HashMap<(Date<Utc>, &str), Duration> = HashMap::new();
+    for scheduled_task in &schedule.0 {
+        let task = &scheduled_task.task;
+        let week = monday_of(scheduled_task.when);
+        for tag in &task.tags {
+            let maximum = match maximum(budgets, tag) {
+                Some(maximum) => maximum,
+                None => continue,
+            };
+            let already_planned =
+                planned
+                    .get(&(week, tag.as_str()))
+                    .cloned()
+                    .unwrap_or_else(|| match spent.get(tag) {
+                        Some(&spent) if week == this_week => spent,
+                        _ => Duration::zero(),
+                    });
+            if already_planned + task.duration > maximum {
+                let mut in_week: Vec<&Task> = schedule
+                    .0
+                    .iter()
+                    .rev()
+                    .filter(|other| monday_of(other.when) == week && other.task.has_tag(tag))
+                    .map(|other| &other.task)
+                    .collect();
+                in_week.sort_by_key(|other| other.importance);
+                let mut candidates: Vec<u32> = vec![];
+                for other in in_week {
+                    // A task with a daily minimum is scheduled in several parts
+                    if !candidates.contains(&other.id) {
+                        candidates.push(other.id);
+                    }
+                }
+                return Some(OverBudget {
+                    tag: tag.clone(),
+                    task: task.clone(),
+                    candidates,
+                    next_week: (week + Duration::weeks(1)).and_hms(0, 0, 0),
+                });
+            }
+            planned.insert((week, tag.as_str()), already_planned + task.duration);
+        }
+    }
+    None
+}
+
+/// Returns `tasks` with the deadline of enough tasks moved to the end of the week of `start` to
+/// reach the minimum of every tag, if there are enough of them that can be started that week.
+/// The tasks of a tag that are due first are chosen first.
+fn towards_minimums(
+    start: DateTime<Utc>,
+    tasks: &[Task],
+    budgets: &HashMap<String, Budget>,
+    spent: &HashMap<String, Duration>,
+) -> Vec<Task> {
+    let end_of_week = (monday_of(start) + Duration::weeks(1)).and_hms(0, 0, 0);
+    let mut tasks = tasks.to_vec();
+    for (tag, budget) in budgets {
+        let minimum = match budget.minimum {
+            Some(minimum) => minimum,
+            None => continue,
+        };
+        let mut missing = minimum - spent.get(tag).cloned().unwrap_or_else(Duration::zero);
+        let mut candidates: Vec<&mut Task> = tasks
+            .iter_mut()
+            .filter(|task| {
+                task.has_tag(tag)
+                    && task
+                        .start_after
+                        .map_or(true, |start_after| start_after < end_of_week)
+            })
+            .collect();
+        candidates.sort_by_key(|task| task.deadline);
+        for task in candidates {
+            if missing <= Duration::zero() {
+                break;
+            }
+            if end_of_week < task.deadline {
+                task.deadline = end_of_week;
+            }
+            missing = missing - task.duration;
+        }
+    }
+    tasks
+}
+
+fn maximum(budgets: &HashMap<String, Budget>, tag: &str) -> Option<Duration> {
+    budgets.get(tag).and_then(|budget| budget.maximum)
+}
+
+/// Puts the original tasks back in `schedule`, which was made with changed copies of them. The
+/// durations are kept, since tasks with a daily minimum are scheduled in parts.
+fn restore(schedule: Schedule, originals: &[Task]) -> Schedule {
+    Schedule(
+        schedule
+            .0
+            .into_iter()
+            .map(|mut scheduled_task| {
+                scheduled_task.restore(|task| {
+                    originals.iter().find(|original| original.id == task.id)
+                });
+                scheduled_task
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::task::fixtures::{monday, task};
+
+    fn budgets(maximum: Option<i64>, minimum: Option<i64>) -> HashMap<String, Budget> {
+        let budget = Budget {
+            maximum: maximum.map(Duration::hours),
+            minimum: minimum.map(Duration::hours),
+        };
+        vec![("meetings".to_owned(), budget)].into_iter().collect()
+    }
+
+    fn schedule_within(
+        tasks: Vec<Task>,
+        strategy: SchedulingStrategy,
+        budgets: &HashMap<String, Budget>,
+        spent: &HashMap<String, Duration>,
+    ) -> Result<Schedule> {
+        Schedule::schedule_within_budgets(
+            monday(),
+            tasks,
+            strategy,
+            budgets,
+            spent,
+            &[],
+            &Default::default(),
+            &Utc,
+        )
+    }
+
+    fn hours_in_first_week(schedule: &Schedule, tag: &str) -> Duration {
+        let next_monday = Utc.ymd(2019, 3, 11).and_hms(0, 0, 0);
+        schedule
+            .0
+            .iter()
+            .filter(|scheduled_task| {
+                scheduled_task.when < next_monday && scheduled_task.task.has_tag(tag)
+            })
+            .fold(Duration::zero(), |total, scheduled_task| {
+                total + scheduled_task.task.duration
+            })
+    }
+
+    #[test]
+    fn maximums_are_never_exceeded() {
+        let tasks: Vec<Task> = (0..4)
+            .map(|id| task(id, 2, monday() + Duration::days(20)).with_tag("meetings"))
+            .collect();
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let schedule = schedule_within(
+                tasks.clone(),
+                *strategy,
+                &budgets(Some(5), None),
+                &HashMap::new(),
+            )
+            .unwrap();
+            assert_eq!(schedule.0.len(), 4);
+            assert_eq!(
+                hours_in_first_week(&schedule, "meetings"),
+                Duration::hours(4)
+            );
+            // The tasks come out as they went in
+            for scheduled_task in &schedule.0 {
+                assert!(tasks.contains(&scheduled_task.task));
+            }
+        }
+    }
+
+    #[test]
+    fn time_spent_counts_towards_the_maximum() {
+        let tasks = vec![
+            task(0, 2, monday() + Duration::days(20)).with_tag("meetings"),
+            task(1, 2, monday() + Duration::days(20)).with_tag("other"),
+        ];
+        let spent = vec![("meetings".to_owned(), Duration::hours(4))]
+            .into_iter()
+            .collect();
+        let schedule = schedule_within(
+            tasks,
+            SchedulingStrategy::Importance,
+            &budgets(Some(5), None),
+            &spent,
+        )
+        .unwrap();
+        assert_eq!(hours_in_first_week(&schedule, "meetings"), Duration::zero());
+        assert_eq!(hours_in_first_week(&schedule, "other"), Duration::hours(2));
+    }
+
+    #[test]
+    fn the_least_important_tasks_wait_for_the_budget() {
+        // The important task can only start later in the week, after the other one
+        let important = Task {
+            start_after: Some(monday() + Duration::days(2)),
+            ..task(0, 3, monday() + Duration::days(20))
+                .with_tag("meetings")
+                .with_importance(9)
+        };
+        let tasks = vec![
+            important,
+            task(1, 3, monday() + Duration::days(20))
+                .with_tag("meetings")
+                .with_importance(2),
+        ];
+        let schedule = schedule_within(
+            tasks,
+            SchedulingStrategy::Urgency,
+            &budgets(Some(5), None),
+            &HashMap::new(),
+        )
+        .unwrap();
+        let next_monday = Utc.ymd(2019, 3, 11).and_hms(0, 0, 0);
+        let slot = |id| {
+            schedule
+                .0
+                .iter()
+                .find(|scheduled_task| scheduled_task.task.id == id)
+                .unwrap()
+        };
+        assert!(slot(0).when < next_monday);
+        assert_eq!(slot(1).when, next_monday);
+        assert_eq!(
+            slot(1).held_back_by,
+            Some(Constraint::Budget("meetings".to_owned()))
+        );
+
+        // A less important task that can't wait makes a more important one wait instead
+        let tasks = vec![
+            task(0, 3, monday() + Duration::days(20))
+                .with_tag("meetings")
+                .with_importance(9),
+            task(1, 3, monday() + Duration::days(3))
+                .with_tag("meetings")
+                .with_importance(2),
+        ];
+        let schedule = schedule_within(
+            tasks,
+            SchedulingStrategy::Importance,
+            &budgets(Some(5), None),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(schedule.0.len(), 2);
+        assert_eq!(schedule.0[0].task.id, 1);
+        assert!(next_monday <= schedule.0[1].when);
+    }
+
+    #[test]
+    fn tasks_that_would_push_out_others_dont_wait() {
+        let next_monday = Utc.ymd(2019, 3, 11).and_hms(0, 0, 0);
+        let tasks = vec![
+            task(0, 2, monday() + Duration::days(20))
+                .with_tag("meetings")
+                .with_importance(2)
+                .with_daily_minimum(2),
+            task(1, 4, monday() + Duration::days(20))
+                .with_tag("meetings")
+                .with_importance(9)
+                .with_daily_minimum(1),
+            // Only the daily part of the least important task leaves too little time for this one
+            Task {
+                start_after: Some(next_monday + Duration::hours(20)),
+                ..task(2, 3, next_monday + Duration::days(1))
+            },
+        ];
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let schedule = schedule_within(
+                tasks.clone(),
+                *strategy,
+                &budgets(Some(5), None),
+                &HashMap::new(),
+            )
+            .unwrap();
+            for scheduled_task in &schedule.0 {
+                match scheduled_task.task.id {
+                    0 => assert!(scheduled_task.when < next_monday),
+                    1 => assert!(next_monday <= scheduled_task.when),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tasks_that_cant_wait_for_the_budget_fail() {
+        let tasks = vec![
+            task(0, 4, monday() + Duration::days(3)).with_tag("meetings"),
+            task(1, 4, monday() + Duration::days(3)).with_tag("meetings"),
+        ];
+        let error = schedule_within(
+            tasks,
+            SchedulingStrategy::Urgency,
+            &budgets(Some(5), None),
+            &HashMap::new(),
+        )
+        .unwrap_err();
+        match error {
+            Error::OverBudget { tag, .. } => assert_eq!(tag, "meetings"),
+            error => panic!("unexpected error: {}", error),
+        }
+        let too_long = vec![task(0, 6, monday() + Duration::days(20)).with_tag("meetings")];
+        assert!(schedule_within(
+            too_long,
+            SchedulingStrategy::Urgency,
+            &budgets(Some(5), None),
+            &HashMap::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn minimums_are_planned_this_week_when_possible() {
+        // Without the minimum, the more important task with a distant deadline would go first and
+        // the other one would be planned as late as possible
+        let tasks = vec![
+            task(0, 2, monday() + Duration::days(30))
+                .with_tag("meetings")
+                .with_importance(1),
+            task(1, 170, monday() + Duration::days(40))
+                .with_tag("other")
+                .with_importance(9),
+        ];
+        let schedule = schedule_within(
+            tasks.clone(),
+            SchedulingStrategy::Importance,
+            &budgets(None, Some(2)),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            hours_in_first_week(&schedule, "meetings"),
+            Duration::hours(2)
+        );
+        assert!(schedule
+            .0
+            .iter()
+            .any(|scheduled_task| scheduled_task.task == tasks[0]));
+
+        // A minimum that can't be met doesn't stand in the way of the deadlines
+        let tasks = vec![
+            task(0, 2, monday() + Duration::days(30))
+                .with_tag("meetings")
+                .with_importance(1),
+            task(1, 167, monday() + Duration::days(7))
+                .with_tag("other")
+                .with_importance(9),
+        ];
+        let schedule = schedule_within(
+            tasks,
+            SchedulingStrategy::Importance,
+            &budgets(None, Some(2)),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(hours_in_first_week(&schedule, "meetings"), Duration::zero());
+    }
+}
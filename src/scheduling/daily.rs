@@ -0,0 +1,425 @@
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::configuration::LocalTime;
+use crate::Task;
+
+use super::{Constraint, Error, Result, Schedule};
+
+/// The tasks with a daily minimum that were split into parts, one for every day, to be scheduled
+/// like separate tasks.
+pub(super) struct DailyParts {
+    originals: HashMap<u32, Task>,
+}
+
+impl DailyParts {
+    /// Splits every task with a daily minimum in `tasks` into parts, and returns them together
+    /// with the other tasks.
+    ///
+    /// From the first day the task can be worked on until it's done, every day gets a part of the
+    /// size of the daily minimum, which has to be scheduled in that day. Days that don't have that
+    /// much time in one stretch outside of the `unavailable` periods, which have to be merged, are
+    /// skipped. When the deadline comes before the task is done, the rest of it is one more part
+    /// that can be scheduled anywhere before the deadline, like the tasks without a daily minimum.
+    /// The days run from midnight to midnight in `local_time`, like the days off.
+    pub(super) fn split(
+        start: DateTime<Utc>,
+        tasks: Vec<Task>,
+        unavailable: &[Range<DateTime<Utc>>],
+        local_time: &LocalTime,
+    ) -> Result<(DailyParts, Vec<Task>)> {
+        let mut originals = HashMap::new();
+        let mut parts = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let minimum = match task.daily_minimum {
+                Some(minimum) => minimum,
+                None => {
+                    parts.push(task);
+                    continue;
+                }
+            };
+            if minimum <= Duration::zero() || Duration::days(1) <= minimum {
+                bail!(Error::InvalidDailyMinimum { task });
+            }
+            parts.extend(split_task(start, &task, minimum, unavailable, local_time));
+            originals.insert(task.id, task);
+        }
+        Ok((DailyParts { originals }, parts))
+    }
+
+    /// Puts the original tasks back in `schedule`, with the duration of the part they were
+    /// scheduled for. A part that waits for its day to start is held back by the daily minimum.
+    pub(super) fn join(&self, schedule: Schedule) -> Schedule {
+        Schedule(
+            schedule
+                .0
+                .into_iter()
+                .map(|mut scheduled_task| {
+                    if let Some(original) = self.original(&scheduled_task.task) {
+                        match scheduled_task.held_back_by {
+                            Some(Constraint::StartAfter(start_after))
+                                if original.start_after != Some(start_after) =>
+                            {
+                                scheduled_task.held_back_by = Some(Constraint::DailyMinimum);
+                            }
+                            _ => (),
+                        }
+                    }
+                    scheduled_task.restore(|task| self.original(task));
+                    scheduled_task
+                })
+                .collect(),
+        )
+    }
+
+    /// Puts the original tasks back in `error`, where it is about parts of them.
+    pub(super) fn join_error(&self, mut error: Error) -> Error {
+        let (task, conflicting) = error.tasks_mut();
+        for task in task.into_iter().chain(conflicting) {
+            if let Some(original) = self.original(task) {
+                *task = original.clone();
+            }
+        }
+        error
+    }
+
+    fn original(&self, part: &Task) -> Option<&Task> {
+        self.originals.get(&part.id)
+    }
+}
+
+/// Tells whether `task` is a part of a task with a daily minimum for a single day. Only those
+/// parts keep the daily minimum, the rest of the task doesn't.
+fn is_daily_part(task: &Task) -> bool {
+    task.daily_minimum.is_some()
+}
+
+/// Returns the parts of tasks with a daily minimum first, followed by the other tasks, so that the
+/// daily minimums get their time before the flexible work is distributed.
+pub(super) fn daily_parts_first(tasks: &[Arc<Task>]) -> impl Iterator<Item = &Arc<Task>> {
+    let parts = tasks.iter().filter(|task| is_daily_part(task));
+    let others = tasks.iter().filter(|task| !is_daily_part(task));
+    parts.chain(others)
+}
+
+fn split_task(
+    start: DateTime<Utc>,
+    task: &Task,
+    minimum: Duration,
+    unavailable: &[Range<DateTime<Utc>>],
+    local_time: &LocalTime,
+) -> Vec<Task> {
+    let mut parts = vec![];
+    let mut left = task.duration;
+    let mut day_start = super::earliest_start(start, task);
+    while left > Duration::zero() {
+        // Every day has its own midnight, which moves with daylight saving time
+        let next_day = match local_time.local(day_start).date().succ_opt() {
+            Some(next_day) => local_time.moment_at(next_day.and_hms(0, 0, 0)),
+            None => break,
+        };
+        // The last day until the deadline is left for the rest of the task
+        if task.deadline <= next_day {
+            break;
+        }
+        let part = min(minimum, left);
+        if part <= longest_free_stretch(day_start..next_day, unavailable) {
+            parts.push(Task {
+                duration: part,
+                start_after: Some(day_start),
+                deadline: next_day,
+                ..task.clone()
+            });
+            left = left - part;
+        }
+        day_start = next_day;
+    }
+    if left > Duration::zero() || parts.is_empty() {
+        parts.push(Task {
+            duration: left,
+            daily_minimum: None,
+            ..task.clone()
+        });
+    }
+    parts
+}
+
+/// Returns the longest stretch of time in `day` outside of the `unavailable` periods, which are
+/// chronological and don't overlap.
+fn longest_free_stretch(
+    day: Range<DateTime<Utc>>,
+    unavailable: &[Range<DateTime<Utc>>],
+) -> Duration {
+    let mut longest = Duration::zero();
+    let mut free_from = day.start;
+    for period in unavailable
+        .iter()
+        .filter(|period| day.start < period.end && period.start < day.end)
+    {
+        longest = max(longest, period.start.signed_duration_since(free_from));
+        free_from = max(free_from, period.end);
+    }
+    max(longest, day.end.signed_duration_since(free_from))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::configuration::SchedulingStrategy;
+    use crate::task::fixtures::{monday, task, SpringForward};
+
+    /// Returns how long task `id` is scheduled on every day, in chronological order.
+    fn hours_per_day(schedule: &Schedule, id: u32) -> Vec<(Date<Utc>, Duration)> {
+        let mut days: Vec<(Date<Utc>, Duration)> = vec![];
+        for scheduled_task in schedule
+            .0
+            .iter()
+            .filter(|scheduled_task| scheduled_task.task.id == id)
+        {
+            let day = scheduled_task.when.date();
+            match days.last_mut() {
+                Some(last) if last.0 == day => last.1 = last.1 + scheduled_task.task.duration,
+                _ => days.push((day, scheduled_task.task.duration)),
+            }
+        }
+        days
+    }
+
+    #[test]
+    fn daily_minimums_are_kept_every_day_until_done() {
+        let tasks = vec![
+            task(1, 10, monday() + Duration::days(14)).with_daily_minimum(2),
+            task(2, 20, monday() + Duration::days(3)),
+        ];
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let schedule = Schedule::schedule(monday(), tasks.clone(), *strategy).unwrap();
+            let days: Vec<Date<Utc>> = (0..5)
+                .map(|day| monday().date() + Duration::days(day))
+                .collect();
+            assert_eq!(
+                hours_per_day(&schedule, 1),
+                days.into_iter()
+                    .map(|day| (day, Duration::hours(2)))
+                    .collect::<Vec<_>>()
+            );
+            // The parts come out as the original task, with the duration of the part
+            for scheduled_task in schedule
+                .0
+                .iter()
+                .filter(|scheduled_task| scheduled_task.task.id == 1)
+            {
+                assert_eq!(
+                    scheduled_task.task,
+                    Task {
+                        duration: Duration::hours(2),
+                        ..tasks[0].clone()
+                    }
+                );
+            }
+            assert_eq!(
+                schedule
+                    .0
+                    .iter()
+                    .filter(|scheduled_task| scheduled_task.task == tasks[1])
+                    .count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn the_rest_is_scheduled_freely_when_the_deadline_comes_first() {
+        let tasks = vec![task(1, 5, monday() + Duration::days(2)).with_daily_minimum(1)];
+        let schedule = Schedule::schedule(monday(), tasks, SchedulingStrategy::Urgency).unwrap();
+        let total = schedule
+            .0
+            .iter()
+            .fold(Duration::zero(), |total, scheduled_task| {
+                total + scheduled_task.task.duration
+            });
+        assert_eq!(total, Duration::hours(5));
+        // A part on Monday and on Tuesday, and the rest before the deadline on Wednesday
+        assert_eq!(schedule.0.len(), 3);
+        assert!(schedule.0.iter().all(|scheduled_task| scheduled_task.when
+            + scheduled_task.task.duration
+            <= monday() + Duration::days(2)));
+    }
+
+    #[test]
+    fn unavailable_days_are_skipped() {
+        let tuesday = Utc.ymd(2019, 3, 5);
+        let unavailable = vec![monday()..tuesday.and_hms(0, 0, 0)];
+        let tasks = vec![task(1, 2, monday() + Duration::days(14)).with_daily_minimum(1)];
+        let schedule = Schedule::schedule_around(
+            monday(),
+            tasks,
+            SchedulingStrategy::Importance,
+            &unavailable,
+            &Default::default(),
+            &Utc,
+        )
+        .unwrap();
+        assert_eq!(
+            hours_per_day(&schedule, 1),
+            vec![
+                (tuesday, Duration::hours(1)),
+                (tuesday.succ(), Duration::hours(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn days_with_just_enough_time_are_used() {
+        // Only the two hours after the start of the schedule are free on Monday
+        let free_until = monday() + *super::super::SCHEDULE_DELAY + Duration::hours(2);
+        let tuesday = Utc.ymd(2019, 3, 5);
+        let unavailable = vec![free_until..tuesday.and_hms(0, 0, 0)];
+        let tasks = vec![task(1, 4, monday() + Duration::days(14)).with_daily_minimum(2)];
+        let schedule = Schedule::schedule_around(
+            monday(),
+            tasks,
+            SchedulingStrategy::Importance,
+            &unavailable,
+            &Default::default(),
+            &Utc,
+        )
+        .unwrap();
+        assert_eq!(
+            hours_per_day(&schedule, 1),
+            vec![
+                (monday().date(), Duration::hours(2)),
+                (tuesday, Duration::hours(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn days_are_local_days() {
+        // Monday 09:00 in UTC is 19:00 in local time, so the local Monday ends at 14:00 in UTC
+        let utc_offset = FixedOffset::east(10 * 3600);
+        let tasks = vec![task(1, 3, monday() + Duration::days(14)).with_daily_minimum(1)];
+        let schedule = Schedule::schedule_around(
+            monday(),
+            tasks,
+            SchedulingStrategy::Urgency,
+            &[],
+            &Default::default(),
+            &utc_offset,
+        )
+        .unwrap();
+        let local_days: Vec<NaiveDate> = schedule
+            .0
+            .iter()
+            .map(|scheduled_task| {
+                let start = scheduled_task.when.with_timezone(&utc_offset);
+                let end = start + scheduled_task.task.duration;
+                assert!(end.date() == start.date() || end.time() == NaiveTime::from_hms(0, 0, 0));
+                start.date().naive_local()
+            })
+            .collect();
+        let monday = NaiveDate::from_ymd(2019, 3, 4);
+        assert_eq!(local_days, vec![monday, monday.succ(), monday.succ().succ()]);
+        // The parts of the later days wait for the local midnight that starts them
+        assert_eq!(
+            schedule.0[1].when,
+            utc_offset
+                .from_local_datetime(&monday.succ().and_hms(0, 0, 0))
+                .unwrap()
+        );
+        let held_back_by: Vec<Option<Constraint>> = schedule
+            .0
+            .iter()
+            .map(|scheduled_task| scheduled_task.held_back_by.clone())
+            .collect();
+        assert_eq!(
+            held_back_by,
+            vec![
+                None,
+                Some(Constraint::DailyMinimum),
+                Some(Constraint::DailyMinimum)
+            ]
+        );
+    }
+
+    #[test]
+    fn days_end_at_their_own_local_midnight() {
+        // The clocks move forward in the night from Saturday 30 to Sunday 31 March 2019, so the
+        // local Sunday is an hour shorter
+        let saturday = Utc.ymd(2019, 3, 30).and_hms(12, 0, 0);
+        let tasks = vec![task(1, 3, saturday + Duration::days(7)).with_daily_minimum(1)];
+        let (_, parts) = DailyParts::split(saturday, tasks, &[], &SpringForward).unwrap();
+        let days: Vec<(DateTime<Utc>, DateTime<Utc>)> = parts
+            .iter()
+            .map(|part| (part.start_after.unwrap(), part.deadline))
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                (saturday, Utc.ymd(2019, 3, 30).and_hms(23, 0, 0)),
+                (
+                    Utc.ymd(2019, 3, 30).and_hms(23, 0, 0),
+                    Utc.ymd(2019, 3, 31).and_hms(22, 0, 0)
+                ),
+                (
+                    Utc.ymd(2019, 3, 31).and_hms(22, 0, 0),
+                    Utc.ymd(2019, 4, 1).and_hms(22, 0, 0)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_rest_is_scheduled_with_the_flexible_work() {
+        let tasks = vec![
+            task(2, 1, monday() + Duration::days(3)),
+            task(1, 5, monday() + Duration::days(2)).with_daily_minimum(1),
+        ];
+        let (_, parts) = DailyParts::split(monday(), tasks, &[], &Utc).unwrap();
+        let parts: Vec<Arc<Task>> = parts.into_iter().map(Arc::new).collect();
+        let order: Vec<(u32, Duration)> = daily_parts_first(&parts)
+            .map(|part| (part.id, part.duration))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (1, Duration::hours(1)),
+                (1, Duration::hours(1)),
+                (2, Duration::hours(1)),
+                (1, Duration::hours(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_are_about_the_whole_task() {
+        // Monday doesn't have enough time left for both daily minimums
+        let tasks = vec![
+            task(1, 16, monday() + Duration::days(14)).with_daily_minimum(8),
+            task(2, 16, monday() + Duration::days(14)).with_daily_minimum(8),
+        ];
+        let error = Schedule::schedule(monday(), tasks.clone(), SchedulingStrategy::Importance)
+            .unwrap_err();
+        match error {
+            Error::NotEnoughTime { task, .. } => assert!(tasks.contains(&task)),
+            error => panic!("unexpected error: {}", error),
+        }
+
+        for &minimum in &[0, 24] {
+            assert_matches!(
+                Schedule::schedule(
+                    monday(),
+                    vec![task(1, 2, monday() + Duration::days(14)).with_daily_minimum(minimum)],
+                    SchedulingStrategy::Urgency
+                ),
+                Err(Error::InvalidDailyMinimum { .. })
+            );
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::Range;
+
+use chrono::prelude::*;
+
+use crate::{Task, Window};
+
+use super::{Schedule, ScheduledTask};
+
+/// Why a slot of a task is where it is in a schedule.
+///
+/// The scheduling strategies end by moving every slot as close to the present as it goes, and
+/// record what stops it there: another slot, a period in which nothing can be scheduled, a moment
+/// before which the task can't start, the day of a daily minimum, a weekly budget or the preferred
+/// window of the task. The slots only ever get the tasks of the same assignee ahead of them, since
+/// every assignee has a timeline of their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub slot: ScheduledTask,
+    /// What keeps the slot from starting any sooner. When nothing does, it starts as soon as the
+    /// schedule could plan it when it was made, or the time before it was freed by a slot that
+    /// moved into the preferred window of its task.
+    pub held_back_by: Option<Constraint>,
+    /// Whether the slot ends right at the deadline of the task, so that it couldn't be any later
+    /// either.
+    pub at_deadline: bool,
+}
+
+/// Something that keeps a slot from starting sooner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    /// The task can't be started before this moment.
+    StartAfter(DateTime<Utc>),
+    /// This task comes first and has the time right before the slot, including its buffer.
+    TaskAhead(Task),
+    /// Nothing can be scheduled in this period, which ends right when the slot starts.
+    Unavailable(Range<DateTime<Utc>>),
+    /// The slot is the part of the daily minimum of the task for the local day it starts on, which
+    /// can't start before that day does.
+    DailyMinimum,
+    /// The weekly maximum of this tag was reached, so the slot waits until the next week.
+    Budget(String),
+    /// The slot waits for the preferred window of its task to open.
+    Window(Window),
+}
+
+impl Schedule {
+    /// Explains every slot of the task with id `task_id` in this schedule, in chronological order,
+    /// from what the strategy that placed the slot recorded (see `ScheduledTask::held_back_by`).
+    pub fn explain(&self, task_id: u32) -> Vec<Explanation> {
+        self.0
+            .iter()
+            .filter(|slot| slot.task.id == task_id)
+            .map(|slot| Explanation {
+                slot: slot.clone(),
+                held_back_by: slot.held_back_by.clone(),
+                at_deadline: slot.when + slot.task.duration == slot.task.deadline,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::configuration::SchedulingStrategy;
+    use crate::task::fixtures::{monday, task};
+
+    #[test]
+    fn strategies_record_what_holds_every_slot_back() {
+        let deadline = monday() + Duration::days(7);
+        let evening = monday().date().and_hms(18, 0, 0);
+        // The hour before it is too short for the first task
+        let unavailable = vec![monday() + Duration::hours(1)..evening];
+        let start_after = evening + Duration::hours(5);
+        let tasks = vec![
+            task(1, 2, deadline).with_importance(9),
+            task(2, 2, deadline).with_importance(1),
+            Task {
+                start_after: Some(start_after),
+                ..task(3, 1, deadline)
+            },
+        ];
+        for &strategy in &[
+            SchedulingStrategy::Importance,
+            SchedulingStrategy::Urgency,
+            SchedulingStrategy::Optimal,
+        ] {
+            let schedule = Schedule::schedule_around(
+                monday(),
+                tasks.clone(),
+                strategy,
+                &unavailable,
+                &Default::default(),
+                &Utc,
+            )
+            .unwrap();
+            let explain = |id| {
+                let explanations = schedule.explain(id);
+                assert_eq!(explanations.len(), 1);
+                explanations[0].held_back_by.clone()
+            };
+            assert_eq!(
+                explain(1),
+                Some(Constraint::Unavailable(unavailable[0].clone()))
+            );
+            assert_eq!(explain(2), Some(Constraint::TaskAhead(tasks[0].clone())));
+            assert_eq!(explain(3), Some(Constraint::StartAfter(start_after)));
+            assert!(schedule.explain(4).is_empty());
+        }
+    }
+
+    #[test]
+    fn slots_that_end_at_the_deadline_are_pinned_by_it() {
+        let deadline = monday() + Duration::days(7);
+        let ahead = task(2, 2, deadline).with_importance(9);
+        let schedule = Schedule(vec![
+            ScheduledTask::new(ahead.clone(), deadline - Duration::hours(4)),
+            ScheduledTask {
+                held_back_by: Some(Constraint::TaskAhead(ahead.clone())),
+                ..ScheduledTask::new(
+                    task(1, 2, deadline).with_importance(1),
+                    deadline - Duration::hours(2),
+                )
+            },
+        ]);
+        let explanations = schedule.explain(1);
+        assert!(explanations[0].at_deadline);
+        assert_eq!(
+            explanations[0].held_back_by,
+            Some(Constraint::TaskAhead(ahead))
+        );
+        let explanations = schedule.explain(2);
+        assert!(!explanations[0].at_deadline);
+        assert_eq!(explanations[0].held_back_by, None);
+    }
+}
@@ -0,0 +1,306 @@
+use std::cmp::{max, min};
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::configuration::FocusSessions;
+use crate::Task;
+
+use super::{Schedule, ScheduledTask};
+
+/// A stretch of focused work in a schedule, like a pomodoro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusSession {
+    pub start: DateTime<Utc>,
+    /// When the work in the session ends.
+    pub end: DateTime<Utc>,
+    /// The slots in the session, which have the part of their task that is done in it as its
+    /// duration.
+    pub slots: Vec<ScheduledTask>,
+    /// When the break after the session ends, if the session has one. Where the next session
+    /// starts later anyway, the time in between is the break.
+    pub pause_until: Option<DateTime<Utc>>,
+}
+
+impl Schedule {
+    /// Slices the schedule into focus sessions with breaks after them. The slots are split where
+    /// a session ends, and what comes after a break is moved later to make room for it.
+    ///
+    /// A break is only taken when moving the rest of the schedule later doesn't push a task past
+    /// its deadline or into one of the periods in which its assignee is unavailable, which
+    /// `unavailable_to` returns (see `Configuration::unavailable_to`). Otherwise the next session
+    /// starts right away. A gap between two slots that is at least as long as a break is one. The
+    /// timeline of every assignee (see `assigned_to`) is sliced on its own. Sessions that don't
+    /// last at all can't hold any work, so then there are none.
+    pub fn focus_sessions<F>(&self, settings: FocusSessions, unavailable_to: F) -> Vec<FocusSession>
+    where
+        F: Fn(Option<&str>) -> Vec<Range<DateTime<Utc>>>,
+    {
+        if settings.focus <= Duration::zero() {
+            return vec![];
+        }
+        let mut assignees: Vec<Option<&str>> = self
+            .0
+            .iter()
+            .map(|slot| slot.task.assignee.as_ref().map(String::as_str))
+            .collect();
+        assignees.sort();
+        assignees.dedup();
+        if assignees.len() > 1 {
+            let mut sessions: Vec<FocusSession> = assignees
+                .into_iter()
+                .flat_map(|assignee| {
+                    self.assigned_to(assignee)
+                        .sessions_of_timeline(settings, &unavailable_to(assignee))
+                })
+                .collect();
+            sessions.sort_by_key(|session| session.start);
+            return sessions;
+        }
+        let assignee = assignees.into_iter().next().unwrap_or(None);
+        self.sessions_of_timeline(settings, &unavailable_to(assignee))
+    }
+
+    /// Slices the schedule into focus sessions like `focus_sessions`, when all of it is the
+    /// timeline of a single assignee, who is unavailable in the `unavailable` periods.
+    fn sessions_of_timeline(
+        &self,
+        settings: FocusSessions,
+        unavailable: &[Range<DateTime<Utc>>],
+    ) -> Vec<FocusSession> {
+        let mut sessions: Vec<FocusSession> = vec![];
+        // Whether the last session can still be added to
+        let mut open = false;
+        // Nothing can start before the work, buffers and breaks before it are over
+        let mut cursor = None;
+        for (index, slot) in self.0.iter().enumerate() {
+            let mut when = cursor.map_or(slot.when, |cursor| max(slot.when, cursor));
+            let mut remaining = slot.task.duration;
+            while remaining > Duration::zero() {
+                if let (true, Some(session)) = (open, sessions.last_mut()) {
+                    if when - session.end >= settings.pause {
+                        open = false;
+                    } else if when >= session.start + settings.focus {
+                        open = false;
+                        let pause_until = session.end + settings.pause;
+                        if self.can_start_later(index, remaining, pause_until, unavailable) {
+                            session.pause_until = Some(pause_until);
+                            when = pause_until;
+                        }
+                    }
+                }
+                if !open {
+                    sessions.push(FocusSession {
+                        start: when,
+                        end: when,
+                        slots: vec![],
+                        pause_until: None,
+                    });
+                    open = true;
+                }
+                let session = sessions.last_mut().unwrap();
+                let part = min(remaining, session.start + settings.focus - when);
+                session.slots.push(ScheduledTask {
+                    task: Task {
+                        duration: part,
+                        ..slot.task.clone()
+                    },
+                    when,
+                    note: slot.note.clone(),
+                    held_back_by: slot.held_back_by.clone(),
+                });
+                session.end = when + part;
+                when = when + part;
+                remaining = remaining - part;
+            }
+            cursor = Some(when + (slot.task.occupied() - slot.task.duration));
+        }
+        sessions
+    }
+
+    /// Tells whether the `remaining` work of the slot at `index` can start at `later`, with the
+    /// slots after it moved as much as that makes them, without any of them ending after its
+    /// deadline or overlapping with an `unavailable` period.
+    fn can_start_later(
+        &self,
+        index: usize,
+        remaining: Duration,
+        later: DateTime<Utc>,
+        unavailable: &[Range<DateTime<Utc>>],
+    ) -> bool {
+        let fits = |task: &Task, start: DateTime<Utc>, duration: Duration| {
+            let end = start + duration;
+            end <= task.deadline
+                && unavailable
+                    .iter()
+                    .all(|period| end <= period.start || period.end <= start)
+        };
+        let task = &self.0[index].task;
+        if !fits(task, later, remaining) {
+            return false;
+        }
+        let mut cursor = later + remaining + (task.occupied() - task.duration);
+        for slot in &self.0[index + 1..] {
+            if cursor <= slot.when {
+                // The rest of the schedule stays where it is
+                return true;
+            }
+            if !fits(&slot.task, cursor, slot.task.duration) {
+                return false;
+            }
+            cursor = cursor + slot.task.occupied();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn morning() -> DateTime<Utc> {
+        Utc.ymd(2019, 3, 4).and_hms(9, 0, 0)
+    }
+
+    fn slot(
+        id: u32,
+        minutes_after_morning: i64,
+        minutes: i64,
+        deadline: DateTime<Utc>,
+    ) -> ScheduledTask {
+        ScheduledTask::new(
+            Task {
+                id,
+                content: format!("task {}", id),
+                deadline,
+                duration: Duration::minutes(minutes),
+                importance: 5,
+                ..Default::default()
+            },
+            morning() + Duration::minutes(minutes_after_morning),
+        )
+    }
+
+    /// Returns the id, the start and the duration in minutes of the slots of every session.
+    fn parts(sessions: &[FocusSession]) -> Vec<Vec<(u32, i64, i64)>> {
+        sessions
+            .iter()
+            .map(|session| {
+                session
+                    .slots
+                    .iter()
+                    .map(|slot| {
+                        let start = (slot.when - morning()).num_minutes();
+                        (slot.task.id, start, slot.task.duration.num_minutes())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slots_are_split_at_sessions_and_moved_after_breaks() {
+        let deadline = morning() + Duration::days(1);
+        let schedule = Schedule(vec![
+            slot(1, 0, 40, deadline),
+            slot(2, 40, 20, deadline),
+            // After a gap that is a break of its own, a new session starts
+            slot(3, 120, 30, deadline),
+        ]);
+        let sessions = schedule.focus_sessions(FocusSessions::default(), |_| vec![]);
+        assert_eq!(
+            parts(&sessions),
+            vec![
+                vec![(1, 0, 25)],
+                vec![(1, 30, 15), (2, 45, 10)],
+                vec![(2, 60, 10)],
+                vec![(3, 120, 25)],
+                vec![(3, 150, 5)],
+            ]
+        );
+        assert_eq!(
+            sessions[0].pause_until,
+            Some(morning() + Duration::minutes(30))
+        );
+        assert_eq!(sessions[2].end, morning() + Duration::minutes(70));
+        assert_eq!(sessions[2].pause_until, None);
+    }
+
+    #[test]
+    fn every_assignee_takes_their_own_breaks() {
+        let deadline = morning() + Duration::days(1);
+        let assigned = |mut slot: ScheduledTask, assignee: &str| {
+            slot.task.assignee = Some(assignee.to_owned());
+            slot
+        };
+        let schedule = Schedule(vec![
+            assigned(slot(1, 0, 30, deadline), "alice"),
+            assigned(slot(2, 10, 30, deadline), "bob"),
+        ]);
+        let sessions = schedule.focus_sessions(FocusSessions::default(), |_| vec![]);
+        assert_eq!(
+            parts(&sessions),
+            vec![
+                vec![(1, 0, 25)],
+                vec![(2, 10, 25)],
+                vec![(1, 30, 5)],
+                vec![(2, 40, 5)],
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_keep_out_of_the_time_off_of_the_assignee() {
+        let deadline = morning() + Duration::days(1);
+        let assigned = |mut slot: ScheduledTask, assignee: &str| {
+            slot.task.assignee = Some(assignee.to_owned());
+            slot
+        };
+        let schedule = Schedule(vec![
+            assigned(slot(1, 0, 30, deadline), "alice"),
+            assigned(slot(2, 0, 30, deadline), "bob"),
+        ]);
+        let unavailable_to = |assignee: Option<&str>| match assignee {
+            Some("bob") => vec![morning() + Duration::minutes(30)..morning() + Duration::hours(2)],
+            _ => vec![],
+        };
+        let sessions = schedule.focus_sessions(FocusSessions::default(), unavailable_to);
+        // Only Bob has to skip the break
+        assert_eq!(
+            parts(&sessions),
+            vec![
+                vec![(1, 0, 25)],
+                vec![(2, 0, 25)],
+                vec![(2, 25, 5)],
+                vec![(1, 30, 5)],
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_are_skipped_rather_than_missing_deadlines() {
+        let schedule = Schedule(vec![
+            slot(1, 0, 25, morning() + Duration::days(1)),
+            slot(2, 25, 25, morning() + Duration::minutes(52)),
+            slot(3, 50, 10, morning() + Duration::days(1)),
+        ]);
+        let unavailable = vec![morning() + Duration::minutes(60)..morning() + Duration::hours(2)];
+        let sessions = schedule.focus_sessions(FocusSessions::default(), |_| unavailable.clone());
+        assert_eq!(
+            parts(&sessions),
+            vec![vec![(1, 0, 25)], vec![(2, 25, 25)], vec![(3, 50, 10)]]
+        );
+        assert!(sessions.iter().all(|session| session.pause_until.is_none()));
+    }
+
+    #[test]
+    fn sessions_without_focus_are_empty() {
+        let schedule = Schedule(vec![slot(1, 0, 30, morning() + Duration::days(1))]);
+        let settings = FocusSessions {
+            focus: Duration::zero(),
+            pause: Duration::minutes(5),
+        };
+        assert!(schedule.focus_sessions(settings, |_| vec![]).is_empty());
+    }
+}
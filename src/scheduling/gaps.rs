@@ -0,0 +1,297 @@
+use std::cmp::max;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::{Duration, MIN_DATE};
+
+use super::treap::Treap;
+
+/// An index of the occupied intervals of a schedule and the free gaps in between them.
+///
+/// Keeping the gaps sorted by their start means that (un)scheduling something only touches the
+/// gaps right next to it. The gaps are also kept in a treap that knows the longest gap in every
+/// subtree, so finding the earliest gap where something fits takes logarithmic time as well.
+///
+/// Entries are identified by a key, which allows several entries to share the same start (as can
+/// happen with tasks that take no time).
+#[derive(Debug)]
+pub struct Gaps {
+    /// Nothing can be scheduled before this moment.
+    lower_bound: DateTime<Utc>,
+    /// The occupied intervals as `(start, end, key)`, in chronological order.
+    entries: BTreeSet<(DateTime<Utc>, DateTime<Utc>, usize)>,
+    /// The non-empty gaps between `lower_bound` and the end of the last entry, from their start to
+    /// their end.
+    gaps: BTreeMap<DateTime<Utc>, DateTime<Utc>>,
+    /// The same gaps, from their start to their length.
+    lengths: Treap<DateTime<Utc>, Duration>,
+}
+
+impl Gaps {
+    /// Returns an index without any entries, where nothing can be scheduled before `lower_bound`.
+    pub fn new(lower_bound: DateTime<Utc>) -> Self {
+        Gaps {
+            lower_bound,
+            entries: BTreeSet::new(),
+            gaps: BTreeMap::new(),
+            lengths: Treap::new(),
+        }
+    }
+
+    /// Marks `start..end` as occupied by `key`. It should not overlap with other entries.
+    pub fn insert(&mut self, start: DateTime<Utc>, end: DateTime<Utc>, key: usize) {
+        let (previous_end, next_start) = self.neighbours(start, end, key);
+        if let Some(next_start) = next_start {
+            if previous_end < next_start {
+                self.remove_gap(previous_end);
+            }
+            if end < next_start {
+                self.insert_gap(end, next_start);
+            }
+        }
+        if previous_end < start {
+            self.insert_gap(previous_end, start);
+        }
+        self.entries.insert((start, end, key));
+    }
+
+    /// Frees `start..end` which was occupied by `key`.
+    pub fn remove(&mut self, start: DateTime<Utc>, end: DateTime<Utc>, key: usize) {
+        self.entries.remove(&(start, end, key));
+        let (previous_end, next_start) = self.neighbours(start, end, key);
+        if previous_end < start {
+            self.remove_gap(previous_end);
+        }
+        if let Some(next_start) = next_start {
+            if end < next_start {
+                self.remove_gap(end);
+            }
+            if previous_end < next_start {
+                self.insert_gap(previous_end, next_start);
+            }
+        }
+    }
+
+    /// Returns the earliest start from `min_start` onwards from where something with the given
+    /// `duration` fits, if it can end before `max_end`.
+    pub fn earliest_fit(
+        &self,
+        duration: Duration,
+        min_start: DateTime<Utc>,
+        max_end: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let min_start = max(min_start, self.lower_bound);
+        let fits_in_gap_of_min_start = self
+            .gaps
+            .range(..=min_start)
+            .next_back()
+            .map_or(false, |(_, &gap_end)| min_start + duration <= gap_end);
+        let earliest_start = if fits_in_gap_of_min_start {
+            min_start
+        } else if duration == Duration::zero() {
+            // Something that takes no time fits anywhere, except strictly inside an entry
+            let smallest_end = MIN_DATE.and_hms(0, 0, 0);
+            self.entries
+                .range((Unbounded, Excluded((min_start, smallest_end, 0))))
+                .next_back()
+                .map_or(min_start, |&(_, end, _)| max(end, min_start))
+        } else {
+            match self.lengths.first_at_least_from(min_start, duration) {
+                Some((gap_start, _)) => gap_start,
+                // As last resort, try after the last entry
+                None => self
+                    .entries
+                    .iter()
+                    .next_back()
+                    .map_or(min_start, |&(_, end, _)| max(end, min_start)),
+            }
+        };
+        if earliest_start + duration <= max_end {
+            Some(earliest_start)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the gap that ends at or contains the moment right before `moment`.
+    pub fn gap_before(&self, moment: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.gaps
+            .range((Unbounded, Excluded(moment)))
+            .next_back()
+            .filter(|&(_, &gap_end)| moment <= gap_end)
+            .map(|(&gap_start, &gap_end)| (gap_start, gap_end))
+    }
+
+    /// Returns the entry that ends right at `moment` and starts before it, if any, with its key.
+    pub fn ending_at(&self, moment: DateTime<Utc>) -> Option<(Range<DateTime<Utc>>, usize)> {
+        let smallest_end = MIN_DATE.and_hms(0, 0, 0);
+        self.entries
+            .range((Unbounded, Excluded((moment, smallest_end, 0))))
+            .next_back()
+            .filter(|&&(_, end, _)| end == moment)
+            .map(|&(start, end, key)| (start..end, key))
+    }
+
+    /// Returns the entries that start at or after `moment`, in chronological order.
+    pub fn entries_from<'a>(
+        &'a self,
+        moment: DateTime<Utc>,
+    ) -> impl Iterator<Item = &'a (DateTime<Utc>, DateTime<Utc>, usize)> + 'a {
+        let smallest_end = MIN_DATE.and_hms(0, 0, 0);
+        self.entries
+            .range((Included((moment, smallest_end, 0)), Unbounded))
+    }
+
+    /// Returns all entries in chronological order.
+    pub fn into_entries(self) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>, usize)> {
+        self.entries.into_iter()
+    }
+
+    fn insert_gap(&mut self, start: DateTime<Utc>, end: DateTime<Utc>) {
+        self.gaps.insert(start, end);
+        self.lengths.insert(start, end.signed_duration_since(start));
+    }
+
+    fn remove_gap(&mut self, start: DateTime<Utc>) {
+        self.gaps.remove(&start);
+        self.lengths.remove(start);
+    }
+
+    /// Returns the end of the entry right before `start..end` (or the lower bound if there is
+    /// none) and the start of the entry right after it, if any. `key` breaks ties between entries
+    /// with the same start and end.
+    fn neighbours(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        key: usize,
+    ) -> (DateTime<Utc>, Option<DateTime<Utc>>) {
+        let previous_end = self
+            .entries
+            .range((Unbounded, Excluded((start, end, key))))
+            .next_back()
+            .map_or(self.lower_bound, |&(_, previous_end, _)| previous_end);
+        let next_start = self
+            .entries
+            .range((Excluded((start, end, key)), Unbounded))
+            .next()
+            .map(|&(next_start, _, _)| next_start);
+        (previous_end, next_start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut gaps = Gaps::new(at(0));
+        gaps.insert(at(5), at(8), 1);
+        assert_eq!(gap_list(&gaps), vec![(0, 5)]);
+        gaps.insert(at(12), at(15), 2);
+        assert_eq!(gap_list(&gaps), vec![(0, 5), (8, 12)]);
+        gaps.insert(at(0), at(2), 3);
+        assert_eq!(gap_list(&gaps), vec![(2, 5), (8, 12)]);
+        gaps.insert(at(8), at(10), 4);
+        assert_eq!(gap_list(&gaps), vec![(2, 5), (10, 12)]);
+        gaps.remove(at(5), at(8), 1);
+        assert_eq!(gap_list(&gaps), vec![(2, 8), (10, 12)]);
+        gaps.remove(at(12), at(15), 2);
+        assert_eq!(gap_list(&gaps), vec![(2, 8)]);
+        gaps.remove(at(0), at(2), 3);
+        assert_eq!(gap_list(&gaps), vec![(0, 8)]);
+        gaps.remove(at(8), at(10), 4);
+        assert!(gap_list(&gaps).is_empty());
+        assert_eq!(gaps.into_entries().count(), 0);
+    }
+
+    #[test]
+    fn test_entries_without_duration() {
+        let mut gaps = Gaps::new(at(0));
+        gaps.insert(at(0), at(5), 1);
+        gaps.insert(at(5), at(5), 2);
+        gaps.insert(at(8), at(10), 3);
+        gaps.insert(at(5), at(5), 4);
+        assert_eq!(gap_list(&gaps), vec![(5, 8)]);
+        gaps.remove(at(0), at(5), 1);
+        assert_eq!(gap_list(&gaps), vec![(0, 5), (5, 8)]);
+        gaps.remove(at(5), at(5), 2);
+        assert_eq!(gap_list(&gaps), vec![(0, 5), (5, 8)]);
+        gaps.remove(at(5), at(5), 4);
+        assert_eq!(gap_list(&gaps), vec![(0, 8)]);
+    }
+
+    #[test]
+    fn test_earliest_fit() {
+        let mut gaps = Gaps::new(at(0));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(3), at(0), at(10)), Some(at(0)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(3), at(0), at(2)), None);
+
+        // free: 0..2, 4..7, 9..
+        gaps.insert(at(2), at(4), 1);
+        gaps.insert(at(7), at(9), 2);
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(0), at(20)), Some(at(0)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(3), at(0), at(20)), Some(at(4)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(3), at(0), at(6)), None);
+        assert_eq!(gaps.earliest_fit(Duration::seconds(4), at(0), at(20)), Some(at(9)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(4), at(0), at(12)), None);
+    }
+
+    #[test]
+    fn test_earliest_fit_with_min_start() {
+        let mut gaps = Gaps::new(at(0));
+        // free: 0..2, 4..7, 9..
+        gaps.insert(at(2), at(4), 1);
+        gaps.insert(at(7), at(9), 2);
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(1), at(20)), Some(at(4)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(3), at(20)), Some(at(4)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(5), at(20)), Some(at(5)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(6), at(20)), Some(at(9)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(12), at(20)), Some(at(12)));
+        assert_eq!(gaps.earliest_fit(Duration::seconds(2), at(12), at(13)), None);
+        assert_eq!(gaps.earliest_fit(Duration::zero(), at(3), at(20)), Some(at(4)));
+        assert_eq!(gaps.earliest_fit(Duration::zero(), at(2), at(20)), Some(at(2)));
+        assert_eq!(gaps.earliest_fit(Duration::zero(), at(4), at(20)), Some(at(4)));
+        assert_eq!(gaps.earliest_fit(Duration::zero(), at(5), at(20)), Some(at(5)));
+        assert_eq!(gaps.earliest_fit(Duration::zero(), at(8), at(8)), None);
+    }
+
+    #[test]
+    fn test_gap_before_and_entries_from() {
+        let mut gaps = Gaps::new(at(0));
+        gaps.insert(at(2), at(4), 1);
+        gaps.insert(at(7), at(9), 2);
+        assert_eq!(gaps.gap_before(at(7)), Some((at(4), at(7))));
+        assert_eq!(gaps.gap_before(at(5)), Some((at(4), at(7))));
+        assert_eq!(gaps.gap_before(at(4)), None);
+        assert_eq!(gaps.gap_before(at(9)), None);
+        let later_keys: Vec<usize> = gaps.entries_from(at(3)).map(|&(_, _, key)| key).collect();
+        assert_eq!(later_keys, vec![2]);
+    }
+
+    #[test]
+    fn test_ending_at() {
+        let mut gaps = Gaps::new(at(0));
+        gaps.insert(at(2), at(4), 1);
+        gaps.insert(at(4), at(4), 2);
+        gaps.insert(at(4), at(6), 3);
+        assert_eq!(gaps.ending_at(at(4)), Some((at(2)..at(4), 1)));
+        assert_eq!(gaps.ending_at(at(6)), Some((at(4)..at(6), 3)));
+        assert_eq!(gaps.ending_at(at(5)), None);
+        assert_eq!(gaps.ending_at(at(2)), None);
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp(seconds, 0)
+    }
+
+    fn gap_list(gaps: &Gaps) -> Vec<(i64, i64)> {
+        gaps.gaps
+            .iter()
+            .map(|(start, end)| (start.timestamp(), end.timestamp()))
+            .collect()
+    }
+}
@@ -1,42 +1,210 @@
-use std::fmt;
-use std::rc::Rc;
+use std::cmp::{max, min, Ordering, Reverse};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
 
 use chrono::prelude::*;
 use chrono::Duration;
 use derive_new::new;
 use lazy_static::lazy_static;
 
-use crate::configuration::SchedulingStrategy;
-use crate::Task;
+use crate::configuration::{LocalTime, SchedulingStrategy, UrgencyCoefficients};
+use crate::{Task, Trigger};
 
-use self::schedule_tree::{Entry, ScheduleTree};
+use self::daily::DailyParts;
+use self::gaps::Gaps;
+use self::schedule_tree::ScheduleTree;
+use self::treap::Treap;
 
 pub use self::errors::*;
+pub use self::explanation::{Constraint, Explanation};
+pub use self::focus::FocusSession;
+pub use self::pace::Pace;
+pub use self::scarcity::{FairSchedule, Shortfall};
 
+mod budgets;
+mod daily;
+mod explanation;
+mod focus;
+mod gaps;
+mod optimal;
+mod pace;
+#[cfg(test)]
+mod properties;
+mod scarcity;
 mod schedule_tree;
+mod treap;
+mod windows;
 
 mod errors {
+    use std::ops::Range;
+
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use thiserror::Error;
+
     use crate::Task;
 
-    error_chain! {
-        errors {
-            DeadlineMissed(task: Task, already_missed: bool) {
-                description("deadline missed")
-                display("I could not schedule {} because you {} the deadline.\nYou might want to \
-                        postpone this task or remove it if it's not longer relevant",
-                        task,
-                        if *already_missed { "missed" } else { "will miss" })
+    /// A `Result` of scheduling.
+    pub type Result<T> = std::result::Result<T, Error>;
+
+    /// Why tasks couldn't be scheduled. Next to the human-readable message of its `Display`, every
+    /// error keeps the task it is about and, where it applies, how much time was lacking and the
+    /// tasks that took up that time, so frontends can present the conflict in their own way.
+    #[derive(Debug, Clone, PartialEq, Eq, Error)]
+    #[non_exhaustive]
+    pub enum Error {
+        /// The task can't be done before its deadline, or its deadline has passed already.
+        #[error(
+            "I could not schedule {task} because you {} the deadline.\nYou might want to postpone \
+             this task or remove it if it's not longer relevant",
+            if *.already_missed { "missed" } else { "will miss" }
+        )]
+        DeadlineMissed {
+            task: Task,
+            already_missed: bool,
+            /// How long after its deadline the task would be done, when it's started right away.
+            shortfall: Duration,
+        },
+        /// The task can't be started early enough to be done before its deadline.
+        #[error(
+            "I could not schedule {task} because it can't be started early enough to be finished \
+             before its deadline.\nYou might want to postpone this task or let it start sooner"
+        )]
+        StartsTooLate {
+            task: Task,
+            /// How long after its deadline the task would be done, when it's started as soon as
+            /// it can be.
+            shortfall: Duration,
+        },
+        /// The other tasks leave too little time to do the task before its deadline.
+        #[error(
+            "I could not schedule {task} because you don't have enough time to do everything.\nYou \
+             might want to decide not to do some things or relax their deadlines"
+        )]
+        NotEnoughTime {
+            task: Task,
+            /// How much more time the task and its buffer need than the longest free stretch
+            /// before its deadline.
+            shortfall: Duration,
+            /// The tasks that were scheduled between the start of the task and its deadline.
+            conflicting: Vec<Task>,
+        },
+        /// Like `NotEnoughTime`, but there would be enough time without the days off and
+        /// holidays.
+        #[error(
+            "I could not schedule {task} because you don't have enough time for it outside of \
+             your days off and holidays.\nYou might want to relax its deadline or take less time \
+             off"
+        )]
+        TimeOff {
+            task: Task,
+            shortfall: Duration,
+            conflicting: Vec<Task>,
+        },
+        #[error(
+            "I could not schedule {task} because its deadline or duration is too far out there \
+             for me to calculate with.\nYou might want to check whether you entered them correctly"
+        )]
+        Overflow { task: Task },
+        #[error(
+            "I could not schedule {task} because the weekly budget of {tag} doesn't leave enough \
+             time for it before its deadline.\nYou might want to raise that budget or relax the \
+             deadline"
+        )]
+        OverBudget { task: Task, tag: String },
+        #[error("I could not schedule {task} because its duration is negative")]
+        InvalidDuration { task: Task },
+        #[error(
+            "I could not schedule {task} because its daily minimum isn't more than nothing and \
+             less than a day"
+        )]
+        InvalidDailyMinimum { task: Task },
+        /// The task waits for other tasks to be done (see `Trigger::Done`), and done one after
+        /// the other, they and the task don't fit before its deadline.
+        #[error(
+            "I could not schedule {task} because it waits for {} other task(s) to be done, and \
+             there isn't enough time to do all of them before its deadline.\nYou might want to \
+             relax its deadline or stop waiting for some of them",
+            .chain.len()
+        )]
+        ChainTooLong {
+            task: Task,
+            /// The tasks the task waits for, in the order they have to be done.
+            chain: Vec<Task>,
+            /// How long the tasks in the chain and the task take together.
+            duration: Duration,
+            /// From when the first task can be started until the deadline of the task.
+            window: Range<DateTime<Utc>>,
+            /// How long after its deadline the task would be done, when the chain is started
+            /// right away.
+            shortfall: Duration,
+        },
+        /// The task waits for other tasks to be done, and these end up waiting for the task in
+        /// turn, so none of them can ever be done.
+        #[error(
+            "I could not schedule {task} because the tasks it waits for end up waiting for it in \
+             turn, so none of them can be done.\nYou might want to stop waiting for one of them"
+        )]
+        CircularWait {
+            task: Task,
+            /// The tasks the task waits for, in the order they wait for each other. The last
+            /// one waits for the task.
+            cycle: Vec<Task>,
+        },
+        #[error("An internal error occurred (This shouldn't happen.): {0}")]
+        Internal(String),
+    }
+
+    impl Error {
+        /// Returns the task that couldn't be scheduled, unless the error is an internal one.
+        pub fn task(&self) -> Option<&Task> {
+            match self {
+                Error::DeadlineMissed { task, .. }
+                | Error::StartsTooLate { task, .. }
+                | Error::NotEnoughTime { task, .. }
+                | Error::TimeOff { task, .. }
+                | Error::Overflow { task }
+                | Error::OverBudget { task, .. }
+                | Error::InvalidDuration { task }
+                | Error::InvalidDailyMinimum { task }
+                | Error::ChainTooLong { task, .. }
+                | Error::CircularWait { task, .. } => Some(task),
+                Error::Internal(_more_info) => None,
             }
-            NotEnoughTime(task: Task) {
-                description("not enough time")
-                display("I could not schedule {} because you don't have enough time to do \
-                        everything.\nYou might want to decide not to do some things or relax \
-                        their deadlines",
-                        task)
+        }
+
+        /// Returns the tasks that took up the time that was lacking, if any.
+        pub fn conflicting(&self) -> &[Task] {
+            match self {
+                Error::NotEnoughTime { conflicting, .. } | Error::TimeOff { conflicting, .. } => {
+                    conflicting
+                }
+                Error::ChainTooLong { chain, .. } => chain,
+                Error::CircularWait { cycle, .. } => cycle,
+                _ => &[],
             }
-            Internal(more_info: String) {
-                description("internal error")
-                display("An internal error occurred (This shouldn't happen.): {}", more_info)
+        }
+
+        /// Returns the task that couldn't be scheduled and the tasks it conflicts with, to change
+        /// them in place.
+        pub(super) fn tasks_mut(&mut self) -> (Option<&mut Task>, &mut [Task]) {
+            match self {
+                Error::NotEnoughTime {
+                    task, conflicting, ..
+                }
+                | Error::TimeOff {
+                    task, conflicting, ..
+                } => (Some(task), conflicting),
+                Error::ChainTooLong { task, chain, .. } => (Some(task), chain),
+                Error::CircularWait { task, cycle } => (Some(task), cycle),
+                Error::DeadlineMissed { task, .. }
+                | Error::StartsTooLate { task, .. }
+                | Error::Overflow { task }
+                | Error::OverBudget { task, .. }
+                | Error::InvalidDuration { task }
+                | Error::InvalidDailyMinimum { task } => (Some(task), &mut []),
+                Error::Internal(_more_info) => (None, &mut []),
             }
         }
     }
@@ -46,15 +214,77 @@ lazy_static! {
     static ref SCHEDULE_DELAY: Duration = Duration::minutes(1);
 }
 
-#[derive(Debug, new)]
+/// A task and when it is scheduled. A task with a daily minimum is scheduled in parts, by as many
+/// scheduled tasks as there are parts, each with the duration of its part as the duration of
+/// the task.
+#[derive(Debug, new, Clone, PartialEq, Eq)]
 pub struct ScheduledTask {
     pub task: Task,
     pub when: DateTime<Utc>,
+    /// A note on this slot in particular, like where to do the task, apart from the task itself.
+    #[new(default)]
+    pub note: Option<String>,
+    /// What keeps this slot from starting any sooner, as the strategy that placed it found (see
+    /// `Schedule::explain`).
+    #[new(default)]
+    pub held_back_by: Option<Constraint>,
+}
+
+impl ScheduledTask {
+    /// Puts back the tasks of this slot and of the slot ahead of it for which `original` returns
+    /// the task they were made from. Their durations are kept, since tasks with a daily minimum
+    /// are scheduled in parts.
+    pub(super) fn restore<'a, F>(&mut self, original: F)
+    where
+        F: Fn(&Task) -> Option<&'a Task>,
+    {
+        let restored = |task: &mut Task| {
+            if let Some(original) = original(task) {
+                *task = Task {
+                    duration: task.duration,
+                    ..original.clone()
+                };
+            }
+        };
+        restored(&mut self.task);
+        if let Some(Constraint::TaskAhead(ref mut ahead)) = self.held_back_by {
+            restored(ahead);
+        }
+    }
+
+    /// Returns when to remind of this task, in chronological order. The task's own reminders
+    /// override the `default_reminders`.
+    pub fn reminders(&self, default_reminders: &[Duration]) -> Vec<DateTime<Utc>> {
+        let offsets = if self.task.reminders.is_empty() {
+            default_reminders
+        } else {
+            &self.task.reminders[..]
+        };
+        let mut reminders: Vec<DateTime<Utc>> = offsets
+            .iter()
+            .filter_map(|&offset| self.when.checked_sub_signed(offset))
+            .collect();
+        reminders.sort();
+        reminders.dedup();
+        reminders
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Schedule(pub Vec<ScheduledTask>);
 
+/// What keeps the slots of tasks from starting any sooner, by their task, as the strategy that
+/// placed them found.
+type HeldBack = HashMap<Arc<Task>, Constraint>;
+
+/// What occupies an entry of the schedule tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Slot {
+    Task(Arc<Task>),
+    /// The period with this index, in which nothing can be scheduled.
+    Unavailable(usize),
+}
+
 impl Schedule {
     /// Schedules tasks according to the given strategy, using the tasks'
     /// deadlines, importance and duration.
@@ -74,156 +304,679 @@ impl Schedule {
     where
         I: IntoIterator<Item = Task>,
     {
-        let mut tree: ScheduleTree<DateTime<Utc>, Rc<Task>> = ScheduleTree::new();
+        Schedule::schedule_around(
+            start,
+            tasks,
+            strategy,
+            &[],
+            &UrgencyCoefficients::default(),
+            &Utc,
+        )
+    }
+
+    /// Schedules tasks like `schedule` does, but keeps them out of the `unavailable` periods,
+    /// like public holidays. The importance strategy ranks the tasks by their `urgency` instead
+    /// of by their importance.
+    ///
+    /// Tasks with a daily minimum are scheduled in a part for every day until they are done (see
+    /// `ScheduledTask`). These parts are scheduled before the other tasks. The days are those of
+    /// `local_time`.
+    pub fn schedule_around<I>(
+        start: DateTime<Utc>,
+        tasks: I,
+        strategy: SchedulingStrategy,
+        unavailable: &[Range<DateTime<Utc>>],
+        urgency: &UrgencyCoefficients,
+        local_time: &LocalTime,
+    ) -> Result<Schedule>
+    where
+        I: IntoIterator<Item = Task>,
+    {
+        let mut tree: ScheduleTree<DateTime<Utc>, Slot> = ScheduleTree::new();
         // Make sure things aren't scheduled before the algorithm is finished.
-        let start = start + *SCHEDULE_DELAY;
-        let tasks: Vec<Rc<Task>> = tasks.into_iter().map(Rc::new).collect();
-        match strategy {
-            SchedulingStrategy::Importance => tree.schedule_according_to_importance(start, tasks),
+        let start = start.checked_add_signed(*SCHEDULE_DELAY).ok_or_else(|| {
+            Error::Internal("the start of the schedule is out of range".to_owned())
+        })?;
+        let unavailable = merge_periods(start, unavailable);
+        let (daily_parts, tasks) =
+            DailyParts::split(start, tasks.into_iter().collect(), &unavailable, local_time)?;
+        let tasks: Vec<Arc<Task>> = tasks.into_iter().map(Arc::new).collect();
+        check_bounds(start, &tasks).map_err(|error| daily_parts.join_error(error))?;
+        for (index, period) in unavailable.iter().enumerate() {
+            let length = period.end.signed_duration_since(period.start);
+            if !tree.schedule_exact(period.start, length, Slot::Unavailable(index)) {
+                bail!(Error::Internal("I couldn't block an unavailable period".to_owned()));
+            }
+        }
+        let held_back = match strategy {
+            SchedulingStrategy::Importance => {
+                tree.schedule_according_to_importance(start, tasks, urgency)
+            }
             SchedulingStrategy::Urgency => tree.schedule_according_to_myrjam(start, tasks),
-        }?;
-        Ok(Schedule::from_tree(tree))
+            SchedulingStrategy::Optimal => {
+                tree.schedule_optimally(start, tasks, &unavailable, urgency)
+            }
+        }
+        .map_err(|error| daily_parts.join_error(error))?;
+        Ok(daily_parts.join(Schedule::from_tree(tree, &held_back)))
+    }
+
+    /// Finds the tasks that wait for others to be done (see `Trigger::Done`) and can't be done
+    /// before their deadline after the whole chain of tasks they wait for, when these are done one
+    /// after the other from `start`, with their buffers kept free. Every task in the chain is done
+    /// outside of the periods in which its assignee is unavailable, which `unavailable_to` returns
+    /// (see `Configuration::unavailable_to`). The `tasks` are all the tasks that aren't done yet,
+    /// including the snoozed ones, which aren't scheduled yet. Tasks that end up waiting for
+    /// themselves get an `Error::CircularWait` instead of an `Error::ChainTooLong`. The errors come
+    /// in the order of `tasks`.
+    pub fn check_dependencies<F>(
+        start: DateTime<Utc>,
+        tasks: &[Task],
+        unavailable_to: F,
+    ) -> Vec<Error>
+    where
+        F: Fn(Option<&str>) -> Vec<Range<DateTime<Utc>>>,
+    {
+        // The merged unavailable periods of every assignee
+        let mut unavailable: HashMap<Option<String>, Vec<Range<DateTime<Utc>>>> = HashMap::new();
+        for task in tasks {
+            unavailable.entry(task.assignee.clone()).or_insert_with(|| {
+                merge_periods(start, &unavailable_to(task.assignee.as_ref().map(String::as_str)))
+            });
+        }
+        let unavailable_to_task = |task: &Task| unavailable[&task.assignee].as_slice();
+        tasks
+            .iter()
+            .filter_map(|task| {
+                let (chain, circular) = waited_for(task, tasks);
+                if circular {
+                    return Some(Error::CircularWait {
+                        task: task.clone(),
+                        cycle: chain.into_iter().cloned().collect(),
+                    });
+                }
+                if chain.is_empty() {
+                    return None;
+                }
+                match chain_end(start, task, &chain, unavailable_to_task) {
+                    Some((end, duration)) if task.deadline < end => Some(Error::ChainTooLong {
+                        task: task.clone(),
+                        window: earliest_start(start, chain[0])..task.deadline,
+                        chain: chain.into_iter().cloned().collect(),
+                        duration,
+                        shortfall: end.signed_duration_since(task.deadline),
+                    }),
+                    Some(_) => None,
+                    None => Some(Error::Overflow { task: task.clone() }),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the latest moment at which each of `tasks` can start, in the same order, when they
+    /// are all done one after the other as close to their deadlines as they fit, outside of the
+    /// `unavailable` periods. The tasks with the latest deadlines come last, and of those the
+    /// least important ones. A task gets `None` when its latest start is out of range.
+    ///
+    /// This way several long tasks with the same distant deadline together start sooner than
+    /// each of them would on its own.
+    pub fn latest_starts(
+        tasks: &[Task],
+        unavailable: &[Range<DateTime<Utc>>],
+    ) -> Vec<Option<DateTime<Utc>>> {
+        let unavailable = match unavailable.iter().map(|period| period.start).min() {
+            Some(earliest) => merge_periods(earliest, unavailable),
+            None => vec![],
+        };
+        let mut order: Vec<usize> = (0..tasks.len()).collect();
+        order.sort_by_key(|&index| {
+            let task = &tasks[index];
+            (Reverse(task.deadline), task.importance, task.id)
+        });
+        let mut latest_starts = vec![None; tasks.len()];
+        // Where the tasks that come later start
+        let mut later: Option<DateTime<Utc>> = None;
+        for index in order {
+            let task = &tasks[index];
+            // The task itself has to be finished by the deadline, but its buffer doesn't
+            let buffer = task.buffer.unwrap_or_else(Duration::zero);
+            let latest_start = task
+                .deadline
+                .checked_add_signed(buffer)
+                .map(|until| later.map_or(until, |later| min(later, until)))
+                .and_then(|until| {
+                    task.duration_with_buffer()
+                        .and_then(|occupied| latest_fit(until, occupied, &unavailable))
+                });
+            if latest_start.is_some() {
+                later = latest_start;
+            }
+            latest_starts[index] = latest_start;
+        }
+        latest_starts
     }
 
-    fn from_tree(tree: ScheduleTree<DateTime<Utc>, Rc<Task>>) -> Schedule {
+    fn from_tree(tree: ScheduleTree<DateTime<Utc>, Slot>, held_back: &HeldBack) -> Schedule {
         let scheduled_tasks = tree
             .into_iter()
-            .map(|entry| ScheduledTask::new((*entry.data).clone(), entry.start))
+            .filter_map(|entry| match entry.data {
+                Slot::Task(task) => Some(ScheduledTask {
+                    held_back_by: held_back.get(&task).cloned(),
+                    ..ScheduledTask::new((*task).clone(), entry.start)
+                }),
+                Slot::Unavailable(_) => None,
+            })
+            .collect();
+        Schedule(scheduled_tasks)
+    }
+
+    /// Gives the slots of this schedule the notes of the same slots in `previous`, which have the
+    /// same task and start at the same moment. The other notes are dropped.
+    pub fn keep_notes(&mut self, previous: &Schedule) {
+        for scheduled_task in &mut self.0 {
+            if let Some(previous_task) = previous.0.iter().find(|previous_task| {
+                previous_task.task.id == scheduled_task.task.id
+                    && previous_task.when == scheduled_task.when
+            }) {
+                scheduled_task.note = previous_task.note.clone();
+            }
+        }
+    }
+
+    /// Returns the timeline of `assignee` in this schedule: the slots of the tasks they do, or of
+    /// the tasks without an assignee when `assignee` is `None`.
+    pub fn assigned_to(&self, assignee: Option<&str>) -> Schedule {
+        let scheduled_tasks = self
+            .0
+            .iter()
+            .filter(|scheduled_task| {
+                scheduled_task.task.assignee.as_ref().map(String::as_str) == assignee
+            })
+            .cloned()
             .collect();
         Schedule(scheduled_tasks)
     }
 }
 
+/// Makes sure that none of the date arithmetic in the scheduling algorithms can overflow, so they
+/// can add and subtract durations without checking every single result.
+///
+/// Every task ends up somewhere between `start` and the latest deadline plus its buffer, so it
+/// suffices that tasks can be shifted by their duration and buffer on both sides of that range,
+/// and that they can be finished when started as early as they are allowed to.
+fn check_bounds(start: DateTime<Utc>, tasks: &[Arc<Task>]) -> Result<()> {
+    let latest_deadline = match tasks.iter().map(|task| task.deadline).max() {
+        Some(deadline) => max(deadline, start),
+        None => return Ok(()),
+    };
+    for task in tasks {
+        let buffer = task.buffer.unwrap_or_else(Duration::zero);
+        if task.duration < Duration::zero() || buffer < Duration::zero() {
+            bail!(Error::InvalidDuration {
+                task: (**task).clone(),
+            });
+        }
+        let occupied = match task.duration_with_buffer() {
+            Some(occupied) => occupied,
+            None => bail!(Error::Overflow {
+                task: (**task).clone(),
+            }),
+        };
+        // The buffer of a task can end after its deadline
+        let in_range = start.checked_sub_signed(occupied).is_some()
+            && start.checked_add_signed(occupied).is_some()
+            && latest_deadline
+                .checked_add_signed(buffer)
+                .and_then(|padded_deadline| padded_deadline.checked_add_signed(occupied))
+                .is_some()
+            && task
+                .start_after
+                .map_or(true, |start_after| start_after.checked_add_signed(occupied).is_some());
+        if !in_range {
+            bail!(Error::Overflow {
+                task: (**task).clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the `unavailable` periods from `start` onwards, in chronological order and with the
+/// overlapping ones merged.
+fn merge_periods(
+    start: DateTime<Utc>,
+    unavailable: &[Range<DateTime<Utc>>],
+) -> Vec<Range<DateTime<Utc>>> {
+    let mut periods: Vec<Range<DateTime<Utc>>> = unavailable
+        .iter()
+        .filter(|period| start < period.end && period.start < period.end)
+        .map(|period| max(start, period.start)..period.end)
+        .collect();
+    periods.sort_by_key(|period| period.start);
+    let mut merged: Vec<Range<DateTime<Utc>>> = Vec::with_capacity(periods.len());
+    for period in periods {
+        match merged.last_mut() {
+            Some(last) if period.start <= last.end => last.end = max(last.end, period.end),
+            _ => merged.push(period),
+        }
+    }
+    merged
+}
+
+/// Returns the latest moment from where `occupied` fits before `until` without overlapping any of
+/// the `unavailable` periods, which are in chronological order and don't overlap.
+fn latest_fit(
+    until: DateTime<Utc>,
+    occupied: Duration,
+    unavailable: &[Range<DateTime<Utc>>],
+) -> Option<DateTime<Utc>> {
+    let mut end = until;
+    for period in unavailable.iter().rev() {
+        if end <= period.start {
+            continue;
+        }
+        let start = end.checked_sub_signed(occupied)?;
+        if period.end <= start {
+            return Some(start);
+        }
+        end = period.start;
+    }
+    end.checked_sub_signed(occupied)
+}
+
+/// The key of the unavailable period with the given index in `Gaps`, which can't be the index of
+/// a task.
+fn unavailable_key(index: usize) -> usize {
+    usize::max_value() - index
+}
+
+/// Returns the chain of `tasks` that `task` waits for to be done, in the order they have to be
+/// done, and whether the chain comes back around to `task`. In that case the chain is in the order
+/// the tasks wait for each other instead. A chain that runs into a circle of other tasks ends
+/// where it would come back around.
+fn waited_for<'a>(task: &Task, tasks: &'a [Task]) -> (Vec<&'a Task>, bool) {
+    let mut chain: Vec<&Task> = vec![];
+    let mut waiting = task;
+    while let Some(Trigger::Done(id)) = waiting.waiting_for {
+        if id == task.id {
+            return (chain, true);
+        }
+        match tasks.iter().find(|other| other.id == id) {
+            Some(other) if chain.iter().all(|link| link.id != id) => {
+                chain.push(other);
+                waiting = other;
+            }
+            _ => break,
+        }
+    }
+    chain.reverse();
+    (chain, false)
+}
+
+/// Returns when `task` is done at the earliest when the `chain` of tasks it waits for is done
+/// first, one after the other from `start`, every one of them outside of the periods that
+/// `unavailable` returns for it, which are in chronological order and don't overlap. The buffers
+/// of the tasks in the chain are kept free before the next one starts. Also returns how long the
+/// tasks take together, without their buffers. Returns `None` when that is out of range.
+fn chain_end<'a, F>(
+    start: DateTime<Utc>,
+    task: &Task,
+    chain: &[&Task],
+    unavailable: F,
+) -> Option<(DateTime<Utc>, Duration)>
+where
+    F: Fn(&Task) -> &'a [Range<DateTime<Utc>>],
+{
+    let mut end = start;
+    let mut duration = Duration::zero();
+    for link in chain {
+        let occupied = link.duration_with_buffer()?;
+        end = earliest_fit(earliest_start(end, link), occupied, unavailable(link))?
+            .checked_add_signed(occupied)?;
+        duration = duration.checked_add(&link.duration)?;
+    }
+    // The task itself has to be done by its deadline, but its buffer doesn't
+    end = earliest_fit(earliest_start(end, task), task.duration, unavailable(task))?
+        .checked_add_signed(task.duration)?;
+    duration = duration.checked_add(&task.duration)?;
+    Some((end, duration))
+}
+
+/// Returns the earliest moment from `from` onwards from where `occupied` fits without overlapping
+/// any of the `unavailable` periods, which are in chronological order and don't overlap.
+fn earliest_fit(
+    from: DateTime<Utc>,
+    occupied: Duration,
+    unavailable: &[Range<DateTime<Utc>>],
+) -> Option<DateTime<Utc>> {
+    let mut start = from;
+    for period in unavailable {
+        if period.end <= start {
+            continue;
+        }
+        if start.checked_add_signed(occupied)? <= period.start {
+            return Some(start);
+        }
+        start = period.end;
+    }
+    start.checked_add_signed(occupied).map(|_end| start)
+}
+
+/// Returns the earliest moment from where `task` can be scheduled.
+fn earliest_start(start: DateTime<Utc>, task: &Task) -> DateTime<Utc> {
+    task.start_after.map_or(start, |start_after| max(start, start_after))
+}
+
+/// Returns what keeps `task` from starting any sooner than `task_start`, where nothing can be
+/// scheduled before `start` and the entry that ends at `task_start` is `ahead`, if any. That entry
+/// is a task in `tasks` when its key is the index of one, and an unavailable period otherwise.
+fn held_back_by(
+    start: DateTime<Utc>,
+    task: &Task,
+    task_start: DateTime<Utc>,
+    ahead: Option<(Range<DateTime<Utc>>, usize)>,
+    tasks: &[Arc<Task>],
+) -> Option<Constraint> {
+    match task.start_after {
+        Some(start_after) if start < start_after && task_start == start_after => {
+            return Some(Constraint::StartAfter(start_after));
+        }
+        _ => (),
+    }
+    if task_start <= start {
+        return None;
+    }
+    let (period, key) = ahead.filter(|(period, _)| period.end == task_start)?;
+    Some(match tasks.get(key) {
+        Some(other) => Constraint::TaskAhead((**other).clone()),
+        None => Constraint::Unavailable(period),
+    })
+}
+
+/// Returns the slots in `tasks` that `held_back` has a constraint for, with that constraint.
+fn held_back_slots(tasks: &[Arc<Task>], held_back: Vec<Option<Constraint>>) -> HeldBack {
+    tasks
+        .iter()
+        .zip(held_back)
+        .filter_map(|(task, constraint)| Some((Arc::clone(task), constraint?)))
+        .collect()
+}
+
 trait TaskScheduler {
-    fn schedule_according_to_importance(&mut self, start: DateTime<Utc>, tasks: Vec<Rc<Task>>) -> Result<()>;
-    fn schedule_according_to_myrjam(&mut self, start: DateTime<Utc>, tasks: Vec<Rc<Task>>) -> Result<()>;
+    fn schedule_according_to_importance(&mut self, start: DateTime<Utc>, tasks: Vec<Arc<Task>>, urgency: &UrgencyCoefficients) -> Result<HeldBack>;
+    fn schedule_according_to_myrjam(&mut self, start: DateTime<Utc>, tasks: Vec<Arc<Task>>) -> Result<HeldBack>;
+    fn schedule_optimally(&mut self, start: DateTime<Utc>, tasks: Vec<Arc<Task>>, unavailable: &[Range<DateTime<Utc>>], urgency: &UrgencyCoefficients) -> Result<HeldBack>;
+    fn schedule_close_to_deadline(&mut self, start: DateTime<Utc>, task: &Arc<Task>) -> Result<()>;
+    fn conflict(&self, window: Range<DateTime<Utc>>, occupied: Duration) -> (Duration, Vec<Task>);
+    fn rebuild(&mut self, tasks: &[Arc<Task>], gaps: Gaps) -> Result<()>;
 }
 
-impl TaskScheduler for ScheduleTree<DateTime<Utc>, Rc<Task>> {
-    /// Schedules `tasks` according to importance while making sure all deadlines are met.
+impl TaskScheduler for ScheduleTree<DateTime<Utc>, Slot> {
+    /// Schedules `tasks` according to importance while making sure all deadlines are met. The
+    /// importance of a task is its urgency according to the `urgency` coefficients at `start`.
     ///
     /// First, all tasks --- starting with the least important until the most important --- are
-    /// scheduled as close as possible to their deadline. Next, all tasks --- starting with the
-    /// most important until the least important --- are put as close to the present as possible.
-    /// For ties on importance, more urgent tasks are scheduled later in the first phase and sooner
-    /// in the second phase.
+    /// scheduled as close as possible to their deadline, after the parts of tasks with a daily
+    /// minimum. Next, all tasks --- starting with the most important until the least important
+    /// --- are put as close to the present as possible. For ties on importance, more urgent tasks
+    /// are scheduled later in the first phase and sooner in the second phase.
     ///
-    /// This algorithm has a terrible performance at the moment and it doesn't work right when the
-    /// lengths of the tasks aren't about the same, but it will do for now.
-    fn schedule_according_to_importance(&mut self, start: DateTime<Utc>, mut tasks: Vec<Rc<Task>>) -> Result<()> {
+    /// Every task records what keeps it from moving any closer to the present when it's put there.
+    ///
+    /// This algorithm doesn't work right when the lengths of the tasks aren't about the same, but it
+    /// will do for now.
+    fn schedule_according_to_importance(&mut self, start: DateTime<Utc>, mut tasks: Vec<Arc<Task>>, urgency: &UrgencyCoefficients) -> Result<HeldBack> {
         // Start by scheduling the least important tasks closest to the deadline, and so on.
-        tasks.sort_by_key(|task| (task.importance, start.signed_duration_since(task.deadline)));
-        for task in &tasks {
-            if task.deadline <= start + task.duration {
-                bail!(ErrorKind::DeadlineMissed(
-                    (**task).clone(),
-                    task.deadline <= start
-                ));
-            }
-            if !self.schedule_close_before(
-                task.deadline,
-                task.duration,
-                Some(start),
-                Rc::clone(task),
-            ) {
-                bail!(ErrorKind::NotEnoughTime((**task).clone()));
-            }
+        tasks.sort_by(|task1, task2| {
+            urgency
+                .urgency(task1, start)
+                .partial_cmp(&urgency.urgency(task2, start))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| task2.deadline.cmp(&task1.deadline))
+        });
+        for task in daily::daily_parts_first(&tasks) {
+            self.schedule_close_to_deadline(start, task)?;
         }
         // Next, shift the most important tasks towards today, and so on, filling up the gaps.
-        // Keep repeating that, until nothing changes anymore (i.e. all gaps are filled).
-        let mut changed = !self.is_empty();
-        while changed {
-            changed = false;
-            for task in tasks.iter().rev() {
-                let scheduled_entry = self.unschedule(task).ok_or_else(|| {
-                    ErrorKind::Internal("I couldn't unschedule a task".to_owned())
-                })?;
-                if !self.schedule_close_after(
-                    start,
-                    task.duration,
-                    Some(scheduled_entry.end),
-                    scheduled_entry.data,
-                ) {
-                    bail!(ErrorKind::Internal(
-                        "I couldn't reschedule a task".to_owned()
-                    ));
-                }
-                let new_start = self.when_scheduled(task).ok_or_else(|| {
-                    ErrorKind::Internal("I couldn't find a task that was just scheduled".to_owned())
-                })?;
-                if scheduled_entry.start != *new_start {
-                    changed = true;
-                    break;
+        // Keep repeating that, until nothing changes anymore (i.e. all gaps are filled). A task that
+        // couldn't be moved only needs to be reconsidered when a gap right before it appears or
+        // when a gap before it grows large enough to fit it.
+        let tasks: Vec<Arc<Task>> = tasks.into_iter().rev().collect();
+        let mut gaps = Gaps::new(start);
+        let mut starts = Vec::with_capacity(tasks.len());
+        for (rank, task) in tasks.iter().enumerate() {
+            let task_start = *self.when_scheduled(&Slot::Task(Arc::clone(task))).ok_or_else(|| {
+                Error::Internal("I couldn't find a task that was just scheduled".to_owned())
+            })?;
+            gaps.insert(task_start, task_start + task.occupied(), rank);
+            starts.push(task_start);
+        }
+        for entry in self.iter() {
+            if let Slot::Unavailable(index) = *entry.data {
+                gaps.insert(entry.start, entry.end, unavailable_key(index));
+            }
+        }
+        let mut held_back: Vec<Option<Constraint>> = vec![None; tasks.len()];
+        let mut movable: BTreeSet<usize> = (0..tasks.len()).collect();
+        // The tasks that couldn't be moved, by when they are scheduled, with their duration
+        let mut stuck = Treap::new();
+        while let Some(rank) = movable.iter().next().cloned() {
+            movable.remove(&rank);
+            let task = &tasks[rank];
+            let old_start = starts[rank];
+            let occupied = task.occupied();
+            let old_end = old_start + occupied;
+            gaps.remove(old_start, old_end, rank);
+            let new_start = gaps
+                .earliest_fit(occupied, earliest_start(start, task), old_end)
+                .ok_or_else(|| Error::Internal("I couldn't reschedule a task".to_owned()))?;
+            held_back[rank] = held_back_by(start, task, new_start, gaps.ending_at(new_start), &tasks);
+            gaps.insert(new_start, new_start + occupied, rank);
+            stuck.insert((new_start, rank), occupied);
+            if new_start == old_start {
+                continue;
+            }
+            starts[rank] = new_start;
+            if let Some((gap_start, gap_end)) = gaps.gap_before(old_end) {
+                let adjacent_entries = gaps
+                    .entries_from(gap_end)
+                    .take_while(|&&(entry_start, _, _)| entry_start == gap_end);
+                for &(entry_start, _, other_rank) in adjacent_entries {
+                    if stuck.remove((entry_start, other_rank)).is_some() {
+                        movable.insert(other_rank);
+                    }
                 }
+                let gap_length = gap_end.signed_duration_since(gap_start);
+                movable.extend(
+                    stuck
+                        .drain_at_most((gap_end, 0), gap_length)
+                        .into_iter()
+                        .map(|(_, other_rank)| other_rank),
+                );
             }
         }
-        Ok(())
+        self.rebuild(&tasks, gaps)?;
+        Ok(held_back_slots(&tasks, held_back))
     }
 
     /// Schedules `tasks` according to deadline first and then according to importance.
     ///
     /// First, all tasks --- starting with the least important until the most important --- are
-    /// scheduled as close as possible to their deadline. Next, all tasks are put as close to the
-    /// present as possible, keeping the order from the first scheduling phase.
+    /// scheduled as close as possible to their deadline, after the parts of tasks with a daily
+    /// minimum. Next, all tasks are put as close to the present as possible, keeping the order
+    /// from the first scheduling phase.
     ///
     /// This algorithm is how Myrjam Van de Vijver does her personal scheduling. A benefit of doing
     /// it this way, is that it is highly robust against contingencies like falling sick. A
     /// disadvantage is that it gives more priority to urgent but less important tasks than to
     /// important but less urgent tasks.
-    fn schedule_according_to_myrjam(&mut self, start: DateTime<Utc>, mut tasks: Vec<Rc<Task>>) -> Result<()> {
+    fn schedule_according_to_myrjam(&mut self, start: DateTime<Utc>, mut tasks: Vec<Arc<Task>>) -> Result<HeldBack> {
         // Start by scheduling the least important tasks closest to the deadline, and so on.
         tasks.sort_by_key(|task| task.importance);
-        for task in tasks {
-            if task.deadline <= start + task.duration {
-                bail!(ErrorKind::DeadlineMissed(
-                    (*task).clone(),
-                    task.deadline <= start
-                ));
-            }
-            if !self.schedule_close_before(
-                task.deadline,
-                task.duration,
-                Some(start),
-                Rc::clone(&task),
-            ) {
-                bail!(ErrorKind::NotEnoughTime((*task).clone()));
-            }
+        for task in daily::daily_parts_first(&tasks) {
+            self.schedule_close_to_deadline(start, task)?;
         }
         // Next, shift the all tasks towards the present, filling up the gaps.
-        let mut entries = vec![];
+        let mut tasks: Vec<Arc<Task>> = vec![];
+        let mut entries: Vec<(usize, DateTime<Utc>, DateTime<Utc>)> = vec![];
+        let mut gaps = Gaps::new(start);
         for entry in self.iter() {
-            entries.push(Entry {
-                start: entry.start,
-                end: entry.end,
-                data: Rc::clone(entry.data),
-            });
+            match entry.data {
+                Slot::Task(task) => {
+                    gaps.insert(entry.start, entry.end, tasks.len());
+                    entries.push((tasks.len(), entry.start, entry.end));
+                    tasks.push(Arc::clone(task));
+                }
+                Slot::Unavailable(index) => {
+                    gaps.insert(entry.start, entry.end, unavailable_key(*index))
+                }
+            }
+        }
+        let mut held_back: Vec<Option<Constraint>> = vec![None; tasks.len()];
+        for (index, old_start, old_end) in entries {
+            gaps.remove(old_start, old_end, index);
+            let task = &tasks[index];
+            let occupied = task.occupied();
+            let new_start = gaps
+                .earliest_fit(occupied, earliest_start(start, task), old_end)
+                .ok_or_else(|| Error::Internal("I couldn't reschedule a task".to_owned()))?;
+            held_back[index] = held_back_by(start, task, new_start, gaps.ending_at(new_start), &tasks);
+            gaps.insert(new_start, new_start + occupied, index);
         }
-        for entry in entries {
-            let scheduled_entry = self
-                .unschedule(&entry.data)
-                .ok_or_else(|| ErrorKind::Internal("I couldn't unschedule a task".to_owned()))?;
-            let task = scheduled_entry.data;
-            if !self.schedule_close_after(start, task.duration, Some(scheduled_entry.end), task) {
-                bail!(ErrorKind::Internal(
-                    "I couldn't reschedule a task".to_owned()
-                ));
+        self.rebuild(&tasks, gaps)?;
+        Ok(held_back_slots(&tasks, held_back))
+    }
+
+    /// Schedules `tasks` in the order that minimizes their weighted lateness, where the weight of
+    /// a task is its urgency according to the `urgency` coefficients at `start`. The tree should
+    /// already hold the `unavailable` periods.
+    ///
+    /// Finding that order takes a search (see `optimal::best_starts`), which only pays off for
+    /// small sets of tasks where the durations vary a lot. Larger sets of tasks, and the ones for
+    /// which the search doesn't find an order where everything fits, are scheduled according to
+    /// importance instead.
+    ///
+    /// In the order that was found, every task starts as soon as it can after the one before it,
+    /// so what keeps it from starting sooner is that task, an unavailable period or the moment it
+    /// can be started.
+    fn schedule_optimally(&mut self, start: DateTime<Utc>, tasks: Vec<Arc<Task>>, unavailable: &[Range<DateTime<Utc>>], urgency: &UrgencyCoefficients) -> Result<HeldBack> {
+        let starts = match optimal::best_starts(start, &tasks, unavailable, urgency) {
+            Some(starts) => starts,
+            None => return self.schedule_according_to_importance(start, tasks, urgency),
+        };
+        for (task, &task_start) in tasks.iter().zip(&starts) {
+            if !self.schedule_exact(task_start, task.occupied(), Slot::Task(Arc::clone(task))) {
+                bail!(Error::Internal("I couldn't schedule a task where it fits".to_owned()));
             }
         }
+        let mut order: Vec<usize> = (0..tasks.len()).collect();
+        order.sort_by_key(|&index| starts[index]);
+        let mut held_back: Vec<Option<Constraint>> = vec![None; tasks.len()];
+        let mut ahead: Option<(Range<DateTime<Utc>>, usize)> = None;
+        for index in order {
+            let task_start = starts[index];
+            let period_ahead = unavailable
+                .iter()
+                .enumerate()
+                .find(|(_, period)| period.end == task_start)
+                .map(|(period_index, period)| (period.clone(), unavailable_key(period_index)));
+            held_back[index] =
+                held_back_by(start, &tasks[index], task_start, period_ahead.or(ahead), &tasks);
+            ahead = Some((task_start..task_start + tasks[index].occupied(), index));
+        }
+        Ok(held_back_slots(&tasks, held_back))
+    }
+
+    /// Schedules `task` as close as possible to its deadline, but not before `start` or before the
+    /// task can be started.
+    fn schedule_close_to_deadline(&mut self, start: DateTime<Utc>, task: &Arc<Task>) -> Result<()> {
+        if task.deadline < start {
+            bail!(Error::DeadlineMissed {
+                task: (**task).clone(),
+                already_missed: true,
+                shortfall: start + task.duration - task.deadline,
+            });
+        }
+        let task_start = earliest_start(start, task);
+        let shortfall = task_start + task.duration - task.deadline;
+        if task_start > start && task.deadline < task_start + task.duration {
+            bail!(Error::StartsTooLate {
+                task: (**task).clone(),
+                shortfall,
+            });
+        }
+        if task.deadline < task_start + task.duration {
+            bail!(Error::DeadlineMissed {
+                task: (**task).clone(),
+                already_missed: false,
+                shortfall,
+            });
+        }
+        // The task itself has to be finished by the deadline, but its buffer doesn't
+        let buffer = task.buffer.unwrap_or_else(Duration::zero);
+        let until = task.deadline + buffer;
+        if !self.schedule_close_before(
+            until,
+            task.occupied(),
+            Some(task_start),
+            Slot::Task(Arc::clone(task)),
+        ) {
+            let (shortfall, conflicting) =
+                self.conflict(task_start..until, task.occupied());
+            bail!(Error::NotEnoughTime {
+                task: (**task).clone(),
+                shortfall,
+                conflicting,
+            });
+        }
         Ok(())
     }
-}
 
-impl fmt::Display for Task {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.content)
+    /// Returns how much more than the longest free stretch in `window` it takes to fit something
+    /// that takes up `occupied`, and the tasks that are scheduled in `window`.
+    fn conflict(&self, window: Range<DateTime<Utc>>, occupied: Duration) -> (Duration, Vec<Task>) {
+        let mut longest_gap = Duration::zero();
+        let mut cursor = window.start;
+        let mut conflicting = vec![];
+        for entry in self.iter() {
+            if entry.end <= window.start || window.end <= entry.start {
+                continue;
+            }
+            longest_gap = max(longest_gap, entry.start.signed_duration_since(cursor));
+            cursor = max(cursor, entry.end);
+            if let Slot::Task(task) = entry.data {
+                conflicting.push((**task).clone());
+            }
+        }
+        longest_gap = max(longest_gap, window.end.signed_duration_since(cursor));
+        (max(Duration::zero(), occupied - longest_gap), conflicting)
+    }
+
+    /// Replaces the contents of this tree by the tasks in `gaps`, where the key of every task
+    /// is its index in `tasks`. The unavailable periods are left out.
+    fn rebuild(&mut self, tasks: &[Arc<Task>], gaps: Gaps) -> Result<()> {
+        *self = ScheduleTree::new();
+        // Since the entries are chronological, every one of them is simply appended to the tree.
+        for (start, _end, index) in gaps.into_entries() {
+            let task = match tasks.get(index) {
+                Some(task) => task,
+                None => continue,
+            };
+            if !self.schedule_exact(start, task.occupied(), Slot::Task(Arc::clone(task))) {
+                bail!(Error::Internal("I couldn't reschedule a task".to_owned()));
+            }
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use assert_matches::assert_matches;
+    use chrono::{MAX_DATE, MIN_DATE};
 
     use super::*;
+    use crate::task::fixtures::task;
 
     macro_rules! test_generic_properties {
         ($($strategy_name:ident: $strategy:expr,)*) => {
@@ -277,6 +1030,7 @@ mod tests {
                             deadline: Utc::now() + Duration::hours(1),
                             duration: Duration::hours(1) - *SCHEDULE_DELAY * 2,
                             importance: 6,
+                            ..Default::default()
                         },
                         Task {
                             id: 1,
@@ -284,6 +1038,7 @@ mod tests {
                             deadline: Utc::now() + Duration::hours(3),
                             duration: Duration::hours(2) - *SCHEDULE_DELAY * 2,
                             importance: 5,
+                            ..Default::default()
                         }];
                         // Normal scheduling
                         {
@@ -320,21 +1075,57 @@ mod tests {
                     fn missed_deadline() {
                         let tasks = taskset_with_missed_deadline();
                         assert_matches!(Schedule::schedule(Utc::now(), tasks, $strategy),
-                                        Err(Error(ErrorKind::DeadlineMissed(_, true), _)));
+                                        Err(Error::DeadlineMissed { already_missed: true, .. }));
                     }
 
                     #[test]
                     fn impossible_deadline() {
                         let tasks = taskset_with_impossible_deadline();
                         assert_matches!(Schedule::schedule(Utc::now(), tasks, $strategy),
-                                        Err(Error(ErrorKind::DeadlineMissed(_, false), _)));
+                                        Err(Error::DeadlineMissed { already_missed: false, .. }));
+                    }
+
+                    #[test]
+                    fn absurd_durations() {
+                        let mut tasks = taskset_of_myrjam();
+                        tasks[2].duration = Duration::max_value();
+                        assert_matches!(Schedule::schedule(Utc::now(), tasks.clone(), $strategy),
+                                        Err(Error::Overflow { ref task })
+                                        if *task == tasks[2]);
+
+                        tasks[2].duration = Duration::min_value();
+                        assert_matches!(Schedule::schedule(Utc::now(), tasks, $strategy),
+                                        Err(Error::InvalidDuration { .. }));
+                    }
+
+                    #[test]
+                    fn absurd_deadlines() {
+                        let mut tasks = taskset_of_myrjam();
+                        tasks[0].deadline = MAX_DATE.and_hms(0, 0, 0);
+                        assert_matches!(Schedule::schedule(Utc::now(), tasks.clone(), $strategy),
+                                        Err(Error::Overflow { .. }));
+
+                        tasks[0].deadline = MIN_DATE.and_hms(0, 0, 0);
+                        assert_matches!(Schedule::schedule(Utc::now(), tasks, $strategy),
+                                        Err(Error::DeadlineMissed { already_missed: true, .. }));
+                    }
+
+                    #[test]
+                    fn absurd_start() {
+                        let tasks = taskset_of_myrjam();
+                        assert_matches!(
+                            Schedule::schedule(MAX_DATE.and_hms(0, 0, 0), tasks.clone(), $strategy),
+                            Err(Error::Overflow { .. }));
+                        assert_matches!(
+                            Schedule::schedule(MIN_DATE.and_hms(0, 0, 0), tasks, $strategy),
+                            Err(Error::Overflow { .. }));
                     }
 
                     #[test]
                     fn out_of_time() {
                         let tasks = taskset_impossible_combination();
                         assert_matches!(Schedule::schedule(Utc::now(), tasks, $strategy),
-                                        Err(Error(ErrorKind::NotEnoughTime(_), _)));
+                                        Err(Error::NotEnoughTime { .. }));
                     }
                 }
              )*
@@ -357,6 +1148,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(6 * 365),
             duration: Duration::hours(1000),
             importance: 10,
+            ..Default::default()
         };
         let task2 = Task {
             id: 2,
@@ -364,6 +1156,7 @@ mod tests {
             deadline: Utc::now() + Duration::hours(2),
             duration: Duration::hours(1),
             importance: 3,
+            ..Default::default()
         };
         let task3 = Task {
             id: 3,
@@ -371,6 +1164,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(365 / 2),
             duration: Duration::hours(50),
             importance: 6,
+            ..Default::default()
         };
         let task4 = Task {
             id: 4,
@@ -378,6 +1172,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(30),
             duration: Duration::hours(10),
             importance: 4,
+            ..Default::default()
         };
         let task5 = Task {
             id: 5,
@@ -385,6 +1180,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(30),
             duration: Duration::hours(5),
             importance: 10,
+            ..Default::default()
         };
         let task6 = Task {
             id: 6,
@@ -392,6 +1188,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(7),
             duration: Duration::minutes(10),
             importance: 5,
+            ..Default::default()
         };
         vec![task1, task2, task3, task4, task5, task6]
     }
@@ -403,6 +1200,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(23 * 365),
             duration: Duration::days(23 * 365) - *SCHEDULE_DELAY * 2,
             importance: 5,
+            ..Default::default()
         };
         let task2 = Task {
             id: 2,
@@ -410,6 +1208,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(65 * 365),
             duration: Duration::days(42 * 365),
             importance: 6,
+            ..Default::default()
         };
         vec![task1, task2]
     }
@@ -484,6 +1283,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(12) + Duration::hours(15),
                 duration: Duration::days(2),
                 importance: 9,
+                ..Default::default()
             },
             Task {
                 id: 1,
@@ -491,6 +1291,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(8) + Duration::hours(15),
                 duration: Duration::days(3),
                 importance: 4,
+                ..Default::default()
             },
             Task {
                 id: 2,
@@ -498,6 +1299,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(13) + Duration::hours(15),
                 duration: Duration::days(2),
                 importance: 2,
+                ..Default::default()
             },
             Task {
                 id: 3,
@@ -505,6 +1307,7 @@ mod tests {
                 deadline: Utc::now() + Duration::hours(33),
                 duration: Duration::hours(3),
                 importance: 3,
+                ..Default::default()
             },
             Task {
                 id: 4,
@@ -512,6 +1315,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(21) + Duration::hours(15),
                 duration: Duration::days(7),
                 importance: 7,
+                ..Default::default()
             },
             Task {
                 id: 5,
@@ -519,6 +1323,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(2) + Duration::hours(15),
                 duration: Duration::hours(1),
                 importance: 8,
+                ..Default::default()
             },
             Task {
                 id: 6,
@@ -526,6 +1331,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(33) + Duration::hours(15),
                 duration: Duration::hours(2),
                 importance: 3,
+                ..Default::default()
             },
             Task {
                 id: 7,
@@ -533,6 +1339,7 @@ mod tests {
                 deadline: Utc::now() + Duration::hours(34),
                 duration: Duration::hours(2),
                 importance: 10,
+                ..Default::default()
             },
             Task {
                 id: 8,
@@ -540,6 +1347,7 @@ mod tests {
                 deadline: Utc::now() + Duration::days(1) + Duration::hours(15),
                 duration: Duration::minutes(30),
                 importance: 5,
+                ..Default::default()
             },
         ]
     }
@@ -587,6 +1395,345 @@ mod tests {
         assert!(are_approx_equal(schedule.0[8].when, expected_when));
     }
 
+    fn taskset_with_waiting_task() -> (DateTime<Utc>, Vec<Task>) {
+        let start = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+        let task1 = Task {
+            id: 1,
+            content: "water the plants".to_string(),
+            deadline: start + Duration::days(3),
+            duration: Duration::hours(1),
+            importance: 10,
+            start_after: Some(start + Duration::days(1)),
+            ..Default::default()
+        };
+        let task2 = Task {
+            id: 2,
+            content: "buy a watering can".to_string(),
+            deadline: start + Duration::days(3),
+            duration: Duration::hours(2),
+            importance: 1,
+            ..Default::default()
+        };
+        (start, vec![task1, task2])
+    }
+
+    #[test]
+    fn tasks_wait_until_they_can_be_started() {
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let (start, tasks) = taskset_with_waiting_task();
+            let schedule = Schedule::schedule(start, tasks.clone(), *strategy).unwrap();
+            // The less important task goes first, since the other one can't be done yet
+            assert_eq!(schedule.0[0].task, tasks[1]);
+            assert_eq!(schedule.0[0].when, start + *SCHEDULE_DELAY);
+            assert_eq!(schedule.0[1].task, tasks[0]);
+            assert_eq!(schedule.0[1].when, start + Duration::days(1));
+        }
+    }
+
+    #[test]
+    fn tasks_that_cant_be_started_in_time() {
+        let (start, mut tasks) = taskset_with_waiting_task();
+        tasks[0].start_after = Some(tasks[0].deadline - Duration::minutes(30));
+        assert_matches!(Schedule::schedule(start, tasks.clone(), SchedulingStrategy::Urgency),
+                        Err(Error::StartsTooLate { ref task, .. }) if *task == tasks[0]);
+        // Starting in the past doesn't make a difference
+        tasks[0].start_after = Some(start - Duration::days(1));
+        tasks[0].duration = Duration::days(4);
+        assert_matches!(Schedule::schedule(start, tasks, SchedulingStrategy::Urgency),
+                        Err(Error::DeadlineMissed { already_missed: false, .. }));
+    }
+
+    #[test]
+    fn tasks_without_duration_wait_until_they_can_be_started() {
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let (start, mut tasks) = taskset_with_waiting_task();
+            tasks[0].duration = Duration::zero();
+            tasks[0].start_after = Some(start + *SCHEDULE_DELAY + Duration::hours(1));
+            tasks[1].deadline = start + *SCHEDULE_DELAY + Duration::hours(2);
+            let schedule = Schedule::schedule(start, tasks.clone(), *strategy).unwrap();
+            // The task that takes no time doesn't interrupt the other one
+            assert_eq!(schedule.0[0].task, tasks[1]);
+            assert_eq!(schedule.0[0].when, start + *SCHEDULE_DELAY);
+            assert_eq!(schedule.0[1].task, tasks[0]);
+            assert_eq!(schedule.0[1].when, start + *SCHEDULE_DELAY + Duration::hours(2));
+        }
+    }
+
+    #[test]
+    fn buffers_keep_tasks_apart() {
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let (start, mut tasks) = taskset_with_waiting_task();
+            tasks[0].start_after = None;
+            tasks[0].buffer = Some(Duration::minutes(15));
+            tasks[1].buffer = Some(Duration::minutes(30));
+            let schedule = Schedule::schedule(start, tasks.clone(), *strategy).unwrap();
+            assert_eq!(schedule.0[0].task, tasks[0]);
+            assert_eq!(schedule.0[0].when, start + *SCHEDULE_DELAY);
+            assert_eq!(schedule.0[1].task, tasks[1]);
+            assert_eq!(
+                schedule.0[1].when,
+                start + *SCHEDULE_DELAY + Duration::minutes(75)
+            );
+        }
+    }
+
+    #[test]
+    fn buffers_take_up_time() {
+        let (start, mut tasks) = taskset_with_waiting_task();
+        tasks[0].start_after = None;
+        tasks[0].deadline = start + Duration::hours(3) + *SCHEDULE_DELAY;
+        tasks[1].deadline = start + Duration::hours(3) + *SCHEDULE_DELAY;
+        assert!(Schedule::schedule(start, tasks.clone(), SchedulingStrategy::Urgency).is_ok());
+        // The buffer of the last task can run past its deadline, but the one of the first can't
+        tasks[0].buffer = Some(Duration::minutes(1));
+        tasks[1].buffer = Some(Duration::minutes(1));
+        assert_matches!(Schedule::schedule(start, tasks, SchedulingStrategy::Urgency),
+                        Err(Error::NotEnoughTime { .. }));
+    }
+
+    #[test]
+    fn conflicts_tell_how_much_time_is_lacking_and_for_what() {
+        let (start, mut tasks) = taskset_with_waiting_task();
+        tasks[0].deadline = start + Duration::hours(26);
+        tasks[1].deadline = start + Duration::hours(26);
+        tasks[1].duration = Duration::hours(25);
+        let error = Schedule::schedule(start, tasks.clone(), SchedulingStrategy::Urgency)
+            .unwrap_err();
+        assert_eq!(error.task(), Some(&tasks[0]));
+        assert_eq!(error.conflicting(), &tasks[1..]);
+        assert_matches!(error, Error::NotEnoughTime { shortfall, .. }
+                        if shortfall == Duration::hours(1));
+    }
+
+    #[test]
+    fn unavailable_periods_are_kept_free() {
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let (start, mut tasks) = taskset_with_waiting_task();
+            tasks[0].start_after = None;
+            let urgency = UrgencyCoefficients::default();
+            let unavailable = vec![
+                start - Duration::hours(1)..start + Duration::days(1),
+                start + Duration::days(1) + Duration::hours(1)
+                    ..start + Duration::days(1) + Duration::minutes(90),
+                start + Duration::days(1) + Duration::minutes(80)
+                    ..start + Duration::days(1) + Duration::hours(2),
+            ];
+            let schedule = Schedule::schedule_around(
+                start,
+                tasks.clone(),
+                *strategy,
+                &unavailable,
+                &urgency,
+                &Utc,
+            )
+            .unwrap();
+            assert_eq!(schedule.0.len(), 2);
+            assert_eq!(schedule.0[0].task, tasks[0]);
+            assert_eq!(schedule.0[0].when, start + Duration::days(1));
+            assert_eq!(schedule.0[1].task, tasks[1]);
+            assert_eq!(schedule.0[1].when, start + Duration::days(1) + Duration::hours(2));
+        }
+    }
+
+    #[test]
+    fn urgency_can_overtake_importance() {
+        let (start, mut tasks) = taskset_with_waiting_task();
+        tasks[0].start_after = None;
+        tasks[0].deadline = start + Duration::days(30);
+        tasks[1].deadline = start + Duration::days(1);
+        let strategy = SchedulingStrategy::Importance;
+        let schedule = Schedule::schedule(start, tasks.clone(), strategy).unwrap();
+        assert_eq!(schedule.0[0].task, tasks[0]);
+        // Task 2 is due soon enough to become more urgent than task 1
+        let urgency = UrgencyCoefficients {
+            importance: 1.0,
+            due: 12.0,
+            horizon: Duration::days(7),
+        };
+        let schedule = Schedule::schedule_around(
+            start,
+            tasks.clone(),
+            strategy,
+            &[],
+            &urgency,
+            &Utc,
+        )
+        .unwrap();
+        assert_eq!(schedule.0[0].task, tasks[1]);
+        assert_eq!(schedule.0[1].task, tasks[0]);
+    }
+
+    #[test]
+    fn chains_of_waiting_tasks_have_to_fit_before_the_deadline() {
+        let start = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+        let waiting_for = |id, task: Task| Task {
+            waiting_for: Some(Trigger::Done(id)),
+            ..task
+        };
+        let tasks = vec![
+            task(1, 2, start + Duration::hours(10)),
+            waiting_for(1, task(2, 1, start + Duration::hours(10))),
+            waiting_for(2, task(3, 2, start + Duration::hours(4))),
+            task(4, 1, start + Duration::hours(4)),
+        ];
+        assert_eq!(
+            Schedule::check_dependencies(start, &tasks, |_| vec![]),
+            vec![Error::ChainTooLong {
+                task: tasks[2].clone(),
+                chain: tasks[..2].to_vec(),
+                duration: Duration::hours(5),
+                window: start..start + Duration::hours(4),
+                shortfall: Duration::hours(1),
+            }]
+        );
+        // Every task on its own fits, and so does the chain when it starts early enough
+        assert!(Schedule::schedule(start, tasks.clone(), SchedulingStrategy::Urgency).is_ok());
+        let earlier = start - Duration::hours(1);
+        assert!(Schedule::check_dependencies(earlier, &tasks, |_| vec![]).is_empty());
+        // Unless nothing can be done for a while, or the buffers have to be kept free
+        let unavailable = [start + Duration::hours(1)..start + Duration::minutes(90)];
+        assert_matches!(
+            Schedule::check_dependencies(earlier, &tasks, |_| unavailable.to_vec())[..],
+            [Error::ChainTooLong { shortfall, .. }] if shortfall == Duration::minutes(30)
+        );
+        let mut buffered = tasks.clone();
+        buffered[0].buffer = Some(Duration::minutes(15));
+        buffered[1].buffer = Some(Duration::minutes(15));
+        buffered[2].buffer = Some(Duration::hours(10));
+        assert_matches!(
+            Schedule::check_dependencies(earlier, &buffered, |_| vec![])[..],
+            [Error::ChainTooLong { shortfall, .. }] if shortfall == Duration::minutes(30)
+        );
+        // Only the time off of whoever does a task in the chain holds it up
+        let mut assigned = tasks.clone();
+        assigned[1].assignee = Some("alice".to_owned());
+        let unavailable_to = |assignee: Option<&str>| match assignee {
+            Some("alice") => unavailable.to_vec(),
+            _ => vec![],
+        };
+        assert_matches!(
+            Schedule::check_dependencies(earlier, &assigned, unavailable_to)[..],
+            [Error::ChainTooLong { shortfall, .. }] if shortfall == Duration::minutes(30)
+        );
+        assigned[1].assignee = Some("bob".to_owned());
+        assert!(Schedule::check_dependencies(earlier, &assigned, unavailable_to).is_empty());
+        // Tasks that wait for each other can never be done, however much time there is
+        let tasks = vec![
+            task(1, 1, start + Duration::hours(10)),
+            waiting_for(3, task(2, 1, start + Duration::hours(100))),
+            waiting_for(4, task(3, 1, start + Duration::hours(100))),
+            waiting_for(2, task(4, 1, start + Duration::hours(100))),
+        ];
+        let errors = Schedule::check_dependencies(start, &tasks, |_| vec![]);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0],
+            Error::CircularWait {
+                task: tasks[1].clone(),
+                cycle: tasks[2..].to_vec(),
+            }
+        );
+        let tasks = vec![waiting_for(1, task(1, 1, start + Duration::hours(10)))];
+        assert_eq!(
+            Schedule::check_dependencies(start, &tasks, |_| vec![]),
+            vec![Error::CircularWait {
+                task: tasks[0].clone(),
+                cycle: vec![],
+            }]
+        );
+        // A chain that runs into a circle ends where it would come back around
+        let tasks = vec![
+            waiting_for(2, task(1, 2, start + Duration::hours(3))),
+            waiting_for(3, task(2, 2, start + Duration::hours(100))),
+            waiting_for(2, task(3, 2, start + Duration::hours(100))),
+        ];
+        assert_matches!(waited_for(&tasks[0], &tasks), (ref chain, false)
+                        if *chain == vec![&tasks[2], &tasks[1]]);
+    }
+
+    #[test]
+    fn tasks_that_are_due_together_start_sooner_together() {
+        let deadline = Utc.ymd(2019, 4, 1).and_hms(9, 0, 0);
+        let tasks = vec![
+            task(1, 100, deadline).with_importance(3),
+            task(2, 100, deadline).with_importance(7),
+            task(3, 100, deadline - Duration::hours(150)),
+            task(4, 100, deadline),
+        ];
+        let latest_starts = Schedule::latest_starts(&tasks, &[]);
+        assert_eq!(
+            latest_starts,
+            vec![
+                Some(deadline - Duration::hours(100)),
+                Some(deadline - Duration::hours(300)),
+                Some(deadline - Duration::hours(400)),
+                Some(deadline - Duration::hours(200)),
+            ]
+        );
+        // Nothing can be done while unavailable, and tasks aren't split around it
+        let unavailable = [
+            deadline - Duration::hours(150)..deadline - Duration::hours(120),
+            deadline - Duration::hours(110)..deadline - Duration::hours(105),
+        ];
+        assert_eq!(
+            Schedule::latest_starts(&tasks[..1], &unavailable),
+            vec![Some(deadline - Duration::hours(100))]
+        );
+        assert_eq!(
+            Schedule::latest_starts(&tasks[..2], &unavailable),
+            vec![
+                Some(deadline - Duration::hours(100)),
+                Some(deadline - Duration::hours(250)),
+            ]
+        );
+    }
+
+    #[test]
+    fn own_reminders_override_the_default_ones() {
+        let when = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+        let mut scheduled_task = ScheduledTask::new(Task::default(), when);
+        let default_reminders = [Duration::minutes(10)];
+        assert_eq!(
+            scheduled_task.reminders(&default_reminders),
+            vec![when - Duration::minutes(10)]
+        );
+        scheduled_task.task.reminders = vec![Duration::hours(1), Duration::days(1)];
+        assert_eq!(
+            scheduled_task.reminders(&default_reminders),
+            vec![when - Duration::days(1), when - Duration::hours(1)]
+        );
+        assert!(ScheduledTask::new(Task::default(), when).reminders(&[]).is_empty());
+    }
+
+    #[test]
+    fn notes_stay_with_slots_that_dont_move() {
+        let when = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+        let task = |id| Task {
+            id,
+            ..Task::default()
+        };
+        let noted = |id, when, note: &str| ScheduledTask {
+            note: Some(note.to_owned()),
+            ..ScheduledTask::new(task(id), when)
+        };
+        let previous = Schedule(vec![
+            noted(1, when, "at the library"),
+            noted(2, when + Duration::hours(1), "bring a pen"),
+        ]);
+        let mut schedule = Schedule(vec![
+            ScheduledTask::new(task(2), when),
+            ScheduledTask::new(task(1), when),
+        ]);
+        schedule.keep_notes(&previous);
+        assert_eq!(
+            schedule,
+            Schedule(vec![
+                ScheduledTask::new(task(2), when),
+                noted(1, when, "at the library"),
+            ])
+        );
+    }
+
     fn taskset_with_missed_deadline() -> Vec<Task> {
         let task1 = Task {
             id: 1,
@@ -594,6 +1741,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(3),
             duration: Duration::days(1),
             importance: 5,
+            ..Default::default()
         };
         let task2 = Task {
             id: 2,
@@ -601,6 +1749,7 @@ mod tests {
             deadline: Utc::now() - Duration::days(1),
             duration: Duration::minutes(5),
             importance: 5,
+            ..Default::default()
         };
         vec![task1, task2]
     }
@@ -612,6 +1761,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(3),
             duration: Duration::days(1),
             importance: 5,
+            ..Default::default()
         };
         let task2 = Task {
             id: 2,
@@ -619,6 +1769,7 @@ mod tests {
             deadline: Utc::now() + Duration::hours(23),
             duration: Duration::days(1),
             importance: 5,
+            ..Default::default()
         };
         vec![task1, task2]
     }
@@ -630,6 +1781,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(1),
             duration: Duration::days(1) - *SCHEDULE_DELAY * 2,
             importance: 5,
+            ..Default::default()
         };
         let task2 = Task {
             id: 2,
@@ -637,6 +1789,7 @@ mod tests {
             deadline: Utc::now() + Duration::days(2),
             duration: Duration::days(1) + Duration::minutes(1),
             importance: 5,
+            ..Default::default()
         };
         vec![task1, task2]
     }
@@ -0,0 +1,259 @@
+use std::cmp::{max, Ordering};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use chrono::prelude::*;
+
+use crate::configuration::UrgencyCoefficients;
+use crate::Task;
+
+/// The most tasks that are searched for the best order. Larger task sets go to the importance
+/// strategy instead.
+pub(super) const MAX_TASKS: usize = 50;
+
+/// How many partial orders the search looks at before it settles for the best order it found.
+const MAX_NODES: usize = 200_000;
+
+/// Searches for the order of `tasks` that minimizes their weighted lateness, where the weight of a
+/// task is its urgency at `start` and every task is finished before its deadline. Returns when
+/// every task starts, in the order of `tasks`, or `None` when no order was found in which every
+/// task fits, in which case the caller should fall back to a heuristic.
+///
+/// The lateness of a task is how long after its deadline it ends, so it's negative when it's on
+/// time. Since the deadlines are fixed, this is the same as getting the weighted sum of the ends
+/// as low as possible: important tasks go first, unless that makes others miss their deadline.
+///
+/// The search is a depth-first branch and bound over the order in which the tasks start. Every
+/// task starts as soon as it can after the one before it, outside of the `unavailable` periods,
+/// which are chronological and don't overlap. Branches are cut off when a task that's left can't
+/// make its deadline anymore, when the same tasks were already planned up to the same moment at a
+/// lower cost, or when even the best case for the tasks that are left, which ignores all their
+/// constraints, doesn't beat the best order so far. When the search takes too long, the best
+/// order so far is used.
+pub(super) fn best_starts(
+    start: DateTime<Utc>,
+    tasks: &[Arc<Task>],
+    unavailable: &[Range<DateTime<Utc>>],
+    urgency: &UrgencyCoefficients,
+) -> Option<Vec<DateTime<Utc>>> {
+    if tasks.len() > MAX_TASKS {
+        return None;
+    }
+    let mut search = Search {
+        start,
+        tasks,
+        unavailable,
+        weights: tasks
+            .iter()
+            .map(|task| urgency.urgency(task, start).max(0.0))
+            .collect(),
+        starts: vec![start; tasks.len()],
+        best: None,
+        seen: HashMap::new(),
+        nodes: 0,
+    };
+    let left: Vec<usize> = (0..tasks.len()).collect();
+    search.branch(start, 0, &left, 0.0);
+    search.best.map(|(_cost, starts)| starts)
+}
+
+struct Search<'a> {
+    start: DateTime<Utc>,
+    tasks: &'a [Arc<Task>],
+    unavailable: &'a [Range<DateTime<Utc>>],
+    weights: Vec<f64>,
+    /// When every task starts in the order that is being built.
+    starts: Vec<DateTime<Utc>>,
+    /// The lowest cost so far, with the starts that got it.
+    best: Option<(f64, Vec<DateTime<Utc>>)>,
+    /// The lowest cost so far of every set of planned tasks, by the set and when the last of them
+    /// ends.
+    seen: HashMap<(u64, DateTime<Utc>), f64>,
+    nodes: usize,
+}
+
+impl<'a> Search<'a> {
+    /// Tries every task in `left` as the next one, from `cursor` on, after the tasks in `planned`
+    /// cost `cost`.
+    fn branch(&mut self, cursor: DateTime<Utc>, planned: u64, left: &[usize], cost: f64) {
+        if left.is_empty() {
+            if self.best.as_ref().map_or(true, |&(best, _)| cost < best) {
+                self.best = Some((cost, self.starts.clone()));
+            }
+            return;
+        }
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return;
+        }
+        let can_make_deadlines = left.iter().all(|&index| {
+            let task = &self.tasks[index];
+            max(cursor, super::earliest_start(self.start, task)) + task.duration <= task.deadline
+        });
+        if !can_make_deadlines {
+            return;
+        }
+        match self.seen.get(&(planned, cursor)) {
+            Some(&seen_cost) if seen_cost <= cost => return,
+            _ => {
+                self.seen.insert((planned, cursor), cost);
+            }
+        }
+        if let Some((best, _)) = self.best {
+            if cost + self.lower_bound(cursor, left) >= best {
+                return;
+            }
+        }
+
+        // Trying the tasks that are best to do first first finds a good order early on
+        let mut candidates = left.to_vec();
+        candidates.sort_by(|&first, &second| self.compare_ratios(first, second));
+        for &index in &candidates {
+            let task = &self.tasks[index];
+            let task_start = self.fit(max(cursor, super::earliest_start(self.start, task)), task);
+            let end = task_start + task.duration;
+            if task.deadline < end {
+                continue;
+            }
+            self.starts[index] = task_start;
+            let rest: Vec<usize> = left
+                .iter()
+                .cloned()
+                .filter(|&other| other != index)
+                .collect();
+            self.branch(
+                task_start + task.occupied(),
+                planned | 1 << index,
+                &rest,
+                cost + self.weights[index] * self.minutes_since_start(end),
+            );
+        }
+    }
+
+    /// Returns the cost of the tasks in `left` when they are done one after the other from
+    /// `cursor`, in the best order without any of their constraints, by Smith's rule. They can't
+    /// cost any less than that.
+    fn lower_bound(&self, cursor: DateTime<Utc>, left: &[usize]) -> f64 {
+        let mut left = left.to_vec();
+        left.sort_by(|&first, &second| self.compare_ratios(first, second));
+        let mut cursor = cursor;
+        let mut cost = 0.0;
+        for index in left {
+            let task = &self.tasks[index];
+            cost += self.weights[index] * self.minutes_since_start(cursor + task.duration);
+            cursor = cursor + task.occupied();
+        }
+        cost
+    }
+
+    /// Orders tasks by their weight per minute of time they take up, from high to low.
+    fn compare_ratios(&self, first: usize, second: usize) -> Ordering {
+        let occupied = |index: usize| self.tasks[index].occupied().num_seconds() as f64;
+        (self.weights[second] * occupied(first))
+            .partial_cmp(&(self.weights[first] * occupied(second)))
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Returns the first moment from `from` on where `task` fits outside of the unavailable
+    /// periods.
+    fn fit(&self, from: DateTime<Utc>, task: &Task) -> DateTime<Utc> {
+        let occupied = task.occupied();
+        let mut task_start = from;
+        for period in self.unavailable {
+            if period.end <= task_start {
+                continue;
+            }
+            if task_start + occupied <= period.start {
+                break;
+            }
+            task_start = period.end;
+        }
+        task_start
+    }
+
+    fn minutes_since_start(&self, moment: DateTime<Utc>) -> f64 {
+        moment.signed_duration_since(self.start).num_seconds() as f64 / 60.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::super::Schedule;
+    use super::*;
+    use crate::configuration::SchedulingStrategy;
+    use crate::task::fixtures::{monday, task};
+
+    fn order(schedule: &Schedule) -> Vec<u32> {
+        schedule
+            .0
+            .iter()
+            .map(|scheduled_task| scheduled_task.task.id)
+            .collect()
+    }
+
+    #[test]
+    fn short_tasks_go_before_slightly_more_important_long_ones() {
+        let deadline = monday() + Duration::hours(100);
+        let tasks = vec![
+            task(1, 10, deadline),
+            task(2, 1, deadline).with_importance(4),
+        ];
+        let importance =
+            Schedule::schedule(monday(), tasks.clone(), SchedulingStrategy::Importance).unwrap();
+        assert_eq!(order(&importance), vec![1, 2]);
+        let optimal = Schedule::schedule(monday(), tasks, SchedulingStrategy::Optimal).unwrap();
+        assert_eq!(order(&optimal), vec![2, 1]);
+        assert_eq!(optimal.0[0].when, monday() + Duration::minutes(1));
+        assert_eq!(optimal.0[1].when, optimal.0[0].when + Duration::hours(1));
+    }
+
+    #[test]
+    fn every_task_makes_its_deadline() {
+        let tasks = vec![
+            task(1, 1, monday() + Duration::hours(100)).with_importance(9),
+            task(2, 3, monday() + Duration::hours(5)).with_importance(1),
+            Task {
+                start_after: Some(monday() + Duration::hours(6)),
+                ..task(3, 2, monday() + Duration::hours(10))
+            },
+        ];
+        let unavailable = vec![monday() + Duration::hours(4)..monday() + Duration::hours(5)];
+        let schedule = Schedule::schedule_around(
+            monday(),
+            tasks,
+            SchedulingStrategy::Optimal,
+            &unavailable,
+            &Default::default(),
+            &Utc,
+        )
+        .unwrap();
+        // Task 2 would run into the unavailable hour before its deadline after task 1
+        assert_eq!(order(&schedule), vec![2, 1, 3]);
+        for scheduled_task in &schedule.0 {
+            let task = &scheduled_task.task;
+            assert!(scheduled_task.when + task.duration <= task.deadline);
+            assert!(task
+                .start_after
+                .map_or(true, |start_after| start_after <= scheduled_task.when));
+            let end = scheduled_task.when + task.duration;
+            assert!(end <= unavailable[0].start || unavailable[0].end <= scheduled_task.when);
+        }
+    }
+
+    #[test]
+    fn large_sets_of_tasks_are_scheduled_according_to_importance() {
+        let tasks: Vec<Task> = (0..MAX_TASKS as u32 + 1)
+            .map(|id| {
+                task(id, 1 + i64::from(id % 7), monday() + Duration::hours(1000))
+                    .with_importance(id % 10)
+            })
+            .collect();
+        let importance =
+            Schedule::schedule(monday(), tasks.clone(), SchedulingStrategy::Importance).unwrap();
+        let optimal = Schedule::schedule(monday(), tasks, SchedulingStrategy::Optimal).unwrap();
+        assert_eq!(optimal, importance);
+    }
+}
@@ -0,0 +1,179 @@
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::Task;
+
+use super::{Error, Result};
+
+/// How much time has to be spent on a task every week to finish it before its deadline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pace {
+    pub task: Task,
+    /// The time between the start and the deadline of the task, where the task starts when it
+    /// can be started.
+    pub window: Duration,
+    /// How much of the window is needed for the other tasks that are due before the task,
+    /// including their buffers.
+    pub committed: Duration,
+    /// The duration of the task and its buffer, spread evenly over every week of the window.
+    pub per_week: Duration,
+    /// The time that isn't needed for the other tasks, spread evenly over every week of the
+    /// window.
+    pub free_per_week: Duration,
+}
+
+impl Pace {
+    /// Returns whether there is enough free time to keep up the pace.
+    pub fn is_feasible(&self) -> bool {
+        self.per_week <= self.free_per_week
+    }
+
+    /// Calculates how many hours per week have to be dedicated to the task with id `task_id` from
+    /// `start` until its deadline, given that the other tasks that are due before it have to be
+    /// done in the meantime as well.
+    pub fn required<I>(start: DateTime<Utc>, tasks: I, task_id: u32) -> Result<Pace>
+    where
+        I: IntoIterator<Item = Task>,
+    {
+        let (tasks, others): (Vec<Task>, Vec<Task>) =
+            tasks.into_iter().partition(|task| task.id == task_id);
+        let task = tasks
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Internal(format!("there is no task with id {}", task_id)))?;
+        // How long after its deadline the task would be done, when it's started at `from`
+        let shortfall = |task: &Task, from: DateTime<Utc>| {
+            task.duration
+                .checked_sub(&task.deadline.signed_duration_since(from))
+                .ok_or_else(|| Error::Overflow { task: task.clone() })
+        };
+        if task.deadline <= start {
+            bail!(Error::DeadlineMissed {
+                shortfall: shortfall(&task, start)?,
+                task,
+                already_missed: true,
+            });
+        }
+        let task_start = super::earliest_start(start, &task);
+        if task.deadline <= task_start {
+            bail!(Error::StartsTooLate {
+                shortfall: shortfall(&task, task_start)?,
+                task,
+            });
+        }
+        let window = task.deadline.signed_duration_since(task_start);
+        let occupied = |task: &Task| {
+            task.duration_with_buffer()
+                .ok_or_else(|| Error::Overflow { task: task.clone() })
+        };
+        let committed = others
+            .iter()
+            .filter(|other| other.deadline <= task.deadline)
+            .try_fold(Duration::zero(), |total, other| {
+                total
+                    .checked_add(&occupied(other)?)
+                    .ok_or_else(|| Error::Overflow {
+                        task: other.clone(),
+                    })
+            })?;
+        Ok(Pace {
+            per_week: per_week(occupied(&task)?, window),
+            free_per_week: per_week(window - committed, window),
+            task,
+            window,
+            committed,
+        })
+    }
+}
+
+/// Spreads `duration` evenly over the weeks in `window`.
+fn per_week(duration: Duration, window: Duration) -> Duration {
+    // Calculate in milliseconds, with enough room to not overflow, and saturate when the pace is
+    // too fast to represent
+    let per_week = i128::from(duration.num_milliseconds())
+        * i128::from(Duration::weeks(1).num_milliseconds())
+        / i128::from(window.num_milliseconds());
+    let bound = i128::from(i64::max_value());
+    Duration::milliseconds(per_week.max(-bound).min(bound) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::task::fixtures::{monday, task};
+
+    #[test]
+    fn pace_is_spread_over_the_weeks_until_the_deadline() {
+        let tasks = vec![
+            task(1, 12, monday() + Duration::weeks(4)),
+            task(2, 10, monday() + Duration::weeks(1)),
+            task(3, 10, monday() + Duration::weeks(8)),
+        ];
+        let pace = Pace::required(monday(), tasks, 1).unwrap();
+        assert_eq!(pace.task.id, 1);
+        assert_eq!(pace.window, Duration::weeks(4));
+        assert_eq!(pace.per_week, Duration::hours(3));
+        // Task 2 has to be done before the deadline, task 3 can wait until after it
+        assert_eq!(pace.committed, Duration::hours(10));
+        assert_eq!(pace.free_per_week, Duration::hours(7 * 24) - Duration::minutes(150));
+        assert!(pace.is_feasible());
+    }
+
+    #[test]
+    fn pace_of_missed_deadline() {
+        let tasks = vec![task(1, 1, monday() - Duration::hours(1))];
+        assert_matches!(
+            Pace::required(monday(), tasks, 1),
+            Err(Error::DeadlineMissed {
+                already_missed: true,
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn pace_of_task_that_can_only_start_later() {
+        let mut tasks = vec![task(1, 2, monday() + Duration::weeks(3))];
+        tasks[0].start_after = Some(monday() + Duration::weeks(1));
+        let pace = Pace::required(monday(), tasks.clone(), 1).unwrap();
+        assert_eq!(pace.window, Duration::weeks(2));
+        assert_eq!(pace.per_week, Duration::hours(1));
+
+        tasks[0].start_after = Some(tasks[0].deadline);
+        assert_matches!(
+            Pace::required(monday(), tasks, 1),
+            Err(Error::StartsTooLate { .. })
+        );
+    }
+    #[test]
+    fn pace_of_absurd_durations() {
+        let mut tasks = vec![
+            task(1, 1, monday() + Duration::hours(1)),
+            Task {
+                duration: Duration::max_value(),
+                ..task(2, 0, monday() + Duration::hours(1))
+            },
+            task(3, 1, monday() + Duration::hours(1)),
+        ];
+        assert_matches!(
+            Pace::required(monday(), tasks.clone(), 1),
+            Err(Error::Overflow { .. })
+        );
+        tasks[1].buffer = Some(Duration::hours(1));
+        assert_matches!(
+            Pace::required(monday(), tasks.clone(), 2),
+            Err(Error::Overflow { ref task }) if task.id == 2
+        );
+
+        // A pace that is too fast to represent is as fast as it gets
+        let tasks = vec![Task {
+            duration: Duration::max_value() / 2,
+            ..task(1, 0, monday() + Duration::hours(1))
+        }];
+        let pace = Pace::required(monday(), tasks, 1).unwrap();
+        assert_eq!(pace.per_week, Duration::max_value());
+        assert!(!pace.is_feasible());
+    }
+}
@@ -0,0 +1,418 @@
+//! Properties that every schedule has, whatever the strategy, checked on task sets that are
+//! generated at random. The durations, deadlines and unavailable periods are whole quarters of an
+//! hour after the first moment something can be scheduled, so that tasks without a duration,
+//! tasks with the same deadline and deadlines right where an unavailable period starts or ends
+//! come up often. The schedules start on a Sunday evening, so that the daily minimums and the
+//! weekly budgets run into the next day and week.
+
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use proptest::prelude::*;
+
+use crate::configuration::{Budget, SchedulingStrategy, UrgencyCoefficients};
+use crate::util::monday_of;
+use crate::{Days, Task, Window};
+
+use super::{Error, Schedule, ScheduledTask, SCHEDULE_DELAY};
+
+const STRATEGIES: [SchedulingStrategy; 3] = [
+    SchedulingStrategy::Importance,
+    SchedulingStrategy::Urgency,
+    SchedulingStrategy::Optimal,
+];
+
+const TAGS: [&str; 2] = ["work", "chores"];
+
+/// The quarter of an hour at midnight, when Monday starts a new day and a new week.
+const MIDNIGHT: i64 = 24;
+
+/// Sunday evening, a minute before the first moment a task can be scheduled at 18:00.
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2019, 3, 10).and_hms(17, 59, 0)
+}
+
+/// The moment `quarters` quarters of an hour after the first moment a task can be scheduled.
+fn after(quarters: i64) -> DateTime<Utc> {
+    start() + *SCHEDULE_DELAY + Duration::minutes(15 * quarters)
+}
+
+/// Up to 6 tasks of at most 2 hours, with a deadline in the first 12 hours, some of which have a
+/// buffer, can't start right away, have a daily minimum of at most an hour, a preferred window or
+/// a tag. More tasks would make the optimal strategy too slow to check many task sets.
+fn tasks() -> impl Strategy<Value = Vec<Task>> {
+    let task = (
+        0i64..=8,
+        1u32..=10,
+        0i64..=48,
+        prop::option::of(0i64..=2),
+        prop::option::of(0i64..=48),
+        prop::option::of(1i64..=4),
+        prop::option::of(window()),
+        prop::option::of(prop::sample::select(TAGS.to_vec())),
+    );
+    prop::collection::vec(task, 0..=6).prop_map(|tasks| {
+        tasks
+            .into_iter()
+            .enumerate()
+            .map(|(id, task)| {
+                let (duration, importance, deadline, buffer, start_after, minimum, window, tag) =
+                    task;
+                Task {
+                    id: id as u32,
+                    content: format!("task {}", id),
+                    deadline: after(deadline),
+                    duration: Duration::minutes(15 * duration),
+                    importance,
+                    tags: tag.into_iter().map(str::to_owned).collect(),
+                    buffer: buffer.map(|buffer| Duration::minutes(15 * buffer)),
+                    start_after: start_after.map(after),
+                    daily_minimum: minimum.map(|minimum| Duration::minutes(15 * minimum)),
+                    preferred_window: window,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    })
+}
+
+/// Windows of at most 4 hours that start on a quarter of an hour, on any days, some of which stay
+/// open until midnight and some of which close the next day.
+fn window() -> impl Strategy<Value = Window> {
+    let days = prop::sample::select(vec![Days::Every, Days::Weekdays, Days::Weekends]);
+    (days, 0u32..96, prop::option::of(1u32..=16)).prop_map(|(days, from, length)| {
+        let time =
+            |quarters: u32| NaiveTime::from_num_seconds_from_midnight(quarters % 96 * 15 * 60, 0);
+        Window {
+            days,
+            from: time(from),
+            until: length.map(|length| time(from + length)),
+        }
+    })
+}
+
+/// A weekly maximum and minimum of at most 3 hours for some of the tags.
+fn budgets() -> impl Strategy<Value = HashMap<String, Budget>> {
+    let budget = (prop::option::of(1i64..=12), prop::option::of(1i64..=12));
+    prop::collection::vec(prop::option::of(budget), TAGS.len()).prop_map(|budgets| {
+        TAGS.iter()
+            .zip(budgets)
+            .filter_map(|(tag, budget)| {
+                let (maximum, minimum) = budget?;
+                let budget = Budget {
+                    maximum: maximum.map(|maximum| Duration::minutes(15 * maximum)),
+                    minimum: minimum.map(|minimum| Duration::minutes(15 * minimum)),
+                };
+                Some(((*tag).to_owned(), budget))
+            })
+            .collect()
+    })
+}
+
+/// Up to 3 unavailable periods of at most 2 hours, which can overlap or touch each other.
+fn unavailable() -> impl Strategy<Value = Vec<Range<DateTime<Utc>>>> {
+    let period = (0i64..=48, 1i64..=8);
+    prop::collection::vec(period, 0..=3).prop_map(|periods| {
+        periods
+            .into_iter()
+            .map(|(from, length)| after(from)..after(from + length))
+            .collect()
+    })
+}
+
+/// Returns the longest stretch of whole quarters of an hour in `period` outside of the
+/// `unavailable` periods.
+fn longest_free_stretch(
+    period: Range<DateTime<Utc>>,
+    unavailable: &[Range<DateTime<Utc>>],
+) -> Duration {
+    let mut longest = Duration::zero();
+    let mut stretch = Duration::zero();
+    let mut quarter = period.start;
+    while quarter < period.end {
+        let next = quarter + Duration::minutes(15);
+        if unavailable
+            .iter()
+            .any(|unavailable| unavailable.start < next && quarter < unavailable.end)
+        {
+            stretch = Duration::zero();
+        } else {
+            stretch = stretch + Duration::minutes(15);
+            longest = max(longest, stretch);
+        }
+        quarter = next;
+    }
+    longest
+}
+
+/// Checks that `schedule` has every one of the `tasks` in full, and nothing else, in one slot
+/// unless it has a daily minimum, and that the slots start after `start` and after the
+/// `start_after` of their task, end by their deadline and don't overlap with each other or with
+/// the `unavailable` periods. A buffer only has to stay clear of other slots. A task with a daily
+/// minimum gets it on the first day when it's due later, that day has the time for it and it
+/// doesn't wait for a budget, and no week has more planned for a tag than the maximum of its
+/// budget.
+fn check(
+    tasks: &[Task],
+    unavailable: &[Range<DateTime<Utc>>],
+    budgets: &HashMap<String, Budget>,
+    schedule: &Schedule,
+) -> std::result::Result<(), TestCaseError> {
+    for task in tasks {
+        let slots: Vec<&ScheduledTask> = schedule
+            .0
+            .iter()
+            .filter(|slot| slot.task.id == task.id)
+            .collect();
+        prop_assert!(
+            slots.len() == 1 || (task.daily_minimum.is_some() && !slots.is_empty()),
+            "task {} is scheduled {} times",
+            task.id,
+            slots.len()
+        );
+        let planned = slots.iter().fold(Duration::zero(), |planned, slot| {
+            planned + slot.task.duration
+        });
+        prop_assert_eq!(
+            planned,
+            task.duration,
+            "task {} isn't planned in full",
+            task.id
+        );
+        for slot in &slots {
+            let part = Task {
+                duration: task.duration,
+                ..slot.task.clone()
+            };
+            prop_assert_eq!(&part, task);
+        }
+        // A task that waits for the budget of its tag can miss its first day
+        let waits = task.tags.iter().any(|tag| {
+            budgets
+                .get(tag)
+                .map_or(false, |budget| budget.maximum.is_some())
+        });
+        if let (Some(minimum), false) = (task.daily_minimum, waits) {
+            let earliest = task
+                .start_after
+                .map_or(after(0), |start_after| max(after(0), start_after));
+            let first_day = earliest..after(MIDNIGHT);
+            let part = min(minimum, task.duration);
+            if after(MIDNIGHT) < task.deadline
+                && part <= longest_free_stretch(first_day.clone(), unavailable)
+            {
+                let on_first_day = slots
+                    .iter()
+                    .filter(|slot| slot.when + slot.task.duration <= first_day.end)
+                    .fold(Duration::zero(), |planned, slot| {
+                        planned + slot.task.duration
+                    });
+                prop_assert!(
+                    part <= on_first_day,
+                    "task {} doesn't get its daily minimum on the first day",
+                    task.id
+                );
+            }
+        }
+    }
+    for slot in &schedule.0 {
+        prop_assert!(
+            tasks.iter().any(|task| task.id == slot.task.id),
+            "task {} shouldn't be scheduled",
+            slot.task.id
+        );
+        let end = slot.when + slot.task.duration;
+        prop_assert!(
+            after(0) <= slot.when,
+            "task {} starts too soon",
+            slot.task.id
+        );
+        prop_assert!(
+            slot.task
+                .start_after
+                .map_or(true, |start_after| start_after <= slot.when),
+            "task {} starts before it can",
+            slot.task.id
+        );
+        prop_assert!(
+            end <= slot.task.deadline,
+            "task {} ends too late",
+            slot.task.id
+        );
+        for period in unavailable {
+            prop_assert!(
+                end <= period.start || period.end <= slot.when,
+                "task {} is scheduled in an unavailable period",
+                slot.task.id
+            );
+        }
+    }
+    for (index, slot) in schedule.0.iter().enumerate() {
+        for other in &schedule.0[index + 1..] {
+            let overlap = slot.when < other.when + other.task.occupied()
+                && other.when < slot.when + slot.task.occupied();
+            prop_assert!(
+                !overlap,
+                "tasks {} and {} overlap",
+                slot.task.id,
+                other.task.id
+            );
+        }
+    }
+    let mut planned: HashMap<(Date<Utc>, &str), Duration> = HashMap::new();
+    for slot in &schedule.0 {
+        for tag in &slot.task.tags {
+            let week = planned
+                .entry((monday_of(slot.when), tag.as_str()))
+                .or_insert_with(Duration::zero);
+            *week = *week + slot.task.duration;
+        }
+    }
+    for (&(week, tag), &planned) in &planned {
+        if let Some(maximum) = budgets.get(tag).and_then(|budget| budget.maximum) {
+            prop_assert!(
+                planned <= maximum,
+                "{} is planned for {} in the week of {}",
+                planned,
+                tag,
+                week
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Tells whether the slot of `task` at `when` is in `schedule`.
+fn has_slot(schedule: &Schedule, task: &Task, when: DateTime<Utc>) -> bool {
+    schedule
+        .0
+        .iter()
+        .any(|slot| slot.task == *task && slot.when == when)
+}
+
+proptest! {
+    #[test]
+    fn schedules_keep_to_the_deadlines_without_overlaps(
+        tasks in tasks(),
+        unavailable in unavailable(),
+    ) {
+        for &strategy in &STRATEGIES {
+            let schedule = Schedule::schedule_around(
+                start(),
+                tasks.clone(),
+                strategy,
+                &unavailable,
+                &UrgencyCoefficients::default(),
+                &Utc,
+            );
+            match &schedule {
+                Ok(schedule) => check(&tasks, &unavailable, &HashMap::new(), schedule)?,
+                Err(Error::Internal(more_info)) => {
+                    prop_assert!(false, "{:?} failed internally: {}", strategy, more_info)
+                }
+                // Not every task set that is generated can be scheduled
+                Err(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn schedules_keep_to_the_budgets_and_move_into_windows(
+        tasks in tasks(),
+        unavailable in unavailable(),
+        budgets in budgets(),
+    ) {
+        for &strategy in &STRATEGIES {
+            let schedule = Schedule::schedule_within_budgets(
+                start(),
+                tasks.clone(),
+                strategy,
+                &budgets,
+                &HashMap::new(),
+                &unavailable,
+                &UrgencyCoefficients::default(),
+                &Utc,
+            );
+            let mut schedule = match schedule {
+                Ok(schedule) => schedule,
+                Err(Error::Internal(more_info)) => {
+                    prop_assert!(false, "{:?} failed internally: {}", strategy, more_info);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            check(&tasks, &unavailable, &budgets, &schedule)?;
+            let before = schedule.clone();
+            schedule.keep_to_windows(start(), &unavailable, &Utc);
+            check(&tasks, &unavailable, &budgets, &schedule)?;
+            // Only the slots outside of their window move, and only into it
+            let in_window = |slot: &ScheduledTask| {
+                slot.task.preferred_window.map_or(true, |window| {
+                    window.contains(slot.when..slot.when + slot.task.duration, &Utc)
+                })
+            };
+            for slot in &before.0 {
+                prop_assert!(
+                    !in_window(slot) || has_slot(&schedule, &slot.task, slot.when),
+                    "task {} moves out of its window",
+                    slot.task.id
+                );
+            }
+            for slot in &schedule.0 {
+                prop_assert!(
+                    in_window(slot) || has_slot(&before, &slot.task, slot.when),
+                    "task {} moves somewhere else outside of its window",
+                    slot.task.id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn schedules_are_the_same_every_time(tasks in tasks(), unavailable in unavailable()) {
+        for &strategy in &STRATEGIES {
+            let schedule = |tasks: Vec<Task>| {
+                Schedule::schedule_around(
+                    start(),
+                    tasks,
+                    strategy,
+                    &unavailable,
+                    &UrgencyCoefficients::default(),
+                    &Utc,
+                )
+            };
+            prop_assert_eq!(schedule(tasks.clone()), schedule(tasks.clone()));
+        }
+    }
+
+    #[test]
+    fn tasks_that_fit_one_after_the_other_are_scheduled(
+        durations in prop::collection::vec(0i64..=8, 0..=6),
+        importances in prop::collection::vec(1u32..=10, 6),
+    ) {
+        // Every task has the same deadline, right when all of them are done.
+        let deadline = after(durations.iter().sum());
+        let tasks: Vec<Task> = durations
+            .iter()
+            .zip(&importances)
+            .enumerate()
+            .map(|(id, (&duration, &importance))| Task {
+                id: id as u32,
+                content: format!("task {}", id),
+                deadline,
+                duration: Duration::minutes(15 * duration),
+                importance,
+                ..Default::default()
+            })
+            .collect();
+        for &strategy in &STRATEGIES {
+            match Schedule::schedule(start(), tasks.clone(), strategy) {
+                Ok(schedule) => check(&tasks, &[], &HashMap::new(), &schedule)?,
+                Err(error) => prop_assert!(false, "{:?} failed: {}", strategy, error),
+            }
+        }
+    }
+}
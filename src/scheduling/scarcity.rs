@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::configuration::{LocalTime, SchedulingStrategy, UrgencyCoefficients};
+use crate::Task;
+
+use super::{Error, Result, Schedule};
+
+/// A schedule that only contains part of the tasks, because there wasn't enough time for all of
+/// them.
+#[derive(Debug)]
+pub struct FairSchedule {
+    pub schedule: Schedule,
+    /// How every project fared, one entry per project that had tasks to schedule.
+    pub shortfalls: Vec<Shortfall>,
+}
+
+/// How much time a project asked for and how much of it it got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortfall {
+    /// The tag of the project, or `None` for the tasks that don't belong to any weighted project.
+    pub project: Option<String>,
+    pub weight: u32,
+    /// The total duration of the project's tasks.
+    pub requested: Duration,
+    /// The total duration of the project's tasks that made it into the schedule.
+    pub allocated: Duration,
+    /// The tasks that were left out of the schedule.
+    pub dropped: Vec<Task>,
+}
+
+impl Shortfall {
+    /// Returns how much time this project was shorted.
+    pub fn shorted(&self) -> Duration {
+        self.requested - self.allocated
+    }
+}
+
+impl Schedule {
+    /// Schedules as many tasks as possible when there isn't enough time to do everything, sharing
+    /// the scarce time between projects according to their weights.
+    ///
+    /// A task belongs to the project of its first tag that appears in `weights`. Tasks without
+    /// such a tag together form one more project with weight 1.
+    ///
+    /// As long as the tasks can't be scheduled, the tasks that are due no later than the one that
+    /// couldn't be scheduled compete for the time before its deadline. A task is left out of the
+    /// project that has the most time in those tasks compared to its weight, and within that
+    /// project, the least important of them goes first. Tasks whose deadline can't be met at all
+    /// are always left out. The `unavailable` periods, the `urgency` coefficients and the
+    /// `local_time` are used like in `schedule_around`.
+    pub fn schedule_fairly<I>(
+        start: DateTime<Utc>,
+        tasks: I,
+        strategy: SchedulingStrategy,
+        weights: &HashMap<String, u32>,
+        unavailable: &[Range<DateTime<Utc>>],
+        urgency: &UrgencyCoefficients,
+        local_time: &LocalTime,
+    ) -> Result<FairSchedule>
+    where
+        I: IntoIterator<Item = Task>,
+    {
+        let mut kept: Vec<Task> = tasks.into_iter().collect();
+        let mut shortfalls: Vec<Shortfall> = vec![];
+        for task in &kept {
+            let project = project_of(task, weights);
+            let index = match position(&shortfalls, project) {
+                Some(index) => index,
+                None => {
+                    shortfalls.push(Shortfall {
+                        project: project.map(str::to_owned),
+                        weight: project.map_or(1, |project| weights[project]),
+                        requested: Duration::zero(),
+                        allocated: Duration::zero(),
+                        dropped: vec![],
+                    });
+                    shortfalls.len() - 1
+                }
+            };
+            let requested = shortfalls[index]
+                .requested
+                .checked_add(&task.duration)
+                .ok_or_else(|| Error::Overflow { task: task.clone() })?;
+            shortfalls[index].requested = requested;
+            shortfalls[index].allocated = requested;
+        }
+
+        let schedule = loop {
+            let attempt = Schedule::schedule_around(
+                start,
+                kept.clone(),
+                strategy,
+                unavailable,
+                urgency,
+                local_time,
+            );
+            let failed_task = match attempt {
+                Ok(schedule) => break schedule,
+                Err(Error::DeadlineMissed { task, .. })
+                | Err(Error::StartsTooLate { task, .. }) => {
+                    leave_out(&mut kept, &mut shortfalls, weights, |kept_task| *kept_task == task)?;
+                    continue;
+                }
+                Err(Error::NotEnoughTime { task, .. }) => task,
+                Err(error) => return Err(error),
+            };
+            // Only the tasks that are due at the same time or earlier compete for the time that
+            // was lacking.
+            let competes = |task: &Task| task.deadline <= failed_task.deadline;
+            let mut contested: Vec<(&Shortfall, Duration)> = vec![];
+            for shortfall in &shortfalls {
+                let mut competing = kept.iter().filter(|task| {
+                    competes(task) && project_of(task, weights) == project_name(shortfall)
+                });
+                let first = match competing.next() {
+                    Some(first) => first,
+                    None => continue,
+                };
+                let contested_time = competing.try_fold(first.duration, |total, task| {
+                    total
+                        .checked_add(&task.duration)
+                        .ok_or_else(|| Error::Overflow { task: task.clone() })
+                })?;
+                contested.push((shortfall, contested_time));
+            }
+            let project = contested
+                .into_iter()
+                .max_by(|&(shortfall, contested_time), &(other, other_contested_time)| {
+                    // Compare the contested time per weight without dividing by zero
+                    let share = contested_time.num_milliseconds() as i128 * other.weight as i128;
+                    let other_share =
+                        other_contested_time.num_milliseconds() as i128 * shortfall.weight as i128;
+                    share.cmp(&other_share)
+                })
+                .map(|(shortfall, _)| shortfall.project.clone())
+                .ok_or_else(|| Error::Internal("I couldn't find a task to leave out".to_owned()))?;
+            let least_important = kept
+                .iter()
+                .filter(|task| {
+                    competes(task) && project_of(task, weights) == project.as_ref().map(String::as_str)
+                })
+                .min_by_key(|task| (task.importance, start.signed_duration_since(task.deadline)))
+                .cloned()
+                .ok_or_else(|| Error::Internal("I couldn't find a task to leave out".to_owned()))?;
+            leave_out(&mut kept, &mut shortfalls, weights, |task| *task == least_important)?;
+        };
+        Ok(FairSchedule {
+            schedule,
+            shortfalls,
+        })
+    }
+}
+
+/// Returns the tag of the project `task` belongs to, if any.
+fn project_of<'a>(task: &'a Task, weights: &HashMap<String, u32>) -> Option<&'a str> {
+    task.tags
+        .iter()
+        .find(|tag| weights.contains_key(tag.as_str()))
+        .map(String::as_str)
+}
+
+fn project_name(shortfall: &Shortfall) -> Option<&str> {
+    shortfall.project.as_ref().map(String::as_str)
+}
+
+fn position(shortfalls: &[Shortfall], project: Option<&str>) -> Option<usize> {
+    shortfalls
+        .iter()
+        .position(|shortfall| project_name(shortfall) == project)
+}
+
+/// Moves the first kept task that matches `predicate` to the dropped tasks of its project.
+fn leave_out<P>(
+    kept: &mut Vec<Task>,
+    shortfalls: &mut [Shortfall],
+    weights: &HashMap<String, u32>,
+    predicate: P,
+) -> Result<()>
+where
+    P: Fn(&Task) -> bool,
+{
+    let task = match kept.iter().position(predicate) {
+        Some(position) => kept.remove(position),
+        None => bail!(Error::Internal("I couldn't leave out a task".to_owned())),
+    };
+    let index = position(shortfalls, project_of(&task, weights))
+        .ok_or_else(|| Error::Internal("I couldn't find the project of a task".to_owned()))?;
+    let shortfall = &mut shortfalls[index];
+    shortfall.allocated = shortfall.allocated - task.duration;
+    shortfall.dropped.push(task);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::fixtures::{monday, task};
+
+    fn weights(weights: &[(&str, u32)]) -> HashMap<String, u32> {
+        weights
+            .iter()
+            .map(|&(project, weight)| (project.to_owned(), weight))
+            .collect()
+    }
+
+    fn schedule_fairly(
+        tasks: Vec<Task>,
+        strategy: SchedulingStrategy,
+        weights: &HashMap<String, u32>,
+    ) -> FairSchedule {
+        Schedule::schedule_fairly(
+            monday(),
+            tasks,
+            strategy,
+            weights,
+            &[],
+            &Default::default(),
+            &Utc,
+        )
+            .unwrap()
+    }
+
+    fn shortfall<'a>(fair_schedule: &'a FairSchedule, project: &str) -> &'a Shortfall {
+        fair_schedule
+            .shortfalls
+            .iter()
+            .find(|shortfall| project_name(shortfall) == Some(project))
+            .unwrap()
+    }
+
+    #[test]
+    fn time_is_shared_according_to_weights() {
+        // 12 hours of work for both projects, but only a bit less than 9 hours of time
+        let mut tasks = vec![];
+        for id in 0..6 {
+            tasks.push(task(id, 2, monday() + Duration::hours(9)).with_tag("work"));
+            tasks.push(task(id + 10, 2, monday() + Duration::hours(9)).with_tag("home"));
+        }
+        tasks.push(
+            task(20, 2, monday() + Duration::hours(100))
+                .with_tag("home")
+                .with_importance(1),
+        );
+        for strategy in &[SchedulingStrategy::Importance, SchedulingStrategy::Urgency] {
+            let weights = weights(&[("work", 3), ("home", 1)]);
+            let fair_schedule = schedule_fairly(tasks.clone(), *strategy, &weights);
+            let work = shortfall(&fair_schedule, "work");
+            let home = shortfall(&fair_schedule, "home");
+            assert_eq!(work.requested, Duration::hours(12));
+            assert_eq!(work.allocated, Duration::hours(6));
+            assert_eq!(work.shorted(), Duration::hours(6));
+            assert_eq!(work.dropped.len(), 3);
+            // The task that is due later doesn't compete for the scarce time
+            assert_eq!(home.requested, Duration::hours(14));
+            assert_eq!(home.allocated, Duration::hours(4));
+            assert_eq!(home.dropped.len(), 5);
+            assert!(!home.dropped.iter().any(|task| task.id == 20));
+            assert_eq!(fair_schedule.schedule.0.len(), 5);
+        }
+    }
+
+    #[test]
+    fn nothing_is_dropped_when_there_is_enough_time() {
+        let tasks = vec![
+            task(0, 2, monday() + Duration::hours(10)).with_tag("work"),
+            task(1, 2, monday() + Duration::hours(10)).with_tag("other"),
+        ];
+        let weights = weights(&[("work", 1)]);
+        let fair_schedule = schedule_fairly(tasks, SchedulingStrategy::Importance, &weights);
+        assert_eq!(fair_schedule.schedule.0.len(), 2);
+        assert_eq!(fair_schedule.shortfalls.len(), 2);
+        for shortfall in &fair_schedule.shortfalls {
+            assert_eq!(shortfall.shorted(), Duration::zero());
+        }
+        assert!(fair_schedule
+            .shortfalls
+            .iter()
+            .any(|shortfall| shortfall.project.is_none() && shortfall.weight == 1));
+    }
+
+    #[test]
+    fn impossible_deadlines_are_dropped() {
+        let tasks = vec![
+            task(0, 2, monday() + Duration::hours(1)).with_tag("work"),
+            task(1, 2, monday() + Duration::hours(10)).with_tag("work"),
+        ];
+        let weights = weights(&[("work", 1)]);
+        let fair_schedule = schedule_fairly(tasks, SchedulingStrategy::Urgency, &weights);
+        assert_eq!(fair_schedule.schedule.0.len(), 1);
+        assert_eq!(shortfall(&fair_schedule, "work").dropped[0].id, 0);
+    }
+
+    #[test]
+    fn huge_durations_are_an_error() {
+        let deadline = monday() + Duration::hours(10);
+        let tasks = vec![
+            Task {
+                duration: Duration::max_value(),
+                ..task(0, 0, deadline).with_tag("work")
+            },
+            Task {
+                duration: Duration::max_value(),
+                ..task(1, 0, deadline).with_tag("work")
+            },
+        ];
+        let weights = weights(&[("work", 1)]);
+        let result = Schedule::schedule_fairly(
+            monday(),
+            tasks,
+            SchedulingStrategy::Importance,
+            &weights,
+            &[],
+            &Default::default(),
+            &Utc,
+        );
+        match result {
+            Err(Error::Overflow { task }) => assert_eq!(task.id, 1),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+}
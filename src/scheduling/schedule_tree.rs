@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::ops::{Add, Range, Sub};
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::util::WithSideEffects;
 
@@ -11,7 +11,7 @@ use crate::util::WithSideEffects;
 pub struct ScheduleTree<T, D: Eq + Hash> {
     root: Option<Node<T, D>>,
     scope: Option<Range<T>>,
-    data_map: HashMap<Rc<D>, T>,
+    data_map: HashMap<Arc<D>, T>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -19,7 +19,7 @@ pub enum Node<T, D> {
     Leaf {
         start: T,
         end: T,
-        data: Rc<D>,
+        data: Arc<D>,
     },
     Intermediate {
         free: Range<T>,
@@ -61,8 +61,8 @@ where
     where
         T: Add<W, Output = T>,
     {
-        let data = Rc::new(data);
-        self.schedule_exact_(start, duration, Rc::clone(&data))
+        let data = Arc::new(data);
+        self.schedule_exact_(start, duration, Arc::clone(&data))
             .with_side_effects(|start| self.update_map(start, data))
             .is_some()
     }
@@ -70,12 +70,12 @@ where
     /// See `schedule_exact` for details.
     ///
     /// Returns the start of the scheduling if it succeeded, otherwise None
-    fn schedule_exact_<W>(&mut self, start: T, duration: W, data: Rc<D>) -> Option<T>
+    fn schedule_exact_<W>(&mut self, start: T, duration: W, data: Arc<D>) -> Option<T>
     where
         T: Add<W, Output = T>,
     {
         let end = start + duration;
-        return_on_some!(self.try_schedule_trivial_cases(start, end, Rc::clone(&data)));
+        return_on_some!(self.try_schedule_trivial_cases(start, end, Arc::clone(&data)));
 
         self.root
             .as_mut()
@@ -98,8 +98,8 @@ where
         T: Add<W, Output = T> + Sub<W, Output = T>,
         W: Copy + Debug,
     {
-        let data = Rc::new(data);
-        self.schedule_close_before_(end, duration, min_start, Rc::clone(&data))
+        let data = Arc::new(data);
+        self.schedule_close_before_(end, duration, min_start, Arc::clone(&data))
             .with_side_effects(|start| self.update_map(start, data))
             .is_some()
     }
@@ -112,7 +112,7 @@ where
         end: T,
         duration: W,
         min_start: Option<T>,
-        data: Rc<D>,
+        data: Arc<D>,
     ) -> Option<T>
     where
         T: Add<W, Output = T> + Sub<W, Output = T>,
@@ -121,13 +121,13 @@ where
         assert!(min_start.map_or(true, |min_start| min_start + duration <= end));
 
         let optimal_start = end - duration;
-        return_on_some!(self.try_schedule_trivial_cases(optimal_start, end, Rc::clone(&data)));
+        return_on_some!(self.try_schedule_trivial_cases(optimal_start, end, Arc::clone(&data)));
 
         return_on_some!(self
             .root
             .as_mut()
             .expect("Internal error: root could not be taken as mut ref")
-            .insert_before(end, duration, min_start, Rc::clone(&data)));
+            .insert_before(end, duration, min_start, Arc::clone(&data)));
 
         // As last resort, try to schedule before current scope if min_start allows
         let scope = self
@@ -171,8 +171,8 @@ where
         T: Add<W, Output = T> + Sub<W, Output = T>,
         W: Copy + Debug,
     {
-        let data = Rc::new(data);
-        self.schedule_close_after_(start, duration, max_end, Rc::clone(&data))
+        let data = Arc::new(data);
+        self.schedule_close_after_(start, duration, max_end, Arc::clone(&data))
             .with_side_effects(|start| self.update_map(start, data))
             .is_some()
     }
@@ -185,7 +185,7 @@ where
         start: T,
         duration: W,
         max_end: Option<T>,
-        data: Rc<D>,
+        data: Arc<D>,
     ) -> Option<T>
     where
         T: Add<W, Output = T> + Sub<W, Output = T>,
@@ -194,13 +194,13 @@ where
         assert!(max_end.map_or(true, |max_end| start + duration <= max_end));
 
         let optimal_end = start + duration;
-        return_on_some!(self.try_schedule_trivial_cases(start, optimal_end, Rc::clone(&data)));
+        return_on_some!(self.try_schedule_trivial_cases(start, optimal_end, Arc::clone(&data)));
 
         return_on_some!(self
             .root
             .as_mut()
             .expect("Internal error: root could not be taken as mut ref")
-            .insert_after(start, duration, max_end, Rc::clone(&data)));
+            .insert_after(start, duration, max_end, Arc::clone(&data)));
 
         // As last resort, try to schedule after current scope if max_end allows
         let scope = self
@@ -235,7 +235,7 @@ where
     /// (c) the most optimal start and end fall completely after the right-most child in the tree
     ///
     /// Returns the start of the scheduling if it succeeded, otherwise None
-    fn try_schedule_trivial_cases(&mut self, start: T, end: T, data: Rc<D>) -> Option<T> {
+    fn try_schedule_trivial_cases(&mut self, start: T, end: T, data: Arc<D>) -> Option<T> {
         let new_node = Node::Leaf { start, end, data };
 
         match (self.root.take(), self.scope.take()) {
@@ -284,7 +284,7 @@ where
                     Some(Entry {
                         start,
                         end,
-                        data: Rc::try_unwrap(data).expect("Internal error: rc was not 1"),
+                        data: Arc::try_unwrap(data).expect("Internal error: rc was not 1"),
                     })
                 }
                 Node::Intermediate { .. } => {
@@ -293,7 +293,7 @@ where
                         Entry {
                             start: entry.start,
                             end: entry.end,
-                            data: Rc::try_unwrap(entry.data).expect("Internal error: rc was not 1"),
+                            data: Arc::try_unwrap(entry.data).expect("Internal error: rc was not 1"),
                         }
                     });
                     self.root = Some(root);
@@ -312,7 +312,7 @@ where
         self.data_map.remove(data)
     }
 
-    fn update_map(&mut self, start: T, data: Rc<D>) {
+    fn update_map(&mut self, start: T, data: Arc<D>) {
         let old_value = self.data_map.insert(data, start);
         if old_value.is_some() {
             panic!("Internal error: same data is being entered twice")
@@ -328,18 +328,20 @@ where
     /// Tries to insert a node with given `start`, `end` and `data` as a descendant of this node.
     ///
     /// Returns the start of the scheduling if it succeeded, otherwise None
-    fn insert(&mut self, start: T, end: T, data: Rc<D>) -> Option<T> {
+    fn insert(&mut self, start: T, end: T, data: Arc<D>) -> Option<T> {
         match self {
             Node::Leaf { .. } => None,
             Node::Intermediate { left, right, free } => {
-                if end <= free.start {
-                    left.insert(start, end, data)
-                } else if free.end <= start {
-                    right.insert(start, end, data)
-                } else if free.start <= start && end <= free.end {
+                // This comes first, so that something empty right where self.free starts or ends
+                // goes in it too
+                if free.start <= start && end <= free.end {
                     // [start, end] completely within self.free
                     unchecked_insert(start, end, data, right, free);
                     Some(start)
+                } else if end <= free.start {
+                    left.insert(start, end, data)
+                } else if free.end <= start {
+                    right.insert(start, end, data)
                 } else {
                     // Overlap between [start, end] and self.free
                     None
@@ -358,7 +360,7 @@ where
         end: T,
         duration: W,
         min_start: Option<T>,
-        data: Rc<D>,
+        data: Arc<D>,
     ) -> Option<T>
     where
         T: Sub<W, Output = T>,
@@ -369,14 +371,14 @@ where
             Node::Intermediate { left, right, free } => {
                 // If the end is inside the right child, try that first
                 if free.end < end {
-                    return_on_some!(right.insert_before(end, duration, min_start, Rc::clone(&data)))
+                    return_on_some!(right.insert_before(end, duration, min_start, Arc::clone(&data)))
                 }
                 // Second, try to insert it in the free range of the current node
                 let end = min(end, free.end);
                 if free.start <= end - duration
                     && min_start.map_or(true, |min_start| min_start <= end - duration)
                 {
-                    unchecked_insert(end - duration, end, Rc::clone(&data), right, free);
+                    unchecked_insert(end - duration, end, Arc::clone(&data), right, free);
                     return Some(end - duration);
                 }
 
@@ -400,7 +402,7 @@ where
         start: T,
         duration: W,
         max_end: Option<T>,
-        data: Rc<D>,
+        data: Arc<D>,
     ) -> Option<T>
     where
         T: Ord + Add<W, Output = T>,
@@ -411,7 +413,7 @@ where
             Node::Intermediate { left, right, free } => {
                 // If the start is inside the left child, try that first
                 if start < free.start {
-                    return_on_some!(left.insert_after(start, duration, max_end, Rc::clone(&data)))
+                    return_on_some!(left.insert_after(start, duration, max_end, Arc::clone(&data)))
                 }
                 // Second, try to insert it in the free range of the current node
                 let start = max(start, free.start);
@@ -435,7 +437,7 @@ where
     ///
     /// Returns None if that combination wasn't found, otherwise a tuple of an entry representing
     /// the unscheduled item and the new scope of this node.
-    fn unschedule<'a>(&mut self, start: T, data: &'a D) -> Option<(Entry<T, Rc<D>>, Range<T>)>
+    fn unschedule<'a>(&mut self, start: T, data: &'a D) -> Option<(Entry<T, Arc<D>>, Range<T>)>
     where
         D: PartialEq,
     {
@@ -533,7 +535,7 @@ where
 fn unchecked_insert<T, D>(
     start: T,
     end: T,
-    data: Rc<D>,
+    data: Arc<D>,
     right: &mut Node<T, D>,
     free: &mut Range<T>,
 ) where
@@ -569,7 +571,7 @@ pub struct Iter<'a, T: 'a, D: 'a> {
 #[derive(Debug)]
 pub struct IntoIter<T, D: Eq + Hash> {
     path: Vec<Node<T, D>>,
-    data_map: HashMap<Rc<D>, T>,
+    data_map: HashMap<Arc<D>, T>,
 }
 
 impl<'a, T, D> IntoIterator for &'a ScheduleTree<T, D>
@@ -639,7 +641,7 @@ where
             }
             if let Node::Leaf { start, end, data } = current {
                 self.data_map.remove(&data);
-                let data = Rc::try_unwrap(data).expect("Internal error: rc was more than 1");
+                let data = Arc::try_unwrap(data).expect("Internal error: rc was more than 1");
                 Some(Entry { start, end, data })
             } else {
                 None
@@ -718,7 +720,7 @@ mod tests {
         let scheduled = tree.schedule_exact(14, 2, &data[3]);
         assert!(!scheduled);
 
-        let scheduled = tree.schedule_exact(12, 0, &data[4]);
+        let scheduled = tree.schedule_exact(11, 0, &data[4]);
         assert!(!scheduled);
 
         let scheduled = tree.schedule_exact(9, 2, &data[5]);
@@ -765,6 +767,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_schedule_exact_empty() {
+        let data = generate_data(10);
+
+        let mut tree = ScheduleTree::new();
+        tree.schedule_exact(5, 4, &data[0]);
+        tree.schedule_exact(13, 5, &data[1]);
+
+        // Something empty fits right where the free time starts or ends, but not within an entry
+        assert!(tree.schedule_exact(9, 0, &data[2]));
+        assert!(tree.schedule_exact(13, 0, &data[3]));
+        assert!(!tree.schedule_exact(7, 0, &data[4]));
+        let entries: Vec<(i32, i32, i8)> = tree
+            .iter()
+            .map(|entry| (entry.start, entry.end, **entry.data))
+            .collect();
+        assert_eq!(entries, vec![(5, 9, 0), (9, 9, 2), (13, 13, 3), (13, 18, 1)]);
+    }
+
     #[test]
     fn test_schedule_close_before() {
         let data = generate_data(10);
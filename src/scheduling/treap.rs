@@ -0,0 +1,270 @@
+use std::cmp::{max, min, Ordering};
+
+/// An ordered map that is kept balanced by giving its nodes random priorities, where every node
+/// also knows the smallest and the largest value in its subtree. This allows asking for keys by
+/// their value in logarithmic time.
+#[derive(Debug)]
+pub struct Treap<K, V> {
+    root: Option<Box<Node<K, V>>>,
+    seed: u64,
+}
+
+#[derive(Debug)]
+struct Node<K, V> {
+    key: K,
+    value: V,
+    priority: u64,
+    smallest_value: V,
+    largest_value: V,
+    left: Option<Box<Node<K, V>>>,
+    right: Option<Box<Node<K, V>>>,
+}
+
+impl<K, V> Treap<K, V>
+where
+    K: Copy + Ord,
+    V: Copy + Ord,
+{
+    /// Returns an empty treap.
+    pub fn new() -> Self {
+        Treap {
+            root: None,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Inserts `key` with the given `value`. The key should not be in the treap yet.
+    pub fn insert(&mut self, key: K, value: V) {
+        let node = Box::new(Node {
+            key,
+            value,
+            priority: self.next_priority(),
+            smallest_value: value,
+            largest_value: value,
+            left: None,
+            right: None,
+        });
+        let (before, after) = split(self.root.take(), key);
+        self.root = merge(merge(before, Some(node)), after);
+    }
+
+    /// Removes `key` from the treap, returning its value if it was in there.
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        let (before, after) = split(self.root.take(), key);
+        let (first, after) = match after {
+            Some(after) => pop_first(after),
+            None => (None, None),
+        };
+        let value = match first {
+            Some(first) if first.key == key => Some(first.value),
+            Some(first) => {
+                self.root = merge(before, merge(Some(first), after));
+                return None;
+            }
+            None => None,
+        };
+        self.root = merge(before, after);
+        value
+    }
+
+    /// Removes and returns all keys from `from` onwards whose value is at most `max_value`.
+    pub fn drain_at_most(&mut self, from: K, max_value: V) -> Vec<K> {
+        let (before, after) = split(self.root.take(), from);
+        let mut keys = vec![];
+        let after = drain_at_most(after, max_value, &mut keys);
+        self.root = merge(before, after);
+        keys
+    }
+
+    /// Returns the smallest key from `from` onwards whose value is at least `min_value`.
+    pub fn first_at_least_from(&self, from: K, min_value: V) -> Option<(K, V)> {
+        first_at_least_from(&self.root, from, min_value)
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        // xorshift64*
+        self.seed ^= self.seed >> 12;
+        self.seed ^= self.seed << 25;
+        self.seed ^= self.seed >> 27;
+        self.seed.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+impl<K, V: Copy + Ord> Node<K, V> {
+    /// Recalculates the smallest and largest value of this subtree from its children.
+    fn update(&mut self) {
+        let (mut smallest_value, mut largest_value) = (self.value, self.value);
+        if let Some(left) = &self.left {
+            smallest_value = min(smallest_value, left.smallest_value);
+            largest_value = max(largest_value, left.largest_value);
+        }
+        if let Some(right) = &self.right {
+            smallest_value = min(smallest_value, right.smallest_value);
+            largest_value = max(largest_value, right.largest_value);
+        }
+        self.smallest_value = smallest_value;
+        self.largest_value = largest_value;
+    }
+}
+
+type Link<K, V> = Option<Box<Node<K, V>>>;
+
+/// Splits a treap in the nodes with a key smaller than `key` and the other ones.
+fn split<K: Ord, V: Copy + Ord>(node: Link<K, V>, key: K) -> (Link<K, V>, Link<K, V>) {
+    match node {
+        None => (None, None),
+        Some(mut node) => match node.key.cmp(&key) {
+            Ordering::Less => {
+                let (before, after) = split(node.right.take(), key);
+                node.right = before;
+                node.update();
+                (Some(node), after)
+            }
+            _ => {
+                let (before, after) = split(node.left.take(), key);
+                node.left = after;
+                node.update();
+                (before, Some(node))
+            }
+        },
+    }
+}
+
+/// Merges two treaps, where all keys of `left` are smaller than the ones of `right`.
+fn merge<K, V: Copy + Ord>(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// Takes the node with the smallest key out of a treap, returning it and the rest of the treap.
+fn pop_first<K, V: Copy + Ord>(mut node: Box<Node<K, V>>) -> (Link<K, V>, Link<K, V>) {
+    match node.left.take() {
+        None => {
+            let rest = node.right.take();
+            node.update();
+            (Some(node), rest)
+        }
+        Some(left) => {
+            let (first, rest) = pop_first(left);
+            node.left = rest;
+            node.update();
+            (first, Some(node))
+        }
+    }
+}
+
+fn first_at_least_from<K: Copy + Ord, V: Copy + Ord>(
+    node: &Link<K, V>,
+    from: K,
+    min_value: V,
+) -> Option<(K, V)> {
+    let node = match node {
+        Some(node) if min_value <= node.largest_value => node,
+        _ => return None,
+    };
+    if node.key < from {
+        return first_at_least_from(&node.right, from, min_value);
+    }
+    first_at_least_from(&node.left, from, min_value)
+        .or_else(|| {
+            if min_value <= node.value {
+                Some((node.key, node.value))
+            } else {
+                None
+            }
+        })
+        .or_else(|| first_at_least_from(&node.right, from, min_value))
+}
+
+/// Removes all nodes whose value is at most `max_value` from a treap, collecting their keys.
+fn drain_at_most<K, V: Copy + Ord>(node: Link<K, V>, max_value: V, keys: &mut Vec<K>) -> Link<K, V> {
+    let mut node = match node {
+        Some(node) => node,
+        None => return None,
+    };
+    if max_value < node.smallest_value {
+        return Some(node);
+    }
+    let left = drain_at_most(node.left.take(), max_value, keys);
+    let right = drain_at_most(node.right.take(), max_value, keys);
+    if node.value <= max_value {
+        keys.push(node.key);
+        merge(left, right)
+    } else {
+        node.left = left;
+        node.right = right;
+        node.update();
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut treap = Treap::new();
+        for key in 0..100 {
+            treap.insert(key, key % 7);
+        }
+        assert_eq!(treap.remove(42), Some(0));
+        assert_eq!(treap.remove(42), None);
+        assert_eq!(treap.remove(100), None);
+        assert_eq!(treap.remove(43), Some(1));
+        for key in (0..100).filter(|key| *key != 42 && *key != 43) {
+            assert_eq!(treap.remove(key), Some(key % 7));
+        }
+        assert!(treap.root.is_none());
+    }
+
+    #[test]
+    fn test_drain_at_most() {
+        let mut treap = Treap::new();
+        for key in 0..20 {
+            treap.insert(key, key % 5);
+        }
+        let mut keys = treap.drain_at_most(10, 1);
+        keys.sort();
+        assert_eq!(keys, vec![10, 11, 15, 16]);
+        // Drained keys are not returned twice
+        let mut keys = treap.drain_at_most(9, 2);
+        keys.sort();
+        assert_eq!(keys, vec![12, 17]);
+        let mut keys = treap.drain_at_most(0, 10);
+        keys.sort();
+        assert_eq!(keys, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 13, 14, 18, 19]);
+        assert!(treap.drain_at_most(0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_first_at_least_from() {
+        let mut treap = Treap::new();
+        assert_eq!(treap.first_at_least_from(0, 0), None);
+        for key in 0..20 {
+            treap.insert(key, (key * 7) % 20);
+        }
+        assert_eq!(treap.first_at_least_from(0, 0), Some((0, 0)));
+        assert_eq!(treap.first_at_least_from(0, 10), Some((2, 14)));
+        assert_eq!(treap.first_at_least_from(0, 19), Some((17, 19)));
+        assert_eq!(treap.first_at_least_from(0, 20), None);
+        assert_eq!(treap.first_at_least_from(3, 10), Some((5, 15)));
+        assert_eq!(treap.first_at_least_from(18, 10), Some((19, 13)));
+        assert_eq!(treap.first_at_least_from(18, 14), None);
+        treap.remove(2);
+        assert_eq!(treap.first_at_least_from(0, 10), Some((5, 15)));
+    }
+}
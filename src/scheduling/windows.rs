@@ -0,0 +1,243 @@
+use std::cmp::{min, Reverse};
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::configuration::LocalTime;
+use crate::util::monday_of;
+
+use super::{Constraint, Schedule, SCHEDULE_DELAY};
+
+impl Schedule {
+    /// Moves the slots of tasks that aren't in their preferred window to the first opening of
+    /// that window where they fit, with the windows in `local_time`. More important tasks get
+    /// their windows first.
+    ///
+    /// The windows are a soft constraint: a slot only moves into time that is free, no sooner
+    /// than the strategies schedule anything after `start` and outside of the `unavailable`
+    /// periods, where its task can be started and still ends by its deadline. It also stays in its
+    /// week, so that the weekly budgets still hold, and a part of a task with a daily minimum
+    /// stays on its day. A slot for which there is no such time stays where it is, outside of its
+    /// window.
+    ///
+    /// A slot that moves records what keeps it from starting any sooner in its window, and the
+    /// slots that it held back aren't held back by it anymore.
+    pub fn keep_to_windows(
+        &mut self,
+        start: DateTime<Utc>,
+        unavailable: &[Range<DateTime<Utc>>],
+        local_time: &LocalTime,
+    ) {
+        let start = start + *SCHEDULE_DELAY;
+        let mut outside: Vec<usize> = (0..self.0.len())
+            .filter(|&index| {
+                let slot = &self.0[index];
+                slot.task.preferred_window.map_or(false, |window| {
+                    !window.contains(slot.when..slot.when + slot.task.duration, local_time)
+                })
+            })
+            .collect();
+        outside.sort_by_key(|&index| (Reverse(self.0[index].task.importance), self.0[index].when));
+        for index in outside {
+            if let Some((when, held_back_by)) =
+                self.fit_in_window(index, start, unavailable, local_time)
+            {
+                let moved = &self.0[index];
+                let (id, old_end) = (moved.task.id, moved.when + moved.task.occupied());
+                for slot in &mut self.0 {
+                    match slot.held_back_by {
+                        Some(Constraint::TaskAhead(ref ahead))
+                            if ahead.id == id && slot.when == old_end =>
+                        {
+                            slot.held_back_by = None;
+                        }
+                        _ => (),
+                    }
+                }
+                self.0[index].when = when;
+                self.0[index].held_back_by = held_back_by;
+            }
+        }
+        self.0.sort_by_key(|slot| slot.when);
+    }
+
+    /// Returns the earliest moment in the window of the slot at `index` where it fits, if any,
+    /// with what keeps it from starting any sooner there.
+    fn fit_in_window(
+        &self,
+        index: usize,
+        start: DateTime<Utc>,
+        unavailable: &[Range<DateTime<Utc>>],
+        local_time: &LocalTime,
+    ) -> Option<(DateTime<Utc>, Option<Constraint>)> {
+        let slot = &self.0[index];
+        let task = &slot.task;
+        let window = task.preferred_window?;
+        let (earliest, latest_end, bound) = if task.daily_minimum.is_some() {
+            let day = local_time.local(slot.when).date();
+            (
+                local_time.moment_at(day.and_hms(0, 0, 0)),
+                local_time.moment_at(day.succ().and_hms(0, 0, 0)),
+                Some(Constraint::DailyMinimum),
+            )
+        } else {
+            let monday = monday_of(slot.when);
+            (
+                monday.and_hms(0, 0, 0),
+                (monday + Duration::weeks(1)).and_hms(0, 0, 0),
+                None,
+            )
+        };
+        let task_earliest = super::earliest_start(start, task);
+        let (earliest, bound) = if earliest < task_earliest {
+            let start_after = task
+                .start_after
+                .filter(|&start_after| start < start_after && start_after == task_earliest);
+            (task_earliest, start_after.map(Constraint::StartAfter))
+        } else {
+            (earliest, bound)
+        };
+        let latest_end = min(latest_end, task.deadline);
+        if latest_end <= earliest {
+            return None;
+        }
+        let mut occupied: Vec<(Range<DateTime<Utc>>, Constraint)> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|&(other_index, _)| other_index != index)
+            .map(|(_, other)| {
+                let period = other.when..other.when + other.task.occupied();
+                (period, Constraint::TaskAhead(other.task.clone()))
+            })
+            .chain(
+                unavailable
+                    .iter()
+                    .map(|period| (period.clone(), Constraint::Unavailable(period.clone()))),
+            )
+            .collect();
+        occupied.sort_by_key(|(period, _)| period.start);
+        for opening in window.openings(earliest..latest_end, local_time) {
+            let (mut when, mut held_back_by) = if earliest < opening.start {
+                (opening.start, Some(Constraint::Window(window)))
+            } else {
+                (earliest, bound.clone())
+            };
+            // Skip past everything the slot would overlap with, which only ever moves it later
+            while let Some((period, constraint)) = occupied
+                .iter()
+                .find(|(period, _)| when < period.end && period.start < when + task.occupied())
+            {
+                when = period.end;
+                held_back_by = Some(constraint.clone());
+            }
+            let end = when + task.duration;
+            // A task without a duration is only in the window before it closes
+            if when < opening.end && end <= opening.end && end <= latest_end {
+                return Some((when, held_back_by));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::SchedulingStrategy;
+    use crate::task::fixtures::{monday, task};
+    use crate::task::{Days, Window};
+    use crate::Task;
+
+    fn evenings() -> Option<Window> {
+        Some(Window {
+            days: Days::Every,
+            from: NaiveTime::from_hms(18, 0, 0),
+            until: Some(NaiveTime::from_hms(21, 0, 0)),
+        })
+    }
+
+    #[test]
+    fn slots_move_to_the_first_opening_where_they_fit() {
+        let deadline = monday() + Duration::days(4);
+        let tasks = vec![
+            Task {
+                preferred_window: evenings(),
+                ..task(1, 2, deadline).with_importance(9)
+            },
+            task(2, 3, deadline),
+        ];
+        let mut schedule =
+            Schedule::schedule(monday(), tasks.clone(), SchedulingStrategy::Importance).unwrap();
+        assert_eq!(schedule.0[0].task.id, 1);
+        assert_eq!(
+            schedule.0[1].held_back_by,
+            Some(Constraint::TaskAhead(tasks[0].clone()))
+        );
+        schedule.keep_to_windows(monday(), &[], &Utc);
+        assert_eq!(schedule.0[0].task, tasks[1]);
+        assert_eq!(schedule.0[1].task, tasks[0]);
+        assert_eq!(schedule.0[1].when, monday().date().and_hms(18, 0, 0));
+        // The moved slot waits for its window, and no longer holds back the slot it was ahead of
+        assert_eq!(
+            schedule.0[1].held_back_by,
+            Some(Constraint::Window(evenings().unwrap()))
+        );
+        assert_eq!(schedule.0[0].held_back_by, None);
+
+        // Without time in the window before the deadline, the slot stays where it was
+        let tasks = vec![Task {
+            deadline: monday() + Duration::hours(4),
+            ..tasks[0].clone()
+        }];
+        let mut schedule =
+            Schedule::schedule(monday(), tasks, SchedulingStrategy::Urgency).unwrap();
+        let unchanged = schedule.clone();
+        schedule.keep_to_windows(monday(), &[], &Utc);
+        assert_eq!(schedule, unchanged);
+    }
+
+    #[test]
+    fn more_important_tasks_get_their_window_first() {
+        let deadline = monday() + Duration::days(4);
+        let tasks = vec![
+            Task {
+                preferred_window: evenings(),
+                ..task(1, 2, deadline).with_importance(3)
+            },
+            Task {
+                preferred_window: evenings(),
+                ..task(2, 2, deadline).with_importance(8)
+            },
+        ];
+        let mut schedule =
+            Schedule::schedule(monday(), tasks, SchedulingStrategy::Importance).unwrap();
+        let evening = monday().date().and_hms(18, 0, 0);
+        // Tuesday evening is unavailable
+        let unavailable = vec![evening + Duration::days(1)..evening + Duration::days(2)];
+        schedule.keep_to_windows(monday(), &unavailable, &Utc);
+        let slot_of = |id| schedule.0.iter().find(|slot| slot.task.id == id).unwrap();
+        assert_eq!(slot_of(2).when, evening);
+        assert_eq!(slot_of(1).when, evening + Duration::days(2));
+        assert_eq!(
+            slot_of(1).held_back_by,
+            Some(Constraint::Window(evenings().unwrap()))
+        );
+
+        // A slot that has to skip past something in its window is held back by that
+        let tasks = vec![Task {
+            preferred_window: evenings(),
+            ..task(1, 2, deadline)
+        }];
+        let mut schedule =
+            Schedule::schedule(monday(), tasks, SchedulingStrategy::Importance).unwrap();
+        let unavailable = vec![evening..evening + Duration::minutes(30)];
+        schedule.keep_to_windows(monday(), &unavailable, &Utc);
+        assert_eq!(schedule.0[0].when, evening + Duration::minutes(30));
+        assert_eq!(
+            schedule.0[0].held_back_by,
+            Some(Constraint::Unavailable(unavailable[0].clone()))
+        );
+    }
+}
@@ -0,0 +1,590 @@
+//! Reports on how the plans worked out: how much was planned and done per week, how good the
+//! estimates were and how many tasks were finished in time. `Insights` looks for patterns in a
+//! month of that history, to plan the next ones better.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::configuration::Budget;
+use crate::util::monday_of;
+use crate::{Schedule, Task};
+
+/// A copy of a schedule as it was accepted, kept to compare the plan with what was done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub taken: DateTime<Utc>,
+    pub planned: Vec<PlannedTask>,
+}
+
+/// A task as it was planned in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedTask {
+    pub task_id: u32,
+    pub when: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+impl Snapshot {
+    pub fn of(taken: DateTime<Utc>, schedule: &Schedule) -> Snapshot {
+        let planned = schedule
+            .0
+            .iter()
+            .map(|scheduled_task| PlannedTask {
+                task_id: scheduled_task.task.id,
+                when: scheduled_task.when,
+                duration: scheduled_task.task.duration,
+            })
+            .collect();
+        Snapshot { taken, planned }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statistics {
+    /// The weeks in which something was planned or done, in chronological order.
+    pub weeks: Vec<Week>,
+    /// How many tasks were completed, by tag.
+    pub completed_per_tag: BTreeMap<String, usize>,
+    pub completed: usize,
+    /// How many of the completed tasks were completed before their deadline.
+    pub completed_on_time: usize,
+    /// The total duration of the completed tasks for which the time spent was logged.
+    pub estimated: Duration,
+    /// The total time spent on those same tasks.
+    pub spent: Duration,
+}
+
+/// The hours planned and done in a week, which runs from Monday to Sunday in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Week {
+    pub monday: Date<Utc>,
+    pub planned: Duration,
+    pub completed: Duration,
+}
+
+impl Statistics {
+    /// Gathers statistics about `completed_tasks`, comparing them with the plans in `snapshots`.
+    ///
+    /// A planned task counts in the week it was planned in according to the last snapshot that
+    /// was taken before it, so a plan that was replaced before it came due doesn't count. The
+    /// time of a completed task is the time spent on it if that was logged, or its duration
+    /// otherwise.
+    pub fn compute(completed_tasks: &[Task], snapshots: &[Snapshot]) -> Statistics {
+        let mut weeks: BTreeMap<Date<Utc>, Week> = BTreeMap::new();
+
+        for planned_task in due_plans(snapshots) {
+            let week = week_of(&mut weeks, planned_task.when);
+            week.planned = week.planned + planned_task.duration;
+        }
+
+        let mut statistics = Statistics {
+            weeks: vec![],
+            completed_per_tag: BTreeMap::new(),
+            completed: 0,
+            completed_on_time: 0,
+            estimated: Duration::zero(),
+            spent: Duration::zero(),
+        };
+        for task in completed_tasks {
+            let completed = match task.completed {
+                Some(completed) => completed,
+                None => continue,
+            };
+            let week = week_of(&mut weeks, completed);
+            week.completed = week.completed + task.time_spent.unwrap_or(task.duration);
+            statistics.completed += 1;
+            if completed <= task.deadline {
+                statistics.completed_on_time += 1;
+            }
+            for tag in &task.tags {
+                *statistics.completed_per_tag.entry(tag.clone()).or_insert(0) += 1;
+            }
+            if let Some(time_spent) = task.time_spent {
+                statistics.estimated = statistics.estimated + task.duration;
+                statistics.spent = statistics.spent + time_spent;
+            }
+        }
+        statistics.weeks = weeks.into_iter().map(|(_, week)| week).collect();
+        statistics
+    }
+
+    /// Returns the fraction of the completed tasks that were completed in time, if any were.
+    pub fn on_time_rate(&self) -> Option<f64> {
+        if self.completed == 0 {
+            None
+        } else {
+            Some(self.completed_on_time as f64 / self.completed as f64)
+        }
+    }
+
+    /// Returns how much time was spent on tasks compared to their estimated duration, if any time
+    /// was logged. More than 1 means that the tasks took longer than estimated.
+    pub fn estimate_ratio(&self) -> Option<f64> {
+        if self.estimated == Duration::zero() {
+            None
+        } else {
+            Some(self.spent.num_seconds() as f64 / self.estimated.num_seconds() as f64)
+        }
+    }
+}
+
+/// The most pending tasks that `Insights` lists as slipping often.
+pub const MOST_SLIPPED: usize = 5;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Patterns in the history of a month: on which days of the week more is planned than done,
+/// which tags are estimated worst and how often tasks slip. Like the statistics, they are found
+/// in what is stored locally, and days run from midnight to midnight in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Insights {
+    /// The first day of the month.
+    pub month: Date<Utc>,
+    /// How much was planned and done on every day of the week, from Monday to Sunday.
+    pub workloads: Vec<Workload>,
+    /// How long the tasks with a tag that were completed in the month took compared to their
+    /// estimates, for the tasks for which the time spent was logged. The worst estimated tags
+    /// come first, whether they took longer or shorter than estimated.
+    pub estimates: Vec<TagEstimate>,
+    /// How many tasks were completed in the month.
+    pub completed: usize,
+    /// How many times those tasks slipped in total before they were completed.
+    pub slipped: u32,
+    /// The pending tasks that slipped most often, at most `MOST_SLIPPED` of them, the most often
+    /// slipped first.
+    pub most_slipped: Vec<Task>,
+}
+
+/// How much was planned and done on one day of the week during a month.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workload {
+    pub weekday: Weekday,
+    /// On how many of these days something was planned.
+    pub days: usize,
+    /// On how many of those days less was done than planned.
+    pub overcommitted: usize,
+    pub planned: Duration,
+    pub completed: Duration,
+}
+
+/// How long the completed tasks with a tag took compared to their estimates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagEstimate {
+    pub tag: String,
+    /// How many completed tasks with the tag had their time spent logged.
+    pub tasks: usize,
+    /// The total duration of those tasks.
+    pub estimated: Duration,
+    /// The total time spent on them.
+    pub spent: Duration,
+}
+
+impl Insights {
+    /// Finds patterns in the history of the month that `month` falls in, from the tasks that were
+    /// completed in it and the plans for it in `snapshots`. What was planned for a day follows the
+    /// same rules as in `Statistics::compute`. The `pending_tasks` tell which tasks keep slipping.
+    pub fn compute(
+        month: Date<Utc>,
+        completed_tasks: &[Task],
+        pending_tasks: &[Task],
+        snapshots: &[Snapshot],
+    ) -> Insights {
+        let month = month.with_day(1).unwrap();
+        let next_month = if month.month() == 12 {
+            Utc.ymd(month.year() + 1, 1, 1)
+        } else {
+            Utc.ymd(month.year(), month.month() + 1, 1)
+        };
+        let in_month = |moment: DateTime<Utc>| month <= moment.date() && moment.date() < next_month;
+        let completed_tasks: Vec<&Task> = completed_tasks
+            .iter()
+            .filter(|task| task.completed.map_or(false, in_month))
+            .collect();
+
+        // What was planned and what was done, by day
+        let mut days: BTreeMap<Date<Utc>, (Duration, Duration)> = BTreeMap::new();
+        for planned_task in due_plans(snapshots) {
+            if in_month(planned_task.when) {
+                let (planned, _) = day_of(&mut days, planned_task.when.date());
+                *planned = *planned + planned_task.duration;
+            }
+        }
+        for task in &completed_tasks {
+            let (_, completed) = day_of(&mut days, task.completed.unwrap().date());
+            *completed = *completed + task.time_spent.unwrap_or(task.duration);
+        }
+        let mut workloads: Vec<Workload> = WEEKDAYS
+            .iter()
+            .map(|&weekday| Workload {
+                weekday,
+                days: 0,
+                overcommitted: 0,
+                planned: Duration::zero(),
+                completed: Duration::zero(),
+            })
+            .collect();
+        for (date, (planned, completed)) in days {
+            let workload = &mut workloads[date.weekday().num_days_from_monday() as usize];
+            if planned > Duration::zero() {
+                workload.days += 1;
+                if completed < planned {
+                    workload.overcommitted += 1;
+                }
+            }
+            workload.planned = workload.planned + planned;
+            workload.completed = workload.completed + completed;
+        }
+
+        let mut estimates: HashMap<&str, TagEstimate> = HashMap::new();
+        for task in &completed_tasks {
+            let time_spent = match task.time_spent {
+                Some(time_spent) if task.duration > Duration::zero() => time_spent,
+                _ => continue,
+            };
+            for tag in &task.tags {
+                let estimate = estimates.entry(tag).or_insert_with(|| TagEstimate {
+                    tag: tag.clone(),
+                    tasks: 0,
+                    estimated: Duration::zero(),
+                    spent: Duration::zero(),
+                });
+                estimate.tasks += 1;
+                estimate.estimated = estimate.estimated + task.duration;
+                estimate.spent = estimate.spent + time_spent;
+            }
+        }
+        let mut estimates: Vec<TagEstimate> = estimates
+            .into_iter()
+            .map(|(_, estimate)| estimate)
+            .collect();
+        // Taking twice as long is as far off as taking half as long
+        let error = |estimate: &TagEstimate| estimate.ratio().ln().abs();
+        estimates.sort_by(|estimate, other| {
+            error(other)
+                .partial_cmp(&error(estimate))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| estimate.tag.cmp(&other.tag))
+        });
+
+        let mut most_slipped: Vec<Task> = pending_tasks
+            .iter()
+            .filter(|task| task.slipped > 0)
+            .cloned()
+            .collect();
+        most_slipped.sort_by_key(|task| Reverse(task.slipped));
+        most_slipped.truncate(MOST_SLIPPED);
+
+        Insights {
+            month,
+            workloads,
+            estimates,
+            completed: completed_tasks.len(),
+            slipped: completed_tasks.iter().map(|task| task.slipped).sum(),
+            most_slipped,
+        }
+    }
+
+    /// Returns how many times the tasks that were completed in the month slipped on average, if
+    /// any were completed.
+    pub fn average_slips(&self) -> Option<f64> {
+        if self.completed == 0 {
+            None
+        } else {
+            Some(f64::from(self.slipped) / self.completed as f64)
+        }
+    }
+}
+
+impl Workload {
+    /// Returns the fraction of the planned time that was done, if anything was planned.
+    pub fn completion_rate(&self) -> Option<f64> {
+        if self.planned == Duration::zero() {
+            None
+        } else {
+            Some(self.completed.num_seconds() as f64 / self.planned.num_seconds() as f64)
+        }
+    }
+}
+
+impl TagEstimate {
+    /// Returns how much time was spent compared to the estimates. More than 1 means that the tasks
+    /// took longer than estimated.
+    pub fn ratio(&self) -> f64 {
+        self.spent.num_seconds() as f64 / self.estimated.num_seconds() as f64
+    }
+}
+
+/// How much of its weekly budget a tag is using in the current week.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetUse {
+    pub tag: String,
+    pub budget: Budget,
+    /// The time spent on the tasks with the tag that were completed this week.
+    pub spent: Duration,
+    /// The time that the accepted schedule still plans for the tag during the rest of the week.
+    pub planned: Duration,
+}
+
+impl BudgetUse {
+    /// Returns how every tag in `budgets` uses its budget in the week of `now`, in alphabetical
+    /// order. Nothing is planned without an `accepted` schedule.
+    pub fn this_week(
+        now: DateTime<Utc>,
+        budgets: &HashMap<String, Budget>,
+        completed_tasks: &[Task],
+        accepted: Option<&Schedule>,
+    ) -> Vec<BudgetUse> {
+        let spent = spent_per_tag(now, completed_tasks);
+        let next_week = monday_of(now) + Duration::weeks(1);
+        let mut budget_uses: Vec<BudgetUse> = budgets
+            .iter()
+            .map(|(tag, &budget)| {
+                let planned = accepted
+                    .into_iter()
+                    .flat_map(|schedule| &schedule.0)
+                    .filter(|scheduled_task| {
+                        now <= scheduled_task.when
+                            && scheduled_task.when.date() < next_week
+                            && scheduled_task.task.has_tag(tag)
+                            && !completed_tasks
+                                .iter()
+                                .any(|task| task.id == scheduled_task.task.id)
+                    })
+                    .fold(Duration::zero(), |total, scheduled_task| {
+                        total + scheduled_task.task.duration
+                    });
+                BudgetUse {
+                    tag: tag.clone(),
+                    budget,
+                    spent: spent.get(tag).cloned().unwrap_or_else(Duration::zero),
+                    planned,
+                }
+            })
+            .collect();
+        budget_uses.sort_by(|budget_use, other| budget_use.tag.cmp(&other.tag));
+        budget_uses
+    }
+}
+
+/// Returns how much time was spent per tag on the `completed_tasks` that were completed in the
+/// week of `now`. Like in the statistics, that is the time spent on a task if it was logged, or
+/// its duration otherwise.
+pub(crate) fn spent_per_tag(
+    now: DateTime<Utc>,
+    completed_tasks: &[Task],
+) -> HashMap<String, Duration> {
+    let this_week = monday_of(now);
+    let mut spent = HashMap::new();
+    for task in completed_tasks {
+        if task.completed.map(monday_of) != Some(this_week) {
+            continue;
+        }
+        for tag in &task.tags {
+            let total = spent.entry(tag.clone()).or_insert_with(Duration::zero);
+            *total = *total + task.time_spent.unwrap_or(task.duration);
+        }
+    }
+    spent
+}
+
+/// Returns the planned tasks in `snapshots` that count: a task counts when it was planned by the
+/// last snapshot that was taken before it was due.
+fn due_plans(snapshots: &[Snapshot]) -> Vec<&PlannedTask> {
+    let mut snapshots: Vec<&Snapshot> = snapshots.iter().collect();
+    snapshots.sort_by_key(|snapshot| snapshot.taken);
+    let mut due = vec![];
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let replaced = snapshots.get(index + 1).map(|next| next.taken);
+        due.extend(snapshot.planned.iter().filter(|planned_task| {
+            snapshot.taken <= planned_task.when
+                && replaced.map_or(true, |replaced| planned_task.when < replaced)
+        }));
+    }
+    due
+}
+
+/// Returns what was planned and done on `date`, adding it when it isn't there yet.
+fn day_of(
+    days: &mut BTreeMap<Date<Utc>, (Duration, Duration)>,
+    date: Date<Utc>,
+) -> &mut (Duration, Duration) {
+    days.entry(date)
+        .or_insert_with(|| (Duration::zero(), Duration::zero()))
+}
+
+/// Returns the week that `moment` falls in, adding it when it isn't there yet.
+fn week_of(weeks: &mut BTreeMap<Date<Utc>, Week>, moment: DateTime<Utc>) -> &mut Week {
+    let monday = monday_of(moment);
+    weeks.entry(monday).or_insert_with(|| Week {
+        monday,
+        planned: Duration::zero(),
+        completed: Duration::zero(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::fixtures::{monday, task};
+    use crate::ScheduledTask;
+
+    fn planned(task_id: u32, hours_after_monday: i64, hours: i64) -> PlannedTask {
+        PlannedTask {
+            task_id,
+            when: monday() + Duration::hours(hours_after_monday),
+            duration: Duration::hours(hours),
+        }
+    }
+
+    #[test]
+    fn plans_are_compared_with_what_was_done() {
+        let deadline = monday() + Duration::days(3);
+        let snapshots = vec![
+            // Only the part of this plan before the next snapshot counts
+            Snapshot {
+                taken: monday(),
+                planned: vec![planned(1, 1, 2), planned(2, 30, 4)],
+            },
+            Snapshot {
+                taken: monday() + Duration::days(1),
+                planned: vec![planned(2, 26, 3), planned(3, 24 * 7, 1)],
+            },
+        ];
+        let mut task1 = task(1, 2, deadline).with_tag("work");
+        task1.completed = Some(monday() + Duration::hours(3));
+        task1.time_spent = Some(Duration::hours(3));
+        let mut task2 = task(2, 3, deadline).with_tag("work");
+        task2.completed = Some(monday() + Duration::days(4));
+        let mut task3 = task(3, 1, deadline).with_tag("home");
+        task3.completed = Some(monday() + Duration::days(8));
+        task3.time_spent = Some(Duration::hours(1));
+        let statistics = Statistics::compute(&[task1, task2, task3], &snapshots);
+
+        assert_eq!(statistics.weeks.len(), 2);
+        assert_eq!(statistics.weeks[0].monday, monday().date());
+        assert_eq!(statistics.weeks[0].planned, Duration::hours(5));
+        assert_eq!(statistics.weeks[0].completed, Duration::hours(6));
+        assert_eq!(statistics.weeks[1].planned, Duration::hours(1));
+        assert_eq!(statistics.weeks[1].completed, Duration::hours(1));
+        assert_eq!(statistics.completed_per_tag["work"], 2);
+        assert_eq!(statistics.completed_per_tag["home"], 1);
+        assert_eq!(statistics.completed, 3);
+        assert_eq!(statistics.completed_on_time, 1);
+        assert_eq!(statistics.on_time_rate(), Some(1.0 / 3.0));
+        assert_eq!(statistics.estimated, Duration::hours(3));
+        assert_eq!(statistics.spent, Duration::hours(4));
+        assert_eq!(statistics.estimate_ratio(), Some(4.0 / 3.0));
+    }
+
+    #[test]
+    fn no_statistics_without_history() {
+        let statistics = Statistics::compute(&[], &[]);
+        assert!(statistics.weeks.is_empty());
+        assert_eq!(statistics.on_time_rate(), None);
+        assert_eq!(statistics.estimate_ratio(), None);
+    }
+
+    #[test]
+    fn insights_show_where_plans_fall_short() {
+        let deadline = monday() + Duration::days(3);
+        // Monday plans 4 hours and Tuesday 2, but only 1 and 3 hours get done
+        let snapshots = vec![Snapshot {
+            taken: monday(),
+            planned: vec![planned(1, 1, 4), planned(2, 24, 2)],
+        }];
+        let mut task1 = task(1, 4, deadline).with_tag("work");
+        task1.completed = Some(monday() + Duration::hours(5));
+        task1.time_spent = Some(Duration::hours(1));
+        task1.slipped = 1;
+        let mut task2 = task(2, 2, deadline).with_tag("home");
+        task2.completed = Some(monday() + Duration::hours(26));
+        task2.time_spent = Some(Duration::hours(3));
+        task2.slipped = 2;
+        let mut last_month = task(3, 1, deadline).with_tag("work");
+        last_month.completed = Some(monday() - Duration::days(7));
+        let mut pending = vec![
+            task(4, 1, deadline).with_tag("work"),
+            task(5, 1, deadline).with_tag("work"),
+            task(6, 1, deadline).with_tag("home"),
+        ];
+        pending[0].slipped = 1;
+        pending[1].slipped = 3;
+        let insights = Insights::compute(
+            monday().date(),
+            &[task1, task2, last_month],
+            &pending,
+            &snapshots,
+        );
+
+        assert_eq!(insights.month, Utc.ymd(2019, 3, 1));
+        let monday_workload = &insights.workloads[0];
+        assert_eq!(monday_workload.weekday, Weekday::Mon);
+        assert_eq!(
+            (monday_workload.days, monday_workload.overcommitted),
+            (1, 1)
+        );
+        assert_eq!(monday_workload.completion_rate(), Some(0.25));
+        let tuesday_workload = &insights.workloads[1];
+        assert_eq!(
+            (tuesday_workload.days, tuesday_workload.overcommitted),
+            (1, 0)
+        );
+        assert_eq!(insights.workloads[2].completion_rate(), None);
+        // Taking a quarter of the estimate is further off than taking half again as long
+        let tags: Vec<&str> = insights.estimates.iter().map(|e| e.tag.as_str()).collect();
+        assert_eq!(tags, vec!["work", "home"]);
+        assert_eq!(insights.estimates[1].ratio(), 1.5);
+        assert_eq!(insights.completed, 2);
+        assert_eq!(insights.average_slips(), Some(1.5));
+        let most_slipped: Vec<u32> = insights.most_slipped.iter().map(|task| task.id).collect();
+        assert_eq!(most_slipped, vec![5, 4]);
+    }
+
+    #[test]
+    fn budget_use_counts_this_week() {
+        let deadline = monday() + Duration::days(3);
+        let mut done_last_week = task(1, 2, deadline).with_tag("health");
+        done_last_week.completed = Some(monday() - Duration::days(1));
+        let mut done = task(2, 3, deadline).with_tag("health");
+        done.completed = Some(monday() + Duration::hours(2));
+        done.time_spent = Some(Duration::hours(1));
+        let scheduled = |task: Task, hours_after_monday| ScheduledTask {
+            task,
+            when: monday() + Duration::hours(hours_after_monday),
+            note: None,
+            held_back_by: None,
+        };
+        let accepted = Schedule(vec![
+            scheduled(done.clone(), 1),
+            scheduled(task(3, 2, deadline).with_tag("health"), 5),
+            scheduled(task(4, 4, deadline).with_tag("health"), 24 * 7),
+            scheduled(task(5, 1, deadline).with_tag("work"), 6),
+        ]);
+        let budget = Budget {
+            maximum: None,
+            minimum: Some(Duration::hours(6)),
+        };
+        let budgets = vec![("health".to_owned(), budget)].into_iter().collect();
+        let now = monday() + Duration::hours(3);
+        assert_eq!(
+            BudgetUse::this_week(now, &budgets, &[done_last_week, done], Some(&accepted)),
+            vec![BudgetUse {
+                tag: "health".to_owned(),
+                budget,
+                spent: Duration::hours(1),
+                planned: Duration::hours(2),
+            }]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Suggestions for new tasks, learned from the tasks that were completed before.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::Duration;
+
+use crate::Task;
+
+/// How alike a completed task has to be to a new one to base an estimate on it, between 0 (not
+/// at all) and 1 (the same words and tags).
+const MIN_SIMILARITY: f64 = 0.3;
+/// How many of the most similar tasks an estimate is based on at most.
+const MAX_SIMILAR_TASKS: usize = 5;
+/// How sure a tag suggestion has to be to be made at all.
+const MIN_TAG_CONFIDENCE: f64 = 0.5;
+/// In how many tasks a word has to appear to learn tags from it.
+const MIN_TAG_SUPPORT: usize = 2;
+
+/// How long a new task will probably take, judging by similar tasks that were completed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    /// The median of the time spent on the similar tasks, in whole minutes.
+    pub duration: Duration,
+    /// The completed tasks the estimate is based on, the most similar one first.
+    pub similar_tasks: Vec<Task>,
+}
+
+impl Estimate {
+    /// Estimates the duration of a task with the given content and tags from the most similar
+    /// `completed_tasks`, if any of them are similar enough.
+    ///
+    /// Tasks are compared by the words in their content and by their tags. The time of a
+    /// completed task is the time spent on it if that was logged, or its duration otherwise.
+    pub fn from_history(
+        content: &str,
+        tags: &[String],
+        completed_tasks: &[Task],
+    ) -> Option<Estimate> {
+        let features = features(content, tags);
+        let mut similar_tasks: Vec<(f64, &Task)> = completed_tasks
+            .iter()
+            .map(|task| (similarity(&features, &features_of(task)), task))
+            .filter(|&(similarity, _)| MIN_SIMILARITY <= similarity)
+            .collect();
+        if similar_tasks.is_empty() {
+            return None;
+        }
+        // The most recently completed task goes first among equally similar ones
+        similar_tasks.sort_by(|(similarity1, task1), (similarity2, task2)| {
+            similarity2
+                .partial_cmp(similarity1)
+                .unwrap()
+                .then_with(|| task2.completed.cmp(&task1.completed))
+        });
+        similar_tasks.truncate(MAX_SIMILAR_TASKS);
+
+        let mut minutes: Vec<i64> = similar_tasks
+            .iter()
+            .map(|(_, task)| task.time_spent.unwrap_or(task.duration).num_minutes())
+            .collect();
+        minutes.sort();
+        let middle = minutes.len() / 2;
+        let median = if minutes.len() % 2 == 0 {
+            (minutes[middle - 1] + minutes[middle]) / 2
+        } else {
+            minutes[middle]
+        };
+        Some(Estimate {
+            duration: Duration::minutes(median),
+            similar_tasks: similar_tasks.into_iter().map(|(_, task)| task.clone()).collect(),
+        })
+    }
+}
+
+/// A tag that a new task probably should get.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagSuggestion {
+    pub tag: String,
+    /// How sure the suggestion is, between 0 and 1.
+    pub confidence: f64,
+}
+
+impl TagSuggestion {
+    /// Suggests tags for a task with the given content that it doesn't have yet, the most likely
+    /// one first.
+    ///
+    /// A tag is suggested for sure when the content contains one of its keywords in `rules`, which
+    /// can be phrases of several words like "pick up", whatever their length or case.
+    /// Otherwise, the confidence in a tag is the largest fraction of the tasks in `history` that
+    /// share a word with the content and have the tag, as long as at least two tasks share that
+    /// word. Suggestions that are less than 50% sure are left out.
+    pub fn suggest(
+        content: &str,
+        tags: &[String],
+        rules: &HashMap<String, Vec<String>>,
+        history: &[Task],
+    ) -> Vec<TagSuggestion> {
+        let content_words = words(content);
+        let phrase = normalised(content);
+        let mut confidences: BTreeMap<&str, f64> = BTreeMap::new();
+        for (tag, keywords) in rules {
+            let matches = |keyword: &String| {
+                let keyword = normalised(keyword);
+                !keyword.trim().is_empty() && phrase.contains(&keyword)
+            };
+            if keywords.iter().any(matches) {
+                confidences.insert(tag, 1.0);
+            }
+        }
+        let history_words: Vec<BTreeSet<String>> =
+            history.iter().map(|task| words(&task.content)).collect();
+        for word in &content_words {
+            let tasks_with_word: Vec<&Task> = history
+                .iter()
+                .zip(&history_words)
+                .filter(|(_, task_words)| task_words.contains(word))
+                .map(|(task, _)| task)
+                .collect();
+            if tasks_with_word.len() < MIN_TAG_SUPPORT {
+                continue;
+            }
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for tag in tasks_with_word.iter().flat_map(|task| &task.tags) {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+            for (tag, count) in counts {
+                let confidence = count as f64 / tasks_with_word.len() as f64;
+                let best = confidences.entry(tag).or_insert(0.0);
+                if *best < confidence {
+                    *best = confidence;
+                }
+            }
+        }
+
+        let mut suggestions: Vec<TagSuggestion> = confidences
+            .into_iter()
+            .filter(|&(tag, confidence)| {
+                MIN_TAG_CONFIDENCE <= confidence && !tags.iter().any(|own_tag| own_tag == tag)
+            })
+            .map(|(tag, confidence)| TagSuggestion {
+                tag: tag.to_owned(),
+                confidence,
+            })
+            .collect();
+        // The tags are in alphabetical order, which stays so among equally likely ones
+        suggestions.sort_by(|suggestion1, suggestion2| {
+            suggestion2
+                .confidence
+                .partial_cmp(&suggestion1.confidence)
+                .unwrap()
+        });
+        suggestions
+    }
+}
+
+/// Returns the lower-cased words of `content` that say something about it, and the tags.
+fn features(content: &str, tags: &[String]) -> BTreeSet<String> {
+    let mut features = words(content);
+    features.extend(tags.iter().map(|tag| format!("+{}", tag)));
+    features
+}
+
+/// Returns the lower-cased words of `content`, leaving out short ones like "a" and "to".
+fn words(content: &str) -> BTreeSet<String> {
+    content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Returns all the lower-cased words of `text`, separated by single spaces and with a space in
+/// front and at the back, so that a normalised phrase is in a normalised text when it contains
+/// these words right after each other.
+fn normalised(text: &str) -> String {
+    let mut normalised = String::from(" ");
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        normalised.push_str(&word.to_lowercase());
+        normalised.push(' ');
+    }
+    normalised
+}
+
+fn features_of(task: &Task) -> BTreeSet<String> {
+    features(&task.content, &task.tags)
+}
+
+/// Returns the Jaccard index of two sets of features.
+fn similarity(features1: &BTreeSet<String>, features2: &BTreeSet<String>) -> f64 {
+    let union = features1.union(features2).count();
+    if union == 0 {
+        return 0.0;
+    }
+    features1.intersection(features2).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use super::*;
+
+    fn completed_task(id: u32, content: &str, tags: &[&str], minutes: i64) -> Task {
+        Task {
+            id,
+            content: content.to_owned(),
+            duration: Duration::hours(1),
+            tags: tags.iter().map(|&tag| tag.to_owned()).collect(),
+            completed: Some(Utc.ymd(2019, 3, 1).and_hms(9, 0, 0) + Duration::days(i64::from(id))),
+            time_spent: Some(Duration::minutes(minutes)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn estimates_come_from_similar_tasks() {
+        let completed_tasks = vec![
+            completed_task(1, "Review the pull request of Ann", &["work"], 30),
+            completed_task(2, "Review pull request of Bob", &["work"], 50),
+            completed_task(3, "Water the plants", &["home"], 5),
+            completed_task(4, "Review a pull request", &[], 40),
+        ];
+        let tags = vec!["work".to_owned()];
+        let estimate =
+            Estimate::from_history("Review pull request of Carl", &tags, &completed_tasks).unwrap();
+        assert_eq!(estimate.duration, Duration::minutes(40));
+        let ids: Vec<u32> = estimate.similar_tasks.iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2, 4, 1]);
+    }
+
+    #[test]
+    fn time_spent_is_preferred_over_the_duration() {
+        let mut completed_tasks = vec![
+            completed_task(1, "Mow the lawn", &[], 40),
+            completed_task(2, "Mow the lawn", &[], 0),
+        ];
+        completed_tasks[1].time_spent = None;
+        let estimate = Estimate::from_history("mow lawn", &[], &completed_tasks).unwrap();
+        assert_eq!(estimate.duration, Duration::minutes(50));
+    }
+
+    #[test]
+    fn tags_are_suggested_by_rules_and_history() {
+        let history = vec![
+            completed_task(1, "Buy milk", &["errand", "home"], 5),
+            completed_task(2, "Buy stamps", &["errand"], 5),
+            completed_task(3, "Buy a birthday present", &["errand", "family"], 5),
+            completed_task(4, "Call grandma", &["family"], 5),
+        ];
+        let rules: HashMap<String, Vec<String>> =
+            vec![("shopping".to_owned(), vec!["Groceries".to_owned()])].into_iter().collect();
+        let suggestions =
+            TagSuggestion::suggest("Buy groceries", &["errand".to_owned()], &rules, &history);
+        assert_eq!(
+            suggestions,
+            vec![TagSuggestion {
+                tag: "shopping".to_owned(),
+                confidence: 1.0,
+            }]
+        );
+        // A single task with a word isn't enough to learn from, and a third isn't sure enough
+        let suggestions = TagSuggestion::suggest("Call my brother to buy", &[], &rules, &history);
+        assert_eq!(
+            suggestions,
+            vec![TagSuggestion {
+                tag: "errand".to_owned(),
+                confidence: 1.0,
+            }]
+        );
+        assert!(TagSuggestion::suggest("Mow the lawn", &[], &rules, &history).is_empty());
+    }
+
+    #[test]
+    fn keywords_can_be_short_or_phrases() {
+        let rules: HashMap<String, Vec<String>> = vec![
+            ("errand".to_owned(), vec!["buy".to_owned(), "pick up".to_owned()]),
+            ("health".to_owned(), vec!["GP".to_owned()]),
+        ]
+        .into_iter()
+        .collect();
+        let suggest = |content| {
+            TagSuggestion::suggest(content, &[], &rules, &[])
+                .into_iter()
+                .map(|suggestion| suggestion.tag)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(suggest("Pick  up the parcel"), vec!["errand"]);
+        assert_eq!(suggest("Call the gp"), vec!["health"]);
+        // Only whole words count
+        assert!(suggest("Pick the upholstery").is_empty());
+        assert!(suggest("Buyer meeting").is_empty());
+    }
+
+    #[test]
+    fn no_estimate_without_similar_tasks() {
+        let completed_tasks = vec![completed_task(1, "Water the plants", &["home"], 5)];
+        assert_eq!(Estimate::from_history("Write a report", &[], &completed_tasks), None);
+        assert_eq!(Estimate::from_history("Water the plants", &[], &[]), None);
+    }
+}
@@ -0,0 +1,536 @@
+//! The tasks that Eva schedules, and the templates to make new ones from.
+
+use std::fmt;
+use std::ops::Range;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use derive_new::new;
+
+use crate::configuration::LocalTime;
+
+#[derive(Debug, new, Clone)]
+pub struct NewTask {
+    pub content: String,
+    pub deadline: DateTime<Utc>,
+    pub duration: Duration,
+    pub importance: u32,
+    pub tags: Vec<String>,
+    /// The task can't be scheduled before this moment.
+    pub start_after: Option<DateTime<Utc>>,
+    /// How long before its scheduled start to be reminded of the task. When there are none, the
+    /// reminders of the configuration apply.
+    pub reminders: Vec<Duration>,
+    /// The breathing room to keep free after the task, before the next one starts. When this is
+    /// `None`, the buffer of the configuration applies.
+    pub buffer: Option<Duration>,
+    /// How soon the work on the task has to start for it to be scheduled. Tasks that can wait
+    /// longer are left for later. When this is `None`, the horizon of the configuration applies.
+    pub horizon: Option<Duration>,
+    /// How much of the task has to be done every day until it's done, to keep up a steady
+    /// progress on it. Without one, the task is scheduled in one go.
+    pub daily_minimum: Option<Duration>,
+    /// Files and links that belong to the task, as paths or URLs. Only these references are
+    /// stored, not what they point to, so they can be opened from wherever the task is read.
+    pub attachments: Vec<String>,
+    /// When the task is best done, like during business hours. Schedules keep to it when there
+    /// is time for it in the window, and plan the task outside of it otherwise.
+    pub preferred_window: Option<Window>,
+    /// Who does the task, when tasks are shared in a household or a team. Every assignee gets a
+    /// timeline of their own, see `Configuration::assignees`.
+    pub assignee: Option<String>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct Task {
+    pub id: u32,
+    pub content: String,
+    pub deadline: DateTime<Utc>,
+    pub duration: Duration,
+    pub importance: u32,
+    pub tags: Vec<String>,
+    /// The task can't be scheduled before this moment.
+    pub start_after: Option<DateTime<Utc>>,
+    /// How long before its scheduled start to be reminded of the task. When there are none, the
+    /// reminders of the configuration apply.
+    pub reminders: Vec<Duration>,
+    /// The breathing room to keep free after the task, before the next one starts. When this is
+    /// `None`, the buffer of the configuration applies.
+    pub buffer: Option<Duration>,
+    /// How soon the work on the task has to start for it to be scheduled. Tasks that can wait
+    /// longer are left for later. When this is `None`, the horizon of the configuration applies.
+    pub horizon: Option<Duration>,
+    /// How much of the task has to be done every day until it's done, to keep up a steady
+    /// progress on it. Without one, the task is scheduled in one go.
+    pub daily_minimum: Option<Duration>,
+    /// Files and links that belong to the task, as paths or URLs. Only these references are
+    /// stored, not what they point to, so they can be opened from wherever the task is read.
+    pub attachments: Vec<String>,
+    /// When the task is best done, like during business hours. Schedules keep to it when there
+    /// is time for it in the window, and plan the task outside of it otherwise.
+    pub preferred_window: Option<Window>,
+    /// Who does the task, when tasks are shared in a household or a team. Every assignee gets a
+    /// timeline of their own, see `Configuration::assignees`.
+    pub assignee: Option<String>,
+    /// When the task was completed, or `None` if it's still to be done.
+    pub completed: Option<DateTime<Utc>>,
+    /// How much time was actually spent on the task, if that was logged.
+    pub time_spent: Option<Duration>,
+    /// How many times the task wasn't finished by the end of the slot it was scheduled in.
+    pub slipped: u32,
+    /// What the task was snoozed until. It's left out of the schedules until this goes off, and
+    /// then it's cleared.
+    pub waiting_for: Option<Trigger>,
+}
+
+/// Something a snoozed task waits for before it's scheduled again.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum Trigger {
+    /// Being at this place, like the office. The place is the one of the configuration.
+    At(String),
+    /// The task with this id being done, or deleted.
+    Done(u32),
+}
+
+/// A time of day on some days of the week, like the mornings or the weekends, in local time (see
+/// `Configuration::local_time`).
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct Window {
+    pub days: Days,
+    /// When the window opens on those days.
+    pub from: NaiveTime,
+    /// When the window closes, or `None` when it stays open until midnight. A window that closes
+    /// at or before it opens stays open until that time the next day.
+    pub until: Option<NaiveTime>,
+}
+
+/// The days of the week that a `Window` is open on.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum Days {
+    Every,
+    /// Monday to Friday.
+    Weekdays,
+    /// Saturday and Sunday.
+    Weekends,
+}
+
+/// Presets for new tasks, which fill in the fields of a task that aren't given otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Template {
+    /// The name to refer to the template by, which is unique.
+    pub name: String,
+    /// The content of the tasks, which can contain placeholders (see `Template::fill_in`).
+    pub content: Option<String>,
+    /// How long after a task is made from the template its deadline is.
+    pub due_in: Option<Duration>,
+    pub duration: Option<Duration>,
+    pub importance: Option<u32>,
+    pub tags: Vec<String>,
+    pub reminders: Vec<Duration>,
+    pub buffer: Option<Duration>,
+    pub horizon: Option<Duration>,
+    pub daily_minimum: Option<Duration>,
+}
+
+impl Default for NewTask {
+    fn default() -> NewTask {
+        NewTask {
+            content: String::new(),
+            deadline: Utc.timestamp(0, 0),
+            duration: Duration::zero(),
+            importance: 0,
+            tags: vec![],
+            start_after: None,
+            reminders: vec![],
+            buffer: None,
+            horizon: None,
+            daily_minimum: None,
+            attachments: vec![],
+            preferred_window: None,
+            assignee: None,
+        }
+    }
+}
+
+impl Default for Task {
+    fn default() -> Task {
+        Task {
+            id: 0,
+            content: String::new(),
+            deadline: Utc.timestamp(0, 0),
+            duration: Duration::zero(),
+            importance: 0,
+            tags: vec![],
+            start_after: None,
+            reminders: vec![],
+            buffer: None,
+            horizon: None,
+            daily_minimum: None,
+            attachments: vec![],
+            preferred_window: None,
+            assignee: None,
+            completed: None,
+            time_spent: None,
+            slipped: 0,
+            waiting_for: None,
+        }
+    }
+}
+
+impl Task {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own_tag| own_tag == tag)
+    }
+
+    /// Returns how long the task occupies a schedule: its duration followed by its buffer, or
+    /// `None` when that is too long to represent.
+    pub fn duration_with_buffer(&self) -> Option<Duration> {
+        self.duration
+            .checked_add(&self.buffer.unwrap_or_else(Duration::zero))
+    }
+
+    /// Returns `duration_with_buffer` for the scheduling algorithms, which make sure that it
+    /// doesn't overflow before they call this.
+    pub(crate) fn occupied(&self) -> Duration {
+        self.duration + self.buffer.unwrap_or_else(Duration::zero)
+    }
+
+    /// Tells whether the work on the task doesn't have to start within its horizon from `now`,
+    /// so that it can be left out of the schedule for now. The task's own horizon overrides the
+    /// `default_horizon`. Without a horizon, nothing is left for later.
+    ///
+    /// A task has to start by its `latest_start` (see `Schedule::latest_starts`), so a long task
+    /// with a distant deadline, or one that is due with other long tasks, can still have to start
+    /// within the horizon.
+    pub fn beyond_horizon(
+        &self,
+        now: DateTime<Utc>,
+        latest_start: DateTime<Utc>,
+        default_horizon: Option<Duration>,
+    ) -> bool {
+        let horizon = match self.horizon.or(default_horizon) {
+            Some(horizon) => horizon,
+            None => return false,
+        };
+        match now.checked_add_signed(horizon) {
+            Some(end_of_horizon) => end_of_horizon <= latest_start,
+            // Out of range, the horizon is too far away to leave anything out
+            None => false,
+        }
+    }
+}
+
+impl Trigger {
+    /// Tells whether the trigger went off when the user is at `place` and the `pending` tasks
+    /// are the ones that aren't done yet.
+    pub fn went_off(&self, place: Option<&str>, pending: &[Task]) -> bool {
+        match self {
+            Trigger::At(trigger_place) => place == Some(trigger_place.as_str()),
+            Trigger::Done(id) => pending.iter().all(|task| task.id != *id),
+        }
+    }
+}
+
+impl Window {
+    /// Returns the periods in which the window is open that overlap with `period`, in
+    /// chronological order, in `local_time`. Openings that follow each other right away, like the
+    /// days of a weekend, are joined.
+    pub fn openings(
+        &self,
+        period: Range<DateTime<Utc>>,
+        local_time: &LocalTime,
+    ) -> Vec<Range<DateTime<Utc>>> {
+        let local_date = |moment: DateTime<Utc>| local_time.local(moment).date();
+        // A window that opened the day before can still be open at the start
+        let first_date = local_date(period.start);
+        let mut date = first_date.pred_opt().unwrap_or(first_date);
+        let mut openings: Vec<Range<DateTime<Utc>>> = vec![];
+        while date <= local_date(period.end) {
+            if let Some(opening) = self.opening_on(date, local_time) {
+                let overlaps = opening.start < period.end && period.start < opening.end;
+                let touches = period.start == period.end && opening.contains(&period.start);
+                match openings.last_mut() {
+                    Some(last) if last.end == opening.start => last.end = opening.end,
+                    _ if overlaps || touches => openings.push(opening),
+                    _ => (),
+                }
+            }
+            date = match date.succ_opt() {
+                Some(next_date) => next_date,
+                None => break,
+            };
+        }
+        openings
+    }
+
+    /// Tells whether all of `period` falls within one opening of the window.
+    pub fn contains(&self, period: Range<DateTime<Utc>>, local_time: &LocalTime) -> bool {
+        self.openings(period.clone(), local_time)
+            .iter()
+            .any(|opening| opening.start <= period.start && period.end <= opening.end)
+    }
+
+    /// Returns when the window is open from `date` on in local time, if it is on that day.
+    fn opening_on(&self, date: NaiveDate, local_time: &LocalTime) -> Option<Range<DateTime<Utc>>> {
+        if !self.days.contains(date.weekday()) {
+            return None;
+        }
+        let next_date = date.succ_opt()?;
+        let closes = match self.until {
+            Some(until) if self.from < until => date.and_time(until),
+            Some(until) => next_date.and_time(until),
+            None => next_date.and_hms(0, 0, 0),
+        };
+        Some(local_time.moment_at(date.and_time(self.from))..local_time.moment_at(closes))
+    }
+}
+
+impl Days {
+    fn contains(self, weekday: Weekday) -> bool {
+        match self {
+            Days::Every => true,
+            Days::Weekdays => weekday.number_from_monday() <= 5,
+            Days::Weekends => weekday.number_from_monday() > 5,
+        }
+    }
+}
+
+impl Template {
+    /// Fills in the placeholders in the `content` of a task that is made `today`: `{date}` (like
+    /// 2019-03-01), `{week}` (the ISO week number), `{month}` (like March) and `{year}`. Other
+    /// text between braces is left as it is.
+    pub fn fill_in(content: &str, today: NaiveDate) -> String {
+        content
+            .replace("{date}", &today.format("%Y-%m-%d").to_string())
+            .replace("{week}", &today.iso_week().week().to_string())
+            .replace("{month}", &today.format("%B").to_string())
+            .replace("{year}", &today.year().to_string())
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.content)
+    }
+}
+
+/// Shows windows like "weekends", "after 20:00" or "weekdays 09:00-17:00".
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+        let days = match self.days {
+            Days::Every => None,
+            Days::Weekdays => Some("weekdays"),
+            Days::Weekends => Some("weekends"),
+        };
+        let time = match (self.from, self.until) {
+            (from, None) if from == midnight => None,
+            (from, None) => Some(format!("after {}", from.format("%H:%M"))),
+            (from, Some(until)) if from == midnight => {
+                Some(format!("before {}", until.format("%H:%M")))
+            }
+            (from, Some(until)) => Some(format!(
+                "{}-{}",
+                from.format("%H:%M"),
+                until.format("%H:%M")
+            )),
+        };
+        match (days, time) {
+            (Some(days), Some(time)) => write!(f, "{} {}", days, time),
+            (Some(days), None) => write!(f, "{}", days),
+            (None, Some(time)) => write!(f, "{}", time),
+            (None, None) => write!(f, "any time"),
+        }
+    }
+}
+
+/// The moment and the tasks that the scheduling tests build on.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    use super::Task;
+    use crate::configuration::LocalTime;
+
+    /// Central European Time in the spring of 2019, when the clocks moved from two to three
+    /// o'clock on Sunday 31 March, at 01:00 in UTC.
+    pub(crate) struct SpringForward;
+
+    impl LocalTime for SpringForward {
+        fn offset_at(&self, moment: DateTime<Utc>) -> FixedOffset {
+            if moment < Utc.ymd(2019, 3, 31).and_hms(1, 0, 0) {
+                FixedOffset::east(3600)
+            } else {
+                FixedOffset::east(7200)
+            }
+        }
+
+        fn moment_at(&self, local: NaiveDateTime) -> DateTime<Utc> {
+            let skipped = NaiveDate::from_ymd(2019, 3, 31).and_hms(2, 0, 0);
+            if local < skipped {
+                Utc.from_utc_datetime(&(local - Duration::hours(1)))
+            } else if local < skipped + Duration::hours(1) {
+                Utc.ymd(2019, 3, 31).and_hms(1, 0, 0)
+            } else {
+                Utc.from_utc_datetime(&(local - Duration::hours(2)))
+            }
+        }
+    }
+
+    /// Monday 4 March 2019 at 09:00, when most tests start.
+    pub(crate) fn monday() -> DateTime<Utc> {
+        Utc.ymd(2019, 3, 4).and_hms(9, 0, 0)
+    }
+
+    /// Task `id` of importance 5, which takes `hours` and is due at `deadline`.
+    pub(crate) fn task(id: u32, hours: i64, deadline: DateTime<Utc>) -> Task {
+        Task {
+            id,
+            content: format!("task {}", id),
+            deadline,
+            duration: Duration::hours(hours),
+            importance: 5,
+            ..Default::default()
+        }
+    }
+
+    impl Task {
+        pub(crate) fn with_importance(self, importance: u32) -> Task {
+            Task { importance, ..self }
+        }
+
+        pub(crate) fn with_tag(mut self, tag: &str) -> Task {
+            self.tags.push(tag.to_owned());
+            self
+        }
+
+        pub(crate) fn with_daily_minimum(self, hours: i64) -> Task {
+            Task {
+                daily_minimum: Some(Duration::hours(hours)),
+                ..self
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::SpringForward;
+
+    #[test]
+    fn tasks_that_must_start_soon_are_within_the_horizon() {
+        let now = Utc.ymd(2019, 3, 1).and_hms(9, 0, 0);
+        let task = |days_left, duration_in_days| Task {
+            deadline: now + Duration::days(days_left),
+            duration: Duration::days(duration_in_days),
+            ..Default::default()
+        };
+        let horizon = Some(Duration::weeks(2));
+        let beyond_horizon =
+            |task: &Task, horizon| task.beyond_horizon(now, task.deadline - task.duration, horizon);
+        assert!(!beyond_horizon(&task(7, 1), horizon));
+        assert!(beyond_horizon(&task(30, 1), horizon));
+        // Distant, but too long to wait until after the horizon
+        assert!(!beyond_horizon(&task(30, 20), horizon));
+        assert!(!beyond_horizon(&task(30, 1), None));
+        let mut impatient_task = task(30, 1);
+        impatient_task.horizon = Some(Duration::weeks(8));
+        assert!(!beyond_horizon(&impatient_task, horizon));
+    }
+
+    #[test]
+    fn triggers_go_off_at_their_place_or_when_their_task_is_gone() {
+        let pending = vec![Task {
+            id: 2,
+            ..Default::default()
+        }];
+        let at_office = Trigger::At("office".to_owned());
+        assert!(at_office.went_off(Some("office"), &pending));
+        assert!(!at_office.went_off(Some("home"), &pending));
+        assert!(!at_office.went_off(None, &pending));
+        assert!(!Trigger::Done(2).went_off(None, &pending));
+        assert!(Trigger::Done(1).went_off(None, &pending));
+    }
+
+    #[test]
+    fn windows_open_in_local_time() {
+        // Friday the 1st of March 2019, in a time zone an hour ahead of UTC
+        let friday = Utc.ymd(2019, 3, 1).and_hms(0, 0, 0);
+        let local_time = &FixedOffset::east(3600);
+        let business_hours = Window {
+            days: Days::Weekdays,
+            from: NaiveTime::from_hms(9, 0, 0),
+            until: Some(NaiveTime::from_hms(17, 0, 0)),
+        };
+        assert_eq!(
+            business_hours.openings(friday..friday + Duration::days(4), local_time),
+            vec![
+                friday + Duration::hours(8)..friday + Duration::hours(16),
+                friday + Duration::hours(80)..friday + Duration::hours(88),
+            ]
+        );
+        assert_eq!(business_hours.to_string(), "weekdays 09:00-17:00");
+
+        let weekends = Window {
+            days: Days::Weekends,
+            from: NaiveTime::from_hms(0, 0, 0),
+            until: None,
+        };
+        let saturday_night = friday + Duration::hours(46)..friday + Duration::hours(49);
+        assert!(weekends.contains(saturday_night, local_time));
+        assert!(!weekends.contains(friday..friday + Duration::hours(24), local_time));
+        assert_eq!(weekends.to_string(), "weekends");
+
+        let late = Window {
+            days: Days::Every,
+            from: NaiveTime::from_hms(22, 0, 0),
+            until: Some(NaiveTime::from_hms(2, 0, 0)),
+        };
+        let after_midnight = friday + Duration::minutes(30)..friday + Duration::minutes(60);
+        assert!(late.contains(after_midnight, local_time));
+        assert_eq!(late.to_string(), "22:00-02:00");
+    }
+
+    #[test]
+    fn windows_follow_the_clocks_across_daylight_saving_time() {
+        // The clocks move forward in the night from Saturday 30 to Sunday 31 March 2019
+        let friday = Utc.ymd(2019, 3, 29).and_hms(0, 0, 0);
+        let business_hours = Window {
+            days: Days::Weekdays,
+            from: NaiveTime::from_hms(9, 0, 0),
+            until: Some(NaiveTime::from_hms(17, 0, 0)),
+        };
+        assert_eq!(
+            business_hours.openings(friday..friday + Duration::days(4), &SpringForward),
+            vec![
+                friday + Duration::hours(8)..friday + Duration::hours(16),
+                friday + Duration::hours(79)..friday + Duration::hours(87),
+            ]
+        );
+        let saturday_night = Window {
+            days: Days::Weekends,
+            from: NaiveTime::from_hms(22, 0, 0),
+            until: Some(NaiveTime::from_hms(2, 30, 0)),
+        };
+        let saturday = friday + Duration::days(1);
+        assert_eq!(
+            saturday_night.openings(saturday..saturday + Duration::days(1), &SpringForward),
+            vec![saturday + Duration::hours(21)..saturday + Duration::hours(25)]
+        );
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let today = NaiveDate::from_ymd(2019, 8, 9);
+        assert_eq!(
+            Template::fill_in("Review week {week} of {month} {year} ({date})", today),
+            "Review week 32 of August 2019 (2019-08-09)"
+        );
+        assert_eq!(
+            Template::fill_in("Water the {plants} on {date}", today),
+            "Water the {plants} on 2019-08-09"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Keeps track of the time that is actually spent on the tasks, in work sessions that are started
+//! and stopped, and compares what is being worked on with what the accepted schedule planned.
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use crate::{Schedule, ScheduledTask, Task};
+
+/// A stretch of time that was spent working on a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub task_id: u32,
+    pub start: DateTime<Utc>,
+    /// When the work stopped, or `None` while it's still going on. Only one session goes on at a
+    /// time.
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// What is being worked on right now, next to what the accepted schedule planned for now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// The session that is going on, with its task as it is now.
+    pub tracking: Option<(Session, Task)>,
+    /// The slot of the accepted schedule that is going on now, if any.
+    pub planned: Option<ScheduledTask>,
+    /// The first slot of the accepted schedule that starts after now, if any.
+    pub next: Option<ScheduledTask>,
+}
+
+impl Session {
+    /// Starts a session on the task with id `task_id` at `start`.
+    pub fn new(task_id: u32, start: DateTime<Utc>) -> Session {
+        Session {
+            task_id,
+            start,
+            end: None,
+        }
+    }
+
+    /// Returns how long the session lasted, or how long it has lasted by `now` while it's still
+    /// going on.
+    pub fn duration(&self, now: DateTime<Utc>) -> Duration {
+        self.end.unwrap_or(now).signed_duration_since(self.start)
+    }
+
+    /// Adds the time of the session, which ended, to the time spent on `task`.
+    pub(crate) fn add_to(&self, task: &mut Task) {
+        let time_spent = task.time_spent.unwrap_or_else(Duration::zero);
+        let lasted = self.end.map_or_else(Duration::zero, |end| end - self.start);
+        task.time_spent = Some(time_spent + lasted);
+    }
+}
+
+impl Status {
+    /// Finds what `accepted` planned at `now`, next to the `tracking` session and its task.
+    pub fn of(
+        now: DateTime<Utc>,
+        accepted: &Schedule,
+        tracking: Option<(Session, Task)>,
+    ) -> Status {
+        let planned = accepted
+            .0
+            .iter()
+            .find(|slot| slot.when <= now && now < slot.when + slot.task.duration)
+            .cloned();
+        let next = accepted
+            .0
+            .iter()
+            .filter(|slot| now < slot.when)
+            .min_by_key(|slot| slot.when)
+            .cloned();
+        Status {
+            tracking,
+            planned,
+            next,
+        }
+    }
+
+    /// Tells whether the task that is being worked on is the one that is planned for now. When
+    /// nothing is planned, whatever is done is on track, and so is taking a break.
+    pub fn on_track(&self) -> bool {
+        match (&self.tracking, &self.planned) {
+            (Some((session, _task)), Some(planned)) => session.task_id == planned.task.id,
+            (None, Some(_planned)) => false,
+            (_, None) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::fixtures::{monday, task};
+
+    #[test]
+    fn the_status_compares_the_session_with_the_slot_of_now() {
+        let deadline = monday() + Duration::days(1);
+        let schedule = Schedule(vec![
+            ScheduledTask::new(task(1, 1, deadline), monday()),
+            ScheduledTask::new(task(2, 1, deadline), monday() + Duration::hours(2)),
+        ]);
+        let now = monday() + Duration::minutes(30);
+        let session = Session::new(2, monday());
+        assert_eq!(session.duration(now), Duration::minutes(30));
+
+        let status = Status::of(
+            now,
+            &schedule,
+            Some((session.clone(), task(2, 1, deadline))),
+        );
+        assert_eq!(status.planned, Some(schedule.0[0].clone()));
+        assert_eq!(status.next, Some(schedule.0[1].clone()));
+        assert!(!status.on_track());
+        assert!(!Status::of(now, &schedule, None).on_track());
+        let status = Status::of(
+            now,
+            &schedule,
+            Some((Session::new(1, now), task(1, 1, deadline))),
+        );
+        assert!(status.on_track());
+
+        let between_slots = monday() + Duration::minutes(90);
+        let status = Status::of(between_slots, &schedule, None);
+        assert_eq!(status.planned, None);
+        assert!(status.on_track());
+    }
+
+    #[test]
+    fn ended_sessions_add_to_the_time_spent() {
+        let deadline = monday() + Duration::days(1);
+        let mut worked_on = Task {
+            time_spent: Some(Duration::minutes(10)),
+            ..task(1, 1, deadline)
+        };
+        let session = Session {
+            end: Some(monday() + Duration::minutes(25)),
+            ..Session::new(1, monday())
+        };
+        session.add_to(&mut worked_on);
+        assert_eq!(worked_on.time_spent, Some(Duration::minutes(35)));
+        let mut new_task = task(2, 1, deadline);
+        session.add_to(&mut new_task);
+        assert_eq!(new_task.time_spent, Some(Duration::minutes(25)));
+    }
+}
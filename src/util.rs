@@ -1,3 +1,6 @@
+use chrono::prelude::*;
+use chrono::Duration;
+
 macro_rules! return_on_some {
     ($e:expr) => {
         if let Some(value) = $e {
@@ -27,3 +30,9 @@ impl<T: Copy> WithSideEffects for Option<T> {
         })
     }
 }
+
+/// Returns the Monday of the week that `moment` falls in. Weeks run from Monday to Sunday in UTC.
+pub fn monday_of(moment: DateTime<Utc>) -> Date<Utc> {
+    let date = moment.date();
+    date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}